@@ -0,0 +1,15 @@
+#![no_main]
+use arbitrary::Unstructured;
+use libfuzzer_sys::fuzz_target;
+
+// generated scripts are well-typed, so they have to compile as well
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    if let Ok(source) = curlywas_fuzz::generate::script(&mut u) {
+        assert!(
+            curlywas_fuzz::compile(source.as_bytes()),
+            "Failed to compile generated script:\n{}",
+            source
+        );
+    }
+});
@@ -0,0 +1,7 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+// arbitrary input must never crash the compiler
+fuzz_target!(|source: &[u8]| {
+    curlywas_fuzz::compile(source);
+});
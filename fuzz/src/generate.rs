@@ -0,0 +1,777 @@
+// Generates random scripts which are well-typed by construction, so every one of them has to
+// compile. The scripts cover globals, consts, inline functions, `let lazy` and `let inline`,
+// memory access, intrinsics and all of the flow control.
+// The scripts are built as source text rather than as an `ast::Script`, so they go through the
+// parser too and a failing input can be printed and compiled on its own.
+
+use arbitrary::{Result, Unstructured};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+use Type::*;
+
+const TYPES: [Type; 4] = [I32, I64, F32, F64];
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            I32 => "i32",
+            I64 => "i64",
+            F32 => "f32",
+            F64 => "f64",
+        }
+    }
+
+    fn is_int(self) -> bool {
+        matches!(self, I32 | I64)
+    }
+}
+
+const INT_OPS: &[&str] = &[
+    "+", "-", "*", "/", "%", "#/", "#%", "&", "|", "^", "<<", ">>", "#>>",
+];
+const FLOAT_OPS: &[&str] = &["+", "-", "*", "/"];
+const COMPARISONS: &[&str] = &["==", "!=", "<", "<=", ">", ">="];
+const UNSIGNED_COMPARISONS: &[&str] = &["#<", "#<=", "#>", "#>="];
+
+// the intrinsics which are plain instructions, by name, parameters and result
+const INTRINSICS: &[(&str, &[Type], Type)] = &[
+    ("i32.rotl", &[I32, I32], I32),
+    ("i32.rotr", &[I32, I32], I32),
+    ("i32.clz", &[I32], I32),
+    ("i32.ctz", &[I32], I32),
+    ("i32.popcnt", &[I32], I32),
+    ("i32.extend8_s", &[I32], I32),
+    ("i32.extend16_s", &[I32], I32),
+    ("i32.wrap_i64", &[I64], I32),
+    ("i32.trunc_f32_s", &[F32], I32),
+    ("i32.trunc_f64_u", &[F64], I32),
+    ("i32.trunc_sat_f32_u", &[F32], I32),
+    ("i32.trunc_sat_f64_s", &[F64], I32),
+    ("i32.reinterpret_f32", &[F32], I32),
+    ("i64.rotl", &[I64, I64], I64),
+    ("i64.rotr", &[I64, I64], I64),
+    ("i64.clz", &[I64], I64),
+    ("i64.ctz", &[I64], I64),
+    ("i64.popcnt", &[I64], I64),
+    ("i64.extend32_s", &[I64], I64),
+    ("i64.extend_i32_s", &[I32], I64),
+    ("i64.extend_i32_u", &[I32], I64),
+    ("i64.trunc_f32_u", &[F32], I64),
+    ("i64.trunc_f64_s", &[F64], I64),
+    ("i64.trunc_sat_f32_s", &[F32], I64),
+    ("i64.trunc_sat_f64_u", &[F64], I64),
+    ("i64.reinterpret_f64", &[F64], I64),
+    ("sqrt", &[F32], F32),
+    ("min", &[F32, F32], F32),
+    ("f32.max", &[F32, F32], F32),
+    ("floor", &[F32], F32),
+    ("f32.nearest", &[F32], F32),
+    ("abs", &[F32], F32),
+    ("f32.copysign", &[F32, F32], F32),
+    ("f32.demote_f64", &[F64], F32),
+    ("f32.convert_i32_u", &[I32], F32),
+    ("f32.convert_i64_s", &[I64], F32),
+    ("f32.reinterpret_i32", &[I32], F32),
+    ("f64.sqrt", &[F64], F64),
+    ("max", &[F64, F64], F64),
+    ("ceil", &[F64], F64),
+    ("trunc", &[F64], F64),
+    ("copysign", &[F64, F64], F64),
+    ("f64.promote_f32", &[F32], F64),
+    ("f64.convert_i32_s", &[I32], F64),
+    ("f64.convert_i64_u", &[I64], F64),
+    ("f64.reinterpret_i64", &[I64], F64),
+];
+
+// load and store operators by type, with the size of the access in bytes
+fn memory_ops(type_: Type) -> &'static [(&'static str, u32)] {
+    match type_ {
+        I32 => &[("?", 1), ("??", 1), ("~", 2), ("~~", 2), ("!", 4)],
+        I64 => &[("!!", 8)],
+        F32 => &[("$", 4)],
+        F64 => &[("$$", 8)],
+    }
+}
+
+// load intrinsics other than the ones covered by the operators
+fn load_intrinsics(type_: Type) -> &'static [&'static str] {
+    match type_ {
+        I32 => &["i32.load", "i32.load16_s"],
+        I64 => &["i64.load8_u", "i64.load16_s", "i64.load32_u"],
+        F32 => &["f32.load"],
+        F64 => &["f64.load"],
+    }
+}
+
+fn store_intrinsics(type_: Type) -> &'static [&'static str] {
+    match type_ {
+        I32 => &["i32.store", "i32.store8"],
+        I64 => &["i64.store", "i64.store16", "i64.store32"],
+        F32 => &["f32.store"],
+        F64 => &["f64.store"],
+    }
+}
+
+struct Function {
+    name: String,
+    params: Vec<Type>,
+    result: Option<Type>,
+    inline: bool,
+}
+
+struct Var {
+    name: String,
+    type_: Type,
+    mutable: bool,
+    // `repeat` indexes are consts, which locals can't shadow
+    repeat_index: bool,
+}
+
+// maximum nesting of expressions and blocks
+const MAX_DEPTH: usize = 6;
+
+struct Generator<'a, 'b> {
+    u: &'b mut Unstructured<'a>,
+    functions: Vec<Function>,
+    // globals and consts
+    globals: Vec<Var>,
+    // locals of the current function, later ones shadow earlier ones of the same name
+    locals: Vec<Var>,
+    labels: Vec<String>,
+    // number of enclosing `while` and `for` loops, for `break` and `continue`
+    loops: usize,
+    result: Option<Type>,
+    inline: bool,
+    depth: usize,
+    next_id: usize,
+}
+
+pub fn script(u: &mut Unstructured) -> Result<String> {
+    Generator {
+        u,
+        functions: Vec::new(),
+        globals: Vec::new(),
+        locals: Vec::new(),
+        labels: Vec::new(),
+        loops: 0,
+        result: None,
+        inline: false,
+        depth: 0,
+        next_id: 0,
+    }
+    .script()
+}
+
+impl<'a, 'b> Generator<'a, 'b> {
+    fn name(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+
+    fn type_(&mut self) -> Result<Type> {
+        Ok(*self.u.choose(&TYPES)?)
+    }
+
+    fn signature(&mut self) -> Result<(Vec<Type>, Option<Type>)> {
+        let mut params = Vec::new();
+        for _ in 0..self.u.int_in_range(0..=3)? {
+            params.push(self.type_()?);
+        }
+        let result = if self.u.arbitrary()? {
+            Some(self.type_()?)
+        } else {
+            None
+        };
+        Ok((params, result))
+    }
+
+    fn script(&mut self) -> Result<String> {
+        let mut out = String::from("import \"env.memory\" memory(1);\n");
+        for _ in 0..self.u.int_in_range(0..=2)? {
+            let name = self.name("import");
+            let (params, result) = self.signature()?;
+            let params_str: Vec<_> = params.iter().map(|t| t.name()).collect();
+            let result_str = result.map_or(String::new(), |t| format!(" -> {}", t.name()));
+            writeln!(
+                out,
+                "import \"env.{0}\" fn {0}({1}){2};",
+                name,
+                params_str.join(", "),
+                result_str
+            )
+            .unwrap();
+            self.functions.push(Function {
+                name,
+                params,
+                result,
+                inline: false,
+            });
+        }
+        if self.u.ratio(1, 4)? {
+            let name = self.name("imported");
+            let type_ = self.type_()?;
+            writeln!(
+                out,
+                "import \"env.{0}\" global mut {0}: {1};",
+                name,
+                type_.name()
+            )
+            .unwrap();
+            self.globals.push(Var {
+                name,
+                type_,
+                mutable: true,
+                repeat_index: false,
+            });
+        }
+        for _ in 0..self.u.int_in_range(0..=3)? {
+            let name = self.name("g");
+            let type_ = self.type_()?;
+            let mutable = self.u.arbitrary()?;
+            let value = self.literal(type_)?;
+            let mut_str = if mutable { "mut " } else { "" };
+            writeln!(
+                out,
+                "global {}{}: {} = {};",
+                mut_str,
+                name,
+                type_.name(),
+                value
+            )
+            .unwrap();
+            self.globals.push(Var {
+                name,
+                type_,
+                mutable,
+                repeat_index: false,
+            });
+        }
+        for _ in 0..self.u.int_in_range(0..=3)? {
+            let name = self.name("C");
+            let type_ = self.type_()?;
+            let value = self.const_expr(type_)?;
+            writeln!(out, "const {}: {} = {};", name, type_.name(), value).unwrap();
+            self.globals.push(Var {
+                name,
+                type_,
+                mutable: false,
+                repeat_index: false,
+            });
+        }
+
+        let first = self.functions.len();
+        for _ in 0..self.u.int_in_range(1..=4)? {
+            let name = self.name("f");
+            let (params, result) = self.signature()?;
+            let inline = self.u.ratio(1, 4)?;
+            self.functions.push(Function {
+                name,
+                params,
+                result,
+                inline,
+            });
+        }
+        for index in first..self.functions.len() {
+            let function = self.function(index)?;
+            out += &function;
+        }
+        Ok(out)
+    }
+
+    fn function(&mut self, index: usize) -> Result<String> {
+        let function = &self.functions[index];
+        let (name, params, result, inline) = (
+            function.name.clone(),
+            function.params.clone(),
+            function.result,
+            function.inline,
+        );
+        self.locals.clear();
+        self.labels.clear();
+        self.loops = 0;
+        self.depth = 0;
+        self.result = result;
+        self.inline = inline;
+
+        let mut params_str = Vec::new();
+        for type_ in params {
+            let name = self.name("p");
+            params_str.push(format!("{}: {}", name, type_.name()));
+            // constant arguments of inline functions are inlined like `let inline`
+            self.locals.push(Var {
+                name,
+                type_,
+                mutable: !inline,
+                repeat_index: false,
+            });
+        }
+        let prefix = if inline {
+            "inline "
+        } else if self.u.arbitrary()? {
+            "export "
+        } else {
+            ""
+        };
+        let result_str = result.map_or(String::new(), |t| format!(" -> {}", t.name()));
+        let body = self.block(result)?;
+        Ok(format!(
+            "{}fn {}({}){} {}\n",
+            prefix,
+            name,
+            params_str.join(", "),
+            result_str,
+            body
+        ))
+    }
+
+    // a block of statements, ending in an expression of type `result`
+    fn block(&mut self, result: Option<Type>) -> Result<String> {
+        let scope = self.locals.len();
+        self.depth += 1;
+        let mut out = String::from("{\n");
+        let count = if self.depth > MAX_DEPTH {
+            0
+        } else {
+            self.u.int_in_range(0..=4)?
+        };
+        for _ in 0..count {
+            let statement = self.statement()?;
+            writeln!(out, "{};", statement).unwrap();
+        }
+        if let Some(type_) = result {
+            let value = self.expr(type_)?;
+            writeln!(out, "{}", value).unwrap();
+        }
+        out.push('}');
+        self.depth -= 1;
+        self.locals.truncate(scope);
+        Ok(out)
+    }
+
+    fn statement(&mut self) -> Result<String> {
+        Ok(match self.u.int_in_range(0..=16)? {
+            0..=2 => {
+                let type_ = self.type_()?;
+                let value = self.expr(type_)?;
+                // shadow an existing variable now and then
+                let shadowable: Vec<String> = self
+                    .locals
+                    .iter()
+                    .filter(|var| !var.repeat_index)
+                    .map(|var| var.name.clone())
+                    .collect();
+                let name = if !shadowable.is_empty() && self.u.ratio(1, 4)? {
+                    self.u.choose(&shadowable)?.clone()
+                } else {
+                    self.name("v")
+                };
+                let (statement, mutable) = match self.u.int_in_range(0..=4)? {
+                    0 => (format!("let {}: {}", name, type_.name()), true),
+                    1 => (format!("let {} = {}", name, value), true),
+                    2 => (format!("let {}: {} = {}", name, type_.name(), value), true),
+                    3 => (format!("let lazy {} = {}", name, value), false),
+                    _ => (format!("let inline {} = {}", name, value), false),
+                };
+                self.locals.push(Var {
+                    name,
+                    type_,
+                    mutable,
+                    repeat_index: false,
+                });
+                statement
+            }
+            3 | 4 => {
+                let vars: Vec<_> = self
+                    .visible()
+                    .into_iter()
+                    .filter(|var| var.mutable)
+                    .map(|var| (var.name.clone(), var.type_))
+                    .collect();
+                if vars.is_empty() {
+                    return self.expr_statement();
+                }
+                let (name, type_) = self.u.choose(&vars)?.clone();
+                let op = if self.u.arbitrary()? {
+                    self.binary_op(type_)?
+                } else {
+                    ""
+                };
+                let value = self.expr(type_)?;
+                format!("{} {}= {}", name, op, value)
+            }
+            5 => {
+                let type_ = self.type_()?;
+                let base = self.expr(I32)?;
+                let value = self.expr(type_)?;
+                if self.u.arbitrary()? {
+                    let &(op, size) = self.u.choose(memory_ops(type_))?;
+                    let offset = self.u.int_in_range(0..=16)? * size;
+                    format!("({}){}{} = {}", base, op, offset, value)
+                } else {
+                    let name = self.u.choose(store_intrinsics(type_))?;
+                    format!("{}({}, {})", name, value, base)
+                }
+            }
+            6 => {
+                let condition = self.expr(I32)?;
+                let if_true = self.block(None)?;
+                if self.u.arbitrary()? {
+                    let if_false = self.block(None)?;
+                    format!("if {} {} else {}", condition, if_true, if_false)
+                } else {
+                    format!("if {} {}", condition, if_true)
+                }
+            }
+            7 | 8 => {
+                let label = self.name("l");
+                self.labels.push(label.clone());
+                let body = self.block(None)?;
+                self.labels.pop();
+                let kind = if self.u.arbitrary()? { "block" } else { "loop" };
+                format!("{} {} {}", kind, label, body)
+            }
+            9 => {
+                let condition = self.expr(I32)?;
+                self.loops += 1;
+                let body = self.block(None)?;
+                self.loops -= 1;
+                format!("while {} {}", condition, body)
+            }
+            10 => {
+                let type_ = self.type_()?;
+                let start = self.expr(type_)?;
+                let end = self.expr(type_)?;
+                let range = if type_.is_int() && self.u.arbitrary()? {
+                    "#.."
+                } else {
+                    ".."
+                };
+                // the default step is only typed like the range if it starts or ends with a literal
+                let step = if type_ != I32 || self.u.arbitrary()? {
+                    let sign = if self.u.arbitrary()? { "-" } else { "" };
+                    format!(" step {}{}", sign, self.step(type_)?)
+                } else {
+                    String::new()
+                };
+                let name = self.name("i");
+                self.locals.push(Var {
+                    name: name.clone(),
+                    type_,
+                    mutable: true,
+                    repeat_index: false,
+                });
+                self.loops += 1;
+                let body = self.block(None)?;
+                self.loops -= 1;
+                self.locals.pop();
+                format!("for {} in {}{}{}{} {}", name, start, range, end, step, body)
+            }
+            11 => {
+                let count = self.u.int_in_range(1..=3)?;
+                let name = self.name("k");
+                self.locals.push(Var {
+                    name: name.clone(),
+                    type_: I32,
+                    mutable: false,
+                    repeat_index: true,
+                });
+                let body = self.block(None)?;
+                self.locals.pop();
+                format!("repeat {} as {} {}", count, name, body)
+            }
+            12 => {
+                if !self.labels.is_empty() && self.u.arbitrary()? {
+                    let label = self.u.choose(&self.labels)?.clone();
+                    if self.u.arbitrary()? {
+                        format!("branch {}", label)
+                    } else {
+                        format!("branch_if {}: {}", self.expr(I32)?, label)
+                    }
+                } else if self.loops > 0 {
+                    if self.u.arbitrary()? {
+                        "break"
+                    } else {
+                        "continue"
+                    }
+                    .to_string()
+                } else {
+                    return self.expr_statement();
+                }
+            }
+            13 => {
+                let candidates: Vec<usize> = (0..self.functions.len())
+                    .filter(|&i| self.functions[i].result.is_none() && self.can_call(i))
+                    .collect();
+                if candidates.is_empty() {
+                    return self.expr_statement();
+                }
+                let index = *self.u.choose(&candidates)?;
+                self.call(index)?
+            }
+            14 => {
+                if self.inline {
+                    return self.expr_statement();
+                }
+                match self.result {
+                    Some(type_) => format!("return {}", self.expr(type_)?),
+                    None => "return".to_string(),
+                }
+            }
+            15 => {
+                let name = if self.u.arbitrary()? {
+                    "memory.fill"
+                } else {
+                    "memory.copy"
+                };
+                let dest = self.expr(I32)?;
+                let value = self.expr(I32)?;
+                let size = self.expr(I32)?;
+                format!("{}({}, {}, {})", name, dest, value, size)
+            }
+            _ => format!("assert({})", self.expr(I32)?),
+        })
+    }
+
+    fn expr_statement(&mut self) -> Result<String> {
+        let type_ = self.type_()?;
+        self.expr(type_)
+    }
+
+    fn visible(&self) -> Vec<&Var> {
+        let locals = self.locals.iter().enumerate().filter(|&(index, var)| {
+            self.locals[index + 1..]
+                .iter()
+                .all(|later| later.name != var.name)
+        });
+        let globals = self
+            .globals
+            .iter()
+            .filter(|var| self.locals.iter().all(|local| local.name != var.name));
+        locals.map(|(_, var)| var).chain(globals).collect()
+    }
+
+    // inline functions can't call themselves, so they only call other functions
+    fn can_call(&self, index: usize) -> bool {
+        !(self.inline && self.functions[index].inline)
+    }
+
+    fn call(&mut self, index: usize) -> Result<String> {
+        let name = self.functions[index].name.clone();
+        let params = self.functions[index].params.clone();
+        let mut args = Vec::new();
+        for type_ in params {
+            args.push(self.expr(type_)?);
+        }
+        Ok(format!("{}({})", name, args.join(", ")))
+    }
+
+    fn binary_op(&mut self, type_: Type) -> Result<&'static str> {
+        Ok(self
+            .u
+            .choose(if type_.is_int() { INT_OPS } else { FLOAT_OPS })?)
+    }
+
+    fn literal(&mut self, type_: Type) -> Result<String> {
+        Ok(match type_ {
+            I32 => format!("0x{:x}", self.u.arbitrary::<u32>()?),
+            I64 => format!("0x{:x}i64", self.u.arbitrary::<u64>()?),
+            F32 => match self.u.int_in_range(0..=9)? {
+                0 => "inf".to_string(),
+                1 => "nan".to_string(),
+                _ => format!("{:?}", self.u.arbitrary::<u16>()? as f32 / 4.0),
+            },
+            F64 => match self.u.int_in_range(0..=9)? {
+                0 => "inff64".to_string(),
+                1 => "nanf64".to_string(),
+                _ => format!("{:?}f64", self.u.arbitrary::<u16>()? as f64 / 4.0),
+            },
+        })
+    }
+
+    // a finite, non-NaN step for a for loop
+    fn step(&mut self, type_: Type) -> Result<String> {
+        Ok(match type_ {
+            I32 | I64 => self.literal(type_)?,
+            F32 => format!("{:?}", self.u.arbitrary::<u16>()? as f32 / 4.0),
+            F64 => format!("{:?}f64", self.u.arbitrary::<u16>()? as f64 / 4.0),
+        })
+    }
+
+    // an expression of literals and consts
+    fn const_expr(&mut self, type_: Type) -> Result<String> {
+        if self.depth >= 3 || self.u.ratio(1, 2)? {
+            let consts: Vec<String> = self
+                .globals
+                .iter()
+                .filter(|var| !var.mutable && var.type_ == type_ && var.name.starts_with('C'))
+                .map(|var| var.name.clone())
+                .collect();
+            if !consts.is_empty() && self.u.arbitrary()? {
+                return Ok(self.u.choose(&consts)?.clone());
+            }
+            return self.literal(type_);
+        }
+        self.depth += 1;
+        // casts are only folded in const fns
+        let expr = match self.u.int_in_range(0..=1)? {
+            0 => format!("(-{})", self.const_expr(type_)?),
+            _ => {
+                // division and remainder can fail at compile time
+                let op = loop {
+                    let op = self.binary_op(type_)?;
+                    if !op.contains('/') && !op.contains('%') {
+                        break op;
+                    }
+                };
+                let left = self.const_expr(type_)?;
+                let right = self.const_expr(type_)?;
+                format!("({} {} {})", left, op, right)
+            }
+        };
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    fn leaf(&mut self, type_: Type) -> Result<String> {
+        let vars: Vec<String> = self
+            .visible()
+            .into_iter()
+            .filter(|var| var.type_ == type_)
+            .map(|var| var.name.clone())
+            .collect();
+        if !vars.is_empty() && self.u.ratio(2, 3)? {
+            Ok(self.u.choose(&vars)?.clone())
+        } else {
+            self.literal(type_)
+        }
+    }
+
+    fn expr(&mut self, type_: Type) -> Result<String> {
+        if self.depth >= MAX_DEPTH || self.u.ratio(1, 3)? {
+            return self.leaf(type_);
+        }
+        self.depth += 1;
+        let expr = match self.u.int_in_range(0..=12)? {
+            0 => {
+                if type_ == I32 && self.u.arbitrary()? {
+                    let operand = if self.u.arbitrary()? { I32 } else { I64 };
+                    format!("(!{})", self.expr(operand)?)
+                } else {
+                    format!("(-{})", self.expr(type_)?)
+                }
+            }
+            1 | 2 => {
+                let op = self.binary_op(type_)?;
+                let left = self.expr(type_)?;
+                let right = self.expr(type_)?;
+                format!("({} {} {})", left, op, right)
+            }
+            3 if type_ == I32 => {
+                let operand = self.type_()?;
+                let op = if operand.is_int() && self.u.arbitrary()? {
+                    self.u.choose(UNSIGNED_COMPARISONS)?
+                } else {
+                    self.u.choose(COMPARISONS)?
+                };
+                let left = self.expr(operand)?;
+                let right = self.expr(operand)?;
+                format!("({} {} {})", left, op, right)
+            }
+            4 => {
+                let from = self.type_()?;
+                format!("({} as {})", self.expr(from)?, type_.name())
+            }
+            5 => {
+                let candidates: Vec<_> = INTRINSICS
+                    .iter()
+                    .filter(|(_, _, result)| *result == type_)
+                    .collect();
+                let &&(name, params, _) = self.u.choose(&candidates)?;
+                let mut args = Vec::new();
+                for &param in params {
+                    args.push(self.expr(param)?);
+                }
+                format!("{}({})", name, args.join(", "))
+            }
+            6 => {
+                let base = self.expr(I32)?;
+                if self.u.arbitrary()? {
+                    let &(op, size) = self.u.choose(memory_ops(type_))?;
+                    let offset = self.u.int_in_range(0..=16)? * size;
+                    format!("(({}){}{})", base, op, offset)
+                } else {
+                    let name = self.u.choose(load_intrinsics(type_))?;
+                    let offset = self.u.int_in_range(0..=64)?;
+                    format!("{}({}, {})", name, base, offset)
+                }
+            }
+            7 => {
+                let condition = self.expr(I32)?;
+                let if_true = self.block(Some(type_))?;
+                let if_false = self.block(Some(type_))?;
+                format!("(if {} {} else {})", condition, if_true, if_false)
+            }
+            8 => format!("({})", self.block(Some(type_))?),
+            9 => {
+                let condition = self.expr(I32)?;
+                let if_true = self.expr(type_)?;
+                let if_false = self.expr(type_)?;
+                format!("select({}, {}, {})", condition, if_true, if_false)
+            }
+            10 => {
+                let candidates: Vec<usize> = (0..self.functions.len())
+                    .filter(|&i| self.functions[i].result == Some(type_) && self.can_call(i))
+                    .collect();
+                if candidates.is_empty() {
+                    self.leaf(type_)?
+                } else {
+                    let index = *self.u.choose(&candidates)?;
+                    self.call(index)?
+                }
+            }
+            11 => {
+                // only locals can be assigned with `:=`
+                let locals: Vec<String> = self
+                    .locals
+                    .iter()
+                    .enumerate()
+                    .filter(|&(index, var)| {
+                        var.mutable
+                            && var.type_ == type_
+                            && self.locals[index + 1..]
+                                .iter()
+                                .all(|later| later.name != var.name)
+                    })
+                    .map(|(_, var)| var.name.clone())
+                    .collect();
+                if locals.is_empty() {
+                    self.leaf(type_)?
+                } else {
+                    let name = self.u.choose(&locals)?.clone();
+                    let op = if self.u.arbitrary()? {
+                        self.binary_op(type_)?
+                    } else {
+                        ""
+                    };
+                    format!("({} {}:= {})", name, op, self.expr(type_)?)
+                }
+            }
+            _ => {
+                let value = self.expr(type_)?;
+                let drop_type = self.type_()?;
+                let drop = self.expr(drop_type)?;
+                format!("({} <| {})", value, drop)
+            }
+        };
+        self.depth -= 1;
+        Ok(expr)
+    }
+}
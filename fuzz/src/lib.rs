@@ -0,0 +1,20 @@
+use curlywas::{compile_file, Options};
+
+pub mod generate;
+
+// Compiles `source` like the command line compiler does and checks that a successful
+// compilation produces a valid module. Returns whether the compilation succeeded.
+pub fn compile(source: &[u8]) -> bool {
+    // one file per process, so fuzzing with several jobs doesn't mix up inputs
+    let path = std::env::temp_dir().join(format!("curlywas-fuzz-{}.cwa", std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    match compile_file(&path, Options::default()).0 {
+        Ok(wasm) => {
+            if let Err(err) = wasmparser::validate(&wasm) {
+                panic!("Compiled to an invalid module: {}", err);
+            }
+            true
+        }
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::{bail, Result};
+
+use crate::ast::FileFormat;
+
+// Converts the content of a file included in data to the bytes stored in memory.
+pub fn convert(data: Vec<u8>, format: FileFormat) -> Result<Vec<u8>> {
+    match format {
+        FileFormat::Raw => Ok(data),
+        FileFormat::Image { bits } => image(&data, bits),
+        FileFormat::Palette => palette(&data),
+    }
+}
+
+// Packs the color index of each pixel into `bits` bits, first pixel in the most significant bits.
+// Rows are not padded, so a sprite sheet ends up as one continuous bitstream.
+fn image(data: &[u8], bits: u32) -> Result<Vec<u8>> {
+    let max_colors = 1usize << bits;
+    let indices = match decode(data)? {
+        Decoded::Indexed { indices, .. } => {
+            if let Some(&index) = indices.iter().find(|&&index| index as usize >= max_colors) {
+                bail!(
+                    "Color index {} doesn't fit into {} bits per pixel",
+                    index,
+                    bits
+                );
+            }
+            indices
+        }
+        // images without a palette are indexed by brightness, darkest color first
+        Decoded::Colors(pixels) => {
+            let key = |c: &[u8; 4]| (brightness(c), *c);
+            let mut colors = pixels.clone();
+            colors.sort_by_key(key);
+            colors.dedup();
+            if colors.len() > max_colors {
+                bail!(
+                    "Image has {} colors, {} bits per pixel allow at most {}",
+                    colors.len(),
+                    bits,
+                    max_colors
+                );
+            }
+            pixels
+                .iter()
+                .map(|c| colors.binary_search_by_key(&key(c), key).unwrap() as u8)
+                .collect()
+        }
+    };
+
+    let per_byte = (8 / bits) as usize;
+    Ok(indices
+        .chunks(per_byte)
+        .map(|chunk| {
+            chunk.iter().enumerate().fold(0, |byte, (i, &index)| {
+                byte | index << (8 - bits as usize * (i + 1))
+            })
+        })
+        .collect())
+}
+
+// Each color is stored as a little-endian i32 0xRRGGBB.
+fn palette(data: &[u8]) -> Result<Vec<u8>> {
+    let colors: Vec<[u8; 3]> = match decode(data)? {
+        Decoded::Indexed { palette, .. } => palette
+            .chunks_exact(3)
+            .map(|c| [c[0], c[1], c[2]])
+            .collect(),
+        Decoded::Colors(pixels) => pixels.iter().map(|c| [c[0], c[1], c[2]]).collect(),
+    };
+    Ok(colors.iter().flat_map(|&[r, g, b]| [b, g, r, 0]).collect())
+}
+
+fn brightness(c: &[u8; 4]) -> u32 {
+    c[0] as u32 * 299 + c[1] as u32 * 587 + c[2] as u32 * 114
+}
+
+enum Decoded {
+    Indexed { indices: Vec<u8>, palette: Vec<u8> },
+    // RGBA
+    Colors(Vec<[u8; 4]>),
+}
+
+fn decode(data: &[u8]) -> Result<Decoded> {
+    let reader = png::Decoder::new(data).read_info()?;
+    if reader.info().color_type == png::ColorType::Indexed {
+        let palette = reader
+            .info()
+            .palette
+            .as_deref()
+            .unwrap_or_default()
+            .to_vec();
+        let (frame, buffer) = read_frame(reader)?;
+        let bits = frame.bit_depth as usize;
+        let mut indices = Vec::with_capacity(frame.width as usize * frame.height as usize);
+        for row in buffer.chunks(frame.line_size) {
+            for x in 0..frame.width as usize {
+                let bit = x * bits;
+                let mask = ((1u32 << bits) - 1) as u8;
+                indices.push((row[bit / 8] >> (8 - bits - bit % 8)) & mask);
+            }
+        }
+        return Ok(Decoded::Indexed { indices, palette });
+    }
+
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let (frame, buffer) = read_frame(decoder.read_info()?)?;
+    Ok(Decoded::Colors(
+        buffer
+            .chunks_exact(frame.color_type.samples())
+            .map(|pixel| match *pixel {
+                [v] => [v, v, v, 255],
+                [v, a] => [v, v, v, a],
+                [r, g, b] => [r, g, b, 255],
+                [r, g, b, a] => [r, g, b, a],
+                _ => unreachable!(),
+            })
+            .collect(),
+    ))
+}
+
+fn read_frame(mut reader: png::Reader<&[u8]>) -> Result<(png::OutputInfo, Vec<u8>)> {
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer)?;
+    buffer.truncate(frame.buffer_size());
+    Ok((frame, buffer))
+}
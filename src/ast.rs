@@ -1,6 +1,7 @@
 use std::{fmt, path::PathBuf};
 
 use crate::parser::Span;
+use crate::warnings::Lint;
 
 #[derive(Debug, Default)]
 pub struct Script {
@@ -8,8 +9,14 @@ pub struct Script {
     pub global_vars: Vec<GlobalVar>,
     pub functions: Vec<Function>,
     pub data: Vec<Data>,
+    pub statics: Vec<Static>,
+    pub static_regions: Vec<StaticRegion>,
     pub includes: Vec<Include>,
     pub consts: Vec<GlobalConst>,
+    pub structs: Vec<Struct>,
+    pub macros: Vec<Macro>,
+    pub conditionals: Vec<Conditional>,
+    pub lint_attributes: Vec<LintAttribute>,
 }
 
 impl Script {
@@ -18,7 +25,13 @@ impl Script {
         self.global_vars.append(&mut other.global_vars);
         self.functions.append(&mut other.functions);
         self.data.append(&mut other.data);
+        self.statics.append(&mut other.statics);
+        self.static_regions.append(&mut other.static_regions);
         self.consts.append(&mut other.consts);
+        self.structs.append(&mut other.structs);
+        self.macros.append(&mut other.macros);
+        self.conditionals.append(&mut other.conditionals);
+        self.lint_attributes.append(&mut other.lint_attributes);
         assert!(other.includes.is_empty());
     }
 }
@@ -29,19 +42,44 @@ pub enum TopLevelItem {
     GlobalVar(GlobalVar),
     Function(Function),
     Data(Data),
+    Static(Static),
+    StaticRegion(StaticRegion),
     Include(Include),
     Const(GlobalConst),
+    Struct(Struct),
+    Macro(Macro),
+    ConstIf(Box<Conditional>),
+    LintAttribute(LintAttribute),
+}
+
+// `#[allow(lint)]` or `#[warn(lint)]` in front of a top-level item, the span is that of the item
+#[derive(Debug)]
+pub struct LintAttribute {
+    pub span: Span,
+    pub lint: Lint,
+    pub enabled: bool,
+}
+
+// top-level `if const` block
+#[derive(Debug)]
+pub struct Conditional {
+    pub span: Span,
+    pub condition: Expression,
+    pub if_true: Script,
+    pub if_false: Script,
 }
 
 #[derive(Debug)]
 pub struct Include {
     pub span: Span,
     pub path: String,
+    pub namespace: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct Import {
     pub span: Span,
+    pub public: bool,
     pub import: String,
     pub type_: ImportType,
 }
@@ -64,6 +102,7 @@ pub enum ImportType {
 #[derive(Debug)]
 pub struct GlobalVar {
     pub span: Span,
+    pub public: bool,
     pub name: String,
     pub value: Expression,
     pub type_: Option<Type>,
@@ -73,24 +112,63 @@ pub struct GlobalVar {
 #[derive(Debug)]
 pub struct GlobalConst {
     pub span: Span,
+    pub public: bool,
     pub name: String,
     pub value: Expression,
     pub type_: Option<Type>,
 }
 
 #[derive(Debug)]
+pub struct Struct {
+    pub span: Span,
+    pub public: bool,
+    pub name: String,
+    pub fields: Vec<StructField>,
+}
+
+#[derive(Debug)]
+pub struct StructField {
+    pub span: Span,
+    pub name: String,
+    pub type_: DataType,
+}
+
+#[derive(Debug)]
+pub struct Macro {
+    pub span: Span,
+    pub public: bool,
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Expression,
+}
+
+#[derive(Debug, Clone)]
 pub struct Function {
     pub span: Span,
+    pub public: bool,
     pub export: bool,
     pub start: bool,
+    pub inline: bool,
+    pub const_: bool,
+    // `test fn`, only compiled when running tests
+    pub test: bool,
+    // `stub fn`, replaces the imported function of the same name when running tests
+    pub stub: bool,
     pub name: String,
-    pub params: Vec<(String, Type)>,
+    pub params: Vec<Param>,
     pub type_: Option<Type>,
     pub body: Expression,
     pub locals: Locals,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub span: Span,
+    pub name: String,
+    pub type_: Type,
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct Locals {
     pub params: Vec<Local>,
     pub locals: Vec<Local>,
@@ -105,6 +183,7 @@ impl Locals {
             name,
             type_,
             index: Some(id),
+            param: true,
         });
         id
     }
@@ -115,7 +194,8 @@ impl Locals {
             span,
             name,
             type_,
-            index: store.then(|| id),
+            index: store.then_some(id),
+            param: false,
         });
         id
     }
@@ -144,34 +224,119 @@ impl std::ops::IndexMut<u32> for Locals {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Local {
     pub span: Span,
     pub name: String,
     pub type_: Type,
     pub index: Option<u32>,
+    // a param of the function or of an expanded inline function
+    pub param: bool,
 }
 
 #[derive(Debug)]
 pub struct Data {
     pub offset: Box<Expression>,
     pub data: Vec<DataValues>,
+    // `data <address> compressed { }`, decompressed by the start function
+    pub compressed: bool,
 }
 
+// `static name { ... }` or `static name: [type; count]`, a data block placed
+// by the compiler in the static region. Defines the consts `name` and `name_len`.
 #[derive(Debug)]
+pub struct Static {
+    pub span: Span,
+    pub public: bool,
+    pub name: String,
+    pub data: Vec<DataValues>,
+}
+
+// `static_region(start[, end]);`
+#[derive(Debug)]
+pub struct StaticRegion {
+    pub span: Span,
+    pub start: Expression,
+    pub end: Option<Expression>,
+}
+
+#[derive(Debug, Clone)]
 pub enum DataValues {
     Array {
         type_: DataType,
         values: Vec<Expression>,
     },
-    String(String),
+    String(Vec<u8>),
     File {
         path: PathBuf,
+        format: FileFormat,
+        // `file(path, offset, length)` only includes a slice of the file
+        offset: Option<Expression>,
+        length: Option<Expression>,
         data: Vec<u8>,
     },
+    Repeat {
+        count: Expression,
+        values: Vec<DataValues>,
+    },
+    Fill {
+        value: Expression,
+        count: Expression,
+    },
+    Zeros(Expression),
+    Align(Expression),
+    // defines the consts `name` (the address) and `name_len` (the size of the value following it)
+    Label {
+        span: Span,
+        public: bool,
+        name: String,
+    },
 }
 
-#[derive(Debug, Clone)]
+impl DataValues {
+    // all expressions, including those of nested `repeat` blocks
+    pub fn expressions(&self) -> Vec<&Expression> {
+        match self {
+            DataValues::Array { values, .. } => values.iter().collect(),
+            DataValues::String(_) | DataValues::Label { .. } => vec![],
+            DataValues::File { offset, length, .. } => offset.iter().chain(length.iter()).collect(),
+            DataValues::Repeat { count, values } => std::iter::once(count)
+                .chain(values.iter().flat_map(|v| v.expressions()))
+                .collect(),
+            DataValues::Fill { value, count } => vec![value, count],
+            DataValues::Zeros(count) => vec![count],
+            DataValues::Align(alignment) => vec![alignment],
+        }
+    }
+
+    pub fn expressions_mut(&mut self) -> Vec<&mut Expression> {
+        match self {
+            DataValues::Array { values, .. } => values.iter_mut().collect(),
+            DataValues::String(_) | DataValues::Label { .. } => vec![],
+            DataValues::File { offset, length, .. } => {
+                offset.iter_mut().chain(length.iter_mut()).collect()
+            }
+            DataValues::Repeat { count, values } => std::iter::once(count)
+                .chain(values.iter_mut().flat_map(|v| v.expressions_mut()))
+                .collect(),
+            DataValues::Fill { value, count } => vec![value, count],
+            DataValues::Zeros(count) => vec![count],
+            DataValues::Align(alignment) => vec![alignment],
+        }
+    }
+}
+
+// how a file included in data is converted when loading it
+#[derive(Debug, Clone, Copy)]
+pub enum FileFormat {
+    Raw,
+    // `image(path, format: ..)`, the color index of each pixel packed into `bits` bits
+    Image { bits: u32 },
+    // `palette(path)`
+    Palette,
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum DataType {
     I8,
     I16,
@@ -181,6 +346,17 @@ pub enum DataType {
     F64,
 }
 
+impl DataType {
+    pub fn size(self) -> u32 {
+        match self {
+            DataType::I8 => 1,
+            DataType::I16 => 2,
+            DataType::I32 | DataType::F32 => 4,
+            DataType::I64 | DataType::F64 => 8,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MemoryLocation {
     pub span: Span,
@@ -226,10 +402,10 @@ impl Expression {
     }
 
     pub fn is_const(&self) -> bool {
-        match self.expr {
-            Expr::I32Const(_) | Expr::I64Const(_) | Expr::F32Const(_) | Expr::F64Const(_) => true,
-            _ => false,
-        }
+        matches!(
+            self.expr,
+            Expr::I32Const(_) | Expr::I64Const(_) | Expr::F32Const(_) | Expr::F64Const(_)
+        )
     }
 }
 
@@ -316,6 +492,27 @@ pub enum Expr {
         value: Box<Expression>,
         drop: Box<Expression>,
     },
+    Repeat {
+        count: Box<Expression>,
+        index: String,
+        body: Box<Expression>,
+    },
+    ConstIf {
+        condition: Box<Expression>,
+        if_true: Box<Expression>,
+        if_false: Option<Box<Expression>>,
+    },
+    // `for index in start..end: value` in data
+    Sequence {
+        index: String,
+        start: Box<Expression>,
+        end: Box<Expression>,
+        value: Box<Expression>,
+    },
+    // string literal, replaced by its address in the string pool before constant folding
+    Str(Vec<u8>),
+    // `assert(condition)`, turned into a check when running tests and removed otherwise
+    Assert(Box<Expression>),
     Error,
 }
 
@@ -327,6 +524,147 @@ impl Expr {
             span,
         }
     }
+
+    // `inf`, `nan`, `inff64` and `nanf64` are literals unless shadowed by a name in scope
+    pub fn float_constant(name: &str) -> Option<Expr> {
+        match name {
+            "inf" => Some(Expr::F32Const(f32::INFINITY)),
+            "nan" => Some(Expr::F32Const(f32::NAN)),
+            "inff64" => Some(Expr::F64Const(f64::INFINITY)),
+            "nanf64" => Some(Expr::F64Const(f64::NAN)),
+            _ => None,
+        }
+    }
+
+    pub fn one(type_: Type) -> Expr {
+        match type_ {
+            Type::I32 => Expr::I32Const(1),
+            Type::I64 => Expr::I64Const(1),
+            Type::F32 => Expr::F32Const(1.0),
+            Type::F64 => Expr::F64Const(1.0),
+        }
+    }
+
+    // all direct sub-expressions
+    pub fn children(&self) -> Vec<&Expression> {
+        use Expr::*;
+        match self {
+            Block {
+                statements,
+                final_expression,
+            } => statements
+                .iter()
+                .chain(final_expression.iter().map(|e| &**e))
+                .collect(),
+            Let { value, .. } | Return { value } => value.iter().map(|e| &**e).collect(),
+            Poke {
+                mem_location,
+                value,
+            } => vec![&mem_location.left, &mem_location.right, value],
+            Peek(mem_location) => vec![&mem_location.left, &mem_location.right],
+            Loop { block, .. } | LabelBlock { block, .. } => vec![block],
+            BranchIf { condition, .. } => vec![condition],
+            UnaryOp { value, .. }
+            | Assign { value, .. }
+            | LocalTee { value, .. }
+            | Cast { value, .. }
+            | Assert(value) => vec![value],
+            BinOp { left, right, .. } => vec![left, right],
+            FuncCall { params, .. } => params.iter().collect(),
+            Select {
+                condition,
+                if_true,
+                if_false,
+            } => vec![condition, if_true, if_false],
+            If {
+                condition,
+                if_true,
+                if_false,
+            }
+            | ConstIf {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                let mut children = vec![&**condition, &**if_true];
+                children.extend(if_false.iter().map(|e| &**e));
+                children
+            }
+            First { value, drop } => vec![value, drop],
+            Repeat { count, body, .. } => vec![count, body],
+            Sequence {
+                start, end, value, ..
+            } => vec![start, end, value],
+            I32Const(_)
+            | I64Const(_)
+            | F32Const(_)
+            | F64Const(_)
+            | Variable { .. }
+            | Branch(_)
+            | Str(_)
+            | Error => vec![],
+        }
+    }
+
+    pub fn children_mut(&mut self) -> Vec<&mut Expression> {
+        use Expr::*;
+        match self {
+            Block {
+                statements,
+                final_expression,
+            } => statements
+                .iter_mut()
+                .chain(final_expression.iter_mut().map(|e| &mut **e))
+                .collect(),
+            Let { value, .. } | Return { value } => value.iter_mut().map(|e| &mut **e).collect(),
+            Poke {
+                mem_location,
+                value,
+            } => vec![&mut mem_location.left, &mut mem_location.right, value],
+            Peek(mem_location) => vec![&mut mem_location.left, &mut mem_location.right],
+            Loop { block, .. } | LabelBlock { block, .. } => vec![block],
+            BranchIf { condition, .. } => vec![condition],
+            UnaryOp { value, .. }
+            | Assign { value, .. }
+            | LocalTee { value, .. }
+            | Cast { value, .. }
+            | Assert(value) => vec![value],
+            BinOp { left, right, .. } => vec![left, right],
+            FuncCall { params, .. } => params.iter_mut().collect(),
+            Select {
+                condition,
+                if_true,
+                if_false,
+            } => vec![condition, if_true, if_false],
+            If {
+                condition,
+                if_true,
+                if_false,
+            }
+            | ConstIf {
+                condition,
+                if_true,
+                if_false,
+            } => {
+                let mut children = vec![&mut **condition, &mut **if_true];
+                children.extend(if_false.iter_mut().map(|e| &mut **e));
+                children
+            }
+            First { value, drop } => vec![value, drop],
+            Repeat { count, body, .. } => vec![count, body],
+            Sequence {
+                start, end, value, ..
+            } => vec![start, end, value],
+            I32Const(_)
+            | I64Const(_)
+            | F32Const(_)
+            | F64Const(_)
+            | Variable { .. }
+            | Branch(_)
+            | Str(_)
+            | Error => vec![],
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -372,8 +710,42 @@ pub enum BinOp {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MemSize {
     Byte,
+    SignedByte,
+    Half,
+    SignedHalf,
     Word,
+    Long,
     Float,
+    Double,
+}
+
+impl MemSize {
+    pub fn type_(self) -> Type {
+        match self {
+            MemSize::Byte
+            | MemSize::SignedByte
+            | MemSize::Half
+            | MemSize::SignedHalf
+            | MemSize::Word => Type::I32,
+            MemSize::Long => Type::I64,
+            MemSize::Float => Type::F32,
+            MemSize::Double => Type::F64,
+        }
+    }
+
+    // log2 of the access width, ie. the natural alignment
+    pub fn bytes(self) -> u64 {
+        1 << self.alignment()
+    }
+
+    pub fn alignment(self) -> u32 {
+        match self {
+            MemSize::Byte | MemSize::SignedByte => 0,
+            MemSize::Half | MemSize::SignedHalf => 1,
+            MemSize::Word | MemSize::Float => 2,
+            MemSize::Long | MemSize::Double => 3,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
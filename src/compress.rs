@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast, emit,
+    parser::{self, Sources},
+    typecheck::report_error,
+};
+
+type Result<T> = std::result::Result<T, ()>;
+
+// Compressed data is stored as a sequence of tokens:
+//   0nnnnnnn: n + 1 literal bytes follow
+//   1nnnnnnn oooooooo oooooooo: copy n + 3 bytes from o + 1 bytes back (o as little-endian u16)
+// Matches can overlap the bytes they produce, so runs are encoded as a match with offset 1.
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 127 + MIN_MATCH;
+const MAX_LITERALS: usize = 128;
+const WINDOW: usize = 65536;
+// how many earlier positions with the same prefix are tried for each match
+const MAX_CHAIN: usize = 256;
+
+const DECOMPRESS: &str = "#decompress";
+const INIT: &str = "#init";
+
+pub struct CompressedBlock {
+    pub address: u32,
+    pub raw_size: usize,
+    pub compressed_size: usize,
+    // the size of the data segments in the module, with and without compression
+    pub raw_cost: usize,
+    pub compressed_cost: usize,
+}
+
+// Adds the decompressor (written in CurlyWas) to the script if it contains compressed data.
+pub fn add_decompressor(script: &mut ast::Script, sources: &mut Sources) -> Result<()> {
+    if !script.data.iter().any(|data| data.compressed) {
+        return Ok(());
+    }
+    let id = sources.add_builtin("<decompress>", include_str!("decompress.cwa"));
+    let mut decompressor = parser::parse(sources, id)?;
+    for func in &mut decompressor.functions {
+        func.name = DECOMPRESS.to_string();
+    }
+    script.merge(decompressor);
+    Ok(())
+}
+
+// Replaces each compressed data block by its compressed data, placed right after all other data,
+// and adds a start function decompressing the blocks before calling the start function of the script.
+pub fn compress_data(script: &mut ast::Script, sources: &Sources) -> Result<Vec<CompressedBlock>> {
+    if !script.data.iter().any(|data| data.compressed) {
+        return Ok(Vec::new());
+    }
+
+    // the compressed data must not overlap any data or the decompressed blocks,
+    // but can share memory with zeros left out of the data segments
+    let mut scratch = 0u64;
+    let mut blocks = Vec::with_capacity(script.data.len());
+    for data in std::mem::take(&mut script.data) {
+        let segments = emit::data_segments(&data);
+        let end = segments
+            .last()
+            .map_or(data.offset.const_i32() as u32 as u64, |(address, bytes)| {
+                *address as u64 + bytes.len() as u64
+            });
+        scratch = scratch.max(end);
+        blocks.push((data, segments));
+    }
+
+    let memory_size = script.imports.iter().find_map(|import| match import.type_ {
+        ast::ImportType::Memory(pages) => Some(pages as u64 * 65536),
+        _ => None,
+    });
+
+    let mut report = Vec::new();
+    let mut calls = Vec::new();
+    for (mut data, segments) in blocks {
+        if !data.compressed {
+            script.data.push(data);
+            continue;
+        }
+
+        // the decompressor writes the whole block, including the zeros between segments
+        let address = data.offset.const_i32() as u32;
+        let mut raw = Vec::new();
+        for (segment_address, bytes) in &segments {
+            raw.resize((segment_address - address) as usize, 0);
+            raw.extend_from_slice(bytes);
+        }
+        if raw.is_empty() {
+            continue;
+        }
+        let compressed = lz_compress(&raw);
+
+        if memory_size.is_some_and(|size| scratch + compressed.len() as u64 > size) {
+            report_error(
+                &format!(
+                    "Not enough memory for the compressed data ({} bytes at {:#x})",
+                    compressed.len(),
+                    scratch
+                ),
+                &data.offset.span,
+                sources,
+            )?;
+            unreachable!()
+        }
+
+        report.push(CompressedBlock {
+            address,
+            raw_size: raw.len(),
+            compressed_size: compressed.len(),
+            raw_cost: segments
+                .iter()
+                .map(|(address, bytes)| segment_cost(*address, bytes.len()))
+                .sum(),
+            compressed_cost: segment_cost(scratch as u32, compressed.len()),
+        });
+        calls.push([
+            scratch as i32,
+            address as i32,
+            (address as usize + raw.len()) as i32,
+        ]);
+
+        let span = data.offset.span.clone();
+        data.offset = Box::new(typed_i32(scratch as i32, &span));
+        data.data = vec![ast::DataValues::String(compressed)];
+        data.compressed = false;
+        scratch += report.last().unwrap().compressed_size as u64;
+        script.data.push(data);
+    }
+
+    let span = script
+        .functions
+        .iter()
+        .find(|func| func.name == DECOMPRESS)
+        .unwrap()
+        .span
+        .clone();
+    let call = |name: &str, params: Vec<ast::Expression>| ast::Expression {
+        type_: None,
+        expr: ast::Expr::FuncCall {
+            name: name.to_string(),
+            params,
+        },
+        span: span.clone(),
+    };
+    let mut statements: Vec<ast::Expression> = calls
+        .iter()
+        .map(|params| {
+            call(
+                DECOMPRESS,
+                params.iter().map(|&p| typed_i32(p, &span)).collect(),
+            )
+        })
+        .collect();
+    if let Some(start) = script.functions.iter_mut().find(|func| func.start) {
+        start.start = false;
+        statements.push(call(&start.name, Vec::new()));
+    }
+    script.functions.push(ast::Function {
+        span: span.clone(),
+        public: false,
+        export: false,
+        start: true,
+        inline: false,
+        const_: false,
+        test: false,
+        stub: false,
+        name: INIT.to_string(),
+        params: Vec::new(),
+        type_: None,
+        body: ast::Expression {
+            type_: None,
+            expr: ast::Expr::Block {
+                statements,
+                final_expression: None,
+            },
+            span,
+        },
+        locals: ast::Locals::default(),
+    });
+
+    Ok(report)
+}
+
+pub fn print_size_report(script: &ast::Script, blocks: &[CompressedBlock], wasm: &[u8]) {
+    if blocks.is_empty() {
+        return;
+    }
+    for block in blocks {
+        eprintln!(
+            "data {:#x} compressed: {} bytes -> {} bytes, {} bytes in the module instead of {}",
+            block.address,
+            block.raw_size,
+            block.compressed_size,
+            block.compressed_cost,
+            block.raw_cost
+        );
+    }
+
+    // the size of the decompressor and of the injected start function
+    let imported = script
+        .imports
+        .iter()
+        .filter(|import| matches!(import.type_, ast::ImportType::Function { .. }))
+        .count();
+    let indices: Vec<usize> = script
+        .functions
+        .iter()
+        .enumerate()
+        .filter(|(_, func)| func.name == DECOMPRESS || func.name == INIT)
+        .map(|(index, _)| imported + index)
+        .collect();
+    let mut code_size = 0;
+    let mut index = imported;
+    for payload in wasmparser::Parser::new(0).parse_all(wasm) {
+        if let Ok(wasmparser::Payload::CodeSectionEntry(body)) = payload {
+            if indices.contains(&index) {
+                let range = body.range();
+                code_size += range.end - range.start;
+            }
+            index += 1;
+        }
+    }
+
+    let raw_cost: usize = blocks.iter().map(|block| block.raw_cost).sum();
+    let compressed_cost: usize = blocks.iter().map(|block| block.compressed_cost).sum();
+    let total = compressed_cost + code_size;
+    eprintln!(
+        "compressed data: {} bytes + {} bytes of decompression code = {} bytes, uncompressed {} bytes ({:+} bytes)",
+        compressed_cost,
+        code_size,
+        total,
+        raw_cost,
+        total as i64 - raw_cost as i64
+    );
+}
+
+fn typed_i32(value: i32, span: &parser::Span) -> ast::Expression {
+    ast::Expression {
+        type_: Some(ast::Type::I32),
+        expr: ast::Expr::I32Const(value),
+        span: span.clone(),
+    }
+}
+
+// size of an active data segment in the data section
+fn segment_cost(address: u32, len: usize) -> usize {
+    // flags, i32.const, the address, end, the length
+    1 + 1 + sleb_len(address as i32) + 1 + uleb_len(len as u32) + len
+}
+
+fn sleb_len(value: i32) -> usize {
+    let bits = 33
+        - if value < 0 {
+            value.leading_ones()
+        } else {
+            value.leading_zeros()
+        };
+    bits.div_ceil(7) as usize
+}
+
+fn uleb_len(value: u32) -> usize {
+    (32 - value.leading_zeros()).div_ceil(7).max(1) as usize
+}
+
+// Greedy LZ77 compression using hash chains to find matches.
+fn lz_compress(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    // the last position of each prefix, and for each position the previous one with the same prefix
+    let mut heads: HashMap<&[u8], usize> = HashMap::new();
+    let mut chain = vec![usize::MAX; data.len()];
+
+    let mut literals = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        let mut best = (0, 0);
+        let mut candidate = data
+            .get(pos..pos + MIN_MATCH)
+            .and_then(|prefix| heads.get(prefix).copied())
+            .unwrap_or(usize::MAX);
+        let max_len = MAX_MATCH.min(data.len() - pos);
+        for _ in 0..MAX_CHAIN {
+            if candidate == usize::MAX || pos - candidate > WINDOW {
+                break;
+            }
+            let len = (0..max_len)
+                .take_while(|&i| data[candidate + i] == data[pos + i])
+                .count();
+            if len > best.0 {
+                best = (len, pos - candidate);
+                if len == max_len {
+                    break;
+                }
+            }
+            candidate = chain[candidate];
+        }
+
+        if best.0 >= MIN_MATCH {
+            push_literals(&mut out, &data[pos - literals..pos]);
+            literals = 0;
+            let (len, offset) = best;
+            out.push(0x80 | (len - MIN_MATCH) as u8);
+            out.extend_from_slice(&((offset - 1) as u16).to_le_bytes());
+            for i in pos..pos + len {
+                insert(&mut heads, &mut chain, data, i);
+            }
+            pos += len;
+        } else {
+            insert(&mut heads, &mut chain, data, pos);
+            literals += 1;
+            pos += 1;
+        }
+    }
+    push_literals(&mut out, &data[pos - literals..pos]);
+    out
+}
+
+fn insert<'a>(
+    heads: &mut HashMap<&'a [u8], usize>,
+    chain: &mut [usize],
+    data: &'a [u8],
+    pos: usize,
+) {
+    if let Some(prefix) = data.get(pos..pos + MIN_MATCH) {
+        chain[pos] = heads.insert(prefix, pos).unwrap_or(usize::MAX);
+    }
+}
+
+fn push_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        out.push((chunk.len() - 1) as u8);
+        out.extend_from_slice(chunk);
+    }
+}
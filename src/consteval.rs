@@ -0,0 +1,602 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast,
+    constfold::{self, Context},
+    parser::Span,
+    typecheck::report_error,
+};
+
+// limits to catch const fns which don't terminate
+const MAX_STEPS: u64 = 10_000_000;
+const MAX_DEPTH: u32 = 256;
+// each level of recursion takes several (in debug builds quite large) frames of `eval`,
+// so calls are evaluated on a thread with a stack big enough for MAX_DEPTH levels
+const STACK_SIZE: usize = 64 << 20;
+
+pub enum CallError {
+    // a const used by the function is not known (yet)
+    UnknownConst(Span),
+    // the error has already been reported
+    Failed,
+}
+
+// Evaluates a call of a const fn at compile time.
+pub fn call(
+    context: &Context,
+    func: &ast::Function,
+    args: Vec<ast::Expr>,
+    span: &Span,
+) -> Result<Option<ast::Expr>, CallError> {
+    let result = std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || {
+                let mut interpreter = Interpreter {
+                    context,
+                    steps: 0,
+                    depth: 0,
+                };
+                interpreter.call(func, args, span)
+            })
+            .expect("failed to spawn a thread for const fn evaluation")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    });
+    match result {
+        Ok(value) => Ok(value),
+        Err(Interrupt::UnknownConst(span)) => Err(CallError::UnknownConst(span)),
+        Err(_) => Err(CallError::Failed),
+    }
+}
+
+enum Interrupt {
+    Branch(String),
+    Return(Option<ast::Expr>),
+    UnknownConst(Span),
+    Failed,
+}
+
+type Eval<T> = std::result::Result<T, Interrupt>;
+
+#[derive(Clone)]
+enum Local {
+    Value(ast::Expr),
+    // `let inline` is evaluated on each use
+    Inline(ast::Expression),
+}
+
+#[derive(Default)]
+struct Frame {
+    scopes: Vec<HashMap<String, Local>>,
+    labels: Vec<String>,
+}
+
+impl Frame {
+    fn lookup(&mut self, name: &str) -> Option<&mut Local> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+}
+
+struct Interpreter<'a, 'b> {
+    context: &'a Context<'b>,
+    steps: u64,
+    depth: u32,
+}
+
+impl<'a, 'b> Interpreter<'a, 'b> {
+    fn call(
+        &mut self,
+        func: &ast::Function,
+        args: Vec<ast::Expr>,
+        span: &Span,
+    ) -> Eval<Option<ast::Expr>> {
+        if self.depth >= MAX_DEPTH {
+            return self.error("Const fn recursion too deep", span);
+        }
+        if args.len() != func.params.len() {
+            return self.error(
+                &format!(
+                    "Const fn {} expects {} argument{}",
+                    func.name,
+                    func.params.len(),
+                    if func.params.len() == 1 { "" } else { "s" }
+                ),
+                span,
+            );
+        }
+        let mut params = HashMap::new();
+        for (ast::Param { name, type_, .. }, arg) in func.params.iter().zip(args) {
+            if type_of(&arg) != *type_ {
+                return self.error(
+                    &format!(
+                        "Param {} of const fn {} has type {}, got {}",
+                        name,
+                        func.name,
+                        type_,
+                        type_of(&arg)
+                    ),
+                    span,
+                );
+            }
+            params.insert(name.clone(), Local::Value(arg));
+        }
+
+        let mut frame = Frame {
+            scopes: vec![params],
+            labels: Vec::new(),
+        };
+        self.depth += 1;
+        let result = match self.eval(&mut frame, &func.body) {
+            Err(Interrupt::Return(value)) => Ok(value),
+            result => result,
+        };
+        self.depth -= 1;
+        let value = result?;
+
+        match (func.type_, &value) {
+            (Some(type_), Some(value)) if type_of(value) == type_ => (),
+            (None, None) => (),
+            (Some(type_), _) => {
+                return self.error(
+                    &format!(
+                        "Const fn {} didn't return a value of type {}",
+                        func.name, type_
+                    ),
+                    &func.span,
+                )
+            }
+            (None, Some(_)) => {
+                return self.error(
+                    &format!(
+                        "Const fn {} returned a value but has no result type",
+                        func.name
+                    ),
+                    &func.span,
+                )
+            }
+        }
+        Ok(value)
+    }
+
+    fn eval(&mut self, frame: &mut Frame, expr: &ast::Expression) -> Eval<Option<ast::Expr>> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return self.error("Evaluation of const fn takes too long", &expr.span);
+        }
+
+        let value = match expr.expr {
+            ast::Expr::Block {
+                ref statements,
+                ref final_expression,
+            } => {
+                frame.scopes.push(HashMap::new());
+                let result = self.eval_block(frame, statements, final_expression.as_deref());
+                frame.scopes.pop();
+                result?
+            }
+            ast::Expr::I32Const(_)
+            | ast::Expr::I64Const(_)
+            | ast::Expr::F32Const(_)
+            | ast::Expr::F64Const(_) => Some(expr.expr.clone()),
+            ast::Expr::Variable { ref name, .. } => {
+                if let Some(local) = frame.lookup(name) {
+                    match local.clone() {
+                        Local::Value(value) => Some(value),
+                        Local::Inline(value) => Some(self.value(frame, &value)?),
+                    }
+                } else if let Some(value) = self.context.consts.get(name) {
+                    Some(value.clone())
+                } else if let Some(value) = ast::Expr::float_constant(name) {
+                    Some(value)
+                } else {
+                    return Err(Interrupt::UnknownConst(expr.span.clone()));
+                }
+            }
+            ast::Expr::Let {
+                ref name,
+                type_,
+                ref value,
+                let_type,
+                ..
+            } => {
+                let local = match (value, type_) {
+                    (Some(value), _) if let_type == ast::LetType::Inline => {
+                        Local::Inline(value.as_ref().clone())
+                    }
+                    (Some(value), _) => {
+                        let value = self.value(frame, value)?;
+                        if type_.is_some_and(|type_| type_ != type_of(&value)) {
+                            return self.error("Type mismatch", &expr.span);
+                        }
+                        Local::Value(value)
+                    }
+                    (None, Some(type_)) => Local::Value(zero(type_)),
+                    (None, None) => return self.error("Type missing", &expr.span),
+                };
+                frame.scopes.last_mut().unwrap().insert(name.clone(), local);
+                None
+            }
+            ast::Expr::Poke { .. } | ast::Expr::Peek(_) => {
+                return self.error("Memory can't be accessed in a const fn", &expr.span)
+            }
+            ast::Expr::Loop {
+                ref label,
+                ref block,
+            } => {
+                frame.labels.push(label.clone());
+                let result = loop {
+                    match self.eval(frame, block) {
+                        Err(Interrupt::Branch(ref target)) if target == label => (),
+                        result => break result,
+                    }
+                };
+                frame.labels.pop();
+                result?
+            }
+            ast::Expr::LabelBlock {
+                ref label,
+                ref block,
+            } => {
+                frame.labels.push(label.clone());
+                let result = match self.eval(frame, block) {
+                    Err(Interrupt::Branch(ref target)) if target == label => Ok(None),
+                    result => result,
+                };
+                frame.labels.pop();
+                result?
+            }
+            ast::Expr::Branch(ref label) => return self.branch(frame, label, &expr.span),
+            ast::Expr::BranchIf {
+                ref condition,
+                ref label,
+            } => {
+                if self.i32_value(frame, condition)? != 0 {
+                    return self.branch(frame, label, &expr.span);
+                }
+                None
+            }
+            ast::Expr::UnaryOp { op, ref value } => {
+                let value = self.value(frame, value)?;
+                match constfold::fold_unop(op, &value) {
+                    Some(result) => Some(result),
+                    None => return self.error("Invalid operand type", &expr.span),
+                }
+            }
+            ast::Expr::BinOp {
+                op,
+                ref left,
+                ref right,
+            } => {
+                let left = self.value(frame, left)?;
+                let right = self.value(frame, right)?;
+                if type_of(&left) != type_of(&right) {
+                    return self.error("Type mismatch", &expr.span);
+                }
+                match constfold::fold_binop(op, &left, &right) {
+                    Some(result) => Some(result),
+                    None if matches!(left, ast::Expr::F32Const(_) | ast::Expr::F64Const(_)) => {
+                        return self.error("Operator not supported for floats", &expr.span)
+                    }
+                    None => return self.error("Integer division by zero or overflow", &expr.span),
+                }
+            }
+            ast::Expr::Assign {
+                ref name,
+                ref value,
+                ..
+            } => {
+                let value = self.value(frame, value)?;
+                self.assign(frame, name, value, &expr.span)?;
+                None
+            }
+            ast::Expr::LocalTee {
+                ref name,
+                ref value,
+                ..
+            } => {
+                let value = self.value(frame, value)?;
+                self.assign(frame, name, value.clone(), &expr.span)?;
+                Some(value)
+            }
+            ast::Expr::Cast { ref value, type_ } => {
+                let value = self.value(frame, value)?;
+                match cast(&value, type_) {
+                    Some(result) => Some(result),
+                    None => {
+                        return self.error(
+                            &format!("Value out of range for cast to {}", type_),
+                            &expr.span,
+                        )
+                    }
+                }
+            }
+            ast::Expr::FuncCall {
+                ref name,
+                ref params,
+            } => {
+                if name == "sizeof" || name == "offsetof" {
+                    let value =
+                        constfold::fold_struct_query(self.context, name, params, &expr.span)
+                            .map_err(|_| Interrupt::Failed)?;
+                    return Ok(Some(ast::Expr::I32Const(value)));
+                }
+                let mut args = Vec::with_capacity(params.len());
+                for param in params {
+                    args.push(self.value(frame, param)?);
+                }
+                if let Some(func) = self.context.const_fns.get(name) {
+                    self.call(func, args, &expr.span)?
+                } else if let Some(value) = builtin(name, &args) {
+                    Some(value)
+                } else {
+                    return self.error(
+                        &format!("{} can't be called in a const fn", name),
+                        &expr.span,
+                    );
+                }
+            }
+            ast::Expr::Select {
+                ref condition,
+                ref if_true,
+                ref if_false,
+            } => {
+                let if_true = self.value(frame, if_true)?;
+                let if_false = self.value(frame, if_false)?;
+                if type_of(&if_true) != type_of(&if_false) {
+                    return self.error("Type mismatch", &expr.span);
+                }
+                if self.i32_value(frame, condition)? != 0 {
+                    Some(if_true)
+                } else {
+                    Some(if_false)
+                }
+            }
+            ast::Expr::If {
+                ref condition,
+                ref if_true,
+                ref if_false,
+            }
+            | ast::Expr::ConstIf {
+                ref condition,
+                ref if_true,
+                ref if_false,
+            } => {
+                if self.i32_value(frame, condition)? != 0 {
+                    self.eval(frame, if_true)?
+                } else if let Some(if_false) = if_false {
+                    self.eval(frame, if_false)?
+                } else {
+                    None
+                }
+            }
+            ast::Expr::Return { ref value } => {
+                let value = match value {
+                    Some(value) => Some(self.value(frame, value)?),
+                    None => None,
+                };
+                return Err(Interrupt::Return(value));
+            }
+            ast::Expr::First {
+                ref value,
+                ref drop,
+            } => {
+                let value = self.eval(frame, value)?;
+                self.eval(frame, drop)?;
+                value
+            }
+            ast::Expr::Repeat {
+                ref count,
+                ref index,
+                ref body,
+            } => {
+                let count = self.i32_value(frame, count)?;
+                for i in 0..count {
+                    frame.scopes.push(HashMap::from_iter([(
+                        index.clone(),
+                        Local::Value(ast::Expr::I32Const(i)),
+                    )]));
+                    let result = self.eval(frame, body);
+                    frame.scopes.pop();
+                    result?;
+                }
+                None
+            }
+            ast::Expr::Sequence { .. } => {
+                return self.error("Sequences are only allowed in data", &expr.span)
+            }
+            // the address of a string literal is not known before the strings are interned
+            ast::Expr::Str(_) => return Err(Interrupt::UnknownConst(expr.span.clone())),
+            ast::Expr::Assert(_) => {
+                return self.error("assert can only be used in functions", &expr.span)
+            }
+            ast::Expr::Error => unreachable!(),
+        };
+        Ok(value)
+    }
+
+    fn eval_block(
+        &mut self,
+        frame: &mut Frame,
+        statements: &[ast::Expression],
+        final_expression: Option<&ast::Expression>,
+    ) -> Eval<Option<ast::Expr>> {
+        for stmt in statements {
+            self.eval(frame, stmt)?;
+        }
+        match final_expression {
+            Some(expr) => self.eval(frame, expr),
+            None => Ok(None),
+        }
+    }
+
+    fn value(&mut self, frame: &mut Frame, expr: &ast::Expression) -> Eval<ast::Expr> {
+        match self.eval(frame, expr)? {
+            Some(value) => Ok(value),
+            None => self.error("Expected a value", &expr.span),
+        }
+    }
+
+    fn i32_value(&mut self, frame: &mut Frame, expr: &ast::Expression) -> Eval<i32> {
+        match self.value(frame, expr)? {
+            ast::Expr::I32Const(value) => Ok(value),
+            _ => self.error("Expected a value of type i32", &expr.span),
+        }
+    }
+
+    fn branch<T>(&self, frame: &Frame, label: &str, span: &Span) -> Eval<T> {
+        if frame.labels.iter().any(|l| l == label) {
+            Err(Interrupt::Branch(label.to_string()))
+        } else {
+            self.error("Label not found", span)
+        }
+    }
+
+    fn assign(&self, frame: &mut Frame, name: &str, value: ast::Expr, span: &Span) -> Eval<()> {
+        match frame.lookup(name) {
+            Some(Local::Value(local)) if type_of(local) == type_of(&value) => {
+                *local = value;
+                Ok(())
+            }
+            Some(Local::Value(_)) => self.error("Type mismatch", span),
+            Some(Local::Inline(_)) => self.error("Can't assign to inline variable", span),
+            None => self.error("Only local variables can be assigned in a const fn", span),
+        }
+    }
+
+    fn error<T>(&self, msg: &str, span: &Span) -> Eval<T> {
+        let _ = report_error(msg, span, self.context.sources);
+        Err(Interrupt::Failed)
+    }
+}
+
+fn type_of(value: &ast::Expr) -> ast::Type {
+    match value {
+        ast::Expr::I32Const(_) => ast::Type::I32,
+        ast::Expr::I64Const(_) => ast::Type::I64,
+        ast::Expr::F32Const(_) => ast::Type::F32,
+        ast::Expr::F64Const(_) => ast::Type::F64,
+        _ => unreachable!(),
+    }
+}
+
+fn zero(type_: ast::Type) -> ast::Expr {
+    match type_ {
+        ast::Type::I32 => ast::Expr::I32Const(0),
+        ast::Type::I64 => ast::Expr::I64Const(0),
+        ast::Type::F32 => ast::Expr::F32Const(0.0),
+        ast::Type::F64 => ast::Expr::F64Const(0.0),
+    }
+}
+
+// same semantics as the instructions emitted for `as`, which trap on out of range values
+fn cast(value: &ast::Expr, type_: ast::Type) -> Option<ast::Expr> {
+    use ast::Expr::*;
+    use ast::Type::*;
+    fn trunc(v: f64, min: f64, max: f64) -> Option<f64> {
+        let v = v.trunc();
+        (v >= min && v < max).then_some(v)
+    }
+    const I32_RANGE: (f64, f64) = (-2147483648.0, 2147483648.0);
+    const I64_RANGE: (f64, f64) = (-9223372036854775808.0, 9223372036854775808.0);
+    let float = match *value {
+        F32Const(v) => Some(v as f64),
+        F64Const(v) => Some(v),
+        _ => None,
+    };
+    Some(match (value, type_) {
+        (&I32Const(v), I64) => I64Const(v as i64),
+        (&I64Const(v), I32) => I32Const(v as i32),
+        (&I32Const(v), F32) => F32Const(v as f32),
+        (&I64Const(v), F32) => F32Const(v as f32),
+        (&I32Const(v), F64) => F64Const(v as f64),
+        (&I64Const(v), F64) => F64Const(v as f64),
+        (&F32Const(v), F64) => F64Const(v as f64),
+        (&F64Const(v), F32) => F32Const(v as f32),
+        (F32Const(_) | F64Const(_), I32) => {
+            I32Const(trunc(float.unwrap(), I32_RANGE.0, I32_RANGE.1)? as i32)
+        }
+        (F32Const(_) | F64Const(_), I64) => {
+            I64Const(trunc(float.unwrap(), I64_RANGE.0, I64_RANGE.1)? as i64)
+        }
+        _ => value.clone(),
+    })
+}
+
+// The math intrinsics plus some functions only available at compile time.
+fn builtin(name: &str, args: &[ast::Expr]) -> Option<ast::Expr> {
+    use ast::Expr::*;
+    let (prefix, name) = match name.split_once('.') {
+        Some((prefix, name)) => (Some(prefix), name),
+        None => (None, name),
+    };
+    let type_ok = |type_name: &str| prefix.is_none_or(|prefix| prefix == type_name);
+    // integer intrinsics are only available with prefix, like in regular functions
+    let int_ok = |type_name: &str| prefix == Some(type_name);
+
+    macro_rules! float_fns {
+        ($v:expr) => {
+            match name {
+                "sqrt" => $v.sqrt(),
+                "floor" => $v.floor(),
+                "ceil" => $v.ceil(),
+                "trunc" => $v.trunc(),
+                "nearest" => $v.round_ties_even(),
+                "abs" => $v.abs(),
+                "sin" => $v.sin(),
+                "cos" => $v.cos(),
+                "tan" => $v.tan(),
+                "asin" => $v.asin(),
+                "acos" => $v.acos(),
+                "atan" => $v.atan(),
+                "exp" => $v.exp(),
+                "log" => $v.ln(),
+                "log2" => $v.log2(),
+                _ => return None,
+            }
+        };
+        ($a:expr, $b:expr) => {
+            match name {
+                "min" => $a.min($b),
+                "max" => $a.max($b),
+                "copysign" => $a.copysign($b),
+                "atan2" => $a.atan2($b),
+                "pow" => $a.powf($b),
+                _ => return None,
+            }
+        };
+    }
+
+    Some(match *args {
+        [F32Const(v)] if type_ok("f32") => F32Const(float_fns!(v)),
+        [F64Const(v)] if type_ok("f64") => F64Const(float_fns!(v)),
+        [F32Const(a), F32Const(b)] if type_ok("f32") => F32Const(float_fns!(a, b)),
+        [F64Const(a), F64Const(b)] if type_ok("f64") => F64Const(float_fns!(a, b)),
+        [I32Const(v)] if int_ok("i32") => I32Const(match name {
+            "clz" => v.leading_zeros(),
+            "ctz" => v.trailing_zeros(),
+            "popcnt" => v.count_ones(),
+            _ => return None,
+        } as i32),
+        [I64Const(v)] if int_ok("i64") => I64Const(match name {
+            "clz" => v.leading_zeros(),
+            "ctz" => v.trailing_zeros(),
+            "popcnt" => v.count_ones(),
+            _ => return None,
+        } as i64),
+        [I32Const(a), I32Const(b)] if int_ok("i32") => I32Const(match name {
+            "rotl" => a.rotate_left(b as u32),
+            "rotr" => a.rotate_right(b as u32),
+            _ => return None,
+        }),
+        [I64Const(a), I64Const(b)] if int_ok("i64") => I64Const(match name {
+            "rotl" => a.rotate_left(b as u32),
+            "rotr" => a.rotate_right(b as u32),
+            _ => return None,
+        }),
+        _ => return None,
+    })
+}
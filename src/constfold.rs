@@ -1,69 +1,605 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
+
+use ariadne::{Color, Label, Report, ReportKind};
 
 use crate::{
-    ast,
+    ast, consteval,
     parser::{Sources, Span},
     typecheck::{report_duplicate_definition, report_error},
 };
 
 type Result<T> = std::result::Result<T, ()>;
 
-pub fn fold_script(script: &mut ast::Script, sources: &Sources) -> Result<()> {
-    let mut context = Context {
-        consts: HashMap::new(),
-        sources,
-    };
-    fold_consts(&mut context, &mut script.consts)?;
+pub fn fold_script(
+    script: &mut ast::Script,
+    sources: &Sources,
+    defines: &[(String, ast::Expr)],
+) -> Result<()> {
+    let mut result = Ok(());
+    for func in &script.functions {
+        if func.const_ && (func.export || func.start) {
+            result = report_error(
+                "Const functions can't be exported or used as start function",
+                &func.span,
+                sources,
+            );
+        }
+    }
+    result?;
+
+    // const fns are only evaluated at compile time, so they don't end up in the module
+    let mut context = Context::new(sources, defines, &script.functions);
+    script.functions.retain(|func| !func.const_);
+
+    check_structs(&script.structs, sources)?;
+    layout_structs(&mut context, &script.structs);
+    let mut consts = std::mem::take(&mut script.consts);
+    fold_consts(&mut context, &mut consts, &Layout::new(script))?;
+    script.consts = consts;
+
+    // with their addresses assigned, statics are just data blocks
+    for static_ in std::mem::take(&mut script.statics) {
+        let address = context.consts[&static_.name].clone();
+        script.data.push(ast::Data {
+            offset: Box::new(address.with_span(static_.span)),
+            data: static_.data,
+            compressed: false,
+        });
+    }
 
+    context.shadowed = shadowing(script.global_vars.iter().map(|var| &var.name));
     for var in &mut script.global_vars {
-        fold_expr(&context, &mut var.value);
+        fold_expr(&mut context, &mut var.value)?;
     }
 
+    let globals = context.shadowed.len();
     for func in &mut script.functions {
-        fold_expr(&context, &mut func.body);
+        context.shadowed.truncate(globals);
+        context
+            .shadowed
+            .extend(shadowing(func.params.iter().map(|param| &param.name)));
+        fold_expr(&mut context, &mut func.body)?;
     }
+    context.shadowed.clear();
 
     for data in &mut script.data {
-        fold_expr(&context, &mut data.offset);
-        for values in &mut data.data {
-            match values {
-                ast::DataValues::Array { values, .. } => {
-                    for value in values {
-                        fold_expr(&context, value);
-                    }
-                }
-                ast::DataValues::String(_) | ast::DataValues::File { .. } => (),
+        fold_expr(&mut context, &mut data.offset)?;
+        let values = std::mem::take(&mut data.data);
+        data.data = fold_data_values(&mut context, values)?;
+    }
+
+    Ok(())
+}
+
+// Replaces top-level `if const` blocks by the items of the selected branch.
+// As the conditions can depend on consts declared in other conditional blocks,
+// this is repeated until all conditions are resolved.
+pub fn resolve_conditionals(
+    script: &mut ast::Script,
+    sources: &Sources,
+    defines: &[(String, ast::Expr)],
+) -> Result<()> {
+    while !script.conditionals.is_empty() {
+        let mut context = Context::new(sources, defines, &script.functions);
+        layout_structs(&mut context, &script.structs);
+        let mut consts = std::mem::take(&mut script.consts);
+        fold_const_values(&mut context, &mut consts, &Layout::new(script))?;
+        script.consts = consts;
+
+        let mut making_progress = false;
+        for mut conditional in std::mem::take(&mut script.conditionals) {
+            fold_expr(&mut context, &mut conditional.condition)?;
+            if let ast::Expr::I32Const(value) = conditional.condition.expr {
+                script.merge(if value != 0 {
+                    conditional.if_true
+                } else {
+                    conditional.if_false
+                });
+                making_progress = true;
+            } else {
+                script.conditionals.push(conditional);
+            }
+        }
+
+        if !making_progress {
+            for conditional in &script.conditionals {
+                let _ = report_error(
+                    "Condition of if const has to be a constant i32",
+                    &conditional.condition.span,
+                    sources,
+                );
             }
+            return Err(());
         }
     }
 
     Ok(())
 }
 
-struct Context<'a> {
-    consts: HashMap<String, ast::Expr>,
-    sources: &'a Sources,
+pub struct Context<'a> {
+    pub consts: HashMap<String, ast::Expr>,
+    pub const_fns: HashMap<String, ast::Function>,
+    structs: HashMap<String, StructLayout>,
+    pub sources: &'a Sources,
+    // while folding consts, calls to const fns using consts not yet folded are left for later
+    partial: bool,
+    // params, locals and globals named like a float constant (`inf`, `nan`, ...) in scope,
+    // which take precedence over it
+    shadowed: Vec<String>,
 }
 
-fn fold_consts(context: &mut Context, consts: &mut [ast::GlobalConst]) -> Result<()> {
-    let mut spans: HashMap<&str, Span> = HashMap::new();
+impl<'a> Context<'a> {
+    fn new(
+        sources: &'a Sources,
+        defines: &[(String, ast::Expr)],
+        functions: &[ast::Function],
+    ) -> Context<'a> {
+        Context {
+            consts: defines.iter().cloned().collect(),
+            const_fns: functions
+                .iter()
+                .filter(|func| func.const_)
+                .map(|func| (func.name.clone(), func.clone()))
+                .collect(),
+            structs: HashMap::new(),
+            sources,
+            partial: false,
+            shadowed: Vec::new(),
+        }
+    }
+}
 
-    for cnst in consts.iter_mut() {
-        if let Some(prev_span) = spans.insert(&cnst.name, cnst.span.clone()) {
+struct StructLayout {
+    size: u32,
+    fields: HashMap<String, (u32, ast::DataType)>,
+}
+
+struct FieldAccess {
+    base: String,
+    offset: u32,
+    type_: ast::DataType,
+}
+
+impl<'a> Context<'a> {
+    // resolves `base.field` or `base.Struct.field`
+    fn resolve_field(&self, name: &str, span: &Span) -> Result<Option<FieldAccess>> {
+        let (base, path) = match name.split_once('.') {
+            Some(split) => split,
+            None => return Ok(None),
+        };
+        let mut candidates: Vec<(&str, u32, ast::DataType)> =
+            if let Some((struct_name, field)) = path.split_once('.') {
+                self.structs
+                    .get_key_value(struct_name)
+                    .and_then(|(name, layout)| {
+                        layout
+                            .fields
+                            .get(field)
+                            .map(|&(offset, type_)| (name.as_str(), offset, type_))
+                    })
+                    .into_iter()
+                    .collect()
+            } else {
+                self.structs
+                    .iter()
+                    .filter_map(|(name, layout)| {
+                        layout
+                            .fields
+                            .get(path)
+                            .map(|&(offset, type_)| (name.as_str(), offset, type_))
+                    })
+                    .collect()
+            };
+        match candidates.len() {
+            0 => Ok(None),
+            1 => {
+                let (_, offset, type_) = candidates[0];
+                Ok(Some(FieldAccess {
+                    base: base.to_string(),
+                    offset,
+                    type_,
+                }))
+            }
+            _ => {
+                candidates.sort_by_key(|&(name, _, _)| name);
+                let names: Vec<_> = candidates
+                    .iter()
+                    .map(|(struct_name, _, _)| format!("{}.{}.{}", base, struct_name, path))
+                    .collect();
+                report_error(
+                    &format!(
+                        "Field '{}' is ambiguous, use one of {}",
+                        path,
+                        names.join(", ")
+                    ),
+                    span,
+                    self.sources,
+                )?;
+                unreachable!()
+            }
+        }
+    }
+}
+
+impl FieldAccess {
+    fn location(self, span: &Span) -> ast::MemoryLocation {
+        ast::MemoryLocation {
+            span: span.clone(),
+            size: match self.type_ {
+                ast::DataType::I8 => ast::MemSize::Byte,
+                ast::DataType::I16 => ast::MemSize::Half,
+                ast::DataType::I32 => ast::MemSize::Word,
+                ast::DataType::I64 => ast::MemSize::Long,
+                ast::DataType::F32 => ast::MemSize::Float,
+                ast::DataType::F64 => ast::MemSize::Double,
+            },
+            left: Box::new(
+                ast::Expr::Variable {
+                    name: self.base,
+                    local_id: None,
+                }
+                .with_span(span.clone()),
+            ),
+            right: Box::new(ast::Expr::I32Const(self.offset as i32).with_span(span.clone())),
+        }
+    }
+}
+
+fn check_structs(structs: &[ast::Struct], sources: &Sources) -> Result<()> {
+    let mut spans: HashMap<&str, &Span> = HashMap::new();
+    let mut result = Ok(());
+
+    for strct in structs {
+        if let Some(prev_span) = spans.insert(&strct.name, &strct.span) {
+            result = report_duplicate_definition(
+                "Struct already defined",
+                &strct.span,
+                prev_span,
+                sources,
+            );
+            continue;
+        }
+
+        let mut field_spans: HashMap<&str, &Span> = HashMap::new();
+        for field in &strct.fields {
+            if let Some(prev_span) = field_spans.insert(&field.name, &field.span) {
+                result = report_duplicate_definition(
+                    "Field already defined",
+                    &field.span,
+                    prev_span,
+                    sources,
+                );
+            }
+        }
+    }
+
+    result
+}
+
+fn layout_structs(context: &mut Context, structs: &[ast::Struct]) {
+    for strct in structs {
+        if context.structs.contains_key(&strct.name) {
+            continue;
+        }
+
+        let mut layout = StructLayout {
+            size: 0,
+            fields: HashMap::new(),
+        };
+        let mut alignment = 1;
+        for field in &strct.fields {
+            let size = field.type_.size();
+            let offset = layout.size.next_multiple_of(size);
+            layout
+                .fields
+                .insert(field.name.clone(), (offset, field.type_));
+            layout.size = offset + size;
+            alignment = alignment.max(size);
+        }
+        layout.size = layout.size.next_multiple_of(alignment);
+        context.structs.insert(strct.name.clone(), layout);
+    }
+}
+
+pub fn fold_struct_query(
+    context: &Context,
+    name: &str,
+    params: &[ast::Expression],
+    span: &Span,
+) -> Result<i32> {
+    let names: Vec<_> = params
+        .iter()
+        .map(|param| match param.expr {
+            ast::Expr::Variable { ref name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+        .collect();
+    let layout = match names.first() {
+        Some(&Some(struct_name)) => context.structs.get(struct_name),
+        _ => None,
+    };
+    match (name, layout, names.as_slice()) {
+        ("sizeof", Some(layout), [_]) => Ok(layout.size as i32),
+        ("offsetof", Some(layout), [_, Some(field)]) => {
+            if let Some(&(offset, _)) = layout.fields.get(*field) {
+                Ok(offset as i32)
+            } else {
+                report_error("Unknown field", &params[1].span, context.sources)?;
+                unreachable!()
+            }
+        }
+        (_, None, [_, ..]) => {
+            report_error("Unknown struct", &params[0].span, context.sources)?;
+            unreachable!()
+        }
+        _ => {
+            report_error(
+                &format!(
+                    "Expected {}",
+                    if name == "sizeof" {
+                        "sizeof(Struct)"
+                    } else {
+                        "offsetof(Struct, field)"
+                    }
+                ),
+                span,
+                context.sources,
+            )?;
+            unreachable!()
+        }
+    }
+}
+
+// The items determining the addresses of data labels and statics
+struct Layout<'a> {
+    data: &'a [ast::Data],
+    statics: &'a [ast::Static],
+    regions: &'a [ast::StaticRegion],
+    memory_size: Option<u64>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(script: &'a ast::Script) -> Layout<'a> {
+        Layout {
+            data: &script.data,
+            statics: &script.statics,
+            regions: &script.static_regions,
+            memory_size: script.imports.iter().find_map(|import| match import.type_ {
+                ast::ImportType::Memory(pages) => Some(pages as u64 * 65536),
+                _ => None,
+            }),
+        }
+    }
+}
+
+fn fold_consts(
+    context: &mut Context,
+    consts: &mut [ast::GlobalConst],
+    layout: &Layout,
+) -> Result<()> {
+    let mut spans: HashMap<String, Span> = HashMap::new();
+
+    let labels = layout
+        .data
+        .iter()
+        .flat_map(|data| data_labels(&data.data))
+        .chain(layout.statics.iter().flat_map(|static_| {
+            std::iter::once((&static_.name, &static_.span)).chain(data_labels(&static_.data))
+        }))
+        .flat_map(|(name, span)| [(name.clone(), span), (len_name(name), span)]);
+    for (name, span) in consts
+        .iter()
+        .map(|cnst| (cnst.name.clone(), &cnst.span))
+        .chain(labels)
+    {
+        if let Some(prev_span) = spans.insert(name, span.clone()) {
             report_duplicate_definition(
                 "Const already defined",
-                &cnst.span,
+                span,
                 &prev_span,
                 context.sources,
             )?;
         }
     }
 
-    while context.consts.len() < consts.len() {
+    if let [first, region, ..] = layout.regions {
+        report_duplicate_definition(
+            "Static region already defined",
+            &region.span,
+            &first.span,
+            context.sources,
+        )?;
+    }
+
+    fold_const_values(context, consts, layout)?;
+
+    let mut result = Ok(());
+    for cnst in consts.iter() {
+        if !context.consts.contains_key(&cnst.name) {
+            result = report_error(
+                &format!("Failed to fold const '{}'", cnst.name),
+                &cnst.span,
+                context.sources,
+            );
+        }
+    }
+    for (name, span) in layout.data.iter().flat_map(|data| data_labels(&data.data)) {
+        if !context.consts.contains_key(name) {
+            result = report_error(
+                &format!("Failed to determine the address of data label '{}'", name),
+                span,
+                context.sources,
+            );
+        }
+    }
+    for static_ in layout.statics {
+        if !context.consts.contains_key(&static_.name) {
+            result = report_error(
+                &format!(
+                    "Failed to determine the address of static '{}'",
+                    static_.name
+                ),
+                &static_.span,
+                context.sources,
+            );
+        }
+    }
+    result?;
+
+    check_statics(context, layout)
+}
+
+// Checks that the statics stay inside the static region and the memory
+// and don't overlap explicitly placed data blocks.
+fn check_statics(context: &mut Context, layout: &Layout) -> Result<()> {
+    let region_end = match layout
+        .regions
+        .first()
+        .and_then(|region| region.end.as_ref())
+    {
+        Some(end) => match fold_clone(context, end)? {
+            Some(end) => Some(end as u32 as u64),
+            None => {
+                return report_error(
+                    "Static region end has to be a constant i32",
+                    &end.span,
+                    context.sources,
+                )
+            }
+        },
+        None => None,
+    };
+
+    let mut data_blocks = Vec::new();
+    for data in layout.data {
+        if let Some((offset, block)) = measure_data(context, data)? {
+            if block.size > 0 {
+                data_blocks.push((data, offset as u64, offset as u64 + block.size as u64));
+            }
+        }
+    }
+
+    let mut result = Ok(());
+    for static_ in layout.statics {
+        let address = match context.consts.get(&static_.name) {
+            Some(&ast::Expr::I32Const(address)) => address as u32 as u64,
+            _ => continue,
+        };
+        let end = match context.consts.get(&len_name(&static_.name)) {
+            Some(&ast::Expr::I32Const(len)) => address + len as u32 as u64,
+            _ => continue,
+        };
+        let limit = match (region_end, layout.memory_size) {
+            (Some(region_end), _) if end > region_end => Some(("static region", region_end)),
+            (_, Some(size)) if end > size => Some(("memory size", size)),
+            _ => None,
+        };
+        if let Some((what, limit)) = limit {
+            result = report_error(
+                &format!(
+                    "Static '{}' exceeds the {} by {} bytes",
+                    static_.name,
+                    what,
+                    end - limit
+                ),
+                &static_.span,
+                context.sources,
+            );
+        }
+        if end == address {
+            continue;
+        }
+        for &(data, data_start, data_end) in &data_blocks {
+            if address < data_end && data_start < end {
+                result = report_overlap(
+                    &format!(
+                        "Static '{}' ({:#x}..{:#x}) overlaps data block ({:#x}..{:#x})",
+                        static_.name, address, end, data_start, data_end
+                    ),
+                    &static_.span,
+                    &data.offset.span,
+                    context.sources,
+                );
+            }
+        }
+    }
+    result
+}
+
+fn report_overlap(msg: &str, span: &Span, data_span: &Span, sources: &Sources) -> Result<()> {
+    Report::build(ReportKind::Error, span.0, span.1.start)
+        .with_message(msg)
+        .with_label(
+            Label::new(span.clone())
+                .with_message(msg)
+                .with_color(Color::Red),
+        )
+        .with_label(
+            Label::new(data_span.clone())
+                .with_message("Data block placed here")
+                .with_color(Color::Yellow),
+        )
+        .finish()
+        .eprint(sources)
+        .unwrap();
+    Err(())
+}
+
+// Folds as many consts as possible, consts already known (ie. defines) override the declared value.
+// As data labels and statics can depend on consts and the other way around, the data blocks
+// are laid out as soon as all the values determining their size are known.
+fn fold_const_values(
+    context: &mut Context,
+    consts: &mut [ast::GlobalConst],
+    layout: &Layout,
+) -> Result<()> {
+    for cnst in consts.iter_mut() {
+        if let Some(value) = context.consts.get(&cnst.name) {
+            cnst.value.expr = value.clone();
+        }
+    }
+
+    context.partial = true;
+    let result = fold_const_values_partial(context, consts, layout);
+    context.partial = false;
+    result
+}
+
+fn fold_const_values_partial(
+    context: &mut Context,
+    consts: &mut [ast::GlobalConst],
+    layout: &Layout,
+) -> Result<()> {
+    let mut pending_data: Vec<&ast::Data> = layout
+        .data
+        .iter()
+        .filter(|data| !data_labels(&data.data).is_empty())
+        .collect();
+    let mut pending_statics = !layout.statics.is_empty();
+    loop {
         let mut making_progress = false;
+        let mut still_pending = Vec::new();
+        for data in pending_data {
+            if let Some((_, block)) = measure_data(context, data)? {
+                define_labels(context, block.labels);
+                making_progress = true;
+            } else {
+                still_pending.push(data);
+            }
+        }
+        pending_data = still_pending;
+
+        if pending_statics {
+            if let Some(labels) = place_statics(context, layout)? {
+                define_labels(context, labels);
+                pending_statics = false;
+                making_progress = true;
+            }
+        }
+
         for cnst in consts.iter_mut() {
             if !context.consts.contains_key(&cnst.name) {
-                fold_expr(context, &mut cnst.value);
+                fold_expr(context, &mut cnst.value)?;
                 if cnst.value.is_const() {
                     context
                         .consts
@@ -74,95 +610,344 @@ fn fold_consts(context: &mut Context, consts: &mut [ast::GlobalConst]) -> Result
         }
 
         if !making_progress {
-            break;
+            return Ok(());
         }
     }
+}
 
-    let mut result = Ok(());
-    for cnst in consts {
-        if !context.consts.contains_key(&cnst.name) {
-            result = report_error(
-                &format!("Failed to fold const '{}'", cnst.name),
-                &cnst.span,
-                context.sources,
-            );
+fn define_labels(context: &mut Context, labels: Vec<LabelAddress>) {
+    for label in labels {
+        context
+            .consts
+            .insert(label.name.clone(), ast::Expr::I32Const(label.address));
+        context
+            .consts
+            .insert(len_name(label.name), ast::Expr::I32Const(label.len));
+    }
+}
+
+fn len_name(label: &str) -> String {
+    format!("{}_len", label)
+}
+
+fn data_labels(values: &[ast::DataValues]) -> Vec<(&String, &Span)> {
+    let mut labels = Vec::new();
+    for value in values {
+        match value {
+            ast::DataValues::Label { name, span, .. } => labels.push((name, span)),
+            ast::DataValues::Repeat { values, .. } => labels.extend(data_labels(values)),
+            _ => (),
         }
     }
+    labels
+}
 
-    result
+struct LabelAddress<'a> {
+    name: &'a String,
+    address: i32,
+    len: i32,
 }
 
-fn fold_mem_location(context: &Context, mem_location: &mut ast::MemoryLocation) {
-    fold_expr(context, &mut mem_location.left);
-    fold_expr(context, &mut mem_location.right);
+struct BlockLayout<'a> {
+    size: u32,
+    labels: Vec<LabelAddress<'a>>,
 }
 
-fn fold_expr(context: &Context, expr: &mut ast::Expression) {
-    use ast::BinOp::*;
+// Returns the offset and layout of the data block, if all
+// the values determining the layout are known.
+fn measure_data<'a>(
+    context: &mut Context,
+    data: &'a ast::Data,
+) -> Result<Option<(u32, BlockLayout<'a>)>> {
+    let offset = match fold_clone(context, &data.offset)? {
+        Some(offset) => offset as u32,
+        None => return Ok(None),
+    };
+    Ok(measure_block(context, &data.data, offset)?.map(|block| (offset, block)))
+}
+
+fn measure_block<'a>(
+    context: &mut Context,
+    values: &'a [ast::DataValues],
+    offset: u32,
+) -> Result<Option<BlockLayout<'a>>> {
+    let mut position = 0;
+    let mut labels = Vec::new();
+    if !measure_data_values(context, values, offset, &mut position, &mut labels, false)? {
+        return Ok(None);
+    }
+
+    let labels = labels
+        .into_iter()
+        .map(|(name, label_position, len)| LabelAddress {
+            name,
+            address: offset.wrapping_add(label_position) as i32,
+            len: len.unwrap_or(0) as i32,
+        })
+        .collect();
+    Ok(Some(BlockLayout {
+        size: position,
+        labels,
+    }))
+}
+
+// Assigns the addresses of all statics once the start of the static region
+// and the size of each static is known. Statics are placed in the order of
+// decreasing alignment to avoid padding.
+fn place_statics<'a>(
+    context: &mut Context,
+    layout: &Layout<'a>,
+) -> Result<Option<Vec<LabelAddress<'a>>>> {
+    let start = match layout.regions.first() {
+        Some(region) => match fold_clone(context, &region.start)? {
+            Some(start) => start as u32,
+            None => return Ok(None),
+        },
+        // without a static region, the statics are placed after the last data block
+        None => {
+            let mut end = 0u32;
+            for data in layout.data {
+                match measure_data(context, data)? {
+                    Some((offset, block)) => end = end.max(offset.wrapping_add(block.size)),
+                    None => return Ok(None),
+                }
+            }
+            end
+        }
+    };
+
+    let mut statics = Vec::with_capacity(layout.statics.len());
+    for static_ in layout.statics {
+        let alignment = match static_alignment(context, &static_.data)? {
+            Some(alignment) => alignment,
+            None => return Ok(None),
+        };
+        match measure_block(context, &static_.data, 0)? {
+            Some(block) => statics.push((static_, alignment, block.size)),
+            None => return Ok(None),
+        }
+    }
+    statics.sort_by_key(|&(_, alignment, _)| std::cmp::Reverse(alignment));
+
+    let mut labels = Vec::new();
+    let mut address = start;
+    for (static_, alignment, size) in statics {
+        address = address.next_multiple_of(alignment);
+        labels.push(LabelAddress {
+            name: &static_.name,
+            address: address as i32,
+            len: size as i32,
+        });
+        if let Some(block) = measure_block(context, &static_.data, address)? {
+            labels.extend(block.labels);
+        }
+        address = address.wrapping_add(size);
+    }
+    Ok(Some(labels))
+}
+
+// the largest alignment required by the values of a static
+fn static_alignment(context: &mut Context, values: &[ast::DataValues]) -> Result<Option<u32>> {
+    let mut alignment = 1;
+    for value in values {
+        match value {
+            ast::DataValues::Array { type_, .. } => alignment = alignment.max(type_.size()),
+            ast::DataValues::Align(align) => match fold_clone(context, align)? {
+                Some(align) if align > 0 => alignment = alignment.max(align as u32),
+                _ => return Ok(None),
+            },
+            ast::DataValues::Repeat { values, .. } => match static_alignment(context, values)? {
+                Some(align) => alignment = alignment.max(align),
+                None => return Ok(None),
+            },
+            _ => (),
+        }
+    }
+    Ok(Some(alignment))
+}
+
+// same layout as in `emit`
+fn measure_data_values<'a>(
+    context: &mut Context,
+    values: &'a [ast::DataValues],
+    offset: u32,
+    position: &mut u32,
+    // the name, position and size of the value following each label
+    labels: &mut Vec<(&'a String, u32, Option<u32>)>,
+    in_repeat: bool,
+) -> Result<bool> {
+    for value in values {
+        if let ast::DataValues::Label { name, span, .. } = value {
+            if in_repeat {
+                report_error(
+                    "Labels can't be used inside of repeat blocks",
+                    span,
+                    context.sources,
+                )?;
+            }
+            labels.push((name, *position, None));
+            continue;
+        }
+        match value {
+            ast::DataValues::Array { type_, values } => {
+                let unaligned = *position;
+                *position = position.next_multiple_of(type_.size());
+                // labels right before the values point to the first value, not to the padding
+                for label in labels.iter_mut().rev() {
+                    if label.1 != unaligned || label.2.is_some() {
+                        break;
+                    }
+                    label.1 = *position;
+                }
+                let mut count = 0;
+                for value in values {
+                    if let ast::Expr::Sequence {
+                        ref start, ref end, ..
+                    } = value.expr
+                    {
+                        match (fold_clone(context, start)?, fold_clone(context, end)?) {
+                            (Some(start), Some(end)) => {
+                                count += end.saturating_sub(start).max(0) as u32
+                            }
+                            _ => return Ok(false),
+                        }
+                    } else {
+                        count += 1;
+                    }
+                }
+                *position = position.wrapping_add(count.wrapping_mul(type_.size()));
+            }
+            ast::DataValues::String(data) => *position = position.wrapping_add(data.len() as u32),
+            ast::DataValues::File {
+                data,
+                offset: slice_offset,
+                length,
+                ..
+            } => {
+                let slice_offset = match slice_offset {
+                    Some(slice_offset) => match fold_clone(context, slice_offset)? {
+                        Some(slice_offset) => Some(slice_offset),
+                        None => return Ok(false),
+                    },
+                    None => None,
+                };
+                let length = match length {
+                    Some(length) => match fold_clone(context, length)? {
+                        Some(length) => Some(length),
+                        None => return Ok(false),
+                    },
+                    None => None,
+                };
+                // invalid slices are reported when folding the data
+                let range = file_slice(data.len(), slice_offset, length).unwrap_or_default();
+                *position = position.wrapping_add(range.len() as u32)
+            }
+            ast::DataValues::Repeat { count, values } => {
+                let count = match fold_clone(context, count)? {
+                    Some(count) => count,
+                    None => return Ok(false),
+                };
+                for _ in 0..count {
+                    if !measure_data_values(context, values, offset, position, labels, true)? {
+                        return Ok(false);
+                    }
+                }
+            }
+            ast::DataValues::Fill { count, .. } | ast::DataValues::Zeros(count) => {
+                match fold_clone(context, count)? {
+                    Some(count) => *position = position.wrapping_add(count as u32),
+                    None => return Ok(false),
+                }
+            }
+            ast::DataValues::Align(alignment) => match fold_clone(context, alignment)? {
+                Some(alignment) if alignment > 0 => {
+                    let address = offset.wrapping_add(*position);
+                    *position += address.next_multiple_of(alignment as u32) - address;
+                }
+                _ => return Ok(false),
+            },
+            ast::DataValues::Label { .. } => unreachable!(),
+        }
+        if !in_repeat {
+            for label in labels.iter_mut().rev() {
+                if label.2.is_some() {
+                    break;
+                }
+                label.2 = Some(position.wrapping_sub(label.1));
+            }
+        }
+    }
+    Ok(true)
+}
+
+// folds a copy of the expression, returning its value if it's a constant i32
+fn fold_clone(context: &mut Context, expr: &ast::Expression) -> Result<Option<i32>> {
+    let mut expr = expr.clone();
+    fold_expr(context, &mut expr)?;
+    Ok(match expr.expr {
+        ast::Expr::I32Const(value) => Some(value),
+        _ => None,
+    })
+}
+
+fn fold_mem_location(context: &mut Context, mem_location: &mut ast::MemoryLocation) -> Result<()> {
+    fold_expr(context, &mut mem_location.left)?;
+    fold_expr(context, &mut mem_location.right)
+}
+
+// the names which shadow a float constant
+fn shadowing<'a>(names: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+    names
+        .into_iter()
+        .filter(|name| ast::Expr::float_constant(name).is_some())
+        .cloned()
+        .collect()
+}
+
+fn fold_expr(context: &mut Context, expr: &mut ast::Expression) -> Result<()> {
     match expr.expr {
         ast::Expr::Block {
             ref mut statements,
             ref mut final_expression,
         } => {
-            for stmt in statements {
-                fold_expr(context, stmt);
+            let scope = context.shadowed.len();
+            for stmt in statements.iter_mut() {
+                fold_expr(context, stmt)?;
             }
             if let Some(ref mut expr) = final_expression {
-                fold_expr(context, expr);
+                fold_expr(context, expr)?;
+            }
+            context.shadowed.truncate(scope);
+            // eg. a macro expanding to a single constant value
+            if let (true, Some(value)) = (statements.is_empty(), final_expression) {
+                if value.is_const() {
+                    expr.expr = value.expr.clone();
+                }
             }
         }
-        ast::Expr::Let { ref mut value, .. } => {
+        ast::Expr::Let {
+            ref name,
+            ref mut value,
+            ..
+        } => {
             if let Some(ref mut expr) = value {
-                fold_expr(context, expr);
+                fold_expr(context, expr)?;
             }
+            context.shadowed.extend(shadowing([name]));
         }
         ast::Expr::Poke {
             ref mut mem_location,
             ref mut value,
             ..
         } => {
-            fold_mem_location(context, mem_location);
-            fold_expr(context, value);
+            fold_mem_location(context, mem_location)?;
+            fold_expr(context, value)?;
         }
-        ast::Expr::Peek(ref mut mem_location) => fold_mem_location(context, mem_location),
+        ast::Expr::Peek(ref mut mem_location) => fold_mem_location(context, mem_location)?,
+        ast::Expr::Assert(ref mut condition) => fold_expr(context, condition)?,
         ast::Expr::UnaryOp { op, ref mut value } => {
-            fold_expr(context, value);
-            let result = match (op, &value.expr) {
-                (ast::UnaryOp::Negate, ast::Expr::I32Const(value)) => {
-                    Some(ast::Expr::I32Const(-*value))
-                }
-                (ast::UnaryOp::Negate, ast::Expr::I64Const(value)) => {
-                    Some(ast::Expr::I64Const(-*value))
-                }
-                (ast::UnaryOp::Negate, ast::Expr::F32Const(value)) => {
-                    Some(ast::Expr::F32Const(-*value))
-                }
-                (ast::UnaryOp::Negate, ast::Expr::F64Const(value)) => {
-                    Some(ast::Expr::F64Const(-*value))
-                }
-                (ast::UnaryOp::Negate, ast::Expr::Cast { value, type_ }) => {
-                    if let ast::Expr::I32Const(v) = value.expr {
-                        Some(ast::Expr::Cast {
-                            value: Box::new(ast::Expression {
-                                expr: ast::Expr::I32Const(-v),
-                                span: value.span.clone(),
-                                type_: value.type_,
-                            }),
-                            type_: *type_,
-                        })
-                    } else {
-                        None
-                    }
-                }
-                (ast::UnaryOp::Not, ast::Expr::I32Const(value)) => {
-                    Some(ast::Expr::I32Const((*value == 0) as i32))
-                }
-                (ast::UnaryOp::Not, ast::Expr::I64Const(value)) => {
-                    Some(ast::Expr::I32Const((*value == 0) as i32))
-                }
-                _ => None,
-            };
-            if let Some(result) = result {
+            fold_expr(context, value)?;
+            if let Some(result) = fold_unop(op, &value.expr) {
                 expr.expr = result;
             }
         }
@@ -172,181 +957,100 @@ fn fold_expr(context: &Context, expr: &mut ast::Expression) {
             ref mut right,
             ..
         } => {
-            fold_expr(context, left);
-            fold_expr(context, right);
-            match (&left.expr, &right.expr) {
-                (&ast::Expr::I32Const(left), &ast::Expr::I32Const(right)) => {
-                    let result = match op {
-                        Add => left.wrapping_add(right),
-                        Sub => left.wrapping_sub(right),
-                        Mul => left.wrapping_mul(right),
-                        Div => {
-                            if let Some(result) = left.checked_div(right) {
-                                result
-                            } else {
-                                return;
-                            }
-                        }
-                        DivU => {
-                            if let Some(result) = (left as u32).checked_div(right as u32) {
-                                result as i32
-                            } else {
-                                return;
-                            }
-                        }
-                        Rem => {
-                            if let Some(result) = left.checked_rem(right) {
-                                result
-                            } else {
-                                return;
-                            }
-                        }
-                        RemU => {
-                            if let Some(result) = (left as u32).checked_rem(right as u32) {
-                                result as i32
-                            } else {
-                                return;
-                            }
-                        }
-                        And => left & right,
-                        Or => left | right,
-                        Xor => left ^ right,
-                        Eq => (left == right) as i32,
-                        Ne => (left != right) as i32,
-                        Lt => (left < right) as i32,
-                        LtU => ((left as u32) < (right as u32)) as i32,
-                        Le => (left <= right) as i32,
-                        LeU => ((left as u32) <= (right as u32)) as i32,
-                        Gt => (left > right) as i32,
-                        GtU => ((left as u32) > (right as u32)) as i32,
-                        Ge => (left >= right) as i32,
-                        GeU => ((left as u32) >= (right as u32)) as i32,
-                        Shl => left << right,
-                        ShrU => ((left as u32) >> right) as i32,
-                        ShrS => left >> right,
-                    };
-                    expr.expr = ast::Expr::I32Const(result);
-                }
-                (&ast::Expr::I64Const(left), &ast::Expr::I64Const(right)) => {
-                    use ast::Expr::*;
-                    expr.expr = match op {
-                        Add => I64Const(left.wrapping_add(right)),
-                        Sub => I64Const(left.wrapping_sub(right)),
-                        Mul => I64Const(left.wrapping_mul(right)),
-                        Div => {
-                            if let Some(result) = left.checked_div(right) {
-                                I64Const(result)
-                            } else {
-                                return;
-                            }
-                        }
-                        DivU => {
-                            if let Some(result) = (left as u64).checked_div(right as u64) {
-                                I64Const(result as i64)
-                            } else {
-                                return;
-                            }
-                        }
-                        Rem => {
-                            if let Some(result) = left.checked_rem(right) {
-                                I64Const(result)
-                            } else {
-                                return;
-                            }
-                        }
-                        RemU => {
-                            if let Some(result) = (left as u64).checked_rem(right as u64) {
-                                I64Const(result as i64)
-                            } else {
-                                return;
-                            }
-                        }
-                        And => I64Const(left & right),
-                        Or => I64Const(left | right),
-                        Xor => I64Const(left ^ right),
-                        Eq => I32Const((left == right) as i32),
-                        Ne => I32Const((left != right) as i32),
-                        Lt => I32Const((left < right) as i32),
-                        LtU => I32Const(((left as u64) < (right as u64)) as i32),
-                        Le => I32Const((left <= right) as i32),
-                        LeU => I32Const(((left as u64) <= (right as u64)) as i32),
-                        Gt => I32Const((left > right) as i32),
-                        GtU => I32Const(((left as u64) > (right as u64)) as i32),
-                        Ge => I32Const((left >= right) as i32),
-                        GeU => I32Const(((left as u64) >= (right as u64)) as i32),
-                        Shl => I64Const(left << right),
-                        ShrU => I64Const(((left as u64) >> right) as i64),
-                        ShrS => I64Const(left >> right),
-                    };
-                }
-                (&ast::Expr::F32Const(left), &ast::Expr::F32Const(right)) => {
-                    use ast::Expr::*;
-                    expr.expr = match op {
-                        Add => F32Const(left + right),
-                        Sub => F32Const(left - right),
-                        Mul => F32Const(left * right),
-                        Div => F32Const(left / right),
-                        Rem | And | Or | Xor | Shl | ShrU | ShrS | DivU | RemU | LtU | LeU
-                        | GtU | GeU => return,
-                        Eq => I32Const((left == right) as i32),
-                        Ne => I32Const((left != right) as i32),
-                        Lt => I32Const((left < right) as i32),
-                        Le => I32Const((left <= right) as i32),
-                        Gt => I32Const((left > right) as i32),
-                        Ge => I32Const((left >= right) as i32),
-                    };
-                }
-                (&ast::Expr::F64Const(left), &ast::Expr::F64Const(right)) => {
-                    use ast::Expr::*;
-                    expr.expr = match op {
-                        Add => F64Const(left + right),
-                        Sub => F64Const(left - right),
-                        Mul => F64Const(left * right),
-                        Div => F64Const(left / right),
-                        Rem | And | Or | Xor | Shl | ShrU | ShrS | DivU | RemU | LtU | LeU
-                        | GtU | GeU => return,
-                        Eq => I32Const((left == right) as i32),
-                        Ne => I32Const((left != right) as i32),
-                        Lt => I32Const((left < right) as i32),
-                        Le => I32Const((left <= right) as i32),
-                        Gt => I32Const((left > right) as i32),
-                        Ge => I32Const((left >= right) as i32),
-                    };
-                }
-                _ => (),
+            fold_expr(context, left)?;
+            fold_expr(context, right)?;
+            if let Some(result) = fold_binop(op, &left.expr, &right.expr) {
+                expr.expr = result;
             }
         }
         ast::Expr::I32Const(_)
         | ast::Expr::I64Const(_)
         | ast::Expr::F32Const(_)
-        | ast::Expr::F64Const(_) => (),
+        | ast::Expr::F64Const(_)
+        | ast::Expr::Str(_) => (),
         ast::Expr::Variable { ref name, .. } => {
             if let Some(value) = context.consts.get(name) {
                 expr.expr = value.clone();
+            } else if let Some(field) = context.resolve_field(name, &expr.span)? {
+                expr.expr = ast::Expr::Peek(field.location(&expr.span));
+                return fold_expr(context, expr);
+            } else if !context.shadowed.contains(name) {
+                if let Some(value) = ast::Expr::float_constant(name) {
+                    expr.expr = value;
+                }
+            }
+        }
+        ast::Expr::Assign {
+            ref name,
+            ref mut value,
+            ..
+        } => {
+            fold_expr(context, value)?;
+            if let Some(field) = context.resolve_field(name, &expr.span)? {
+                let placeholder = Box::new(ast::Expr::Error.with_span(value.span.clone()));
+                let value = std::mem::replace(value, placeholder);
+                expr.expr = ast::Expr::Poke {
+                    mem_location: field.location(&expr.span),
+                    value,
+                };
+                return fold_expr(context, expr);
             }
         }
-        ast::Expr::Assign { ref mut value, .. } => fold_expr(context, value),
-        ast::Expr::LocalTee { ref mut value, .. } => fold_expr(context, value),
-        ast::Expr::Loop { ref mut block, .. } => fold_expr(context, block),
-        ast::Expr::LabelBlock { ref mut block, .. } => fold_expr(context, block),
+        ast::Expr::LocalTee { ref mut value, .. } => fold_expr(context, value)?,
+        ast::Expr::Loop { ref mut block, .. } => fold_expr(context, block)?,
+        ast::Expr::LabelBlock { ref mut block, .. } => fold_expr(context, block)?,
         ast::Expr::Branch(_) => (),
         ast::Expr::BranchIf {
             ref mut condition, ..
-        } => fold_expr(context, condition),
-        ast::Expr::Cast { ref mut value, .. } => fold_expr(context, value),
+        } => fold_expr(context, condition)?,
+        ast::Expr::Cast { ref mut value, .. } => fold_expr(context, value)?,
         ast::Expr::FuncCall {
             ref name,
             ref mut params,
             ..
         } => {
             for param in params.iter_mut() {
-                fold_expr(context, param);
+                fold_expr(context, param)?;
+            }
+            if name == "sizeof" || name == "offsetof" {
+                expr.expr =
+                    ast::Expr::I32Const(fold_struct_query(context, name, params, &expr.span)?);
+                return Ok(());
+            }
+            if let Some(func) = context.const_fns.get(name) {
+                if !params.iter().all(|param| param.is_const()) {
+                    if context.partial {
+                        return Ok(());
+                    }
+                    return report_error(
+                        "Const fns can only be called with constant arguments",
+                        &expr.span,
+                        context.sources,
+                    );
+                }
+                let args = params.iter().map(|param| param.expr.clone()).collect();
+                match consteval::call(context, func, args, &expr.span) {
+                    Ok(Some(value)) => expr.expr = value,
+                    Ok(None) => {
+                        return report_error(
+                            &format!("Const fn {} has no result", name),
+                            &expr.span,
+                            context.sources,
+                        )
+                    }
+                    Err(consteval::CallError::UnknownConst(_)) if context.partial => (),
+                    Err(consteval::CallError::UnknownConst(span)) => {
+                        return report_error("Unknown const", &span, context.sources)
+                    }
+                    Err(consteval::CallError::Failed) => return Err(()),
+                }
+                return Ok(());
             }
             use ast::Expr::*;
             let params: Vec<_> = params.iter().map(|e| &e.expr).collect();
             expr.expr = match (name.as_str(), params.as_slice()) {
                 ("sqrt", [F32Const(v)]) if *v >= 0.0 => F32Const(v.sqrt()),
-                _ => return,
+                _ => return Ok(()),
             };
         }
         ast::Expr::Select {
@@ -355,32 +1059,384 @@ fn fold_expr(context: &Context, expr: &mut ast::Expression) {
             ref mut if_false,
             ..
         } => {
-            fold_expr(context, condition);
-            fold_expr(context, if_true);
-            fold_expr(context, if_false);
+            fold_expr(context, condition)?;
+            fold_expr(context, if_true)?;
+            fold_expr(context, if_false)?;
         }
         ast::Expr::If {
             ref mut condition,
             ref mut if_true,
             ref mut if_false,
         } => {
-            fold_expr(context, condition);
-            fold_expr(context, if_true);
+            fold_expr(context, condition)?;
+            fold_expr(context, if_true)?;
             if let Some(ref mut if_false) = if_false {
-                fold_expr(context, if_false);
+                fold_expr(context, if_false)?;
             }
         }
         ast::Expr::Return {
             value: Some(ref mut value),
-        } => fold_expr(context, value),
+        } => fold_expr(context, value)?,
         ast::Expr::Return { value: None } => (),
         ast::Expr::First {
             ref mut value,
             ref mut drop,
         } => {
-            fold_expr(context, value);
-            fold_expr(context, drop);
+            fold_expr(context, value)?;
+            fold_expr(context, drop)?;
+        }
+        ast::Expr::Repeat {
+            ref mut count,
+            ref index,
+            ref body,
+        } => {
+            fold_expr(context, count)?;
+            let count = match count.expr {
+                ast::Expr::I32Const(count) if count >= 0 => count,
+                _ => {
+                    return report_error(
+                        "Repeat count has to be a non-negative constant i32",
+                        &count.span,
+                        context.sources,
+                    )
+                }
+            };
+            let mut statements = Vec::with_capacity(count as usize);
+            let prev_value = context.consts.remove(index);
+            for i in 0..count {
+                let mut body = body.as_ref().clone();
+                context.consts.insert(index.clone(), ast::Expr::I32Const(i));
+                fold_expr(context, &mut body)?;
+                statements.push(body);
+            }
+            context.consts.remove(index);
+            if let Some(value) = prev_value {
+                context.consts.insert(index.clone(), value);
+            }
+            expr.expr = ast::Expr::Block {
+                statements,
+                final_expression: None,
+            };
+        }
+        ast::Expr::ConstIf {
+            ref mut condition,
+            ref mut if_true,
+            ref mut if_false,
+        } => {
+            fold_expr(context, condition)?;
+            let value = match condition.expr {
+                ast::Expr::I32Const(value) => value,
+                _ => {
+                    return report_error(
+                        "Condition of if const has to be a constant i32",
+                        &condition.span,
+                        context.sources,
+                    )
+                }
+            };
+            let placeholder = || Box::new(ast::Expr::Error.with_span(expr.span.clone()));
+            let mut branch = if value != 0 {
+                Some(std::mem::replace(if_true, placeholder()))
+            } else {
+                if_false.take()
+            };
+            // only the selected branch is folded (and later type checked)
+            if let Some(ref mut branch) = branch {
+                fold_expr(context, branch)?;
+            }
+            expr.expr = match branch {
+                Some(branch) => branch.expr,
+                None => ast::Expr::Block {
+                    statements: vec![],
+                    final_expression: None,
+                },
+            };
+        }
+        ast::Expr::Sequence { .. } => {
+            return report_error(
+                "Sequences are only allowed in data",
+                &expr.span,
+                context.sources,
+            )
         }
         ast::Expr::Error => unreachable!(),
     }
+    Ok(())
+}
+
+// Folds all values and expands `repeat` blocks and sequences.
+fn fold_data_values(
+    context: &mut Context,
+    data_values: Vec<ast::DataValues>,
+) -> Result<Vec<ast::DataValues>> {
+    let mut result = Vec::with_capacity(data_values.len());
+    for mut values in data_values {
+        match values {
+            ast::DataValues::Array { ref mut values, .. } => {
+                for mut value in std::mem::take(values) {
+                    if let ast::Expr::Sequence { .. } = value.expr {
+                        expand_sequence(context, value, values)?;
+                    } else {
+                        fold_expr(context, &mut value)?;
+                        values.push(value);
+                    }
+                }
+            }
+            ast::DataValues::String(_) | ast::DataValues::Label { .. } => (),
+            ast::DataValues::File {
+                ref mut offset,
+                ref mut length,
+                ref mut data,
+                ..
+            } => {
+                if let Some(mut slice_offset) = offset.take() {
+                    let start = fold_count(context, &mut slice_offset, "File offset")?;
+                    let length = match length.take() {
+                        Some(mut length) => Some(fold_count(context, &mut length, "File length")?),
+                        None => None,
+                    };
+                    match file_slice(data.len(), Some(start as i32), length.map(|l| l as i32)) {
+                        Some(range) => *data = data[range].to_vec(),
+                        None => report_error(
+                            &format!(
+                                "File slice {}..{} is out of range, the file is {} bytes long",
+                                start,
+                                length.map_or("".to_string(), |l| (start as u64 + l as u64)
+                                    .to_string()),
+                                data.len()
+                            ),
+                            &slice_offset.span,
+                            context.sources,
+                        )?,
+                    }
+                }
+            }
+            ast::DataValues::Repeat { mut count, values } => {
+                let count = fold_count(context, &mut count, "Repeat count")?;
+                let values = fold_data_values(context, values)?;
+                for _ in 0..count {
+                    result.extend(values.iter().cloned());
+                }
+                continue;
+            }
+            ast::DataValues::Fill {
+                ref mut value,
+                ref mut count,
+            } => {
+                fold_expr(context, value)?;
+                fold_count(context, count, "Fill count")?;
+            }
+            ast::DataValues::Zeros(ref mut count) => {
+                fold_count(context, count, "Zeros count")?;
+            }
+            ast::DataValues::Align(ref mut alignment) => {
+                if fold_count(context, alignment, "Alignment")? == 0 {
+                    report_error("Alignment can't be zero", &alignment.span, context.sources)?;
+                }
+            }
+        }
+        result.push(values);
+    }
+    Ok(result)
+}
+
+// the range of a file included by `file(path, offset, length)`,
+// `None` if it's not inside of the file
+fn file_slice(len: usize, offset: Option<i32>, length: Option<i32>) -> Option<Range<usize>> {
+    let start = usize::try_from(offset.unwrap_or(0)).ok()?;
+    let end = match length {
+        Some(length) => start.checked_add(usize::try_from(length).ok()?)?,
+        None => len,
+    };
+    (start <= end && end <= len).then_some(start..end)
+}
+
+fn fold_count(context: &mut Context, count: &mut ast::Expression, what: &str) -> Result<u32> {
+    fold_expr(context, count)?;
+    match count.expr {
+        ast::Expr::I32Const(count) if count >= 0 => Ok(count as u32),
+        _ => {
+            report_error(
+                &format!("{} has to be a non-negative constant i32", what),
+                &count.span,
+                context.sources,
+            )?;
+            unreachable!()
+        }
+    }
+}
+
+// expands `for i in start..end: value` to the folded values for each i
+fn expand_sequence(
+    context: &mut Context,
+    sequence: ast::Expression,
+    values: &mut Vec<ast::Expression>,
+) -> Result<()> {
+    let (index, mut start, mut end, value) = match sequence.expr {
+        ast::Expr::Sequence {
+            index,
+            start,
+            end,
+            value,
+        } => (index, start, end, value),
+        _ => unreachable!(),
+    };
+    fold_expr(context, &mut start)?;
+    fold_expr(context, &mut end)?;
+    let (start, end) = match (&start.expr, &end.expr) {
+        (&ast::Expr::I32Const(start), &ast::Expr::I32Const(end)) => (start, end),
+        _ => {
+            return report_error(
+                "Sequence bounds have to be constant i32 values",
+                &sequence.span,
+                context.sources,
+            )
+        }
+    };
+
+    let prev_value = context.consts.remove(&index);
+    for i in start..end {
+        let mut value = value.as_ref().clone();
+        context.consts.insert(index.clone(), ast::Expr::I32Const(i));
+        fold_expr(context, &mut value)?;
+        values.push(value);
+    }
+    context.consts.remove(&index);
+    if let Some(value) = prev_value {
+        context.consts.insert(index, value);
+    }
+    Ok(())
+}
+
+pub fn fold_unop(op: ast::UnaryOp, value: &ast::Expr) -> Option<ast::Expr> {
+    match (op, value) {
+        (ast::UnaryOp::Negate, ast::Expr::I32Const(value)) => {
+            Some(ast::Expr::I32Const(value.wrapping_neg()))
+        }
+        (ast::UnaryOp::Negate, ast::Expr::I64Const(value)) => {
+            Some(ast::Expr::I64Const(value.wrapping_neg()))
+        }
+        (ast::UnaryOp::Negate, ast::Expr::F32Const(value)) => Some(ast::Expr::F32Const(-*value)),
+        (ast::UnaryOp::Negate, ast::Expr::F64Const(value)) => Some(ast::Expr::F64Const(-*value)),
+        (ast::UnaryOp::Negate, ast::Expr::Cast { value, type_ }) => {
+            if let ast::Expr::I32Const(v) = value.expr {
+                Some(ast::Expr::Cast {
+                    value: Box::new(ast::Expression {
+                        expr: ast::Expr::I32Const(-v),
+                        span: value.span.clone(),
+                        type_: value.type_,
+                    }),
+                    type_: *type_,
+                })
+            } else {
+                None
+            }
+        }
+        (ast::UnaryOp::Not, ast::Expr::I32Const(value)) => {
+            Some(ast::Expr::I32Const((*value == 0) as i32))
+        }
+        (ast::UnaryOp::Not, ast::Expr::I64Const(value)) => {
+            Some(ast::Expr::I32Const((*value == 0) as i32))
+        }
+        _ => None,
+    }
+}
+
+pub fn fold_binop(op: ast::BinOp, left: &ast::Expr, right: &ast::Expr) -> Option<ast::Expr> {
+    use ast::BinOp::*;
+    match (left, right) {
+        (&ast::Expr::I32Const(left), &ast::Expr::I32Const(right)) => {
+            let result = match op {
+                Add => left.wrapping_add(right),
+                Sub => left.wrapping_sub(right),
+                Mul => left.wrapping_mul(right),
+                Div => left.checked_div(right)?,
+                DivU => (left as u32).checked_div(right as u32)? as i32,
+                Rem => left.checked_rem(right)?,
+                RemU => (left as u32).checked_rem(right as u32)? as i32,
+                And => left & right,
+                Or => left | right,
+                Xor => left ^ right,
+                Eq => (left == right) as i32,
+                Ne => (left != right) as i32,
+                Lt => (left < right) as i32,
+                LtU => ((left as u32) < (right as u32)) as i32,
+                Le => (left <= right) as i32,
+                LeU => ((left as u32) <= (right as u32)) as i32,
+                Gt => (left > right) as i32,
+                GtU => ((left as u32) > (right as u32)) as i32,
+                Ge => (left >= right) as i32,
+                GeU => ((left as u32) >= (right as u32)) as i32,
+                Shl => left.wrapping_shl(right as u32),
+                ShrU => (left as u32).wrapping_shr(right as u32) as i32,
+                ShrS => left.wrapping_shr(right as u32),
+            };
+            Some(ast::Expr::I32Const(result))
+        }
+        (&ast::Expr::I64Const(left), &ast::Expr::I64Const(right)) => {
+            use ast::Expr::*;
+            Some(match op {
+                Add => I64Const(left.wrapping_add(right)),
+                Sub => I64Const(left.wrapping_sub(right)),
+                Mul => I64Const(left.wrapping_mul(right)),
+                Div => I64Const(left.checked_div(right)?),
+                DivU => I64Const((left as u64).checked_div(right as u64)? as i64),
+                Rem => I64Const(left.checked_rem(right)?),
+                RemU => I64Const((left as u64).checked_rem(right as u64)? as i64),
+                And => I64Const(left & right),
+                Or => I64Const(left | right),
+                Xor => I64Const(left ^ right),
+                Eq => I32Const((left == right) as i32),
+                Ne => I32Const((left != right) as i32),
+                Lt => I32Const((left < right) as i32),
+                LtU => I32Const(((left as u64) < (right as u64)) as i32),
+                Le => I32Const((left <= right) as i32),
+                LeU => I32Const(((left as u64) <= (right as u64)) as i32),
+                Gt => I32Const((left > right) as i32),
+                GtU => I32Const(((left as u64) > (right as u64)) as i32),
+                Ge => I32Const((left >= right) as i32),
+                GeU => I32Const(((left as u64) >= (right as u64)) as i32),
+                Shl => I64Const(left.wrapping_shl(right as u32)),
+                ShrU => I64Const((left as u64).wrapping_shr(right as u32) as i64),
+                ShrS => I64Const(left.wrapping_shr(right as u32)),
+            })
+        }
+        (&ast::Expr::F32Const(left), &ast::Expr::F32Const(right)) => {
+            use ast::Expr::*;
+            Some(match op {
+                Add => F32Const(left + right),
+                Sub => F32Const(left - right),
+                Mul => F32Const(left * right),
+                Div => F32Const(left / right),
+                Rem | And | Or | Xor | Shl | ShrU | ShrS | DivU | RemU | LtU | LeU | GtU | GeU => {
+                    return None
+                }
+                Eq => I32Const((left == right) as i32),
+                Ne => I32Const((left != right) as i32),
+                Lt => I32Const((left < right) as i32),
+                Le => I32Const((left <= right) as i32),
+                Gt => I32Const((left > right) as i32),
+                Ge => I32Const((left >= right) as i32),
+            })
+        }
+        (&ast::Expr::F64Const(left), &ast::Expr::F64Const(right)) => {
+            use ast::Expr::*;
+            Some(match op {
+                Add => F64Const(left + right),
+                Sub => F64Const(left - right),
+                Mul => F64Const(left * right),
+                Div => F64Const(left / right),
+                Rem | And | Or | Xor | Shl | ShrU | ShrS | DivU | RemU | LtU | LeU | GtU | GeU => {
+                    return None
+                }
+                Eq => I32Const((left == right) as i32),
+                Ne => I32Const((left != right) as i32),
+                Lt => I32Const((left < right) as i32),
+                Le => I32Const((left <= right) as i32),
+                Gt => I32Const((left > right) as i32),
+                Ge => I32Const((left >= right) as i32),
+            })
+        }
+        _ => None,
+    }
 }
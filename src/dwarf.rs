@@ -0,0 +1,407 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use wasm_encoder::{encoders, CustomSection, Section};
+use wasmparser::{Parser, Payload};
+
+use crate::{
+    ast,
+    parser::{Sources, Span},
+};
+
+// An emitted function, along with where its code came from.
+pub struct Function<'a> {
+    pub name: &'a str,
+    pub span: &'a Span,
+    pub locals: &'a ast::Locals,
+    // the index of the first instruction emitted for each span
+    pub lines: Vec<(u32, &'a Span)>,
+}
+
+// Appends the DWARF sections describing the functions to the module: a line table mapping
+// each instruction back to the source and the names, types and locations of the locals.
+// Addresses are offsets into the contents of the code section, as expected by wasm debuggers.
+pub fn add_sections(wasm: &mut Vec<u8>, name: &str, functions: &[Function], sources: &Sources) {
+    let code = parse_code(wasm);
+    let mut files = Files::new(sources);
+
+    let debug_line = line_program(functions, &code, sources, &mut files);
+    let (debug_abbrev, debug_info) = debug_info(name, functions, &code, sources, &mut files);
+
+    for (name, data) in [
+        (".debug_abbrev", &debug_abbrev),
+        (".debug_info", &debug_info),
+        (".debug_line", &debug_line),
+    ] {
+        wasm.push(0); // custom section id
+        CustomSection { name, data }.encode(wasm);
+    }
+}
+
+// The code of a function as offsets into the code section.
+struct FunctionCode {
+    // the start of the function (its size) and the end of its last instruction
+    start: u32,
+    end: u32,
+    // the offset of each instruction
+    instructions: Vec<u32>,
+}
+
+struct Code {
+    size: u32,
+    functions: Vec<FunctionCode>,
+}
+
+fn parse_code(wasm: &[u8]) -> Code {
+    let mut code = Code {
+        size: 0,
+        functions: Vec::new(),
+    };
+    let mut start = 0;
+    for payload in Parser::new(0).parse_all(wasm) {
+        match payload.expect("Failed to parse emitted module") {
+            Payload::CodeSectionStart { range, .. } => {
+                start = range.start;
+                code.size = (range.end - range.start) as u32;
+            }
+            Payload::CodeSectionEntry(body) => {
+                let range = body.range();
+                let size_len = encoders::u32((range.end - range.start) as u32).len();
+                let mut instructions = Vec::new();
+                let mut reader = body.get_operators_reader().unwrap();
+                while !reader.eof() {
+                    let (_, offset) = reader.read_with_offset().unwrap();
+                    instructions.push((offset - start) as u32);
+                }
+                code.functions.push(FunctionCode {
+                    start: (range.start - size_len - start) as u32,
+                    end: (range.end - start) as u32,
+                    instructions,
+                });
+            }
+            _ => (),
+        }
+    }
+    code
+}
+
+// The source files referenced, numbered from 1 in the order they are first used.
+// Copies of a source (from macro expansions) refer to the original file.
+// Names are relative to the directory of the script (the first source), which is
+// used as the compilation directory, so they don't depend on where the compiler ran.
+struct Files {
+    dir: PathBuf,
+    indices: HashMap<usize, u32>,
+    names: Vec<String>,
+}
+
+impl Files {
+    fn new(sources: &Sources) -> Files {
+        let dir = match sources.path(0).and_then(Path::parent) {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        Files {
+            dir: dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf()),
+            indices: HashMap::new(),
+            names: Vec::new(),
+        }
+    }
+
+    fn index(&mut self, span: &Span, sources: &Sources) -> u32 {
+        let id = sources.original(span.0);
+        *self.indices.entry(id).or_insert_with(|| {
+            let name = match sources.path(id).and_then(|path| path.canonicalize().ok()) {
+                Some(path) => path
+                    .strip_prefix(&self.dir)
+                    .unwrap_or(&path)
+                    .display()
+                    .to_string(),
+                None => sources.name(id).to_string(),
+            };
+            self.names.push(name);
+            self.names.len() as u32
+        })
+    }
+}
+
+fn line_program(
+    functions: &[Function],
+    code: &Code,
+    sources: &Sources,
+    files: &mut Files,
+) -> Vec<u8> {
+    const DW_LNS_COPY: u8 = 1;
+    const DW_LNS_ADVANCE_PC: u8 = 2;
+    const DW_LNS_ADVANCE_LINE: u8 = 3;
+    const DW_LNS_SET_FILE: u8 = 4;
+    const DW_LNS_SET_COLUMN: u8 = 5;
+    const DW_LNE_END_SEQUENCE: u8 = 1;
+    const DW_LNE_SET_ADDRESS: u8 = 2;
+
+    // a sequence of rows for each function, using only the standard opcodes
+    let mut program = Vec::new();
+    for (function, function_code) in functions.iter().zip(&code.functions) {
+        let Some(&(first, _)) = function.lines.first() else {
+            continue;
+        };
+        let mut address = function_code.instructions[first as usize];
+        program.extend([0, 5, DW_LNE_SET_ADDRESS]);
+        program.extend(address.to_le_bytes());
+        let (mut file, mut line, mut column) = (1, 1, 0);
+        for &(instruction, span) in &function.lines {
+            let row_address = function_code.instructions[instruction as usize];
+            let row_file = files.index(span, sources);
+            let (row_line, row_column) = sources.location(span);
+            if row_file != file {
+                program.push(DW_LNS_SET_FILE);
+                program.extend(encoders::u32(row_file));
+                file = row_file;
+            }
+            if row_line != line {
+                program.push(DW_LNS_ADVANCE_LINE);
+                program.extend(encoders::s64(row_line as i64 - line as i64));
+                line = row_line;
+            }
+            if row_column != column {
+                program.push(DW_LNS_SET_COLUMN);
+                program.extend(encoders::u32(row_column));
+                column = row_column;
+            }
+            if row_address != address {
+                program.push(DW_LNS_ADVANCE_PC);
+                program.extend(encoders::u32(row_address - address));
+                address = row_address;
+            }
+            program.push(DW_LNS_COPY);
+        }
+        program.push(DW_LNS_ADVANCE_PC);
+        program.extend(encoders::u32(function_code.end - address));
+        program.extend([0, 1, DW_LNE_END_SEQUENCE]);
+    }
+
+    let mut header = vec![
+        1,          // minimum_instruction_length
+        1,          // maximum_operations_per_instruction
+        1,          // default_is_stmt
+        -5i8 as u8, // line_base
+        14,         // line_range
+        13,         // opcode_base
+    ];
+    header.extend([0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]); // standard_opcode_lengths
+    header.push(0); // no include_directories
+    for name in &files.names {
+        string(&mut header, name);
+        header.extend([0, 0, 0]); // directory, modification time and size
+    }
+    header.push(0);
+
+    let mut unit = Vec::new();
+    unit.extend(4u16.to_le_bytes()); // version
+    unit.extend((header.len() as u32).to_le_bytes());
+    unit.extend(header);
+    unit.extend(program);
+    with_length(unit)
+}
+
+const DW_TAG_COMPILE_UNIT: u8 = 0x11;
+const DW_TAG_BASE_TYPE: u8 = 0x24;
+const DW_TAG_SUBPROGRAM: u8 = 0x2e;
+const DW_TAG_FORMAL_PARAMETER: u8 = 0x05;
+const DW_TAG_VARIABLE: u8 = 0x34;
+
+const DW_AT_LOCATION: u8 = 0x02;
+const DW_AT_NAME: u8 = 0x03;
+const DW_AT_BYTE_SIZE: u8 = 0x0b;
+const DW_AT_STMT_LIST: u8 = 0x10;
+const DW_AT_LOW_PC: u8 = 0x11;
+const DW_AT_HIGH_PC: u8 = 0x12;
+const DW_AT_LANGUAGE: u8 = 0x13;
+const DW_AT_COMP_DIR: u8 = 0x1b;
+const DW_AT_PRODUCER: u8 = 0x25;
+const DW_AT_DECL_FILE: u8 = 0x3a;
+const DW_AT_DECL_LINE: u8 = 0x3b;
+const DW_AT_ENCODING: u8 = 0x3e;
+const DW_AT_TYPE: u8 = 0x49;
+
+const DW_FORM_ADDR: u8 = 0x01;
+const DW_FORM_DATA2: u8 = 0x05;
+const DW_FORM_DATA4: u8 = 0x06;
+const DW_FORM_STRING: u8 = 0x08;
+const DW_FORM_DATA1: u8 = 0x0b;
+const DW_FORM_UDATA: u8 = 0x0f;
+const DW_FORM_REF4: u8 = 0x13;
+const DW_FORM_SEC_OFFSET: u8 = 0x17;
+const DW_FORM_EXPRLOC: u8 = 0x18;
+
+// the abbreviation codes of the entries in `debug_info`
+const COMPILE_UNIT: u8 = 1;
+const BASE_TYPE: u8 = 2;
+const SUBPROGRAM: u8 = 3;
+const PARAMETER: u8 = 4;
+const VARIABLE: u8 = 5;
+
+// the code, tag, whether it has children and the attributes with their forms
+type Abbreviation = (u8, u8, bool, &'static [(u8, u8)]);
+
+const VARIABLE_ATTRIBUTES: &[(u8, u8)] = &[
+    (DW_AT_NAME, DW_FORM_STRING),
+    (DW_AT_DECL_FILE, DW_FORM_UDATA),
+    (DW_AT_DECL_LINE, DW_FORM_UDATA),
+    (DW_AT_TYPE, DW_FORM_REF4),
+    (DW_AT_LOCATION, DW_FORM_EXPRLOC),
+];
+
+fn abbreviations() -> Vec<u8> {
+    let abbreviations: [Abbreviation; 5] = [
+        (
+            COMPILE_UNIT,
+            DW_TAG_COMPILE_UNIT,
+            true,
+            &[
+                (DW_AT_PRODUCER, DW_FORM_STRING),
+                (DW_AT_LANGUAGE, DW_FORM_DATA2),
+                (DW_AT_NAME, DW_FORM_STRING),
+                (DW_AT_COMP_DIR, DW_FORM_STRING),
+                (DW_AT_STMT_LIST, DW_FORM_SEC_OFFSET),
+                (DW_AT_LOW_PC, DW_FORM_ADDR),
+                (DW_AT_HIGH_PC, DW_FORM_DATA4),
+            ],
+        ),
+        (
+            BASE_TYPE,
+            DW_TAG_BASE_TYPE,
+            false,
+            &[
+                (DW_AT_NAME, DW_FORM_STRING),
+                (DW_AT_ENCODING, DW_FORM_DATA1),
+                (DW_AT_BYTE_SIZE, DW_FORM_DATA1),
+            ],
+        ),
+        (
+            SUBPROGRAM,
+            DW_TAG_SUBPROGRAM,
+            true,
+            &[
+                (DW_AT_NAME, DW_FORM_STRING),
+                (DW_AT_DECL_FILE, DW_FORM_UDATA),
+                (DW_AT_DECL_LINE, DW_FORM_UDATA),
+                (DW_AT_LOW_PC, DW_FORM_ADDR),
+                (DW_AT_HIGH_PC, DW_FORM_DATA4),
+            ],
+        ),
+        (
+            PARAMETER,
+            DW_TAG_FORMAL_PARAMETER,
+            false,
+            VARIABLE_ATTRIBUTES,
+        ),
+        (VARIABLE, DW_TAG_VARIABLE, false, VARIABLE_ATTRIBUTES),
+    ];
+
+    let mut data = Vec::new();
+    for (code, tag, children, attributes) in abbreviations {
+        data.extend([code, tag, children as u8]);
+        for &(attribute, form) in attributes {
+            data.extend([attribute, form]);
+        }
+        data.extend([0, 0]);
+    }
+    data.push(0);
+    data
+}
+
+fn debug_info(
+    name: &str,
+    functions: &[Function],
+    code: &Code,
+    sources: &Sources,
+    files: &mut Files,
+) -> (Vec<u8>, Vec<u8>) {
+    const DW_ATE_FLOAT: u8 = 0x04;
+    const DW_ATE_SIGNED: u8 = 0x05;
+    // there is no language code for CurlyWas, debuggers only need to know how
+    // to show the base types
+    const DW_LANG_C99: u16 = 0x0c;
+    const DW_OP_WASM_LOCATION: u8 = 0xed;
+    const DW_OP_STACK_VALUE: u8 = 0x9f;
+
+    // the unit starts after its length, which references are relative to
+    let mut unit = Vec::new();
+    unit.extend(4u16.to_le_bytes()); // version
+    unit.extend(0u32.to_le_bytes()); // offset into .debug_abbrev
+    unit.push(4); // address size
+
+    unit.push(COMPILE_UNIT);
+    string(&mut unit, "curlywas");
+    unit.extend(DW_LANG_C99.to_le_bytes());
+    string(&mut unit, name);
+    string(&mut unit, &files.dir.to_string_lossy());
+    unit.extend(0u32.to_le_bytes()); // offset into .debug_line
+    unit.extend(0u32.to_le_bytes());
+    unit.extend(code.size.to_le_bytes());
+
+    let mut types = HashMap::new();
+    for (type_, name, encoding, size) in [
+        (ast::Type::I32, "i32", DW_ATE_SIGNED, 4),
+        (ast::Type::I64, "i64", DW_ATE_SIGNED, 8),
+        (ast::Type::F32, "f32", DW_ATE_FLOAT, 4),
+        (ast::Type::F64, "f64", DW_ATE_FLOAT, 8),
+    ] {
+        types.insert(type_, unit.len() as u32 + 4);
+        unit.push(BASE_TYPE);
+        string(&mut unit, name);
+        unit.extend([encoding, size]);
+    }
+
+    for (function, function_code) in functions.iter().zip(&code.functions) {
+        unit.push(SUBPROGRAM);
+        string(&mut unit, function.name);
+        unit.extend(encoders::u32(files.index(function.span, sources)));
+        unit.extend(encoders::u32(sources.line(function.span)));
+        unit.extend(function_code.start.to_le_bytes());
+        unit.extend((function_code.end - function_code.start).to_le_bytes());
+
+        let params = function
+            .locals
+            .params
+            .iter()
+            .map(|local| (PARAMETER, local));
+        let locals = function.locals.locals.iter().map(|local| (VARIABLE, local));
+        for (code, local) in params.chain(locals) {
+            // inline variables aren't stored, compiler generated ones have no name
+            let Some(index) = local.index else {
+                continue;
+            };
+            let Some(name) = local.name.split('#').next().filter(|name| !name.is_empty()) else {
+                continue;
+            };
+            unit.push(code);
+            string(&mut unit, name);
+            unit.extend(encoders::u32(files.index(&local.span, sources)));
+            unit.extend(encoders::u32(sources.line(&local.span)));
+            unit.extend(types[&local.type_].to_le_bytes());
+            let mut location = vec![DW_OP_WASM_LOCATION, 0];
+            location.extend(encoders::u32(index));
+            location.push(DW_OP_STACK_VALUE);
+            unit.extend(encoders::u32(location.len() as u32));
+            unit.extend(location);
+        }
+        unit.push(0);
+    }
+    unit.push(0);
+
+    (abbreviations(), with_length(unit))
+}
+
+fn string(data: &mut Vec<u8>, string: &str) {
+    data.extend(string.as_bytes());
+    data.push(0);
+}
+
+// prefixes a unit with its length, in the 32 bit DWARF format
+fn with_length(unit: Vec<u8>) -> Vec<u8> {
+    let mut data = (unit.len() as u32).to_le_bytes().to_vec();
+    data.extend(unit);
+    data
+}
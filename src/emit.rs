@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::ops::Range;
 
 use wasm_encoder::{
     BlockType, CodeSection, DataSection, EntityType, Export, ExportSection, Function,
@@ -7,12 +8,23 @@ use wasm_encoder::{
 };
 
 use crate::{
-    ast,
+    ast, dwarf,
     intrinsics::{Intrinsics, MemInstruction},
+    namespaces,
+    parser::{Sources, Span},
     Options,
 };
 
-pub fn emit(script: &ast::Script, module_name: &str, options: &Options) -> Vec<u8> {
+// The function called with the source line and address of a memory access failing
+// the checks of a debug build, before trapping.
+pub const MEMORY_TRAP: &str = "memory_trap";
+
+pub fn emit(
+    script: &ast::Script,
+    module_name: &str,
+    options: &Options,
+    sources: &Sources,
+) -> Vec<u8> {
     let mut module = Module::new();
 
     let function_types = collect_function_types(script);
@@ -79,6 +91,10 @@ pub fn emit(script: &ast::Script, module_name: &str, options: &Options) -> Vec<u
         module.section(&imports);
     }
 
+    let intrinsics = Intrinsics::new();
+    // the functions with the source locations of their code, for the DWARF sections
+    let mut debug_functions = Vec::new();
+
     let mut global_section = GlobalSection::new();
     for var in &script.global_vars {
         global_section.global(
@@ -98,8 +114,6 @@ pub fn emit(script: &ast::Script, module_name: &str, options: &Options) -> Vec<u
 
         let mut start_index = None;
 
-        let intrinsics = Intrinsics::new();
-
         for func in script.functions.iter() {
             if func.start {
                 start_index = Some(function_map.len() as u32);
@@ -112,12 +126,25 @@ pub fn emit(script: &ast::Script, module_name: &str, options: &Options) -> Vec<u
             functions.function(type_ as u32);
             if func.export {
                 exports.export(
-                    &func.name,
-                    Export::Function(*function_map.get(&func.name).unwrap() as u32),
+                    namespaces::export_name(&func.name),
+                    Export::Function(*function_map.get(&func.name).unwrap()),
                 );
             }
 
-            code.function(&emit_function(func, &globals, &function_map, &intrinsics));
+            // the trap function itself isn't checked, to not recurse into it
+            let memory_check = (options.debug && func.name != MEMORY_TRAP).then(|| MemoryCheck {
+                range: options.memory_range.clone(),
+                trap: function_map.get(MEMORY_TRAP).copied(),
+                sources,
+            });
+            let body = emit_function(func, &globals, &function_map, &intrinsics, memory_check);
+            code.function(&body.function);
+            debug_functions.push(dwarf::Function {
+                name: &func.name,
+                span: &func.span,
+                locals: &func.locals,
+                lines: body.lines,
+            });
         }
 
         module.section(&functions);
@@ -137,57 +164,18 @@ pub fn emit(script: &ast::Script, module_name: &str, options: &Options) -> Vec<u
         let mut data_section = DataSection::new();
 
         for data in &script.data {
-            let mut segment_data: Vec<u8> = vec![];
-            for values in &data.data {
-                match values {
-                    ast::DataValues::Array { type_, values } => {
-                        let width = match *type_ {
-                            ast::DataType::I8 => 1,
-                            ast::DataType::I16 => 2,
-                            ast::DataType::I32 => 4,
-                            ast::DataType::I64 => 8,
-                            ast::DataType::F32 => 4,
-                            ast::DataType::F64 => 8,
-                        };
-                        while segment_data.len() % width != 0 {
-                            segment_data.push(0);
-                        }
-                        for value in values {
-                            match *type_ {
-                                ast::DataType::I8 => segment_data.push(value.const_i32() as u8),
-                                ast::DataType::I16 => segment_data
-                                    .extend_from_slice(&(value.const_i32() as u16).to_le_bytes()),
-                                ast::DataType::I32 => segment_data
-                                    .extend_from_slice(&(value.const_i32() as u32).to_le_bytes()),
-                                ast::DataType::I64 => segment_data
-                                    .extend_from_slice(&(value.const_i64() as u64).to_le_bytes()),
-                                ast::DataType::F32 => {
-                                    segment_data.extend_from_slice(&value.const_f32().to_le_bytes())
-                                }
-                                ast::DataType::F64 => {
-                                    segment_data.extend_from_slice(&value.const_f64().to_le_bytes())
-                                }
-                            }
-                        }
-                    }
-                    ast::DataValues::String(s) => {
-                        for c in s.chars() {
-                            segment_data.push(c as u8);
-                        }
-                    }
-                    ast::DataValues::File { data, .. } => {
-                        segment_data.extend_from_slice(data);
-                    }
-                }
+            for (address, bytes) in data_segments(data) {
+                data_section.active(
+                    0,
+                    &wasm_encoder::Instruction::I32Const(address as i32),
+                    bytes,
+                );
             }
-            data_section.active(
-                0,
-                &wasm_encoder::Instruction::I32Const(data.offset.const_i32()),
-                segment_data,
-            );
         }
 
-        module.section(&data_section);
+        if !data_section.is_empty() {
+            module.section(&data_section);
+        }
     }
 
     if options.debug {
@@ -240,7 +228,11 @@ pub fn emit(script: &ast::Script, module_name: &str, options: &Options) -> Vec<u
         module.section(&names);
     }
 
-    module.finish()
+    let mut wasm = module.finish();
+    if options.debug {
+        dwarf::add_sections(&mut wasm, module_name, &debug_functions, sources);
+    }
+    wasm
 }
 
 type FunctionTypeKey = (Vec<ast::Type>, Option<ast::Type>);
@@ -269,11 +261,11 @@ fn collect_function_types(script: &ast::Script) -> HashMap<FunctionTypeKey, usiz
 }
 
 fn function_type_key(func: &ast::Function) -> FunctionTypeKey {
-    let param_types: Vec<_> = func.params.iter().map(|(_, type_)| *type_).collect();
+    let param_types: Vec<_> = func.params.iter().map(|param| param.type_).collect();
     (param_types, func.type_)
 }
 
-fn const_instr(expr: &ast::Expression) -> Instruction {
+fn const_instr(expr: &ast::Expression) -> Instruction<'_> {
     match expr.expr {
         ast::Expr::I32Const(v) => Instruction::I32Const(v),
         ast::Expr::F32Const(v) => Instruction::F32Const(v),
@@ -283,44 +275,85 @@ fn const_instr(expr: &ast::Expression) -> Instruction {
     }
 }
 
+// A function body which records the source location of its instructions
+struct FunctionBody<'a> {
+    function: Function,
+    instructions: u32,
+    // the expression currently emitted
+    span: Option<&'a Span>,
+    // the index of the first instruction for each span in the order emitted
+    lines: Vec<(u32, &'a Span)>,
+}
+
+impl<'a> FunctionBody<'a> {
+    fn instruction(&mut self, instruction: &Instruction) -> &mut Self {
+        if let Some(span) = self.span {
+            if !matches!(self.lines.last(), Some(&(_, last)) if std::ptr::eq(last, span)) {
+                self.lines.push((self.instructions, span));
+            }
+        }
+        self.function.instruction(instruction);
+        self.instructions += 1;
+        self
+    }
+}
+
 struct FunctionContext<'a> {
-    function: &'a mut Function,
+    function: FunctionBody<'a>,
     globals: &'a HashMap<&'a str, u32>,
     functions: &'a HashMap<String, u32>,
     locals: &'a ast::Locals,
     labels: Vec<String>,
     let_values: HashMap<u32, Vec<(&'a ast::Expression, ast::LetType)>>,
     intrinsics: &'a Intrinsics,
+    // set in debug builds, along with the local holding the checked address
+    memory_check: Option<(MemoryCheck<'a>, u32)>,
 }
 
-fn emit_function(
-    func: &ast::Function,
-    globals: &HashMap<&str, u32>,
-    functions: &HashMap<String, u32>,
-    intrinsics: &Intrinsics,
-) -> Function {
-    let mut function = Function::new_with_locals_types({
-        let mut locals: Vec<(u32, ast::Type)> = func
-            .locals
-            .locals
-            .iter()
-            .filter_map(|local| local.index.map(|i| (i, local.type_)))
-            .collect();
-        locals.sort();
-        locals.into_iter().map(|(_, t)| map_type(t))
-    });
+struct MemoryCheck<'a> {
+    range: Option<Range<u64>>,
+    trap: Option<u32>,
+    sources: &'a Sources,
+}
+
+fn emit_function<'a>(
+    func: &'a ast::Function,
+    globals: &'a HashMap<&str, u32>,
+    functions: &'a HashMap<String, u32>,
+    intrinsics: &'a Intrinsics,
+    memory_check: Option<MemoryCheck<'a>>,
+) -> FunctionBody<'a> {
+    let mut locals: Vec<(u32, ast::Type)> = func
+        .locals
+        .locals
+        .iter()
+        .filter_map(|local| local.index.map(|i| (i, local.type_)))
+        .collect();
+    locals.sort();
+    let address_local = (func.locals.params.len() + locals.len()) as u32;
+    if memory_check.is_some() {
+        locals.push((address_local, ast::Type::I32));
+    }
+    let function = FunctionBody {
+        function: Function::new_with_locals_types(locals.into_iter().map(|(_, t)| map_type(t))),
+        instructions: 0,
+        span: None,
+        lines: Vec::new(),
+    };
 
     let mut context = FunctionContext {
-        function: &mut function,
+        function,
         globals,
         functions,
         locals: &func.locals,
         labels: vec![],
         let_values: HashMap::new(),
         intrinsics,
+        memory_check: memory_check.map(|check| (check, address_local)),
     };
 
     emit_expression(&mut context, &func.body);
+    let mut function = context.function;
     if func.type_.is_none() && func.body.type_.is_some() {
         function.instruction(&Instruction::Drop);
     }
@@ -335,26 +368,83 @@ fn mem_arg_for_location(mem_location: &ast::MemoryLocation) -> MemArg {
     } else {
         unreachable!()
     };
-    match mem_location.size {
-        ast::MemSize::Byte => MemArg {
-            align: 0,
-            memory_index: 0,
-            offset,
-        },
-        ast::MemSize::Word => MemArg {
-            align: 2,
-            memory_index: 0,
-            offset,
-        },
-        ast::MemSize::Float => MemArg {
-            align: 2,
-            memory_index: 0,
-            offset,
-        },
+    MemArg {
+        align: mem_location.size.alignment(),
+        memory_index: 0,
+        offset,
     }
 }
 
+// Checks the address on the stack (leaving it there) for the access described by `mem_arg`,
+// calling the trap function and trapping if it isn't aligned or outside of the memory range.
+// `aligned` is set for the `!` and `$` accesses, which assume an aligned address. Other accesses,
+// including the intrinsics, only hint at the alignment and may well be unaligned.
+fn emit_memory_check(
+    ctx: &mut FunctionContext,
+    span: &Span,
+    mem_arg: &MemArg,
+    size: u64,
+    aligned: bool,
+) {
+    let Some((ref check, address)) = ctx.memory_check else {
+        return;
+    };
+    let function = &mut ctx.function;
+    function.instruction(&Instruction::LocalTee(address));
+    let mut conditions = 0;
+    // the low bits of the effective address don't depend on the overflow of the sum
+    if aligned && mem_arg.align > 0 {
+        function.instruction(&Instruction::LocalGet(address));
+        function.instruction(&Instruction::I32Const(mem_arg.offset as i32));
+        function.instruction(&Instruction::I32Add);
+        function.instruction(&Instruction::I32Const((1 << mem_arg.align) - 1));
+        function.instruction(&Instruction::I32And);
+        conditions += 1;
+    }
+    if let Some(ref range) = check.range {
+        // compares the address before adding the offset, so it can't overflow
+        if range.start > mem_arg.offset {
+            function.instruction(&Instruction::LocalGet(address));
+            function.instruction(&Instruction::I32Const(
+                (range.start - mem_arg.offset) as i32,
+            ));
+            function.instruction(&Instruction::I32LtU);
+            conditions += 1;
+        }
+        match range.end.checked_sub(mem_arg.offset + size) {
+            Some(limit) if limit >= u32::MAX as u64 => (),
+            Some(limit) => {
+                function.instruction(&Instruction::LocalGet(address));
+                function.instruction(&Instruction::I32Const(limit as i32));
+                function.instruction(&Instruction::I32GtU);
+                conditions += 1;
+            }
+            None => {
+                function.instruction(&Instruction::I32Const(1));
+                conditions += 1;
+            }
+        }
+    }
+    if conditions == 0 {
+        return;
+    }
+    for _ in 1..conditions {
+        function.instruction(&Instruction::I32Or);
+    }
+    function.instruction(&Instruction::If(BlockType::Empty));
+    if let Some(trap) = check.trap {
+        function.instruction(&Instruction::I32Const(check.sources.line(span) as i32));
+        function.instruction(&Instruction::LocalGet(address));
+        function.instruction(&Instruction::I32Const(mem_arg.offset as i32));
+        function.instruction(&Instruction::I32Add);
+        function.instruction(&Instruction::Call(trap));
+    }
+    function.instruction(&Instruction::Unreachable);
+    function.instruction(&Instruction::End);
+}
+
 fn emit_expression<'a>(ctx: &mut FunctionContext<'a>, expr: &'a ast::Expression) {
+    let outer = ctx.function.span.replace(&expr.span);
     match &expr.expr {
         ast::Expr::Block {
             statements,
@@ -396,10 +486,22 @@ fn emit_expression<'a>(ctx: &mut FunctionContext<'a>, expr: &'a ast::Expression)
         ast::Expr::Peek(mem_location) => {
             emit_expression(ctx, &mem_location.left);
             let mem_arg = mem_arg_for_location(mem_location);
+            emit_memory_check(
+                ctx,
+                &expr.span,
+                &mem_arg,
+                mem_location.size.bytes(),
+                matches!(mem_location.size, ast::MemSize::Word | ast::MemSize::Float),
+            );
             ctx.function.instruction(&match mem_location.size {
                 ast::MemSize::Byte => Instruction::I32Load8_U(mem_arg),
+                ast::MemSize::SignedByte => Instruction::I32Load8_S(mem_arg),
+                ast::MemSize::Half => Instruction::I32Load16_U(mem_arg),
+                ast::MemSize::SignedHalf => Instruction::I32Load16_S(mem_arg),
                 ast::MemSize::Word => Instruction::I32Load(mem_arg),
+                ast::MemSize::Long => Instruction::I64Load(mem_arg),
                 ast::MemSize::Float => Instruction::F32Load(mem_arg),
+                ast::MemSize::Double => Instruction::F64Load(mem_arg),
             });
         }
         ast::Expr::Poke {
@@ -407,12 +509,22 @@ fn emit_expression<'a>(ctx: &mut FunctionContext<'a>, expr: &'a ast::Expression)
             value,
         } => {
             emit_expression(ctx, &mem_location.left);
-            emit_expression(ctx, value);
             let mem_arg = mem_arg_for_location(mem_location);
+            emit_memory_check(
+                ctx,
+                &expr.span,
+                &mem_arg,
+                mem_location.size.bytes(),
+                matches!(mem_location.size, ast::MemSize::Word | ast::MemSize::Float),
+            );
+            emit_expression(ctx, value);
             ctx.function.instruction(&match mem_location.size {
-                ast::MemSize::Byte => Instruction::I32Store8(mem_arg),
+                ast::MemSize::Byte | ast::MemSize::SignedByte => Instruction::I32Store8(mem_arg),
+                ast::MemSize::Half | ast::MemSize::SignedHalf => Instruction::I32Store16(mem_arg),
                 ast::MemSize::Word => Instruction::I32Store(mem_arg),
+                ast::MemSize::Long => Instruction::I64Store(mem_arg),
                 ast::MemSize::Float => Instruction::F32Store(mem_arg),
+                ast::MemSize::Double => Instruction::F64Store(mem_arg),
             });
         }
         ast::Expr::UnaryOp { op, value } => {
@@ -636,8 +748,8 @@ fn emit_expression<'a>(ctx: &mut FunctionContext<'a>, expr: &'a ast::Expression)
             } else if let Some(index) = ctx.globals.get(name.as_str()) {
                 ctx.function.instruction(&Instruction::GlobalGet(*index));
             } else {
-                dbg!(name);
-                unreachable!()
+                // the type checker reports unknown variables
+                unreachable!("variable {} is neither a local nor a global", name)
             }
         }
         ast::Expr::Cast { value, type_, .. } => {
@@ -664,30 +776,41 @@ fn emit_expression<'a>(ctx: &mut FunctionContext<'a>, expr: &'a ast::Expression)
             }
         }
         ast::Expr::FuncCall { name, params, .. } => {
-            fn mem_instruction(
-                inst: MemInstruction,
-                params: &[ast::Expression],
-            ) -> Instruction<'static> {
+            fn mem_arg(inst: &MemInstruction, params: &[ast::Expression]) -> MemArg {
                 let offset = params
-                    .get(0)
+                    .first()
                     .map(|e| e.const_i32() as u32 as u64)
                     .unwrap_or(0);
                 let alignment = params.get(1).map(|e| e.const_i32() as u32);
-                (inst.instruction)(MemArg {
+                MemArg {
                     offset,
                     align: alignment.unwrap_or(inst.natural_alignment),
                     memory_index: 0,
-                })
+                }
             }
             if let Some(load) = ctx.intrinsics.find_load(name) {
                 emit_expression(ctx, &params[0]);
-                ctx.function
-                    .instruction(&mem_instruction(load, &params[1..]));
+                let mem_arg = mem_arg(&load, &params[1..]);
+                emit_memory_check(
+                    ctx,
+                    &expr.span,
+                    &mem_arg,
+                    1 << load.natural_alignment,
+                    false,
+                );
+                ctx.function.instruction(&(load.instruction)(mem_arg));
             } else if let Some(store) = ctx.intrinsics.find_store(name) {
                 emit_expression(ctx, &params[1]);
+                let mem_arg = mem_arg(&store, &params[2..]);
+                emit_memory_check(
+                    ctx,
+                    &expr.span,
+                    &mem_arg,
+                    1 << store.natural_alignment,
+                    false,
+                );
                 emit_expression(ctx, &params[0]);
-                ctx.function
-                    .instruction(&mem_instruction(store, &params[2..]));
+                ctx.function.instruction(&(store.instruction)(mem_arg));
             } else {
                 for param in params {
                     emit_expression(ctx, param);
@@ -752,7 +875,124 @@ fn emit_expression<'a>(ctx: &mut FunctionContext<'a>, expr: &'a ast::Expression)
                 ctx.function.instruction(&Instruction::Drop);
             }
         }
-        ast::Expr::Error => unreachable!(),
+        ast::Expr::Repeat { .. }
+        | ast::Expr::ConstIf { .. }
+        | ast::Expr::Sequence { .. }
+        | ast::Expr::Str(_)
+        | ast::Expr::Assert(_)
+        | ast::Expr::Error => {
+            unreachable!()
+        }
+    }
+    ctx.function.span = outer;
+}
+
+// The segments of a data block, longer runs of zeros are left out.
+pub fn data_segments(data: &ast::Data) -> Vec<(u32, Vec<u8>)> {
+    let mut segments = SegmentWriter::new(data.offset.const_i32() as u32);
+    for values in &data.data {
+        match values {
+            ast::DataValues::Array { type_, values } => {
+                let width = type_.size();
+                while !segments.position().is_multiple_of(width) {
+                    segments.push(&[0]);
+                }
+                for value in values {
+                    match *type_ {
+                        ast::DataType::I8 => segments.push(&[value.const_i32() as u8]),
+                        ast::DataType::I16 => {
+                            segments.push(&(value.const_i32() as u16).to_le_bytes())
+                        }
+                        ast::DataType::I32 => {
+                            segments.push(&(value.const_i32() as u32).to_le_bytes())
+                        }
+                        ast::DataType::I64 => {
+                            segments.push(&(value.const_i64() as u64).to_le_bytes())
+                        }
+                        ast::DataType::F32 => segments.push(&value.const_f32().to_le_bytes()),
+                        ast::DataType::F64 => segments.push(&value.const_f64().to_le_bytes()),
+                    }
+                }
+            }
+            ast::DataValues::String(data) | ast::DataValues::File { data, .. } => {
+                segments.push(data);
+            }
+            ast::DataValues::Fill { value, count } => {
+                let value = value.const_i32() as u8;
+                let count = count.const_i32() as usize;
+                if value == 0 {
+                    segments.skip(count as u32);
+                } else {
+                    segments.push(&vec![value; count]);
+                }
+            }
+            ast::DataValues::Zeros(count) => segments.skip(count.const_i32() as u32),
+            ast::DataValues::Align(alignment) => {
+                let address = segments.address();
+                let aligned = address.next_multiple_of(alignment.const_i32() as u32);
+                segments.skip(aligned - address);
+            }
+            ast::DataValues::Label { .. } => (),
+            ast::DataValues::Repeat { .. } => unreachable!(),
+        }
+    }
+    segments.finish()
+}
+
+// Memory is zero initialized, so longer runs of zeros (from `zeros`, `fill(0, ..)`
+// or `align`) are left out by splitting the data into several segments.
+struct SegmentWriter {
+    offset: u32,
+    segments: Vec<(u32, Vec<u8>)>,
+    start: u32,
+    bytes: Vec<u8>,
+}
+
+impl SegmentWriter {
+    // a gap shorter than this is cheaper to write out than starting a new segment
+    const MIN_GAP: u32 = 8;
+
+    fn new(offset: u32) -> SegmentWriter {
+        SegmentWriter {
+            offset,
+            segments: Vec::new(),
+            start: offset,
+            bytes: Vec::new(),
+        }
+    }
+
+    // position relative to the start of the data block
+    fn position(&self) -> u32 {
+        self.address() - self.offset
+    }
+
+    fn address(&self) -> u32 {
+        self.start + self.bytes.len() as u32
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn skip(&mut self, count: u32) {
+        if count < Self::MIN_GAP {
+            self.bytes.resize(self.bytes.len() + count as usize, 0);
+            return;
+        }
+        let address = self.address() + count;
+        if !self.bytes.is_empty() {
+            self.segments
+                .push((self.start, std::mem::take(&mut self.bytes)));
+        }
+        self.start = address;
+    }
+
+    fn finish(mut self) -> Vec<(u32, Vec<u8>)> {
+        // a data block without any gaps always gets a segment, even if it's empty
+        if !self.bytes.is_empty() || (self.segments.is_empty() && self.start == self.offset) {
+            self.segments.push((self.start, self.bytes));
+        }
+        self.segments
     }
 }
 
@@ -2,7 +2,7 @@ use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::{collections::HashSet, fs::File};
 
-use crate::ast;
+use crate::{assets, ast};
 use anyhow::{anyhow, Result};
 
 pub fn resolve_includes(
@@ -11,24 +11,114 @@ pub fn resolve_includes(
     path: &Path,
 ) -> Result<()> {
     let script_dir = path.parent().expect("Script path has no parent");
+    for conditional in &mut script.conditionals {
+        resolve_includes(&mut conditional.if_true, dependencies, path)?;
+        resolve_includes(&mut conditional.if_false, dependencies, path)?;
+    }
     for data in &mut script.data {
-        for values in &mut data.data {
-            if let ast::DataValues::File {
+        load_files(&mut data.data, dependencies, script_dir)?;
+    }
+    for static_ in &mut script.statics {
+        load_files(&mut static_.data, dependencies, script_dir)?;
+    }
+
+    Ok(())
+}
+
+fn load_files(
+    values: &mut [ast::DataValues],
+    dependencies: &mut HashSet<PathBuf>,
+    script_dir: &Path,
+) -> Result<()> {
+    for values in values {
+        match values {
+            ast::DataValues::File {
                 ref path,
+                format,
                 ref mut data,
-            } = values
-            {
+                ..
+            } => {
                 let mut full_path = script_dir.to_path_buf();
                 full_path.push(path);
+                let mut content = Vec::new();
                 File::open(&full_path)
                     .map_err(|e| {
                         anyhow!("Failed to load data from {}: {}", full_path.display(), e)
                     })?
-                    .read_to_end(data)?;
+                    .read_to_end(&mut content)?;
+                *data = assets::convert(content, *format).map_err(|e| {
+                    anyhow!("Failed to convert data from {}: {}", full_path.display(), e)
+                })?;
                 dependencies.insert(full_path);
             }
+            ast::DataValues::Repeat { values, .. } => {
+                load_files(values, dependencies, script_dir)?;
+            }
+            _ => (),
         }
     }
 
     Ok(())
 }
+
+// indices into `conditionals` together with the selected branch,
+// for each level of nested `if const` blocks
+pub type Branch = Vec<(usize, bool)>;
+
+// An include inside of (possibly nested) top-level `if const` blocks.
+pub struct ConditionalInclude {
+    pub include: ast::Include,
+    pub branch: Branch,
+    // source id and offset of each enclosing condition, used to tell apart
+    // includes of the same file under different conditions
+    pub conditions: Vec<(usize, usize, bool)>,
+}
+
+// Removes all includes from the script, including the ones in conditional blocks.
+pub fn take_includes(script: &mut ast::Script) -> Vec<ConditionalInclude> {
+    fn take(
+        script: &mut ast::Script,
+        branch: &mut Branch,
+        conditions: &mut Vec<(usize, usize, bool)>,
+        includes: &mut Vec<ConditionalInclude>,
+    ) {
+        for include in std::mem::take(&mut script.includes) {
+            includes.push(ConditionalInclude {
+                include,
+                branch: branch.clone(),
+                conditions: conditions.clone(),
+            });
+        }
+        for (index, conditional) in script.conditionals.iter_mut().enumerate() {
+            let span = &conditional.span;
+            for (value, script) in [
+                (true, &mut conditional.if_true),
+                (false, &mut conditional.if_false),
+            ] {
+                branch.push((index, value));
+                conditions.push((span.0, span.1.start, value));
+                take(script, branch, conditions, includes);
+                branch.pop();
+                conditions.pop();
+            }
+        }
+    }
+
+    let mut includes = Vec::new();
+    take(script, &mut Vec::new(), &mut Vec::new(), &mut includes);
+    includes
+}
+
+pub fn branch_mut<'a>(
+    script: &'a mut ast::Script,
+    branch: &[(usize, bool)],
+) -> &'a mut ast::Script {
+    branch.iter().fold(script, |script, &(index, value)| {
+        let conditional = &mut script.conditionals[index];
+        if value {
+            &mut conditional.if_true
+        } else {
+            &mut conditional.if_false
+        }
+    })
+}
@@ -0,0 +1,1424 @@
+// A WebAssembly interpreter for the modules emitted by curlywas, used to run tests.
+//
+//     let module = Module::new(&wasm)?;
+//     let mut imports = Imports::new();
+//     imports.function("env", "print", |memory, args| { ...; Ok(None) });
+//     let mut instance = Instance::new(&module, imports)?;
+//     instance.call("upd", &[])?;
+//     assert_eq!(instance.memory()[0x100], 7);
+//
+// It supports the MVP instructions plus non-trapping float-to-int conversions, sign extension
+// and bulk memory copy/fill, but no tables, passive data segments, multi-memory or SIMD.
+
+use std::{collections::HashMap, fmt};
+
+use anyhow::{bail, Result};
+use wasmparser::{
+    DataKind, ExternalKind, FuncType, ImportSectionEntryType, Operator, Parser, Payload, Type,
+    TypeDef, TypeOrFuncType,
+};
+
+const PAGE_SIZE: usize = 65536;
+const MAX_PAGES: u64 = 65536;
+const MAX_CALL_DEPTH: usize = 10000;
+const NONE: usize = usize::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+impl Value {
+    fn from_bits(bits: u64, type_: Type) -> Value {
+        match type_ {
+            Type::I32 => Value::I32(bits as i32),
+            Type::I64 => Value::I64(bits as i64),
+            Type::F32 => Value::F32(f32::from_bits(bits as u32)),
+            Type::F64 => Value::F64(f64::from_bits(bits)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn to_bits(self) -> u64 {
+        match self {
+            Value::I32(v) => v as u32 as u64,
+            Value::I64(v) => v as u64,
+            Value::F32(v) => v.to_bits() as u64,
+            Value::F64(v) => v.to_bits(),
+        }
+    }
+
+    fn type_(self) -> Type {
+        match self {
+            Value::I32(_) => Type::I32,
+            Value::I64(_) => Type::I64,
+            Value::F32(_) => Type::F32,
+            Value::F64(_) => Type::F64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trap {
+    Unreachable,
+    DivisionByZero,
+    IntegerOverflow,
+    InvalidConversion,
+    OutOfBounds,
+    StackOverflow,
+    // an imported function without an implementation was called
+    MissingImport(String),
+    // raised by a host function, or the host calling into the module incorrectly
+    Host(String),
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Trap::Unreachable => write!(f, "unreachable executed"),
+            Trap::DivisionByZero => write!(f, "integer division by zero"),
+            Trap::IntegerOverflow => write!(f, "integer overflow"),
+            Trap::InvalidConversion => write!(f, "invalid conversion to integer"),
+            Trap::OutOfBounds => write!(f, "out of bounds memory access"),
+            Trap::StackOverflow => write!(f, "call stack exhausted"),
+            Trap::MissingImport(name) => write!(f, "called missing import {}", name),
+            Trap::Host(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Trap {}
+
+pub type HostFunction<'h> = Box<dyn FnMut(&mut [u8], &[Value]) -> Result<Option<Value>, Trap> + 'h>;
+
+// Functions and globals provided to the module, by module and field name.
+#[derive(Default)]
+pub struct Imports<'h> {
+    functions: HashMap<(String, String), HostFunction<'h>>,
+    globals: HashMap<(String, String), Value>,
+}
+
+impl<'h> Imports<'h> {
+    pub fn new() -> Imports<'h> {
+        Imports::default()
+    }
+
+    pub fn function(
+        &mut self,
+        module: &str,
+        name: &str,
+        function: impl FnMut(&mut [u8], &[Value]) -> Result<Option<Value>, Trap> + 'h,
+    ) -> &mut Self {
+        self.functions
+            .insert((module.to_string(), name.to_string()), Box::new(function));
+        self
+    }
+
+    pub fn global(&mut self, module: &str, name: &str, value: Value) -> &mut Self {
+        self.globals
+            .insert((module.to_string(), name.to_string()), value);
+        self
+    }
+}
+
+struct Code<'a> {
+    locals: Vec<Type>,
+    ops: Vec<Operator<'a>>,
+    // for each block, loop, if and else the index of the matching end
+    ends: Vec<usize>,
+    // for each if the index of its else
+    elses: Vec<usize>,
+}
+
+// A parsed and validated module, which can be instantiated any number of times.
+pub struct Module<'a> {
+    types: Vec<FuncType>,
+    // the type index of each function, imported functions first
+    functions: Vec<u32>,
+    imported_functions: Vec<(String, String)>,
+    imported_globals: Vec<((String, String), Type)>,
+    globals: Vec<(Type, Operator<'a>)>,
+    exports: HashMap<String, u32>,
+    memory: Option<(u64, Option<u64>)>,
+    start: Option<u32>,
+    data: Vec<(Operator<'a>, &'a [u8])>,
+    code: Vec<Code<'a>>,
+}
+
+impl<'a> Module<'a> {
+    pub fn new(wasm: &'a [u8]) -> Result<Module<'a>> {
+        wasmparser::validate(wasm)?;
+
+        let mut module = Module {
+            types: Vec::new(),
+            functions: Vec::new(),
+            imported_functions: Vec::new(),
+            imported_globals: Vec::new(),
+            globals: Vec::new(),
+            exports: HashMap::new(),
+            memory: None,
+            start: None,
+            data: Vec::new(),
+            code: Vec::new(),
+        };
+
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload? {
+                Payload::TypeSection(reader) => {
+                    for type_ in reader {
+                        match type_? {
+                            TypeDef::Func(type_) => module.types.push(type_),
+                            _ => bail!("Unsupported type"),
+                        }
+                    }
+                }
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        let import = import?;
+                        let name = (
+                            import.module.to_string(),
+                            import.field.unwrap_or("").to_string(),
+                        );
+                        match import.ty {
+                            ImportSectionEntryType::Function(type_) => {
+                                module.functions.push(type_);
+                                module.imported_functions.push(name);
+                            }
+                            ImportSectionEntryType::Global(type_) => {
+                                module.imported_globals.push((name, type_.content_type))
+                            }
+                            ImportSectionEntryType::Memory(memory) => {
+                                module.memory = Some((memory.initial, memory.maximum))
+                            }
+                            _ => bail!("Unsupported import {}.{}", name.0, name.1),
+                        }
+                    }
+                }
+                Payload::FunctionSection(reader) => {
+                    for type_ in reader {
+                        module.functions.push(type_?);
+                    }
+                }
+                Payload::MemorySection(reader) => {
+                    for memory in reader {
+                        let memory = memory?;
+                        module.memory = Some((memory.initial, memory.maximum));
+                    }
+                }
+                Payload::GlobalSection(reader) => {
+                    for global in reader {
+                        let global = global?;
+                        let init = const_operator(global.init_expr.get_operators_reader())?;
+                        module.globals.push((global.ty.content_type, init));
+                    }
+                }
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export?;
+                        if let ExternalKind::Function = export.kind {
+                            module
+                                .exports
+                                .insert(export.field.to_string(), export.index);
+                        }
+                    }
+                }
+                Payload::StartSection { func, .. } => module.start = Some(func),
+                Payload::DataSection(reader) => {
+                    for data in reader {
+                        let data = data?;
+                        match data.kind {
+                            DataKind::Active { init_expr, .. } => {
+                                let offset = const_operator(init_expr.get_operators_reader())?;
+                                module.data.push((offset, data.data));
+                            }
+                            DataKind::Passive => bail!("Unsupported passive data segment"),
+                        }
+                    }
+                }
+                Payload::CodeSectionEntry(body) => {
+                    let mut locals = Vec::new();
+                    for local in body.get_locals_reader()? {
+                        let (count, type_) = local?;
+                        locals.extend(std::iter::repeat_n(type_, count as usize));
+                    }
+                    let ops = body
+                        .get_operators_reader()?
+                        .into_iter()
+                        .collect::<Result<Vec<_>, _>>()?;
+                    module.code.push(Code::new(locals, ops));
+                }
+                Payload::TableSection(_)
+                | Payload::ElementSection(_)
+                | Payload::TagSection(_)
+                | Payload::ModuleSectionStart { .. }
+                | Payload::InstanceSection(_)
+                | Payload::AliasSection(_) => bail!("Unsupported section"),
+                _ => (),
+            }
+        }
+
+        Ok(module)
+    }
+
+    fn function_type(&self, index: u32) -> &FuncType {
+        &self.types[self.functions[index as usize] as usize]
+    }
+
+    // parameter and result count of a block
+    fn block_arity(&self, type_: TypeOrFuncType) -> (usize, usize) {
+        match type_ {
+            TypeOrFuncType::Type(Type::EmptyBlockType) => (0, 0),
+            TypeOrFuncType::Type(_) => (0, 1),
+            TypeOrFuncType::FuncType(index) => {
+                let type_ = &self.types[index as usize];
+                (type_.params.len(), type_.returns.len())
+            }
+        }
+    }
+}
+
+fn const_operator(reader: wasmparser::OperatorsReader) -> Result<Operator> {
+    let ops = reader.into_iter().collect::<Result<Vec<_>, _>>()?;
+    match ops.as_slice() {
+        [op, Operator::End] => Ok(op.clone()),
+        _ => bail!("Unsupported init expression"),
+    }
+}
+
+impl<'a> Code<'a> {
+    fn new(locals: Vec<Type>, ops: Vec<Operator<'a>>) -> Code<'a> {
+        let mut ends = vec![NONE; ops.len()];
+        let mut elses = vec![NONE; ops.len()];
+        let mut open = Vec::new();
+        for (index, op) in ops.iter().enumerate() {
+            match op {
+                Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
+                    open.push(index)
+                }
+                Operator::Else => elses[*open.last().unwrap()] = index,
+                Operator::End => {
+                    if let Some(start) = open.pop() {
+                        ends[start] = index;
+                        if elses[start] != NONE {
+                            ends[elses[start]] = index;
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        Code {
+            locals,
+            ops,
+            ends,
+            elses,
+        }
+    }
+}
+
+struct Frame {
+    code: usize,
+    pc: usize,
+    locals: Vec<u64>,
+    // index of the label of the function body
+    label: usize,
+}
+
+struct Label {
+    target: usize,
+    height: usize,
+    arity: usize,
+    // branching to a loop continues it, so its label stays
+    is_loop: bool,
+}
+
+// An instance of a module with its own memory and globals.
+pub struct Instance<'a, 'h> {
+    module: &'a Module<'a>,
+    host_functions: Vec<Option<HostFunction<'h>>>,
+    globals: Vec<u64>,
+    memory: Vec<u8>,
+    max_pages: u64,
+}
+
+impl<'a, 'h> Instance<'a, 'h> {
+    // Initializes memory and globals and runs the start function. Imported functions
+    // not provided trap when they are called, imported globals not provided are zero.
+    pub fn new(module: &'a Module<'a>, mut imports: Imports<'h>) -> Result<Instance<'a, 'h>, Trap> {
+        let host_functions = module
+            .imported_functions
+            .iter()
+            .map(|name| imports.functions.remove(name))
+            .collect();
+        let mut globals = Vec::new();
+        for (name, type_) in &module.imported_globals {
+            let value = imports.globals.get(name).copied();
+            if value.is_some_and(|value| value.type_() != *type_) {
+                return Err(Trap::Host(format!(
+                    "Imported global {}.{} has the wrong type",
+                    name.0, name.1
+                )));
+            }
+            globals.push(value.map_or(0, Value::to_bits));
+        }
+        let (pages, max_pages) = module.memory.unwrap_or((0, Some(0)));
+        let mut instance = Instance {
+            module,
+            host_functions,
+            globals,
+            memory: vec![0; pages as usize * PAGE_SIZE],
+            max_pages: max_pages.unwrap_or(MAX_PAGES).min(MAX_PAGES),
+        };
+
+        for (_, init) in &module.globals {
+            let value = instance.eval_const(init);
+            instance.globals.push(value);
+        }
+        for (offset, data) in &module.data {
+            let offset = instance.eval_const(offset) as u32 as u64;
+            instance.check_range(offset, data.len() as u64)?;
+            instance.memory[offset as usize..][..data.len()].copy_from_slice(data);
+        }
+        if let Some(start) = module.start {
+            instance.call_index(start, &[])?;
+        }
+
+        Ok(instance)
+    }
+
+    // Calls an exported function.
+    pub fn call(&mut self, name: &str, args: &[Value]) -> Result<Option<Value>, Trap> {
+        match self.module.exports.get(name) {
+            Some(&index) => self.call_index(index, args),
+            None => Err(Trap::Host(format!("No exported function {}", name))),
+        }
+    }
+
+    // Calls a function by its index, imported functions first.
+    pub fn call_index(&mut self, index: u32, args: &[Value]) -> Result<Option<Value>, Trap> {
+        if index as usize >= self.module.functions.len() {
+            return Err(Trap::Host(format!("No function with index {}", index)));
+        }
+        let type_ = self.module.function_type(index);
+        if !args
+            .iter()
+            .map(|arg| arg.type_())
+            .eq(type_.params.iter().copied())
+        {
+            return Err(Trap::Host(format!(
+                "Wrong arguments for function {}",
+                index
+            )));
+        }
+        let mut stack: Vec<u64> = args.iter().map(|arg| arg.to_bits()).collect();
+        self.execute(index, &mut stack)?;
+        Ok(type_
+            .returns
+            .first()
+            .map(|&type_| Value::from_bits(stack[0], type_)))
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    fn eval_const(&self, op: &Operator) -> u64 {
+        match *op {
+            Operator::I32Const { value } => value as u32 as u64,
+            Operator::I64Const { value } => value as u64,
+            Operator::F32Const { value } => value.bits() as u64,
+            Operator::F64Const { value } => value.bits(),
+            Operator::GlobalGet { global_index } => self.globals[global_index as usize],
+            _ => unreachable!(),
+        }
+    }
+
+    fn check_range(&self, address: u64, len: u64) -> Result<usize, Trap> {
+        if address + len > self.memory.len() as u64 {
+            return Err(Trap::OutOfBounds);
+        }
+        Ok(address as usize)
+    }
+
+    fn load<const N: usize>(&self, stack: &mut Vec<u64>, offset: u64) -> Result<[u8; N], Trap> {
+        let address = pop::<u32>(stack) as u64 + offset;
+        let address = self.check_range(address, N as u64)?;
+        Ok(self.memory[address..address + N].try_into().unwrap())
+    }
+
+    fn store<const N: usize>(
+        &mut self,
+        stack: &mut Vec<u64>,
+        offset: u64,
+        value: [u8; N],
+    ) -> Result<(), Trap> {
+        let address = pop::<u32>(stack) as u64 + offset;
+        let address = self.check_range(address, N as u64)?;
+        self.memory[address..address + N].copy_from_slice(&value);
+        Ok(())
+    }
+
+    // Calls a host function right away, or pushes a frame for a function of the module.
+    fn enter(
+        &mut self,
+        index: u32,
+        stack: &mut Vec<u64>,
+        frames: &mut Vec<Frame>,
+        labels: &mut Vec<Label>,
+    ) -> Result<(), Trap> {
+        let module = self.module;
+        let type_ = module.function_type(index);
+        let args_start = stack.len() - type_.params.len();
+        let imported = module.imported_functions.len();
+        if (index as usize) < imported {
+            let args: Vec<Value> = stack
+                .drain(args_start..)
+                .zip(type_.params.iter())
+                .map(|(bits, &type_)| Value::from_bits(bits, type_))
+                .collect();
+            let (module_name, field) = &module.imported_functions[index as usize];
+            let function = match self.host_functions[index as usize] {
+                Some(ref mut function) => function,
+                None => return Err(Trap::MissingImport(format!("{}.{}", module_name, field))),
+            };
+            let result = function(&mut self.memory, &args)?;
+            if result.map(Value::type_) != type_.returns.first().copied() {
+                return Err(Trap::Host(format!(
+                    "Host function {}.{} returned the wrong type",
+                    module_name, field
+                )));
+            }
+            stack.extend(result.map(Value::to_bits));
+            return Ok(());
+        }
+
+        if frames.len() >= MAX_CALL_DEPTH {
+            return Err(Trap::StackOverflow);
+        }
+        let code_index = index as usize - imported;
+        let code = &module.code[code_index];
+        let mut locals = stack.split_off(args_start);
+        locals.resize(locals.len() + code.locals.len(), 0);
+        labels.push(Label {
+            target: code.ops.len(),
+            height: stack.len(),
+            arity: type_.returns.len(),
+            is_loop: false,
+        });
+        frames.push(Frame {
+            code: code_index,
+            pc: 0,
+            locals,
+            label: labels.len() - 1,
+        });
+        Ok(())
+    }
+
+    fn execute(&mut self, index: u32, stack: &mut Vec<u64>) -> Result<(), Trap> {
+        let module = self.module;
+        let mut frames = Vec::new();
+        let mut labels = Vec::new();
+        self.enter(index, stack, &mut frames, &mut labels)?;
+
+        while let Some(frame) = frames.last_mut() {
+            let code = &module.code[frame.code];
+            let Some(op) = code.ops.get(frame.pc) else {
+                frames.pop();
+                continue;
+            };
+            let pc = frame.pc;
+            frame.pc += 1;
+
+            use Operator as O;
+            match *op {
+                O::Unreachable => return Err(Trap::Unreachable),
+                O::Nop => (),
+                O::Block { ty } => {
+                    let (params, results) = module.block_arity(ty);
+                    labels.push(Label {
+                        target: code.ends[pc] + 1,
+                        height: stack.len() - params,
+                        arity: results,
+                        is_loop: false,
+                    });
+                }
+                O::Loop { ty } => {
+                    let (params, _) = module.block_arity(ty);
+                    labels.push(Label {
+                        target: pc + 1,
+                        height: stack.len() - params,
+                        arity: params,
+                        is_loop: true,
+                    });
+                }
+                O::If { ty } => {
+                    let condition = pop::<i32>(stack);
+                    let (params, results) = module.block_arity(ty);
+                    let label = Label {
+                        target: code.ends[pc] + 1,
+                        height: stack.len() - params,
+                        arity: results,
+                        is_loop: false,
+                    };
+                    if condition != 0 {
+                        labels.push(label);
+                    } else if code.elses[pc] != NONE {
+                        labels.push(label);
+                        frame.pc = code.elses[pc] + 1;
+                    } else {
+                        frame.pc = code.ends[pc] + 1;
+                    }
+                }
+                O::Else => {
+                    labels.pop();
+                    frame.pc = code.ends[pc] + 1;
+                }
+                O::End => {
+                    labels.pop();
+                }
+                O::Br { relative_depth } => {
+                    frame.pc = branch(stack, &mut labels, relative_depth as usize)
+                }
+                O::BrIf { relative_depth } => {
+                    if pop::<i32>(stack) != 0 {
+                        frame.pc = branch(stack, &mut labels, relative_depth as usize);
+                    }
+                }
+                O::BrTable { ref table } => {
+                    let index = pop::<u32>(stack);
+                    let depth = table
+                        .targets()
+                        .nth(index as usize)
+                        .map_or(Ok(table.default()), |target| target)
+                        .unwrap();
+                    frame.pc = branch(stack, &mut labels, depth as usize);
+                }
+                O::Return => {
+                    let depth = labels.len() - 1 - frame.label;
+                    frame.pc = branch(stack, &mut labels, depth);
+                }
+                O::Call { function_index } => {
+                    self.enter(function_index, stack, &mut frames, &mut labels)?
+                }
+                O::Drop => {
+                    stack.pop();
+                }
+                O::Select | O::TypedSelect { .. } => {
+                    let condition = pop::<i32>(stack);
+                    let if_false = stack.pop().unwrap();
+                    let if_true = stack.pop().unwrap();
+                    stack.push(if condition != 0 { if_true } else { if_false });
+                }
+                O::LocalGet { local_index } => stack.push(frame.locals[local_index as usize]),
+                O::LocalSet { local_index } => {
+                    frame.locals[local_index as usize] = stack.pop().unwrap()
+                }
+                O::LocalTee { local_index } => {
+                    frame.locals[local_index as usize] = *stack.last().unwrap()
+                }
+                O::GlobalGet { global_index } => stack.push(self.globals[global_index as usize]),
+                O::GlobalSet { global_index } => {
+                    self.globals[global_index as usize] = stack.pop().unwrap()
+                }
+
+                O::I32Load { memarg } => {
+                    let v = i32::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v)
+                }
+                O::I64Load { memarg } => {
+                    let v = i64::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v)
+                }
+                O::F32Load { memarg } => {
+                    let v = f32::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v)
+                }
+                O::F64Load { memarg } => {
+                    let v = f64::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v)
+                }
+                O::I32Load8S { memarg } => {
+                    let v = i8::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v as i32)
+                }
+                O::I32Load8U { memarg } => {
+                    let v = u8::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v as i32)
+                }
+                O::I32Load16S { memarg } => {
+                    let v = i16::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v as i32)
+                }
+                O::I32Load16U { memarg } => {
+                    let v = u16::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v as i32)
+                }
+                O::I64Load8S { memarg } => {
+                    let v = i8::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v as i64)
+                }
+                O::I64Load8U { memarg } => {
+                    let v = u8::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v as i64)
+                }
+                O::I64Load16S { memarg } => {
+                    let v = i16::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v as i64)
+                }
+                O::I64Load16U { memarg } => {
+                    let v = u16::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v as i64)
+                }
+                O::I64Load32S { memarg } => {
+                    let v = i32::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v as i64)
+                }
+                O::I64Load32U { memarg } => {
+                    let v = u32::from_le_bytes(self.load(stack, memarg.offset)?);
+                    push(stack, v as i64)
+                }
+                O::I32Store { memarg } => {
+                    let v = pop::<i32>(stack);
+                    self.store(stack, memarg.offset, v.to_le_bytes())?
+                }
+                O::I64Store { memarg } => {
+                    let v = pop::<i64>(stack);
+                    self.store(stack, memarg.offset, v.to_le_bytes())?
+                }
+                O::F32Store { memarg } => {
+                    let v = pop::<f32>(stack);
+                    self.store(stack, memarg.offset, v.to_le_bytes())?
+                }
+                O::F64Store { memarg } => {
+                    let v = pop::<f64>(stack);
+                    self.store(stack, memarg.offset, v.to_le_bytes())?
+                }
+                O::I32Store8 { memarg } | O::I64Store8 { memarg } => {
+                    let v = pop::<i64>(stack) as u8;
+                    self.store(stack, memarg.offset, v.to_le_bytes())?
+                }
+                O::I32Store16 { memarg } | O::I64Store16 { memarg } => {
+                    let v = pop::<i64>(stack) as u16;
+                    self.store(stack, memarg.offset, v.to_le_bytes())?
+                }
+                O::I64Store32 { memarg } => {
+                    let v = pop::<i64>(stack) as u32;
+                    self.store(stack, memarg.offset, v.to_le_bytes())?
+                }
+                O::MemorySize { .. } => push(stack, (self.memory.len() / PAGE_SIZE) as i32),
+                O::MemoryGrow { .. } => {
+                    let delta = pop::<u32>(stack) as u64;
+                    let pages = (self.memory.len() / PAGE_SIZE) as u64;
+                    if pages + delta > self.max_pages {
+                        push(stack, -1i32);
+                    } else {
+                        self.memory.resize((pages + delta) as usize * PAGE_SIZE, 0);
+                        push(stack, pages as i32);
+                    }
+                }
+                O::MemoryCopy { .. } => {
+                    let len = pop::<u32>(stack) as u64;
+                    let src = self.check_range(pop::<u32>(stack) as u64, len)?;
+                    let dst = self.check_range(pop::<u32>(stack) as u64, len)?;
+                    self.memory.copy_within(src..src + len as usize, dst);
+                }
+                O::MemoryFill { .. } => {
+                    let len = pop::<u32>(stack) as u64;
+                    let value = pop::<u32>(stack) as u8;
+                    let dst = self.check_range(pop::<u32>(stack) as u64, len)?;
+                    self.memory[dst..dst + len as usize].fill(value);
+                }
+
+                O::I32Const { value } => push(stack, value),
+                O::I64Const { value } => push(stack, value),
+                O::F32Const { value } => stack.push(value.bits() as u64),
+                O::F64Const { value } => stack.push(value.bits()),
+
+                O::I32Eqz => unop(stack, |a: i32| (a == 0) as i32),
+                O::I32Eq => binop(stack, |a: i32, b| (a == b) as i32),
+                O::I32Ne => binop(stack, |a: i32, b| (a != b) as i32),
+                O::I32LtS => binop(stack, |a: i32, b| (a < b) as i32),
+                O::I32LtU => binop(stack, |a: u32, b| (a < b) as i32),
+                O::I32GtS => binop(stack, |a: i32, b| (a > b) as i32),
+                O::I32GtU => binop(stack, |a: u32, b| (a > b) as i32),
+                O::I32LeS => binop(stack, |a: i32, b| (a <= b) as i32),
+                O::I32LeU => binop(stack, |a: u32, b| (a <= b) as i32),
+                O::I32GeS => binop(stack, |a: i32, b| (a >= b) as i32),
+                O::I32GeU => binop(stack, |a: u32, b| (a >= b) as i32),
+                O::I64Eqz => unop(stack, |a: i64| (a == 0) as i32),
+                O::I64Eq => binop(stack, |a: i64, b| (a == b) as i32),
+                O::I64Ne => binop(stack, |a: i64, b| (a != b) as i32),
+                O::I64LtS => binop(stack, |a: i64, b| (a < b) as i32),
+                O::I64LtU => binop(stack, |a: u64, b| (a < b) as i32),
+                O::I64GtS => binop(stack, |a: i64, b| (a > b) as i32),
+                O::I64GtU => binop(stack, |a: u64, b| (a > b) as i32),
+                O::I64LeS => binop(stack, |a: i64, b| (a <= b) as i32),
+                O::I64LeU => binop(stack, |a: u64, b| (a <= b) as i32),
+                O::I64GeS => binop(stack, |a: i64, b| (a >= b) as i32),
+                O::I64GeU => binop(stack, |a: u64, b| (a >= b) as i32),
+                O::F32Eq => binop(stack, |a: f32, b| (a == b) as i32),
+                O::F32Ne => binop(stack, |a: f32, b| (a != b) as i32),
+                O::F32Lt => binop(stack, |a: f32, b| (a < b) as i32),
+                O::F32Gt => binop(stack, |a: f32, b| (a > b) as i32),
+                O::F32Le => binop(stack, |a: f32, b| (a <= b) as i32),
+                O::F32Ge => binop(stack, |a: f32, b| (a >= b) as i32),
+                O::F64Eq => binop(stack, |a: f64, b| (a == b) as i32),
+                O::F64Ne => binop(stack, |a: f64, b| (a != b) as i32),
+                O::F64Lt => binop(stack, |a: f64, b| (a < b) as i32),
+                O::F64Gt => binop(stack, |a: f64, b| (a > b) as i32),
+                O::F64Le => binop(stack, |a: f64, b| (a <= b) as i32),
+                O::F64Ge => binop(stack, |a: f64, b| (a >= b) as i32),
+
+                O::I32Clz => unop(stack, |a: i32| a.leading_zeros() as i32),
+                O::I32Ctz => unop(stack, |a: i32| a.trailing_zeros() as i32),
+                O::I32Popcnt => unop(stack, |a: i32| a.count_ones() as i32),
+                O::I32Add => binop(stack, i32::wrapping_add),
+                O::I32Sub => binop(stack, i32::wrapping_sub),
+                O::I32Mul => binop(stack, i32::wrapping_mul),
+                O::I32DivS => try_binop(stack, |a: i32, b| match (a, b) {
+                    (_, 0) => Err(Trap::DivisionByZero),
+                    (i32::MIN, -1) => Err(Trap::IntegerOverflow),
+                    _ => Ok(a / b),
+                })?,
+                O::I32DivU => try_binop(stack, |a: u32, b| {
+                    a.checked_div(b).ok_or(Trap::DivisionByZero)
+                })?,
+                O::I32RemS => try_binop(stack, |a: i32, b| {
+                    a.checked_rem(b)
+                        .or_else(|| (b != 0).then_some(0))
+                        .ok_or(Trap::DivisionByZero)
+                })?,
+                O::I32RemU => try_binop(stack, |a: u32, b| {
+                    a.checked_rem(b).ok_or(Trap::DivisionByZero)
+                })?,
+                O::I32And => binop(stack, |a: i32, b| a & b),
+                O::I32Or => binop(stack, |a: i32, b| a | b),
+                O::I32Xor => binop(stack, |a: i32, b| a ^ b),
+                O::I32Shl => binop(stack, |a: i32, b| a.wrapping_shl(b as u32)),
+                O::I32ShrS => binop(stack, |a: i32, b| a.wrapping_shr(b as u32)),
+                O::I32ShrU => binop(stack, |a: u32, b| a.wrapping_shr(b)),
+                O::I32Rotl => binop(stack, |a: u32, b| a.rotate_left(b)),
+                O::I32Rotr => binop(stack, |a: u32, b| a.rotate_right(b)),
+
+                O::I64Clz => unop(stack, |a: i64| a.leading_zeros() as i64),
+                O::I64Ctz => unop(stack, |a: i64| a.trailing_zeros() as i64),
+                O::I64Popcnt => unop(stack, |a: i64| a.count_ones() as i64),
+                O::I64Add => binop(stack, i64::wrapping_add),
+                O::I64Sub => binop(stack, i64::wrapping_sub),
+                O::I64Mul => binop(stack, i64::wrapping_mul),
+                O::I64DivS => try_binop(stack, |a: i64, b| match (a, b) {
+                    (_, 0) => Err(Trap::DivisionByZero),
+                    (i64::MIN, -1) => Err(Trap::IntegerOverflow),
+                    _ => Ok(a / b),
+                })?,
+                O::I64DivU => try_binop(stack, |a: u64, b| {
+                    a.checked_div(b).ok_or(Trap::DivisionByZero)
+                })?,
+                O::I64RemS => try_binop(stack, |a: i64, b| {
+                    a.checked_rem(b)
+                        .or_else(|| (b != 0).then_some(0))
+                        .ok_or(Trap::DivisionByZero)
+                })?,
+                O::I64RemU => try_binop(stack, |a: u64, b| {
+                    a.checked_rem(b).ok_or(Trap::DivisionByZero)
+                })?,
+                O::I64And => binop(stack, |a: i64, b| a & b),
+                O::I64Or => binop(stack, |a: i64, b| a | b),
+                O::I64Xor => binop(stack, |a: i64, b| a ^ b),
+                O::I64Shl => binop(stack, |a: i64, b| a.wrapping_shl(b as u32)),
+                O::I64ShrS => binop(stack, |a: i64, b| a.wrapping_shr(b as u32)),
+                O::I64ShrU => binop(stack, |a: u64, b| a.wrapping_shr(b as u32)),
+                O::I64Rotl => binop(stack, |a: u64, b| a.rotate_left((b % 64) as u32)),
+                O::I64Rotr => binop(stack, |a: u64, b| a.rotate_right((b % 64) as u32)),
+
+                O::F32Abs => unop(stack, f32::abs),
+                O::F32Neg => unop(stack, |a: f32| -a),
+                O::F32Ceil => unop(stack, f32::ceil),
+                O::F32Floor => unop(stack, f32::floor),
+                O::F32Trunc => unop(stack, f32::trunc),
+                O::F32Nearest => unop(stack, f32::round_ties_even),
+                O::F32Sqrt => unop(stack, f32::sqrt),
+                O::F32Add => binop(stack, |a: f32, b| a + b),
+                O::F32Sub => binop(stack, |a: f32, b| a - b),
+                O::F32Mul => binop(stack, |a: f32, b| a * b),
+                O::F32Div => binop(stack, |a: f32, b| a / b),
+                O::F32Min => binop(stack, |a: f32, b| {
+                    if a.is_nan() || b.is_nan() {
+                        a + b
+                    } else if a == b {
+                        f32::from_bits(a.to_bits() | b.to_bits())
+                    } else {
+                        a.min(b)
+                    }
+                }),
+                O::F32Max => binop(stack, |a: f32, b| {
+                    if a.is_nan() || b.is_nan() {
+                        a + b
+                    } else if a == b {
+                        f32::from_bits(a.to_bits() & b.to_bits())
+                    } else {
+                        a.max(b)
+                    }
+                }),
+                O::F32Copysign => binop(stack, f32::copysign),
+
+                O::F64Abs => unop(stack, f64::abs),
+                O::F64Neg => unop(stack, |a: f64| -a),
+                O::F64Ceil => unop(stack, f64::ceil),
+                O::F64Floor => unop(stack, f64::floor),
+                O::F64Trunc => unop(stack, f64::trunc),
+                O::F64Nearest => unop(stack, f64::round_ties_even),
+                O::F64Sqrt => unop(stack, f64::sqrt),
+                O::F64Add => binop(stack, |a: f64, b| a + b),
+                O::F64Sub => binop(stack, |a: f64, b| a - b),
+                O::F64Mul => binop(stack, |a: f64, b| a * b),
+                O::F64Div => binop(stack, |a: f64, b| a / b),
+                O::F64Min => binop(stack, |a: f64, b| {
+                    if a.is_nan() || b.is_nan() {
+                        a + b
+                    } else if a == b {
+                        f64::from_bits(a.to_bits() | b.to_bits())
+                    } else {
+                        a.min(b)
+                    }
+                }),
+                O::F64Max => binop(stack, |a: f64, b| {
+                    if a.is_nan() || b.is_nan() {
+                        a + b
+                    } else if a == b {
+                        f64::from_bits(a.to_bits() & b.to_bits())
+                    } else {
+                        a.max(b)
+                    }
+                }),
+                O::F64Copysign => binop(stack, f64::copysign),
+
+                O::I32WrapI64 => unop(stack, |a: i64| a as i32),
+                O::I64ExtendI32S => unop(stack, |a: i32| a as i64),
+                O::I64ExtendI32U => unop(stack, |a: u32| a as i64),
+                O::I32Extend8S => unop(stack, |a: i32| a as i8 as i32),
+                O::I32Extend16S => unop(stack, |a: i32| a as i16 as i32),
+                O::I64Extend8S => unop(stack, |a: i64| a as i8 as i64),
+                O::I64Extend16S => unop(stack, |a: i64| a as i16 as i64),
+                O::I64Extend32S => unop(stack, |a: i64| a as i32 as i64),
+
+                O::I32TruncF32S => try_unop(stack, |a: f32| {
+                    Ok(trunc(a as f64, -2f64.powi(31), 2f64.powi(31))? as i32)
+                })?,
+                O::I32TruncF32U => try_unop(stack, |a: f32| {
+                    Ok(trunc(a as f64, 0.0, 2f64.powi(32))? as u32)
+                })?,
+                O::I32TruncF64S => try_unop(stack, |a: f64| {
+                    Ok(trunc(a, -2f64.powi(31), 2f64.powi(31))? as i32)
+                })?,
+                O::I32TruncF64U => {
+                    try_unop(stack, |a: f64| Ok(trunc(a, 0.0, 2f64.powi(32))? as u32))?
+                }
+                O::I64TruncF32S => try_unop(stack, |a: f32| {
+                    Ok(trunc(a as f64, -2f64.powi(63), 2f64.powi(63))? as i64)
+                })?,
+                O::I64TruncF32U => try_unop(stack, |a: f32| {
+                    Ok(trunc(a as f64, 0.0, 2f64.powi(64))? as u64)
+                })?,
+                O::I64TruncF64S => try_unop(stack, |a: f64| {
+                    Ok(trunc(a, -2f64.powi(63), 2f64.powi(63))? as i64)
+                })?,
+                O::I64TruncF64U => {
+                    try_unop(stack, |a: f64| Ok(trunc(a, 0.0, 2f64.powi(64))? as u64))?
+                }
+                // `as` saturates and converts NaN to 0, just like the _sat instructions
+                O::I32TruncSatF32S => unop(stack, |a: f32| a as i32),
+                O::I32TruncSatF32U => unop(stack, |a: f32| a as u32),
+                O::I32TruncSatF64S => unop(stack, |a: f64| a as i32),
+                O::I32TruncSatF64U => unop(stack, |a: f64| a as u32),
+                O::I64TruncSatF32S => unop(stack, |a: f32| a as i64),
+                O::I64TruncSatF32U => unop(stack, |a: f32| a as u64),
+                O::I64TruncSatF64S => unop(stack, |a: f64| a as i64),
+                O::I64TruncSatF64U => unop(stack, |a: f64| a as u64),
+                O::F32ConvertI32S => unop(stack, |a: i32| a as f32),
+                O::F32ConvertI32U => unop(stack, |a: u32| a as f32),
+                O::F32ConvertI64S => unop(stack, |a: i64| a as f32),
+                O::F32ConvertI64U => unop(stack, |a: u64| a as f32),
+                O::F64ConvertI32S => unop(stack, |a: i32| a as f64),
+                O::F64ConvertI32U => unop(stack, |a: u32| a as f64),
+                O::F64ConvertI64S => unop(stack, |a: i64| a as f64),
+                O::F64ConvertI64U => unop(stack, |a: u64| a as f64),
+                O::F32DemoteF64 => unop(stack, |a: f64| a as f32),
+                O::F64PromoteF32 => unop(stack, |a: f32| a as f64),
+                // values are kept as bits, so reinterpreting them doesn't change anything
+                O::I32ReinterpretF32
+                | O::I64ReinterpretF64
+                | O::F32ReinterpretI32
+                | O::F64ReinterpretI64 => (),
+
+                // tables, exceptions and SIMD are never emitted by curlywas
+                _ => return Err(Trap::Host(format!("Unsupported instruction {:?}", op))),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Moves the results of the block to the height of the label and returns where to continue.
+fn branch(stack: &mut Vec<u64>, labels: &mut Vec<Label>, depth: usize) -> usize {
+    let index = labels.len() - 1 - depth;
+    let label = &labels[index];
+    let results = stack.len() - label.arity;
+    stack.drain(label.height..results);
+    let target = label.target;
+    labels.truncate(index + label.is_loop as usize);
+    target
+}
+
+// the integer part of a float, trapping if it's outside of [min, max)
+fn trunc(value: f64, min: f64, max: f64) -> Result<f64, Trap> {
+    if value.is_nan() {
+        return Err(Trap::InvalidConversion);
+    }
+    let value = value.trunc();
+    if value < min || value >= max {
+        return Err(Trap::IntegerOverflow);
+    }
+    Ok(value)
+}
+
+// Values on the stack are stored as their bits, this converts them to and from their types.
+trait Bits: Copy {
+    fn from_bits(bits: u64) -> Self;
+    fn to_bits(self) -> u64;
+}
+
+macro_rules! int_bits {
+    ($($t:ty => $u:ty),*) => {
+        $(impl Bits for $t {
+            fn from_bits(bits: u64) -> Self {
+                bits as $u as $t
+            }
+            fn to_bits(self) -> u64 {
+                self as $u as u64
+            }
+        })*
+    };
+}
+
+int_bits!(i32 => u32, u32 => u32, i64 => u64, u64 => u64);
+
+impl Bits for f32 {
+    fn from_bits(bits: u64) -> Self {
+        f32::from_bits(bits as u32)
+    }
+    fn to_bits(self) -> u64 {
+        f32::to_bits(self) as u64
+    }
+}
+
+impl Bits for f64 {
+    fn from_bits(bits: u64) -> Self {
+        f64::from_bits(bits)
+    }
+    fn to_bits(self) -> u64 {
+        f64::to_bits(self)
+    }
+}
+
+fn pop<T: Bits>(stack: &mut Vec<u64>) -> T {
+    T::from_bits(stack.pop().unwrap())
+}
+
+fn push<T: Bits>(stack: &mut Vec<u64>, value: T) {
+    stack.push(value.to_bits());
+}
+
+fn unop<T: Bits, R: Bits>(stack: &mut Vec<u64>, f: impl Fn(T) -> R) {
+    let a = pop(stack);
+    push(stack, f(a));
+}
+
+fn binop<T: Bits, R: Bits>(stack: &mut Vec<u64>, f: impl Fn(T, T) -> R) {
+    let b = pop(stack);
+    let a = pop(stack);
+    push(stack, f(a, b));
+}
+
+fn try_unop<T: Bits, R: Bits>(
+    stack: &mut Vec<u64>,
+    f: impl Fn(T) -> Result<R, Trap>,
+) -> Result<(), Trap> {
+    let a = pop(stack);
+    push(stack, f(a)?);
+    Ok(())
+}
+
+fn try_binop<T: Bits, R: Bits>(
+    stack: &mut Vec<u64>,
+    f: impl Fn(T, T) -> Result<R, Trap>,
+) -> Result<(), Trap> {
+    let b = pop(stack);
+    let a = pop(stack);
+    push(stack, f(a, b)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ast, intrinsics::Intrinsics};
+    use wasm_encoder as enc;
+
+    // a module with one page of memory exporting a single function `f`
+    fn module(
+        params: &[ast::Type],
+        result: Option<ast::Type>,
+        body: &[enc::Instruction],
+    ) -> Vec<u8> {
+        let val_type = |type_: &ast::Type| match type_ {
+            ast::Type::I32 => enc::ValType::I32,
+            ast::Type::I64 => enc::ValType::I64,
+            ast::Type::F32 => enc::ValType::F32,
+            ast::Type::F64 => enc::ValType::F64,
+        };
+        let mut module = enc::Module::new();
+        let mut types = enc::TypeSection::new();
+        types.function(params.iter().map(val_type), result.iter().map(val_type));
+        module.section(&types);
+        let mut functions = enc::FunctionSection::new();
+        functions.function(0);
+        module.section(&functions);
+        let mut memories = enc::MemorySection::new();
+        memories.memory(enc::MemoryType {
+            minimum: 1,
+            maximum: None,
+            memory64: false,
+        });
+        module.section(&memories);
+        let mut exports = enc::ExportSection::new();
+        exports.export("f", enc::Export::Function(0));
+        module.section(&exports);
+        let mut function = enc::Function::new(Vec::new());
+        for index in 0..params.len() {
+            function.instruction(&enc::Instruction::LocalGet(index as u32));
+        }
+        for instruction in body {
+            function.instruction(instruction);
+        }
+        function.instruction(&enc::Instruction::End);
+        let mut code = enc::CodeSection::new();
+        code.function(&function);
+        module.section(&code);
+        module.finish()
+    }
+
+    fn one(type_: &ast::Type) -> Value {
+        match type_ {
+            ast::Type::I32 => Value::I32(1),
+            ast::Type::I64 => Value::I64(1),
+            ast::Type::F32 => Value::F32(1.0),
+            ast::Type::F64 => Value::F64(1.0),
+        }
+    }
+
+    fn run(
+        params: &[ast::Type],
+        result: Option<ast::Type>,
+        body: &[enc::Instruction],
+        args: &[Value],
+    ) -> Result<Option<Value>, Trap> {
+        let wasm = module(params, result, body);
+        let module = Module::new(&wasm).unwrap();
+        Instance::new(&module, Imports::new())?.call("f", args)
+    }
+
+    #[test]
+    fn every_intrinsic_is_supported() {
+        let intrinsics = Intrinsics::new();
+        for (name, params, result, instruction) in intrinsics.all() {
+            let wasm = module(params, result, std::slice::from_ref(instruction));
+            let module = Module::new(&wasm).unwrap();
+            let args: Vec<Value> = params.iter().map(one).collect();
+            let value = Instance::new(&module, Imports::new())
+                .unwrap()
+                .call("f", &args);
+            assert!(value.is_ok(), "{} {:?}: {:?}", name, params, value);
+        }
+
+        use ast::Type::*;
+        let loads = [
+            ("i32.load", I32),
+            ("i32.load8_s", I32),
+            ("i32.load8_u", I32),
+            ("i32.load16_s", I32),
+            ("i32.load16_u", I32),
+            ("i64.load", I64),
+            ("i64.load8_s", I64),
+            ("i64.load8_u", I64),
+            ("i64.load16_s", I64),
+            ("i64.load16_u", I64),
+            ("i64.load32_s", I64),
+            ("i64.load32_u", I64),
+            ("f32.load", F32),
+            ("f64.load", F64),
+        ];
+        for (name, type_) in loads {
+            let load = intrinsics.find_load(name).unwrap();
+            let instruction = (load.instruction)(enc::MemArg {
+                offset: 8,
+                align: load.natural_alignment,
+                memory_index: 0,
+            });
+            let value = run(&[I32], Some(type_), &[instruction], &[Value::I32(8)]);
+            assert_eq!(
+                value,
+                Ok(Some(Value::from_bits(0, val_type(type_)))),
+                "{}",
+                name
+            );
+        }
+        let stores = [
+            "i32.store",
+            "i32.store8",
+            "i32.store16",
+            "i64.store",
+            "i64.store8",
+            "i64.store16",
+            "i64.store32",
+            "f32.store",
+            "f64.store",
+        ];
+        for name in stores {
+            let store = intrinsics.find_store(name).unwrap();
+            let instruction = (store.instruction)(enc::MemArg {
+                offset: 8,
+                align: store.natural_alignment,
+                memory_index: 0,
+            });
+            let params = [I32, store.type_];
+            let value = run(
+                &params,
+                None,
+                &[instruction],
+                &[Value::I32(8), one(&store.type_)],
+            );
+            assert_eq!(value, Ok(None), "{}", name);
+        }
+    }
+
+    fn val_type(type_: ast::Type) -> Type {
+        match type_ {
+            ast::Type::I32 => Type::I32,
+            ast::Type::I64 => Type::I64,
+            ast::Type::F32 => Type::F32,
+            ast::Type::F64 => Type::F64,
+        }
+    }
+
+    #[test]
+    fn integer_traps() {
+        use ast::Type::*;
+        use enc::Instruction as I;
+        let i32_op = |op: I<'static>, a: i32, b: i32| {
+            run(
+                &[I32, I32],
+                Some(I32),
+                &[op],
+                &[Value::I32(a), Value::I32(b)],
+            )
+        };
+        assert_eq!(i32_op(I::I32DivS, 1, 0), Err(Trap::DivisionByZero));
+        assert_eq!(i32_op(I::I32DivU, 1, 0), Err(Trap::DivisionByZero));
+        assert_eq!(i32_op(I::I32RemS, 1, 0), Err(Trap::DivisionByZero));
+        assert_eq!(i32_op(I::I32RemU, 1, 0), Err(Trap::DivisionByZero));
+        assert_eq!(i32_op(I::I32DivS, i32::MIN, -1), Err(Trap::IntegerOverflow));
+        assert_eq!(i32_op(I::I32RemS, i32::MIN, -1), Ok(Some(Value::I32(0))));
+        assert_eq!(i32_op(I::I32DivS, -7, 2), Ok(Some(Value::I32(-3))));
+        assert_eq!(i32_op(I::I32DivU, -7, 2), Ok(Some(Value::I32(0x7ffffffc))));
+        assert_eq!(i32_op(I::I32RemS, -7, 2), Ok(Some(Value::I32(-1))));
+        assert_eq!(i32_op(I::I32Shl, 1, 33), Ok(Some(Value::I32(2))));
+        assert_eq!(i32_op(I::I32Rotr, 1, 1), Ok(Some(Value::I32(i32::MIN))));
+
+        let i64_op = |op: I<'static>, a: i64, b: i64| {
+            run(
+                &[I64, I64],
+                Some(I64),
+                &[op],
+                &[Value::I64(a), Value::I64(b)],
+            )
+        };
+        assert_eq!(i64_op(I::I64DivS, 1, 0), Err(Trap::DivisionByZero));
+        assert_eq!(i64_op(I::I64RemU, 1, 0), Err(Trap::DivisionByZero));
+        assert_eq!(i64_op(I::I64DivS, i64::MIN, -1), Err(Trap::IntegerOverflow));
+        assert_eq!(i64_op(I::I64Rotl, 1, 65), Ok(Some(Value::I64(2))));
+
+        let trunc =
+            |op: I<'static>, value: f32| run(&[F32], Some(I32), &[op], &[Value::F32(value)]);
+        assert_eq!(
+            trunc(I::I32TruncF32S, f32::NAN),
+            Err(Trap::InvalidConversion)
+        );
+        assert_eq!(trunc(I::I32TruncF32S, 3e9), Err(Trap::IntegerOverflow));
+        assert_eq!(trunc(I::I32TruncF32U, -1.0), Err(Trap::IntegerOverflow));
+        assert_eq!(trunc(I::I32TruncF32U, -0.5), Ok(Some(Value::I32(0))));
+        assert_eq!(
+            trunc(I::I32TruncF32U, 3e9),
+            Ok(Some(Value::I32(3_000_000_000u32 as i32)))
+        );
+        assert_eq!(
+            trunc(I::I32TruncSatF32S, 3e9),
+            Ok(Some(Value::I32(i32::MAX)))
+        );
+        assert_eq!(trunc(I::I32TruncSatF32U, f32::NAN), Ok(Some(Value::I32(0))));
+    }
+
+    #[test]
+    fn float_semantics() {
+        use ast::Type::*;
+        use enc::Instruction as I;
+        let f32_op = |op: I<'static>, a: f32, b: f32| {
+            run(
+                &[F32, F32],
+                Some(F32),
+                &[op],
+                &[Value::F32(a), Value::F32(b)],
+            )
+        };
+        let bits = |value: Result<Option<Value>, Trap>| match value {
+            Ok(Some(Value::F32(v))) => v.to_bits(),
+            _ => panic!("{:?}", value),
+        };
+        assert_eq!(bits(f32_op(I::F32Min, 0.0, -0.0)), (-0.0f32).to_bits());
+        assert_eq!(bits(f32_op(I::F32Max, -0.0, 0.0)), 0.0f32.to_bits());
+        assert!(f32::from_bits(bits(f32_op(I::F32Min, 1.0, f32::NAN))).is_nan());
+        assert_eq!(bits(f32_op(I::F32Copysign, 2.0, -0.0)), (-2.0f32).to_bits());
+
+        let nearest = |value: f64| run(&[F64], Some(F64), &[I::F64Nearest], &[Value::F64(value)]);
+        assert_eq!(nearest(2.5), Ok(Some(Value::F64(2.0))));
+        assert_eq!(nearest(-3.5), Ok(Some(Value::F64(-4.0))));
+
+        let convert = run(&[I64], Some(F32), &[I::F32ConvertI64U], &[Value::I64(-1)]);
+        assert_eq!(convert, Ok(Some(Value::F32(1.8446744e19))));
+        let reinterpret = run(
+            &[F32],
+            Some(I32),
+            &[I::I32ReinterpretF32],
+            &[Value::F32(-1.0)],
+        );
+        assert_eq!(reinterpret, Ok(Some(Value::I32(0xbf800000u32 as i32))));
+    }
+
+    #[test]
+    fn memory_bounds() {
+        use ast::Type::*;
+        use enc::Instruction as I;
+        let memarg = enc::MemArg {
+            offset: 0,
+            align: 2,
+            memory_index: 0,
+        };
+        let load = |address: i32| {
+            run(
+                &[I32],
+                Some(I32),
+                &[I::I32Load(memarg)],
+                &[Value::I32(address)],
+            )
+        };
+        assert_eq!(load(65532), Ok(Some(Value::I32(0))));
+        assert_eq!(load(65533), Err(Trap::OutOfBounds));
+        assert_eq!(load(-1), Err(Trap::OutOfBounds));
+        let offset = enc::MemArg {
+            offset: 65533,
+            ..memarg
+        };
+        assert_eq!(
+            run(&[I32], Some(I32), &[I::I32Load(offset)], &[Value::I32(0)]),
+            Err(Trap::OutOfBounds)
+        );
+        let store = |address: i32| {
+            run(
+                &[I32, I32],
+                None,
+                &[I::I32Store8(enc::MemArg { align: 0, ..memarg })],
+                &[Value::I32(address), Value::I32(1)],
+            )
+        };
+        assert_eq!(store(65535), Ok(None));
+        assert_eq!(store(65536), Err(Trap::OutOfBounds));
+        let fill = |address: i32, len: i32| {
+            run(
+                &[I32, I32, I32],
+                None,
+                &[I::MemoryFill(0)],
+                &[Value::I32(address), Value::I32(0), Value::I32(len)],
+            )
+        };
+        assert_eq!(fill(65530, 6), Ok(None));
+        assert_eq!(fill(65530, 7), Err(Trap::OutOfBounds));
+        let grow = run(&[I32], Some(I32), &[I::MemoryGrow(0)], &[Value::I32(2)]);
+        assert_eq!(grow, Ok(Some(Value::I32(1))));
+    }
+
+    #[test]
+    fn compiled_script() {
+        let path = std::env::temp_dir().join(format!("curlywas-interp-{}.cwa", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+                import "env.memory" memory(1);
+                import "env.log" fn log(i32, f32) -> i32;
+                import "env.scale" global scale: i32;
+
+                global mut count = 0;
+
+                export fn upd(x: i32) -> i32 {
+                    count += 1;
+                    i32.store(log(x * scale, 0.5), 0x100);
+                    count
+                }
+
+                data 0x200 { i8(1, 2, 3) }
+            "#,
+        )
+        .unwrap();
+        let wasm = crate::compile_file(&path, crate::Options::default()).0;
+        std::fs::remove_file(&path).unwrap();
+        let wasm = wasm.unwrap();
+
+        let module = Module::new(&wasm).unwrap();
+        let mut calls = Vec::new();
+        let mut imports = Imports::new();
+        imports
+            .function("env", "log", |memory, args| {
+                calls.push((args.to_vec(), memory[0x201]));
+                Ok(Some(Value::I32(42)))
+            })
+            .global("env", "scale", Value::I32(3));
+        let mut instance = Instance::new(&module, imports).unwrap();
+        assert_eq!(
+            instance.call("upd", &[Value::I32(5)]),
+            Ok(Some(Value::I32(1)))
+        );
+        assert_eq!(
+            instance.call("upd", &[Value::I32(6)]),
+            Ok(Some(Value::I32(2)))
+        );
+        assert_eq!(instance.memory()[0x100], 42);
+        instance.memory_mut()[0x201] = 9;
+        assert!(instance.call("upd", &[Value::F32(1.0)]).is_err());
+        assert!(instance.call("missing", &[]).is_err());
+        drop(instance);
+        assert_eq!(
+            calls,
+            [
+                (vec![Value::I32(15), Value::F32(0.5)], 2),
+                (vec![Value::I32(18), Value::F32(0.5)], 2)
+            ]
+        );
+    }
+}
@@ -3,9 +3,9 @@ use enc::MemArg;
 use std::collections::HashMap;
 use wasm_encoder as enc;
 
-pub struct Intrinsics(
-    HashMap<String, HashMap<Vec<Type>, (Option<Type>, enc::Instruction<'static>)>>,
-);
+type Overloads = HashMap<Vec<Type>, (Option<Type>, enc::Instruction<'static>)>;
+
+pub struct Intrinsics(HashMap<String, Overloads>);
 
 impl Intrinsics {
     pub fn new() -> Intrinsics {
@@ -30,6 +30,18 @@ impl Intrinsics {
             .map(|(_, i)| i.clone())
     }
 
+    // every overload of every intrinsic with its parameter and result types
+    #[cfg(test)]
+    pub fn all(
+        &self,
+    ) -> impl Iterator<Item = (&str, &[Type], Option<Type>, &enc::Instruction<'static>)> {
+        self.0.iter().flat_map(|(name, overloads)| {
+            overloads
+                .iter()
+                .map(move |(params, (ret, ins))| (name.as_str(), params.as_slice(), *ret, ins))
+        })
+    }
+
     fn add_instructions(&mut self) {
         use enc::Instruction as I;
         use Type::*;
@@ -53,7 +65,7 @@ impl Intrinsics {
         self.inst("f32/trunc", &[F32], Some(F32), I::F32Trunc);
         self.inst("f32/nearest", &[F32], Some(F32), I::F32Nearest);
         self.inst("f32/abs", &[F32], Some(F32), I::F32Abs);
-        self.inst("f32.copysign", &[F32, F32], Some(F32), I::F32Copysign);
+        self.inst("f32/copysign", &[F32, F32], Some(F32), I::F32Copysign);
 
         self.inst("f64/sqrt", &[F64], Some(F64), I::F64Sqrt);
         self.inst("f64/min", &[F64, F64], Some(F64), I::F64Min);
@@ -63,7 +75,7 @@ impl Intrinsics {
         self.inst("f64/trunc", &[F64], Some(F64), I::F64Trunc);
         self.inst("f64/nearest", &[F64], Some(F64), I::F64Nearest);
         self.inst("f64/abs", &[F64], Some(F64), I::F64Abs);
-        self.inst("f64.copysign", &[F64, F64], Some(F64), I::F64Copysign);
+        self.inst("f64/copysign", &[F64, F64], Some(F64), I::F64Copysign);
 
         self.inst("i32.wrap_i64", &[I64], Some(I32), I::I32WrapI64);
         self.inst("i64.extend_i32_s", &[I32], Some(I64), I::I64ExtendI32S);
@@ -83,14 +95,14 @@ impl Intrinsics {
         self.inst("f64.promote_f32", &[F32], Some(F64), I::F64PromoteF32);
 
         self.inst("f32.convert_i32_s", &[I32], Some(F32), I::F32ConvertI32S);
-        self.inst("f32.convert_i64_s", &[I64], Some(F32), I::F32ConvertI32S);
-        self.inst("f64.convert_i32_s", &[I32], Some(F64), I::F32ConvertI32S);
-        self.inst("f64.convert_i64_s", &[I64], Some(F64), I::F32ConvertI32S);
+        self.inst("f32.convert_i64_s", &[I64], Some(F32), I::F32ConvertI64S);
+        self.inst("f64.convert_i32_s", &[I32], Some(F64), I::F64ConvertI32S);
+        self.inst("f64.convert_i64_s", &[I64], Some(F64), I::F64ConvertI64S);
 
         self.inst("f32.convert_i32_u", &[I32], Some(F32), I::F32ConvertI32U);
-        self.inst("f32.convert_i64_u", &[I64], Some(F32), I::F32ConvertI32U);
-        self.inst("f64.convert_i32_u", &[I32], Some(F64), I::F32ConvertI32U);
-        self.inst("f64.convert_i64_u", &[I64], Some(F64), I::F32ConvertI32U);
+        self.inst("f32.convert_i64_u", &[I64], Some(F32), I::F32ConvertI64U);
+        self.inst("f64.convert_i32_u", &[I32], Some(F64), I::F64ConvertI32U);
+        self.inst("f64.convert_i64_u", &[I64], Some(F64), I::F64ConvertI64U);
 
         self.inst(
             "i32.reinterpret_f32",
@@ -192,7 +204,7 @@ impl Intrinsics {
             "f64.load" => MemInstruction::new(F64, I::F64Load, 3),
             _ => return None,
         };
-        return Some(ins);
+        Some(ins)
     }
 
     pub fn find_store(&self, name: &str) -> Option<MemInstruction> {
@@ -210,7 +222,7 @@ impl Intrinsics {
             "f64.store" => MemInstruction::new(F64, I::F64Store, 3),
             _ => return None,
         };
-        return Some(ins);
+        Some(ins)
     }
 }
 
@@ -233,3 +245,109 @@ impl MemInstruction {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a module with a single function running `instruction` on its parameters
+    fn module(params: &[Type], result: Option<Type>, instruction: &enc::Instruction) -> Vec<u8> {
+        let val_type = |type_: &Type| match type_ {
+            Type::I32 => enc::ValType::I32,
+            Type::I64 => enc::ValType::I64,
+            Type::F32 => enc::ValType::F32,
+            Type::F64 => enc::ValType::F64,
+        };
+        let mut module = enc::Module::new();
+        let mut types = enc::TypeSection::new();
+        types.function(params.iter().map(val_type), result.iter().map(val_type));
+        module.section(&types);
+        let mut functions = enc::FunctionSection::new();
+        functions.function(0);
+        module.section(&functions);
+        let mut memories = enc::MemorySection::new();
+        memories.memory(enc::MemoryType {
+            minimum: 1,
+            maximum: None,
+            memory64: false,
+        });
+        module.section(&memories);
+        let mut function = enc::Function::new([]);
+        for index in 0..params.len() {
+            function.instruction(&enc::Instruction::LocalGet(index as u32));
+        }
+        function.instruction(instruction);
+        function.instruction(&enc::Instruction::End);
+        let mut code = enc::CodeSection::new();
+        code.function(&function);
+        module.section(&code);
+        module.finish()
+    }
+
+    // validates the types of the entry and checks that the instruction has the same name
+    fn check(name: &str, params: &[Type], result: Option<Type>, instruction: &enc::Instruction) {
+        let wasm = module(params, result, instruction);
+        if let Err(err) = wasmparser::validate(&wasm) {
+            panic!("{} {:?} -> {:?}: {}", name, params, result, err);
+        }
+        // names without a type prefix are shortcuts for the full name
+        if name.contains('.') {
+            let wat = wasmprinter::print_bytes(&wasm).unwrap();
+            assert!(
+                wat.lines()
+                    .any(|line| line.split_whitespace().next() == Some(name)),
+                "{} {:?} doesn't emit {}:\n{}",
+                name,
+                params,
+                name,
+                wat
+            );
+        }
+    }
+
+    #[test]
+    fn instructions_match_signatures() {
+        for (name, params, result, instruction) in Intrinsics::new().all() {
+            check(name, params, result, instruction);
+        }
+    }
+
+    #[test]
+    fn memory_instructions_match_signatures() {
+        let intrinsics = Intrinsics::new();
+        for type_ in ["i32", "i64", "f32", "f64"] {
+            for size in ["", "8_s", "8_u", "16_s", "16_u", "32_s", "32_u"] {
+                let name = format!("{}.load{}", type_, size);
+                if let Some(load) = intrinsics.find_load(&name) {
+                    let memarg = MemArg {
+                        offset: 0,
+                        align: load.natural_alignment,
+                        memory_index: 0,
+                    };
+                    check(
+                        &name,
+                        &[Type::I32],
+                        Some(load.type_),
+                        &(load.instruction)(memarg),
+                    );
+                }
+            }
+            for size in ["", "8", "16", "32"] {
+                let name = format!("{}.store{}", type_, size);
+                if let Some(store) = intrinsics.find_store(&name) {
+                    let memarg = MemArg {
+                        offset: 0,
+                        align: store.natural_alignment,
+                        memory_index: 0,
+                    };
+                    check(
+                        &name,
+                        &[Type::I32, store.type_],
+                        None,
+                        &(store.instruction)(memarg),
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast,
+    intrinsics::Intrinsics,
+    parser::{Sources, Span},
+    warnings::{source_name, Lint, Warnings},
+};
+
+// Checks for the ways `let lazy` and `let inline` change the meaning of code,
+// following the order in which `emit` places the delayed values:
+// * a lazy value is evaluated at the first use of the variable in the source, which
+//   might be in a branch of an `if` while the variable is also used outside of it
+// * the locals read by a lazy value are assigned to between its `let` and first use
+//   (but not by the evaluation of a lazy value declared before it, which would also
+//   happen before its `let` if they were evaluated eagerly)
+// * an inline value with side effects, which are repeated at every use
+pub fn check_function(
+    func: &ast::Function,
+    functions: &HashSet<&str>,
+    intrinsics: &Intrinsics,
+    sources: &Sources,
+    warnings: &mut Warnings,
+) {
+    let mut checker = Checker {
+        func,
+        functions,
+        intrinsics,
+        let_values: HashMap::new(),
+        lazy: HashMap::new(),
+        lets: 0,
+        evaluating: Vec::new(),
+        branches: Vec::new(),
+        next_branch: 0,
+        sources,
+        warnings,
+    };
+    checker.check(&func.body);
+}
+
+struct Lazy<'a> {
+    // numbers the lazy `let`s in the order they appear
+    position: u32,
+    // the locals read by the value, including those read through inline variables
+    inputs: HashSet<u32>,
+    // the first assignment to one of the inputs since the `let`
+    reassigned: Option<(u32, &'a Span)>,
+    // where the value is evaluated, along with the branches containing it
+    first_use: Option<(&'a Span, Vec<u32>)>,
+    reported: bool,
+}
+
+struct Checker<'a, 'b> {
+    func: &'a ast::Function,
+    functions: &'b HashSet<&'b str>,
+    intrinsics: &'b Intrinsics,
+    // the values of lazy and inline variables not emitted yet, as in `emit`
+    let_values: HashMap<u32, (&'a ast::Expression, ast::LetType)>,
+    lazy: HashMap<u32, Lazy<'a>>,
+    lets: u32,
+    // the positions of the lazy values currently being evaluated
+    evaluating: Vec<u32>,
+    // the `if` branches containing the current expression
+    branches: Vec<u32>,
+    next_branch: u32,
+    sources: &'b Sources,
+    warnings: &'b mut Warnings,
+}
+
+impl<'a, 'b> Checker<'a, 'b> {
+    fn check(&mut self, expr: &'a ast::Expression) {
+        match expr.expr {
+            ast::Expr::Let {
+                value: Some(ref value),
+                let_type,
+                local_id: Some(id),
+                ref name,
+                ..
+            } if let_type != ast::LetType::Normal => {
+                if let_type == ast::LetType::Inline {
+                    self.check_side_effects(name, &expr.span, value);
+                } else {
+                    let mut inputs = HashSet::new();
+                    self.inputs(value, &mut inputs);
+                    self.lets += 1;
+                    self.lazy.insert(
+                        id,
+                        Lazy {
+                            position: self.lets,
+                            inputs,
+                            reassigned: None,
+                            first_use: None,
+                            reported: false,
+                        },
+                    );
+                }
+                self.let_values.insert(id, (value, let_type));
+            }
+            ast::Expr::Let {
+                value: Some(ref value),
+                local_id: Some(id),
+                ..
+            }
+            | ast::Expr::Assign {
+                ref value,
+                local_id: Some(id),
+                ..
+            }
+            | ast::Expr::LocalTee {
+                ref value,
+                local_id: Some(id),
+                ..
+            } => {
+                self.check(value);
+                self.assigned(id, &expr.span);
+            }
+            ast::Expr::Variable {
+                local_id: Some(id), ..
+            } => self.used(id, &expr.span),
+            ast::Expr::If {
+                ref condition,
+                ref if_true,
+                ref if_false,
+            } => {
+                self.check(condition);
+                self.check_branch(if_true);
+                if let Some(if_false) = if_false {
+                    self.check_branch(if_false);
+                }
+            }
+            ast::Expr::Select {
+                ref condition,
+                ref if_true,
+                ref if_false,
+            } => {
+                self.check(if_true);
+                self.check(if_false);
+                self.check(condition);
+            }
+            _ => {
+                for child in expr.expr.children() {
+                    self.check(child);
+                }
+            }
+        }
+    }
+
+    fn check_branch(&mut self, expr: &'a ast::Expression) {
+        self.branches.push(self.next_branch);
+        self.next_branch += 1;
+        self.check(expr);
+        self.branches.pop();
+    }
+
+    fn used(&mut self, id: u32, span: &'a Span) {
+        match self.let_values.get(&id).copied() {
+            // the value is emitted here, like in `emit`
+            Some((value, let_type)) => {
+                if let_type == ast::LetType::Lazy {
+                    self.let_values.remove(&id);
+                    let lazy = self.lazy.get_mut(&id).unwrap();
+                    lazy.first_use = Some((span, self.branches.clone()));
+                    let position = lazy.position;
+                    self.check_reassigned(id, span);
+                    self.evaluating.push(position);
+                    self.check(value);
+                    self.evaluating.pop();
+                } else {
+                    self.check(value);
+                }
+            }
+            None => self.check_initialized(id, span),
+        }
+    }
+
+    fn assigned(&mut self, id: u32, span: &'a Span) {
+        // where the assignment would happen if lazy values were evaluated at their `let`
+        let position = self.evaluating.last().copied().unwrap_or(self.lets);
+        for lazy in self.lazy.values_mut() {
+            if lazy.first_use.is_none()
+                && lazy.reassigned.is_none()
+                && lazy.position <= position
+                && lazy.inputs.contains(&id)
+            {
+                lazy.reassigned = Some((id, span));
+            }
+        }
+    }
+
+    fn check_reassigned(&mut self, id: u32, span: &Span) {
+        let (input, assignment) = match self.lazy[&id].reassigned {
+            Some(reassigned) => reassigned,
+            None => return,
+        };
+        let locals = &self.func.locals;
+        if let (Some(name), Some(input)) = (
+            source_name(&locals[id].name),
+            source_name(&locals[input].name),
+        ) {
+            self.warnings.warn_with_note(
+                Lint::LazyInputReassigned,
+                &format!(
+                    "Lazy variable {} is evaluated after {} was assigned to",
+                    name, input
+                ),
+                span,
+                Some((assignment, &format!("{} is assigned to here", input))),
+                self.sources,
+            );
+        }
+    }
+
+    // a use after the first one has to be inside all the branches containing the first one
+    fn check_initialized(&mut self, id: u32, span: &Span) {
+        let lazy = match self.lazy.get_mut(&id) {
+            Some(lazy) if !lazy.reported => lazy,
+            _ => return,
+        };
+        let (first_use, branches) = match lazy.first_use {
+            Some((first_use, ref branches)) => (first_use, branches),
+            None => return,
+        };
+        if self.branches.starts_with(branches) {
+            return;
+        }
+        lazy.reported = true;
+        if let Some(name) = source_name(&self.func.locals[id].name) {
+            self.warnings.warn_with_note(
+                Lint::LazyInBranch,
+                &format!(
+                    "Lazy variable {} is only evaluated in a branch, but used outside of it",
+                    name
+                ),
+                first_use,
+                Some((span, "Used here without being evaluated")),
+                self.sources,
+            );
+        }
+    }
+
+    // the locals a lazy value depends on, other lazy values are stored once evaluated
+    fn inputs(&self, expr: &ast::Expression, inputs: &mut HashSet<u32>) {
+        if let ast::Expr::Variable {
+            local_id: Some(id), ..
+        } = expr.expr
+        {
+            if inputs.insert(id) {
+                if let Some((value, ast::LetType::Inline)) = self.let_values.get(&id) {
+                    self.inputs(value, inputs);
+                }
+            }
+        }
+        for child in expr.expr.children() {
+            self.inputs(child, inputs);
+        }
+    }
+
+    fn check_side_effects(&mut self, name: &str, span: &Span, value: &ast::Expression) {
+        if let Some((effect_span, effect)) = self.side_effect(value) {
+            if let Some(name) = source_name(name) {
+                self.warnings.warn_with_note(
+                    Lint::InlineSideEffect,
+                    &format!(
+                        "Inline variable {} has side effects, which happen at every use",
+                        name
+                    ),
+                    span,
+                    Some((effect_span, &effect)),
+                    self.sources,
+                );
+            }
+        }
+    }
+
+    fn side_effect<'e>(&self, expr: &'e ast::Expression) -> Option<(&'e Span, String)> {
+        match expr.expr {
+            ast::Expr::FuncCall { ref name, .. } if self.functions.contains(name.as_str()) => {
+                return Some((&expr.span, format!("Calls {}", name)));
+            }
+            ast::Expr::FuncCall { ref name, .. }
+                if self.intrinsics.find_store(name).is_some()
+                    || matches!(name.as_str(), "memory.copy" | "memory.fill" | "memory.grow") =>
+            {
+                return Some((&expr.span, "Writes to memory".to_string()));
+            }
+            ast::Expr::Poke { .. } => return Some((&expr.span, "Writes to memory".to_string())),
+            _ => (),
+        }
+        expr.expr
+            .children()
+            .into_iter()
+            .find_map(|child| self.side_effect(child))
+    }
+}
@@ -2,24 +2,141 @@ use anyhow::{bail, Result};
 use parser::Sources;
 use std::collections::HashSet;
 use std::ffi::OsStr;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
+pub use testing::TestSummary;
+pub use warnings::Lint;
+
+mod assets;
 mod ast;
+mod compress;
+mod consteval;
 mod constfold;
+mod dwarf;
 mod emit;
 mod includes;
+pub mod interp;
 mod intrinsics;
+mod lazy;
+mod macros;
+mod namespaces;
 mod parser;
+mod strings;
+mod testing;
 mod typecheck;
+mod warnings;
 
 #[derive(Default)]
 pub struct Options {
     pub(crate) debug: bool,
+    // the addresses memory accesses are checked against in debug builds
+    pub(crate) memory_range: Option<Range<u64>>,
+    pub(crate) defines: Vec<(String, ast::Expr)>,
+    pub(crate) string_format: StringFormat,
+    pub(crate) size_report: bool,
+    pub(crate) lints: Vec<(Lint, bool)>,
+    // compile test functions and stubs, set by `test_file`
+    pub(crate) test: bool,
+}
+
+// How string literals in expressions are stored in memory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StringFormat {
+    #[default]
+    NulTerminated,
+    // a single byte holding the length, followed by the string
+    LengthPrefixed,
+    Raw,
+}
+
+impl std::str::FromStr for StringFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<StringFormat> {
+        match s {
+            "nul" => Ok(StringFormat::NulTerminated),
+            "length" => Ok(StringFormat::LengthPrefixed),
+            "raw" => Ok(StringFormat::Raw),
+            _ => bail!("Invalid string format {} (expected nul, length or raw)", s),
+        }
+    }
 }
 
 impl Options {
     pub fn with_debug(self) -> Self {
-        Options { debug: true }
+        Options {
+            debug: true,
+            ..self
+        }
+    }
+
+    // Makes the memory checks of debug builds also check that each access lies
+    // within `range`, not only that it is aligned.
+    pub fn with_memory_range(self, range: Range<u64>) -> Self {
+        Options {
+            memory_range: Some(range),
+            ..self
+        }
+    }
+
+    // Defines a const overriding any const of the same name in the script.
+    // An empty value defines the const as 1, the value is parsed like a
+    // literal in a script (without any expressions).
+    pub fn with_define(mut self, name: &str, value: &str) -> Result<Self> {
+        let value = value.trim().replace('_', "");
+        let parse_i32 = |v: &str| -> Option<i32> {
+            let (negative, v) = match v.strip_prefix('-') {
+                Some(v) => (true, v),
+                None => (false, v),
+            };
+            let value = match v.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()? as i32,
+                None => v.parse::<u32>().ok()? as i32,
+            };
+            Some(if negative {
+                value.wrapping_neg()
+            } else {
+                value
+            })
+        };
+        let expr = if value.is_empty() {
+            ast::Expr::I32Const(1)
+        } else if let Some(v) = value.strip_suffix("f64") {
+            ast::Expr::F64Const(v.parse()?)
+        } else if let Some(v) = value.strip_suffix("i64") {
+            ast::Expr::I64Const(v.parse()?)
+        } else if let Some(v) = parse_i32(&value) {
+            ast::Expr::I32Const(v)
+        } else if let Ok(v) = value.parse::<f32>() {
+            ast::Expr::F32Const(v)
+        } else {
+            bail!("Invalid value for define {}: {}", name, value);
+        };
+        self.defines.push((name.to_string(), expr));
+        Ok(self)
+    }
+
+    pub fn with_string_format(self, string_format: StringFormat) -> Self {
+        Options {
+            string_format,
+            ..self
+        }
+    }
+
+    // Enables or disables a warning for the whole script, items can still
+    // override this with an `#[allow(..)]` or `#[warn(..)]` attribute.
+    pub fn with_lint(mut self, lint: Lint, enabled: bool) -> Self {
+        self.lints.push((lint, enabled));
+        self
+    }
+
+    // Prints how much compressing data saved to stderr.
+    pub fn with_size_report(self) -> Self {
+        Options {
+            size_report: true,
+            ..self
+        }
     }
 }
 
@@ -28,71 +145,187 @@ pub struct CompiledModule {
     pub dependencies: Vec<PathBuf>,
 }
 
-pub fn compile_file<P: AsRef<Path>>(path: P, options: Options) -> (Result<Vec<u8>>, Vec<PathBuf>) {
-    fn compile_file_inner(
-        path: &Path,
-        options: Options,
-        dependencies: &mut HashSet<PathBuf>,
-    ) -> Result<Vec<u8>> {
-        let mut script = ast::Script::default();
-
-        let mut sources = Sources::new();
-
-        let mut pending_files = vec![(path.to_path_buf(), None)];
-        while let Some((path, span)) = pending_files.pop() {
-            match sources.add(&path) {
-                Ok((id, true)) => {
-                    dependencies.insert(path.clone());
-                    let mut new_script = match parser::parse(&sources, id) {
-                        Ok(script) => script,
-                        Err(_) => bail!("Parse failed"),
-                    };
+// A script compiled to wasm, along with everything needed to run its tests.
+struct Compilation {
+    wasm: Vec<u8>,
+    script: ast::Script,
+    sources: Sources,
+    asserts: Vec<parser::Span>,
+}
+
+fn compile_script(
+    path: &Path,
+    options: &Options,
+    dependencies: &mut HashSet<PathBuf>,
+) -> Result<Compilation> {
+    let mut sources = Sources::new();
+    let mut modules = Vec::new();
+    let mut loaded = HashSet::new();
+
+    // Every module after the first one (the root script) is merged into the
+    // script (or conditional branch) that included it.
+    let mut parents: Vec<Option<(usize, includes::Branch)>> = Vec::new();
+    let mut module_keys = Vec::new();
 
-                    includes::resolve_includes(&mut new_script, dependencies, &path)?;
+    let mut pending_files = vec![(path.to_path_buf(), None, String::new(), None, Vec::new())];
+    while let Some((path, span, namespace, parent, conditions)) = pending_files.pop() {
+        match sources.add(&path) {
+            Ok(id) => {
+                let key = (id, namespace.clone(), conditions.clone());
+                if !loaded.insert(key.clone()) {
+                    continue; // already parsed this include into this namespace
+                }
+                dependencies.insert(path.clone());
+                let mut new_script = match parser::parse(&sources, id) {
+                    Ok(script) => script,
+                    Err(_) => bail!("Parse failed"),
+                };
 
-                    for include in std::mem::take(&mut new_script.includes) {
-                        let mut path = path
-                            .parent()
-                            .expect("Script path has no parent")
-                            .to_path_buf();
-                        path.push(include.path);
-                        pending_files.push((path, Some(include.span)));
-                    }
+                includes::resolve_includes(&mut new_script, dependencies, &path)?;
 
-                    script.merge(new_script);
+                for include in includes::take_includes(&mut new_script) {
+                    let mut path = path
+                        .parent()
+                        .expect("Script path has no parent")
+                        .to_path_buf();
+                    path.push(include.include.path);
+                    let include_namespace = match include.include.namespace {
+                        Some(name) => namespaces::qualify(&namespace, &name),
+                        None => namespace.clone(),
+                    };
+                    let mut include_conditions: Vec<(usize, usize, bool)> = conditions.clone();
+                    include_conditions.extend(include.conditions);
+                    include_conditions.sort_unstable();
+                    include_conditions.dedup();
+                    pending_files.push((
+                        path,
+                        Some(include.include.span),
+                        include_namespace,
+                        Some((modules.len(), include.branch)),
+                        include_conditions,
+                    ));
                 }
-                Ok((_, false)) => (), // already parsed this include
-                Err(err) => {
-                    if let Some(span) = span {
-                        let _ = typecheck::report_error(&err.to_string(), &span, &sources);
-                    } else {
-                        eprintln!("Failed to load script {}: {}", path.display(), err);
-                    }
-                    bail!("Parse failed");
+
+                modules.push(namespaces::Module {
+                    namespace,
+                    script: new_script,
+                });
+                parents.push(parent);
+                module_keys.push(key);
+            }
+            Err(err) => {
+                if let Some(span) = span {
+                    let _ = typecheck::report_error(&err.to_string(), &span, &sources);
+                } else {
+                    eprintln!("Failed to load script {}: {}", path.display(), err);
                 }
+                bail!("Parse failed");
             }
         }
+    }
 
-        if constfold::fold_script(&mut script, &sources).is_err() {
-            bail!("Constant folding failed");
-        }
-        if typecheck::tc_script(&mut script, &sources).is_err() {
-            bail!("Type check failed");
+    if namespaces::resolve_namespaces(&mut modules, &sources).is_err() {
+        bail!("Name resolution failed");
+    }
+    // A file included both unconditionally and inside an `if const` (into the same namespace) is
+    // loaded once for each, the copy depending on more conditions is dropped along with its includes.
+    let redundant: Vec<bool> = module_keys
+        .iter()
+        .map(|(id, namespace, conditions)| {
+            module_keys
+                .iter()
+                .any(|(other_id, other_namespace, other_conditions)| {
+                    other_id == id
+                        && other_namespace == namespace
+                        && other_conditions.len() < conditions.len()
+                        && other_conditions.iter().all(|c| conditions.contains(c))
+                })
+        })
+        .collect();
+    // modules are always loaded after the module including them
+    while modules.len() > 1 {
+        let module = modules.pop().unwrap();
+        let (parent, branch) = parents
+            .pop()
+            .unwrap()
+            .expect("Included module has no parent");
+        if !redundant[modules.len()] {
+            includes::branch_mut(&mut modules[parent].script, &branch).merge(module.script);
         }
-        let wasm = emit::emit(
-            &script,
-            &path
-                .file_stem()
-                .unwrap_or_else(|| OsStr::new("unknown"))
-                .to_string_lossy(),
-            &options,
-        );
-        Ok(wasm)
     }
+    let mut script = modules.pop().unwrap().script;
+
+    let mut warnings = warnings::Warnings::new(&options.lints);
+    warnings.add_attributes(&mut script);
+    warnings::check_unused_items(&script, &sources, &mut warnings);
 
+    if constfold::resolve_conditionals(&mut script, &sources, &options.defines).is_err() {
+        bail!("Conditional compilation failed");
+    }
+    if compress::add_decompressor(&mut script, &mut sources).is_err() {
+        bail!("Failed to add decompressor");
+    }
+    if macros::expand_macros(&mut script, &mut sources).is_err() {
+        bail!("Macro expansion failed");
+    }
+    let asserts = match testing::prepare_tests(&mut script, &sources, options.test) {
+        Ok(asserts) => asserts,
+        Err(_) => bail!("Failed to prepare tests"),
+    };
+    if strings::intern_strings(&mut script, &sources, options.string_format).is_err() {
+        bail!("String interning failed");
+    }
+    if constfold::fold_script(&mut script, &sources, &options.defines).is_err() {
+        bail!("Constant folding failed");
+    }
+    if typecheck::tc_script(&mut script, &sources, &mut warnings).is_err() {
+        bail!("Type check failed");
+    }
+    warnings::check_functions(&script, &sources, &mut warnings);
+    let compressed = match compress::compress_data(&mut script, &sources) {
+        Ok(compressed) => compressed,
+        Err(_) => bail!("Compression failed"),
+    };
+    let wasm = emit::emit(
+        &script,
+        &path
+            .file_stem()
+            .unwrap_or_else(|| OsStr::new("unknown"))
+            .to_string_lossy(),
+        options,
+        &sources,
+    );
+    if options.size_report {
+        compress::print_size_report(&script, &compressed, &wasm);
+    }
+    Ok(Compilation {
+        wasm,
+        script,
+        sources,
+        asserts,
+    })
+}
+
+pub fn compile_file<P: AsRef<Path>>(path: P, options: Options) -> (Result<Vec<u8>>, Vec<PathBuf>) {
     let mut dependencies = HashSet::new();
 
-    let result = compile_file_inner(path.as_ref(), options, &mut dependencies);
+    let result = compile_script(path.as_ref(), &options, &mut dependencies)
+        .map(|compilation| compilation.wasm);
 
     (result, dependencies.into_iter().collect())
 }
+
+// Compiles the script including its tests and runs each of them in the built-in interpreter.
+pub fn test_file<P: AsRef<Path>>(path: P, options: Options) -> Result<TestSummary> {
+    let options = Options {
+        test: true,
+        ..options
+    };
+    let compilation = compile_script(path.as_ref(), &options, &mut HashSet::new())?;
+    testing::run_tests(
+        &compilation.script,
+        &compilation.sources,
+        &compilation.asserts,
+        &compilation.wasm,
+    )
+}
@@ -0,0 +1,380 @@
+use ariadne::{Color, Label, Report, ReportKind};
+use std::collections::HashMap;
+
+use crate::{
+    ast,
+    parser::{Sources, Span},
+    typecheck::report_duplicate_definition,
+};
+
+type Result<T> = std::result::Result<T, ()>;
+
+pub fn expand_macros(script: &mut ast::Script, sources: &mut Sources) -> Result<()> {
+    let mut macros: HashMap<String, ast::Macro> = HashMap::new();
+    let mut result = Ok(());
+
+    for mac in std::mem::take(&mut script.macros) {
+        if let Some(prev) = macros.get(&mac.name) {
+            result = report_duplicate_definition(
+                "Macro already defined",
+                &mac.span,
+                &prev.span,
+                sources,
+            );
+        } else {
+            macros.insert(mac.name.clone(), mac);
+        }
+    }
+
+    let mut context = Context {
+        macros: &macros,
+        sources,
+        stack: Vec::new(),
+        next_id: 0,
+    };
+
+    for var in &mut script.global_vars {
+        expand_expr(&mut context, &mut var.value)?;
+    }
+
+    for cnst in &mut script.consts {
+        expand_expr(&mut context, &mut cnst.value)?;
+    }
+
+    for func in &mut script.functions {
+        expand_expr(&mut context, &mut func.body)?;
+    }
+
+    for data in &mut script.data {
+        expand_expr(&mut context, &mut data.offset)?;
+        for values in &mut data.data {
+            for value in values.expressions_mut() {
+                expand_expr(&mut context, value)?;
+            }
+        }
+    }
+
+    for static_ in &mut script.statics {
+        for values in &mut static_.data {
+            for value in values.expressions_mut() {
+                expand_expr(&mut context, value)?;
+            }
+        }
+    }
+
+    for region in &mut script.static_regions {
+        expand_expr(&mut context, &mut region.start)?;
+        if let Some(ref mut end) = region.end {
+            expand_expr(&mut context, end)?;
+        }
+    }
+
+    result
+}
+
+struct Context<'a> {
+    macros: &'a HashMap<String, ast::Macro>,
+    sources: &'a mut Sources,
+    stack: Vec<&'a str>,
+    next_id: u32,
+}
+
+fn expand_expr<'a>(context: &mut Context<'a>, expr: &mut ast::Expression) -> Result<()> {
+    for child in expr.expr.children_mut() {
+        expand_expr(context, child)?;
+    }
+
+    if let ast::Expr::FuncCall {
+        ref name,
+        ref mut params,
+    } = expr.expr
+    {
+        let macros = context.macros;
+        if let Some(mac) = macros.get(name) {
+            let params = std::mem::take(params);
+            *expr = instantiate(context, mac, params, &expr.span)?;
+            context.stack.push(&mac.name);
+            let result = expand_expr(context, expr);
+            context.stack.pop();
+            result?;
+        }
+    }
+
+    Ok(())
+}
+
+fn instantiate(
+    context: &mut Context,
+    mac: &ast::Macro,
+    args: Vec<ast::Expression>,
+    span: &Span,
+) -> Result<ast::Expression> {
+    if args.len() != mac.params.len() {
+        return report_macro_error(
+            &format!(
+                "Macro {} expects {} argument{}",
+                mac.name,
+                mac.params.len(),
+                if mac.params.len() == 1 { "" } else { "s" }
+            ),
+            span,
+            &mac.span,
+            "Macro defined here",
+            context.sources,
+        );
+    }
+    if context.stack.contains(&mac.name.as_str()) {
+        return report_macro_error(
+            &format!("Recursive expansion of macro {}", mac.name),
+            span,
+            &mac.span,
+            "Macro defined here",
+            context.sources,
+        );
+    }
+
+    // The expanded code gets its own copy of the source containing the macro,
+    // so that errors reported in it also tell where the macro was invoked.
+    let name = format!(
+        "macro {} expanded at {}, in {}",
+        mac.name,
+        context.sources.describe_location(span),
+        context.sources.name(mac.span.0)
+    );
+    let source_id = context.sources.add_copy(mac.span.0, name);
+    let mut body = mac.body.clone();
+    remap_spans(&mut body, source_id);
+
+    let mut expansion = Expansion {
+        params: mac.params.iter().cloned().zip(args).collect(),
+        scopes: Vec::new(),
+        labels: Vec::new(),
+        suffix: format!("#{}", context.next_id),
+        sources: context.sources,
+    };
+    context.next_id += 1;
+    expansion.substitute(&mut body)?;
+
+    body.span = span.clone();
+    Ok(body)
+}
+
+// Substitutes the macro params in a copy of the macro body and renames
+// all variables and labels declared in it, so they can't clash with the
+// ones at the invocation site.
+struct Expansion<'a> {
+    params: HashMap<String, ast::Expression>,
+    scopes: Vec<HashMap<String, String>>,
+    labels: Vec<(String, String)>,
+    suffix: String,
+    sources: &'a Sources,
+}
+
+enum Substitution {
+    Name(String),
+    Expression(ast::Expression),
+}
+
+impl<'a> Expansion<'a> {
+    fn substitute(&mut self, expr: &mut ast::Expression) -> Result<()> {
+        match expr.expr {
+            ast::Expr::Block { .. } => {
+                self.scopes.push(HashMap::new());
+                let result = self.substitute_children(expr);
+                self.scopes.pop();
+                result?;
+            }
+            ast::Expr::Let {
+                ref mut name,
+                ref mut value,
+                ..
+            } => {
+                if let Some(value) = value {
+                    self.substitute(value)?;
+                }
+                let renamed = format!("{}{}", name, self.suffix);
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(name.clone(), renamed.clone());
+                }
+                *name = renamed;
+            }
+            ast::Expr::Variable { ref mut name, .. } => match self.resolve(name, &expr.span)? {
+                Some(Substitution::Name(renamed)) => *name = renamed,
+                Some(Substitution::Expression(value)) => *expr = value,
+                None => (),
+            },
+            ast::Expr::Assign {
+                ref mut name,
+                ref mut value,
+                ..
+            }
+            | ast::Expr::LocalTee {
+                ref mut name,
+                ref mut value,
+                ..
+            } => {
+                self.substitute(value)?;
+                match self.resolve(name, &expr.span)? {
+                    Some(Substitution::Name(renamed)) => *name = renamed,
+                    Some(Substitution::Expression(_)) => {
+                        let arg = &self.params[name.as_str()];
+                        *name = self.arg_name(arg, &expr.span)?;
+                    }
+                    None => (),
+                }
+            }
+            ast::Expr::Loop {
+                ref mut label,
+                ref mut block,
+            }
+            | ast::Expr::LabelBlock {
+                ref mut label,
+                ref mut block,
+            } => {
+                let renamed = if let Some(arg) = self.params.get(label) {
+                    self.arg_name(arg, &expr.span)?
+                } else {
+                    format!("{}{}", label, self.suffix)
+                };
+                self.labels.push((label.clone(), renamed.clone()));
+                *label = renamed;
+                let result = self.substitute(block);
+                self.labels.pop();
+                result?;
+            }
+            ast::Expr::Branch(ref mut label) => *label = self.resolve_label(label, &expr.span)?,
+            ast::Expr::BranchIf {
+                ref mut condition,
+                ref mut label,
+            } => {
+                self.substitute(condition)?;
+                *label = self.resolve_label(label, &expr.span)?;
+            }
+            ast::Expr::Repeat {
+                ref mut count,
+                ref mut index,
+                ref mut body,
+            } => {
+                self.substitute(count)?;
+                self.substitute_indexed(index, body)?;
+            }
+            ast::Expr::Sequence {
+                ref mut index,
+                ref mut start,
+                ref mut end,
+                ref mut value,
+            } => {
+                self.substitute(start)?;
+                self.substitute(end)?;
+                self.substitute_indexed(index, value)?;
+            }
+            _ => self.substitute_children(expr)?,
+        }
+        Ok(())
+    }
+
+    // `body` is in the scope of the (renamed) `index`
+    fn substitute_indexed(&mut self, index: &mut String, body: &mut ast::Expression) -> Result<()> {
+        let renamed = format!("{}{}", index, self.suffix);
+        self.scopes
+            .push(HashMap::from_iter([(index.clone(), renamed.clone())]));
+        *index = renamed;
+        let result = self.substitute(body);
+        self.scopes.pop();
+        result
+    }
+
+    fn substitute_children(&mut self, expr: &mut ast::Expression) -> Result<()> {
+        for child in expr.expr.children_mut() {
+            self.substitute(child)?;
+        }
+        Ok(())
+    }
+
+    // `name` can be a field access like `base.field`, in which case only the base is substituted
+    fn resolve(&self, name: &str, span: &Span) -> Result<Option<Substitution>> {
+        let (base, field) = match name.split_once('.') {
+            Some((base, field)) => (base, Some(field)),
+            None => (name, None),
+        };
+        let join = |base: &str| match field {
+            Some(field) => format!("{}.{}", base, field),
+            None => base.to_string(),
+        };
+        if let Some(renamed) = self.scopes.iter().rev().find_map(|scope| scope.get(base)) {
+            return Ok(Some(Substitution::Name(join(renamed))));
+        }
+        if let Some(arg) = self.params.get(base) {
+            return Ok(Some(if field.is_some() {
+                Substitution::Name(join(&self.arg_name(arg, span)?))
+            } else {
+                Substitution::Expression(arg.clone())
+            }));
+        }
+        Ok(None)
+    }
+
+    fn resolve_label(&self, label: &str, span: &Span) -> Result<String> {
+        if let Some((_, renamed)) = self.labels.iter().rev().find(|(l, _)| l == label) {
+            Ok(renamed.clone())
+        } else if let Some(arg) = self.params.get(label) {
+            self.arg_name(arg, span)
+        } else {
+            Ok(label.to_string())
+        }
+    }
+
+    // params used as variable or label names have to be passed a plain name
+    fn arg_name(&self, arg: &ast::Expression, span: &Span) -> Result<String> {
+        match arg.expr {
+            ast::Expr::Variable { ref name, .. } => Ok(name.clone()),
+            _ => report_macro_error(
+                "Expected a name as macro argument",
+                span,
+                &arg.span,
+                "Argument given here",
+                self.sources,
+            ),
+        }
+    }
+}
+
+fn remap_spans(expr: &mut ast::Expression, source_id: usize) {
+    expr.span.0 = source_id;
+    match expr.expr {
+        ast::Expr::Peek(ref mut mem_location)
+        | ast::Expr::Poke {
+            ref mut mem_location,
+            ..
+        } => mem_location.span.0 = source_id,
+        _ => (),
+    }
+    for child in expr.expr.children_mut() {
+        remap_spans(child, source_id);
+    }
+}
+
+fn report_macro_error<T>(
+    msg: &str,
+    span: &Span,
+    other_span: &Span,
+    other_msg: &str,
+    sources: &Sources,
+) -> Result<T> {
+    Report::build(ReportKind::Error, span.0, span.1.start)
+        .with_message(msg)
+        .with_label(
+            Label::new(span.clone())
+                .with_message(msg)
+                .with_color(Color::Red),
+        )
+        .with_label(
+            Label::new(other_span.clone())
+                .with_message(other_msg)
+                .with_color(Color::Yellow),
+        )
+        .finish()
+        .eprint(sources)
+        .unwrap();
+    Err(())
+}
@@ -1,11 +1,18 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use std::io::prelude::*;
+use std::ops::Range;
 use std::{fs::File, path::PathBuf};
 
-use curlywas::{compile_file, Options};
+use curlywas::{compile_file, test_file, Options};
 
 fn main() -> Result<()> {
-    let mut args = pico_args::Arguments::from_env();
+    let mut args: Vec<_> = std::env::args_os().skip(1).collect();
+    // `curlywas test file.cwa` runs the tests of the script instead of compiling it
+    let test = args.first().is_some_and(|arg| arg == "test");
+    if test {
+        args.remove(0);
+    }
+    let mut args = pico_args::Arguments::from_vec(args);
 
     let mut options = Options::default();
 
@@ -13,8 +20,45 @@ fn main() -> Result<()> {
         options = options.with_debug();
     }
 
+    if args.contains(["-s", "--size-report"]) {
+        options = options.with_size_report();
+    }
+
+    for define in args.values_from_str::<_, String>(["-D", "--define"])? {
+        let (name, value) = define.split_once('=').unwrap_or((&define, ""));
+        options = options.with_define(name, value)?;
+    }
+
+    for lint in args.values_from_str(["-W", "--warn"])? {
+        options = options.with_lint(lint, true);
+    }
+
+    for lint in args.values_from_str(["-A", "--allow"])? {
+        options = options.with_lint(lint, false);
+    }
+
+    if let Some(range) = args.opt_value_from_fn("--memory-range", parse_range)? {
+        options = options.with_memory_range(range);
+    }
+
+    if let Some(format) = args.opt_value_from_str("--strings")? {
+        options = options.with_string_format(format);
+    }
+
     let mut filename = args.free_from_os_str::<PathBuf, bool>(|s| Ok(s.into()))?;
 
+    if test {
+        let summary = test_file(&filename, options)?;
+        if summary.failed > 0 {
+            bail!(
+                "{} of {} tests failed",
+                summary.failed,
+                summary.passed + summary.failed
+            );
+        }
+        return Ok(());
+    }
+
     let wasm = compile_file(&filename, options).0?;
 
     wasmparser::validate(&wasm)?;
@@ -24,3 +68,15 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+// parses a range of addresses like `0x100..0x10000`
+fn parse_range(range: &str) -> Result<Range<u64>> {
+    let parse = |value: &str| match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    let Some((start, end)) = range.split_once("..") else {
+        bail!("Invalid memory range {} (expected start..end)", range);
+    };
+    Ok(parse(start)?..parse(end)?)
+}
@@ -0,0 +1,335 @@
+use ariadne::{Color, Label, Report, ReportKind};
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast,
+    parser::{Sources, Span},
+};
+
+type Result<T> = std::result::Result<T, ()>;
+
+// A parsed source file together with the namespace it was included into,
+// eg. `lib::util` for a file included `as util` from a file included `as lib`.
+// The root script and files included without `as` share the namespace of their includer.
+pub struct Module {
+    pub namespace: String,
+    pub script: ast::Script,
+}
+
+pub fn qualify(namespace: &str, name: &str) -> String {
+    if namespace.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}::{}", namespace, name)
+    }
+}
+
+// functions from namespaced includes are exported under their plain name
+pub fn export_name(name: &str) -> &str {
+    name.rsplit("::").next().unwrap()
+}
+
+struct Symbol {
+    span: Span,
+    public: bool,
+}
+
+// Prefixes the names of all top-level items with the namespace of their module
+// and resolves all references to them to these qualified names.
+pub fn resolve_namespaces(modules: &mut [Module], sources: &Sources) -> Result<()> {
+    let mut symbols: HashMap<String, Symbol> = HashMap::new();
+
+    for module in modules.iter_mut() {
+        define_symbols(&mut symbols, &module.namespace, &mut module.script);
+    }
+
+    for module in modules.iter_mut() {
+        let mut resolver = Resolver {
+            symbols: &symbols,
+            namespace: &module.namespace,
+            scopes: Vec::new(),
+            sources,
+        };
+        resolver.resolve_script(&mut module.script)?;
+    }
+
+    Ok(())
+}
+
+fn define_symbols(
+    symbols: &mut HashMap<String, Symbol>,
+    namespace: &str,
+    script: &mut ast::Script,
+) {
+    let mut define = |name: &mut String, span: &Span, public: bool| {
+        *name = qualify(namespace, name);
+        symbols.entry(name.clone()).or_insert(Symbol {
+            span: span.clone(),
+            public,
+        });
+    };
+    for import in &mut script.imports {
+        match import.type_ {
+            ast::ImportType::Variable { ref mut name, .. }
+            | ast::ImportType::Function { ref mut name, .. } => {
+                define(name, &import.span, import.public)
+            }
+            ast::ImportType::Memory(_) => (),
+        }
+    }
+    for var in &mut script.global_vars {
+        define(&mut var.name, &var.span, var.public);
+    }
+    for cnst in &mut script.consts {
+        define(&mut cnst.name, &cnst.span, cnst.public);
+    }
+    for func in &mut script.functions {
+        define(&mut func.name, &func.span, func.public);
+    }
+    for strct in &mut script.structs {
+        define(&mut strct.name, &strct.span, strct.public);
+    }
+    for mac in &mut script.macros {
+        define(&mut mac.name, &mac.span, mac.public);
+    }
+    for data in &mut script.data {
+        define_labels(&mut define, &mut data.data);
+    }
+    for static_ in &mut script.statics {
+        define(
+            &mut format!("{}_len", static_.name),
+            &static_.span,
+            static_.public,
+        );
+        define(&mut static_.name, &static_.span, static_.public);
+        define_labels(&mut define, &mut static_.data);
+    }
+    // items in both branches of an `if const` can share a name
+    for conditional in &mut script.conditionals {
+        define_symbols(symbols, namespace, &mut conditional.if_true);
+        define_symbols(symbols, namespace, &mut conditional.if_false);
+    }
+}
+
+fn define_labels(
+    define: &mut impl FnMut(&mut String, &Span, bool),
+    values: &mut [ast::DataValues],
+) {
+    for value in values {
+        match value {
+            ast::DataValues::Label { span, public, name } => {
+                // each label also defines `<label>_len`
+                define(&mut format!("{}_len", name), span, *public);
+                define(name, span, *public);
+            }
+            ast::DataValues::Repeat { values, .. } => define_labels(define, values),
+            _ => (),
+        }
+    }
+}
+
+struct Resolver<'a> {
+    symbols: &'a HashMap<String, Symbol>,
+    namespace: &'a str,
+    scopes: Vec<HashSet<String>>,
+    sources: &'a Sources,
+}
+
+impl<'a> Resolver<'a> {
+    fn resolve_script(&mut self, script: &mut ast::Script) -> Result<()> {
+        for var in &mut script.global_vars {
+            self.resolve_expr(&mut var.value)?;
+        }
+        for cnst in &mut script.consts {
+            self.resolve_expr(&mut cnst.value)?;
+        }
+        for func in &mut script.functions {
+            self.scopes = vec![func.params.iter().map(|param| param.name.clone()).collect()];
+            self.resolve_expr(&mut func.body)?;
+        }
+        for mac in &mut script.macros {
+            self.scopes = vec![mac.params.iter().cloned().collect()];
+            self.resolve_expr(&mut mac.body)?;
+        }
+        self.scopes.clear();
+        for data in &mut script.data {
+            self.resolve_expr(&mut data.offset)?;
+            for values in &mut data.data {
+                for value in values.expressions_mut() {
+                    self.resolve_expr(value)?;
+                }
+            }
+        }
+        for static_ in &mut script.statics {
+            for values in &mut static_.data {
+                for value in values.expressions_mut() {
+                    self.resolve_expr(value)?;
+                }
+            }
+        }
+        for region in &mut script.static_regions {
+            self.resolve_expr(&mut region.start)?;
+            if let Some(ref mut end) = region.end {
+                self.resolve_expr(end)?;
+            }
+        }
+        for conditional in &mut script.conditionals {
+            self.resolve_expr(&mut conditional.condition)?;
+            self.resolve_script(&mut conditional.if_true)?;
+            self.resolve_script(&mut conditional.if_false)?;
+        }
+        Ok(())
+    }
+
+    fn resolve_expr(&mut self, expr: &mut ast::Expression) -> Result<()> {
+        match expr.expr {
+            ast::Expr::Block { .. } => {
+                self.scopes.push(HashSet::new());
+                let result = self.resolve_children(expr);
+                self.scopes.pop();
+                result?;
+            }
+            ast::Expr::Let {
+                ref name,
+                ref mut value,
+                ..
+            } => {
+                if let Some(value) = value {
+                    self.resolve_expr(value)?;
+                }
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(name.clone());
+                }
+            }
+            ast::Expr::Variable { ref mut name, .. } => self.resolve_variable(name, &expr.span)?,
+            ast::Expr::Assign {
+                ref mut name,
+                ref mut value,
+                ..
+            }
+            | ast::Expr::LocalTee {
+                ref mut name,
+                ref mut value,
+                ..
+            } => {
+                self.resolve_expr(value)?;
+                self.resolve_variable(name, &expr.span)?;
+            }
+            ast::Expr::FuncCall {
+                ref mut name,
+                ref mut params,
+            } => {
+                // the field name in `offsetof(Struct, field)` is not a reference to an item
+                let count = if name == "offsetof" { 1 } else { params.len() };
+                for param in params.iter_mut().take(count) {
+                    self.resolve_expr(param)?;
+                }
+                if let Some(resolved) = self.resolve(name, &expr.span)? {
+                    *name = resolved;
+                }
+            }
+            ast::Expr::Repeat {
+                ref mut count,
+                ref index,
+                ref mut body,
+            } => {
+                self.resolve_expr(count)?;
+                self.resolve_indexed(index, body)?;
+            }
+            ast::Expr::Sequence {
+                ref index,
+                ref mut start,
+                ref mut end,
+                ref mut value,
+            } => {
+                self.resolve_expr(start)?;
+                self.resolve_expr(end)?;
+                self.resolve_indexed(index, value)?;
+            }
+            _ => self.resolve_children(expr)?,
+        }
+        Ok(())
+    }
+
+    fn resolve_indexed(&mut self, index: &str, body: &mut ast::Expression) -> Result<()> {
+        self.scopes.push(HashSet::from_iter([index.to_string()]));
+        let result = self.resolve_expr(body);
+        self.scopes.pop();
+        result
+    }
+
+    fn resolve_children(&mut self, expr: &mut ast::Expression) -> Result<()> {
+        for child in expr.expr.children_mut() {
+            self.resolve_expr(child)?;
+        }
+        Ok(())
+    }
+
+    // handles field accesses like `base.field` and `base.Struct.field`
+    fn resolve_variable(&self, name: &mut String, span: &Span) -> Result<()> {
+        let (base, path) = match name.split_once('.') {
+            Some((base, path)) => (base, Some(path)),
+            None => (name.as_str(), None),
+        };
+        let base = if self.scopes.iter().any(|scope| scope.contains(base)) {
+            base.to_string()
+        } else {
+            self.resolve(base, span)?
+                .unwrap_or_else(|| base.to_string())
+        };
+        let path = match path.and_then(|path| path.split_once('.')) {
+            Some((struct_name, field)) => match self.resolve(struct_name, span)? {
+                Some(resolved) => Some(format!("{}.{}", resolved, field)),
+                None => Some(format!("{}.{}", struct_name, field)),
+            },
+            None => path.map(|path| path.to_string()),
+        };
+        *name = match path {
+            Some(path) => format!("{}.{}", base, path),
+            None => base,
+        };
+        Ok(())
+    }
+
+    // Looks up `name` in the namespace of the module and then in each enclosing namespace.
+    // Names not found are left alone, they are either locals, intrinsics or errors
+    // reported in later stages.
+    fn resolve(&self, name: &str, span: &Span) -> Result<Option<String>> {
+        let mut namespace = self.namespace;
+        loop {
+            let qualified = qualify(namespace, name);
+            if let Some(symbol) = self.symbols.get(&qualified) {
+                // items of nested namespaces are only accessible if they are `pub`
+                let item_namespace = qualified.rsplit_once("::").map_or("", |(ns, _)| ns);
+                if item_namespace != namespace && !symbol.public {
+                    return report_private(name, span, &symbol.span, self.sources);
+                }
+                return Ok(Some(qualified));
+            }
+            if namespace.is_empty() {
+                return Ok(None);
+            }
+            namespace = namespace.rsplit_once("::").map_or("", |(ns, _)| ns);
+        }
+    }
+}
+
+fn report_private<T>(name: &str, span: &Span, item_span: &Span, sources: &Sources) -> Result<T> {
+    let msg = format!("{} is not pub", name);
+    Report::build(ReportKind::Error, span.0, span.1.start)
+        .with_message(&msg)
+        .with_label(
+            Label::new(span.clone())
+                .with_message(&msg)
+                .with_color(Color::Red),
+        )
+        .with_label(
+            Label::new(item_span.clone())
+                .with_message("Defined here")
+                .with_color(Color::Yellow),
+        )
+        .finish()
+        .eprint(sources)
+        .unwrap();
+    Err(())
+}
@@ -1,4 +1,7 @@
+#![allow(clippy::result_large_err)]
+
 use crate::ast;
+use crate::warnings::Lint;
 use anyhow::Result;
 use ariadne::{Color, Fmt, Label, Report, ReportKind};
 use chumsky::prelude::*;
@@ -8,13 +11,17 @@ use std::{
     io::Read,
     ops::Range,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
 pub type Span = (usize, Range<usize>);
 
 pub struct SourceFile {
-    source: ariadne::Source,
-    path: PathBuf,
+    source: Arc<ariadne::Source>,
+    path: Option<PathBuf>,
+    name: String,
+    // the source this is a copy of, see `add_copy`
+    copy_of: Option<usize>,
 }
 
 pub struct Sources(Vec<SourceFile>);
@@ -24,20 +31,88 @@ impl Sources {
         Sources(Vec::new())
     }
 
-    pub fn add(&mut self, path: &Path) -> Result<(usize, bool)> {
+    pub fn add(&mut self, path: &Path) -> Result<usize> {
         let canonical = path.canonicalize()?;
         for (index, source) in self.0.iter().enumerate() {
-            if source.path.canonicalize()? == canonical {
-                return Ok((index, false));
+            if let Some(ref source_path) = source.path {
+                if source_path.canonicalize()? == canonical {
+                    return Ok(index);
+                }
             }
         }
         let mut source = String::new();
         File::open(path)?.read_to_string(&mut source)?;
         self.0.push(SourceFile {
-            source: ariadne::Source::from(source),
-            path: path.to_path_buf(),
+            source: Arc::new(ariadne::Source::from(source)),
+            path: Some(path.to_path_buf()),
+            name: path.display().to_string(),
+            copy_of: None,
+        });
+        Ok(self.0.len() - 1)
+    }
+
+    // adds a copy of an existing source under a different name, used to show
+    // where code generated from that source (eg. by a macro expansion) came from
+    pub fn add_copy(&mut self, id: usize, name: String) -> usize {
+        let source = self.0[id].source.clone();
+        self.0.push(SourceFile {
+            source,
+            path: None,
+            name,
+            copy_of: Some(id),
+        });
+        self.0.len() - 1
+    }
+
+    // adds a source built into the compiler, like the decompressor for compressed data
+    pub fn add_builtin(&mut self, name: &str, source: &str) -> usize {
+        self.0.push(SourceFile {
+            source: Arc::new(ariadne::Source::from(source)),
+            path: None,
+            name: name.to_string(),
+            copy_of: None,
         });
-        Ok((self.0.len() - 1, true))
+        self.0.len() - 1
+    }
+
+    // the id of the source parsed from the file, for copies made by `add_copy`
+    pub fn original(&self, mut id: usize) -> usize {
+        while let Some(copy_of) = self.0[id].copy_of {
+            id = copy_of;
+        }
+        id
+    }
+
+    pub fn name(&self, id: usize) -> &str {
+        &self.0[id].name
+    }
+
+    // the file the source was loaded from, None for builtin sources and copies
+    pub fn path(&self, id: usize) -> Option<&Path> {
+        self.0[id].path.as_deref()
+    }
+
+    // the (1-based) line the span starts on
+    pub fn line(&self, span: &Span) -> u32 {
+        self.location(span).0
+    }
+
+    // the (1-based) line and column the span starts at
+    pub fn location(&self, span: &Span) -> (u32, u32) {
+        self.0[span.0]
+            .source
+            .get_offset_line(span.1.start)
+            .map_or((0, 0), |(_, line, column)| {
+                (line as u32 + 1, column as u32 + 1)
+            })
+    }
+
+    pub fn describe_location(&self, span: &Span) -> String {
+        let file = &self.0[span.0];
+        match file.source.get_offset_line(span.1.start) {
+            Some((_, line, column)) => format!("{}:{}:{}", file.name, line + 1, column + 1),
+            None => file.name.clone(),
+        }
     }
 }
 
@@ -50,11 +125,11 @@ impl std::ops::Index<usize> for Sources {
 
 impl ariadne::Cache<usize> for &Sources {
     fn fetch(&mut self, id: &usize) -> Result<&ariadne::Source, Box<dyn std::fmt::Debug + '_>> {
-        Ok(&self.0[*id].source)
+        Ok(&*self.0[*id].source)
     }
 
     fn display<'a>(&self, id: &'a usize) -> Option<Box<dyn std::fmt::Display + 'a>> {
-        Some(Box::new(self.0[*id].path.clone().display().to_string()))
+        Some(Box::new(self.0[*id].name.clone()))
     }
 }
 
@@ -77,11 +152,12 @@ enum Token {
     Return,
     Ident(String),
     Str(String),
+    ByteStr(Vec<u8>),
     Int(i32),
     Int64(i64),
     IntFloat(i32),
-    Float(String),
-    Float64(String),
+    Float(u32),
+    Float64(u64),
     Op(String),
     Ctrl(char),
 }
@@ -106,11 +182,12 @@ impl fmt::Display for Token {
             Token::Return => write!(f, "return"),
             Token::Ident(s) => write!(f, "{}", s),
             Token::Str(s) => write!(f, "{:?}", s),
+            Token::ByteStr(s) => write!(f, "b\"{}\"", s.escape_ascii()),
             Token::Int(v) => write!(f, "{}", v),
             Token::Int64(v) => write!(f, "{}", v),
             Token::IntFloat(v) => write!(f, "{}_f", v),
-            Token::Float(v) => write!(f, "{}", v),
-            Token::Float64(v) => write!(f, "{}", v),
+            Token::Float(v) => write!(f, "{}", f32::from_bits(*v)),
+            Token::Float64(v) => write!(f, "{}f64", f64::from_bits(*v)),
             Token::Op(s) => write!(f, "{}", s),
             Token::Ctrl(c) => write!(f, "{}", c),
         }
@@ -250,16 +327,88 @@ fn report_errors(errors: Vec<Simple<String, Span>>, sources: &Sources) {
 
 type LexerError = Simple<char, Span>;
 fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = LexerError> {
-    let float64 = text::digits(10)
-        .chain::<char, _, _>(just('.').chain(text::digits(10)))
-        .then_ignore(just("f64"))
-        .collect::<String>()
-        .map(Token::Float64);
+    let exponent = |marker: &'static str| {
+        one_of(marker)
+            .ignore_then(one_of("+-").or_not())
+            .then(text::digits(10))
+            .map(|(sign, digits): (Option<char>, String)| {
+                let mut exponent = String::new();
+                if let Some(sign) = sign {
+                    exponent.push(sign);
+                }
+                exponent + &digits
+            })
+    };
 
-    let float = text::digits(10)
-        .chain::<char, _, _>(just('.').chain(text::digits(10)))
-        .collect::<String>()
-        .map(Token::Float);
+    let decimal_float = text::digits(10)
+        .then(just('.').ignore_then(text::digits(10)).or_not())
+        .then(exponent("eE").or_not())
+        .try_map(|((int, frac), exp), span: Span| {
+            if frac.is_none() && exp.is_none() {
+                return Err(LexerError::custom(span, "Not a float literal"));
+            }
+            let mut number = int;
+            if let Some(frac) = frac {
+                number.push('.');
+                number += &frac;
+            }
+            if let Some(exp) = exp {
+                number.push('e');
+                number += &exp;
+            }
+            Ok(FloatLiteral::Decimal(number))
+        });
+
+    let hex_float = just("0x")
+        .ignore_then(text::digits(16))
+        .then(just('.').ignore_then(text::digits(16).or_not()).or_not())
+        .then(exponent("pP"))
+        .validate(|((int, frac), exp), span: Span, emit| {
+            let frac = frac.flatten().unwrap_or_default();
+            // exponents outside of i32 are rejected, so the arithmetic in `hex_float_bits`
+            // can't overflow
+            let exponent = exp
+                .parse::<i64>()
+                .ok()
+                .and_then(|exp| exp.checked_sub(4 * frac.len() as i64))
+                .filter(|exp| i32::try_from(*exp).is_ok());
+            match exponent {
+                Some(exponent) => FloatLiteral::Hex {
+                    exponent,
+                    digits: int + &frac,
+                },
+                None => {
+                    emit(LexerError::custom(span, "Hex float out of range"));
+                    FloatLiteral::Decimal("0".to_string())
+                }
+            }
+        });
+
+    let float = hex_float
+        .or(decimal_float)
+        .then(just("f64").or_not())
+        .validate(|(literal, f64_suffix), span: Span, emit| {
+            if f64_suffix.is_some() {
+                let value = literal.to_f64().unwrap_or_else(|| {
+                    emit(LexerError::custom(
+                        span,
+                        "Float literal out of range for f64",
+                    ));
+                    f64::INFINITY
+                });
+                Token::Float64(value.to_bits())
+            } else {
+                let value = literal.to_f32().unwrap_or_else(|| {
+                    emit(LexerError::custom(
+                        span,
+                        "Float literal out of range for f32",
+                    ));
+                    f32::INFINITY
+                });
+                Token::Float(value.to_bits())
+            }
+        })
+        .boxed();
 
     let integer = just::<_, _, LexerError>("0x")
         .ignore_then(text::digits(16))
@@ -288,15 +437,34 @@ fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = LexerError> {
             .map_err(|err| LexerError::custom(span, err.to_string()))
     });
 
-    let str_ = just('\\')
+    let string_body = just('\\')
         .then(any())
         .map(|t| vec![t.0, t.1])
         .or(none_of("\"").map(|c| vec![c]))
         .repeated()
         .flatten()
         .delimited_by(just('"'), just('"'))
-        .collect::<String>()
-        .map(|s| Token::Str(parse_string_escapes(s)));
+        .collect::<String>();
+
+    // invalid strings are reported, but still produce a token to not derail the parser
+    let str_ = string_body.clone().validate(|s, span, emit| {
+        let bytes = parse_string_escapes(&s, StringKind::Text).unwrap_or_else(|msg| {
+            emit(LexerError::custom(span, msg));
+            Vec::new()
+        });
+        Token::Str(String::from_utf8(bytes).unwrap())
+    });
+
+    let byte_str = just('b')
+        .ignore_then(string_body)
+        .validate(|s, span, emit| {
+            Token::ByteStr(
+                parse_string_escapes(&s, StringKind::Bytes).unwrap_or_else(|msg| {
+                    emit(LexerError::custom(span, msg));
+                    Vec::new()
+                }),
+            )
+        });
 
     let char_ = just('\\')
         .then(any())
@@ -306,14 +474,27 @@ fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = LexerError> {
         .flatten()
         .delimited_by(just('\''), just('\''))
         .collect::<String>()
-        .map(|s| {
-            let s = parse_string_escapes(s);
-            let mut value = 0;
-            for (i, c) in s.chars().enumerate() {
-                // TODO: generate error on overflow
-                if i < 4 {
-                    value |= (c as u32) << (i * 8);
+        .validate(|s: String, span: Span, emit| {
+            let bytes = match parse_string_escapes(&s, StringKind::Char) {
+                Ok(bytes) if bytes.len() > 4 => {
+                    emit(LexerError::custom(
+                        span,
+                        format!(
+                            "Character literal is {} bytes long, the maximum is 4",
+                            bytes.len()
+                        ),
+                    ));
+                    Vec::new()
                 }
+                Ok(bytes) => bytes,
+                Err(msg) => {
+                    emit(LexerError::custom(span, msg));
+                    Vec::new()
+                }
+            };
+            let mut value = 0;
+            for (i, byte) in bytes.into_iter().enumerate() {
+                value |= (byte as u32) << (i * 8);
             }
             Token::Int(value as i32)
         });
@@ -335,21 +516,37 @@ fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = LexerError> {
         just("->"),
         just(":="),
         just("<|"),
+        just("??"),
+        just("~~"),
+        just("!!"),
+        just("$$"),
+        just(".."),
+        just("#.."),
     ))
     .map(|s| s.to_string())
     .or(one_of("+-*/%&^|<=>").map(|s: char| s.to_string()))
     .map(Token::Op)
     .boxed();
 
-    let ctrl = one_of("(){};,:?!$").map(Token::Ctrl);
+    let ctrl = one_of("(){}[];,:?!$~#").map(Token::Ctrl);
 
-    fn ident() -> impl Parser<char, String, Error = LexerError> + Copy + Clone {
-        filter(|c: &char| c.is_ascii_alphabetic() || *c == '_')
+    fn ident() -> impl Parser<char, String, Error = LexerError> + Copy {
+        let segment = filter(|c: &char| c.is_ascii_alphabetic() || *c == '_')
             .map(Some)
             .chain::<char, Vec<_>, _>(
-                filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_' || *c == '.').repeated(),
+                filter(|c: &char| c.is_ascii_alphanumeric() || *c == '_')
+                    // don't swallow the `..` of a range like `a..b`
+                    .or(just('.').then_ignore(filter(|c: &char| *c != '.').rewind()))
+                    .repeated(),
             )
-            .collect()
+            .collect::<String>();
+        // qualified names like `module::name`
+        segment
+            .then(just(':').then(just(':')).ignore_then(segment).repeated())
+            .map(|(first, rest): (String, Vec<String>)| {
+                rest.into_iter()
+                    .fold(first, |name, segment| name + "::" + &segment)
+            })
     }
 
     let ident = ident().map(|ident: String| match ident.as_str() {
@@ -378,7 +575,7 @@ fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = LexerError> {
     let comment = single_line.or(multi_line);
 
     let token = choice((
-        float, float64, int64, int_float, int, str_, char_, op, ctrl, ident,
+        float, int64, int_float, int, byte_str, str_, char_, op, ctrl, ident,
     ))
     .recover_with(skip_then_retry_until([]));
 
@@ -406,12 +603,31 @@ fn map_token<O>(
     })
 }
 
-fn parse_string_escapes(s: String) -> String {
-    let mut result = String::new();
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StringKind {
+    // UTF-8 text, byte escapes are limited to ASCII
+    Text,
+    // `b"..."`, escapes produce exact bytes, other characters have to be ASCII
+    Bytes,
+    // character literals, both UTF-8 characters and byte escapes are allowed
+    Char,
+}
+
+fn parse_string_escapes(s: &str, kind: StringKind) -> Result<Vec<u8>, String> {
+    fn push_char(result: &mut Vec<u8>, c: char) {
+        result.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes())
+    }
+    let mut result = Vec::new();
     let mut chars = s.chars().peekable();
     while let Some(c) = chars.next() {
         if c != '\\' {
-            result.push(c);
+            if kind == StringKind::Bytes && !c.is_ascii() {
+                return Err(format!(
+                    "Non-ASCII character '{}' in byte string, use escapes instead",
+                    c
+                ));
+            }
+            push_char(&mut result, c);
         } else if let Some(c) = chars.next() {
             match c {
                 '0'..='9' | 'a'..='f' | 'A'..='F' => {
@@ -419,18 +635,141 @@ fn parse_string_escapes(s: String) -> String {
                     if let Some('0'..='9' | 'a'..='f' | 'A'..='F') = chars.peek() {
                         number.push(chars.next().unwrap());
                     }
-                    result.push(u8::from_str_radix(&number, 16).unwrap() as char);
+                    let byte = u8::from_str_radix(&number, 16).unwrap();
+                    if kind == StringKind::Text && byte > 0x7f {
+                        return Err(format!(
+                            "Escape \\{} is not valid UTF-8, use a byte string (b\"...\") or \\u{{{}}}",
+                            number, number
+                        ));
+                    }
+                    result.push(byte);
+                }
+                'u' if kind == StringKind::Bytes => {
+                    return Err(
+                        "Unicode escape in byte string, use byte escapes instead".to_string()
+                    )
+                }
+                'u' => {
+                    let mut number = String::new();
+                    if chars.next() == Some('{') {
+                        for c in chars.by_ref() {
+                            if c == '}' {
+                                break;
+                            }
+                            number.push(c);
+                        }
+                    }
+                    match u32::from_str_radix(&number, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                    {
+                        Some(c) => push_char(&mut result, c),
+                        None => {
+                            return Err("Invalid unicode escape, expected \\u{<hex code point>}"
+                                .to_string())
+                        }
+                    }
                 }
-                'n' => result.push('\n'),
-                'r' => result.push('\r'),
-                't' => result.push('\t'),
-                other => result.push(other),
+                'n' => result.push(b'\n'),
+                'r' => result.push(b'\r'),
+                't' => result.push(b'\t'),
+                other => push_char(&mut result, other),
+            }
+        } else {
+            result.push(b'\\');
+        }
+    }
+    Ok(result)
+}
+
+enum FloatLiteral {
+    Decimal(String),
+    Hex { digits: String, exponent: i64 },
+}
+
+impl FloatLiteral {
+    fn to_f32(&self) -> Option<f32> {
+        match self {
+            FloatLiteral::Decimal(s) => s.parse::<f32>().ok().filter(|v| v.is_finite()),
+            FloatLiteral::Hex { digits, exponent } => {
+                hex_float_bits(digits, *exponent, 23, 127).map(|bits| f32::from_bits(bits as u32))
+            }
+        }
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        match self {
+            FloatLiteral::Decimal(s) => s.parse::<f64>().ok().filter(|v| v.is_finite()),
+            FloatLiteral::Hex { digits, exponent } => {
+                hex_float_bits(digits, *exponent, 52, 1023).map(f64::from_bits)
             }
+        }
+    }
+}
+
+// Converts the hex digits `digits` times 2^`exponent` into the bit pattern of an
+// IEEE float with the given mantissa size and exponent bias, rounding to nearest even.
+// Returns None if the value is too large to be represented. The exponent has to be
+// within i32, so the exponent arithmetic can't overflow.
+fn hex_float_bits(digits: &str, exponent: i64, mantissa_bits: u32, bias: i64) -> Option<u64> {
+    let mut mantissa: u128 = 0;
+    let mut exponent = exponent;
+    let mut sticky = false;
+    for c in digits.chars() {
+        let digit = c.to_digit(16).unwrap() as u128;
+        if mantissa >> 120 == 0 {
+            mantissa = mantissa * 16 + digit;
+        } else {
+            exponent += 4;
+            sticky |= digit != 0;
+        }
+    }
+    if mantissa == 0 {
+        return Some(0);
+    }
+
+    let msb = 127 - mantissa.leading_zeros() as i64;
+    let mut value_exponent = msb + exponent;
+    let precision = mantissa_bits as i64 + 1;
+    let min_exponent = 1 - bias;
+    let subnormal = value_exponent < min_exponent;
+    let shift = msb - (precision - 1)
+        + if subnormal {
+            min_exponent - value_exponent
         } else {
-            result.push('\\');
+            0
+        };
+
+    let mut significand = if shift <= 0 {
+        mantissa << -shift
+    } else if shift >= 128 {
+        0
+    } else {
+        let truncated = mantissa >> shift;
+        let rest = mantissa & ((1 << shift) - 1);
+        let half = 1 << (shift - 1);
+        if rest > half || (rest == half && (sticky || truncated & 1 == 1)) {
+            truncated + 1
+        } else {
+            truncated
         }
+    };
+
+    if subnormal {
+        // a carry out of the subnormal range yields the smallest normal number
+        return Some(significand as u64);
+    }
+    if significand == 1 << precision {
+        significand >>= 1;
+        value_exponent += 1;
     }
-    result
+    if value_exponent > bias {
+        return None;
+    }
+    Some(
+        (((value_exponent + bias) as u64) << mantissa_bits)
+            | (significand as u64 & ((1 << mantissa_bits) - 1)),
+    )
 }
 
 type ScriptError = Simple<Token, Span>;
@@ -445,6 +784,15 @@ fn script_parser() -> impl Parser<Token, ast::Script, Error = ScriptError> + Clo
     })
     .labelled("identifier");
 
+    let field_path = identifier
+        .then(
+            just(Token::Op("->".to_string()))
+                .ignore_then(identifier)
+                .repeated(),
+        )
+        .foldl(|base, field| format!("{}.{}", base, field))
+        .boxed();
+
     let integer = map_token(|tok, _| match tok {
         Token::Int(v) => Some(*v),
         _ => None,
@@ -455,6 +803,13 @@ fn script_parser() -> impl Parser<Token, ast::Script, Error = ScriptError> + Clo
         _ => None,
     });
 
+    // the data of a string or byte string
+    let bytes = map_token(|tok, _| match tok {
+        Token::Str(s) => Some(s.as_bytes().to_vec()),
+        Token::ByteStr(s) => Some(s.clone()),
+        _ => None,
+    });
+
     let product_op = just(Token::Op("*".to_string()))
         .to(ast::BinOp::Mul)
         .or(just(Token::Op("/".to_string())).to(ast::BinOp::Div))
@@ -480,417 +835,527 @@ fn script_parser() -> impl Parser<Token, ast::Script, Error = ScriptError> + Clo
     let mut expression_out = None;
     let block = recursive(|block| {
         let mut block_expression = None;
-        let expression = recursive(|expression| {
-            let val = map_token(|tok, span| match tok {
-                Token::Int(v) => Some(ast::Expr::I32Const(*v)),
-                Token::Int64(v) => Some(ast::Expr::I64Const(*v)),
-                Token::IntFloat(v) => Some(ast::Expr::Cast {
-                    value: Box::new(ast::Expr::I32Const(*v).with_span(span.clone())),
-                    type_: ast::Type::F32,
-                }),
-                Token::Float(v) => Some(ast::Expr::F32Const(v.parse().unwrap())),
-                Token::Float64(v) => Some(ast::Expr::F64Const(v.parse().unwrap())),
-                _ => None,
-            })
-            .labelled("value");
+        let expression =
+            recursive(|expression| {
+                let val = map_token(|tok, span| match tok {
+                    Token::Int(v) => Some(ast::Expr::I32Const(*v)),
+                    Token::Int64(v) => Some(ast::Expr::I64Const(*v)),
+                    Token::IntFloat(v) => Some(ast::Expr::Cast {
+                        value: Box::new(ast::Expr::I32Const(*v).with_span(span.clone())),
+                        type_: ast::Type::F32,
+                    }),
+                    Token::Float(v) => Some(ast::Expr::F32Const(f32::from_bits(*v))),
+                    Token::Float64(v) => Some(ast::Expr::F64Const(f64::from_bits(*v))),
+                    Token::Str(s) => Some(ast::Expr::Str(s.as_bytes().to_vec())),
+                    Token::ByteStr(s) => Some(ast::Expr::Str(s.clone())),
+                    _ => None,
+                })
+                .labelled("value");
 
-            let variable = filter_map(|span, tok| match tok {
-                Token::Ident(id) => Ok(ast::Expr::Variable {
-                    name: id,
-                    local_id: None,
-                }),
-                _ => Err(ScriptError::expected_input_found(
-                    span,
-                    Vec::new(),
-                    Some(tok),
-                )),
-            })
-            .labelled("variable");
+                let variable = field_path
+                    .clone()
+                    .map(|name| ast::Expr::Variable {
+                        name,
+                        local_id: None,
+                    })
+                    .labelled("variable");
+
+                let local_tee = identifier
+                    .then(just(Token::Op(":=".to_string())).ignore_then(expression.clone()))
+                    .map(|(name, expr)| ast::Expr::LocalTee {
+                        name,
+                        value: Box::new(expr),
+                        local_id: None,
+                    })
+                    .boxed();
 
-            let local_tee = identifier
-                .then(just(Token::Op(":=".to_string())).ignore_then(expression.clone()))
-                .map(|(name, expr)| ast::Expr::LocalTee {
-                    name,
-                    value: Box::new(expr),
-                    local_id: None,
-                })
-                .boxed();
+                let local_tee_op = identifier
+                    .then(
+                        product_op
+                            .clone()
+                            .or(sum_op.clone())
+                            .or(shift_op.clone())
+                            .or(bit_op.clone()),
+                    )
+                    .then_ignore(just(Token::Op(":=".to_string())))
+                    .then(expression.clone())
+                    .map_with_span(|((name, op), expr), span| ast::Expr::LocalTee {
+                        name: name.clone(),
+                        value: Box::new(
+                            ast::Expr::BinOp {
+                                left: Box::new(
+                                    ast::Expr::Variable {
+                                        name,
+                                        local_id: None,
+                                    }
+                                    .with_span(span.clone()),
+                                ),
+                                right: Box::new(expr),
+                                op,
+                            }
+                            .with_span(span),
+                        ),
+                        local_id: None,
+                    })
+                    .boxed();
 
-            let local_tee_op = identifier
-                .then(
-                    product_op
-                        .clone()
-                        .or(sum_op.clone())
-                        .or(shift_op.clone())
-                        .or(bit_op.clone()),
-                )
-                .then_ignore(just(Token::Op(":=".to_string())))
-                .then(expression.clone())
-                .map_with_span(|((name, op), expr), span| ast::Expr::LocalTee {
-                    name: name.clone(),
-                    value: Box::new(
-                        ast::Expr::BinOp {
-                            left: Box::new(
-                                ast::Expr::Variable {
-                                    name,
-                                    local_id: None,
-                                }
-                                .with_span(span.clone()),
-                            ),
-                            right: Box::new(expr),
-                            op,
-                        }
-                        .with_span(span),
-                    ),
-                    local_id: None,
-                })
-                .boxed();
+                let loop_expr = just(Token::Loop)
+                    .ignore_then(identifier)
+                    .then(block.clone())
+                    .map(|(label, block)| ast::Expr::Loop {
+                        label,
+                        block: Box::new(block),
+                    });
 
-            let loop_expr = just(Token::Loop)
-                .ignore_then(identifier)
-                .then(block.clone())
-                .map(|(label, block)| ast::Expr::Loop {
-                    label,
-                    block: Box::new(block),
+                let label_block_expr = just(Token::Block)
+                    .ignore_then(identifier)
+                    .then(block.clone())
+                    .map(|(label, block)| ast::Expr::LabelBlock {
+                        label,
+                        block: Box::new(block),
+                    });
+
+                let const_if_expr = recursive::<_, ast::Expr, _, _, _>(|const_if_expr| {
+                    just(Token::If)
+                        .ignore_then(just(Token::Ident("const".to_string())))
+                        .ignore_then(expression.clone())
+                        .then(block.clone())
+                        .then(
+                            just(Token::Else)
+                                .ignore_then(block.clone().or(
+                                    const_if_expr.map_with_span(|expr, span| expr.with_span(span)),
+                                ))
+                                .or_not(),
+                        )
+                        .map(|((condition, if_true), if_false)| ast::Expr::ConstIf {
+                            condition: Box::new(condition),
+                            if_true: Box::new(if_true),
+                            if_false: if_false.map(Box::new),
+                        })
                 });
 
-            let label_block_expr = just(Token::Block)
-                .ignore_then(identifier)
-                .then(block.clone())
-                .map(|(label, block)| ast::Expr::LabelBlock {
-                    label,
-                    block: Box::new(block),
-                });
+                let if_expr =
+                    recursive::<_, ast::Expr, _, _, _>(|if_expr| {
+                        just(Token::If)
+                            .ignore_then(expression.clone())
+                            .then(block.clone())
+                            .then(
+                                just(Token::Else)
+                                    .ignore_then(block.clone().or(
+                                        if_expr.map_with_span(|expr, span| expr.with_span(span)),
+                                    ))
+                                    .or_not(),
+                            )
+                            .map(|((condition, if_true), if_false)| ast::Expr::If {
+                                condition: Box::new(condition),
+                                if_true: Box::new(if_true),
+                                if_false: if_false.map(Box::new),
+                            })
+                    });
+
+                let repeat_expr = just(Token::Ident("repeat".to_string()))
+                    .ignore_then(expression.clone())
+                    .then_ignore(just(Token::As))
+                    .then(identifier)
+                    .then(block.clone())
+                    .map(|((count, index), body)| ast::Expr::Repeat {
+                        count: Box::new(count),
+                        index,
+                        body: Box::new(body),
+                    });
 
-            let if_expr = recursive::<_, ast::Expr, _, _, _>(|if_expr| {
-                just(Token::If)
+                let while_loop = just(Token::Ident("while".to_string()))
                     .ignore_then(expression.clone())
                     .then(block.clone())
+                    .map(|(condition, body)| LoopSugar::While { condition, body });
+
+                let for_loop = just(Token::Ident("for".to_string()))
+                    .ignore_then(identifier)
+                    .then_ignore(just(Token::Ident("in".to_string())))
+                    .then(expression.clone())
                     .then(
-                        just(Token::Else)
-                            .ignore_then(
-                                block
-                                    .clone()
-                                    .or(if_expr.map_with_span(|expr, span| expr.with_span(span))),
-                            )
+                        just(Token::Op("..".to_string()))
+                            .to(false)
+                            .or(just(Token::Op("#..".to_string())).to(true)),
+                    )
+                    .then(expression.clone())
+                    .then(
+                        just(Token::Ident("step".to_string()))
+                            .ignore_then(expression.clone())
                             .or_not(),
                     )
-                    .map(|((condition, if_true), if_false)| ast::Expr::If {
+                    .then(block.clone())
+                    .map(
+                        |(((((var, start), unsigned), end), step), body)| LoopSugar::For {
+                            var,
+                            start: Box::new(start),
+                            end: Box::new(end),
+                            step: step.map(Box::new),
+                            unsigned,
+                            body,
+                        },
+                    );
+
+                let sugared_loop = identifier
+                    .then_ignore(just(Token::Ctrl(':')))
+                    .or_not()
+                    .then(while_loop.or(for_loop))
+                    .map_with_span(|(name, sugar), span| sugar.desugar(name, span))
+                    .boxed();
+
+                let block_expr = loop_expr
+                    .or(label_block_expr)
+                    .or(const_if_expr)
+                    .or(if_expr)
+                    .or(repeat_expr)
+                    .or(sugared_loop)
+                    .boxed();
+
+                block_expression = Some(block_expr.clone());
+
+                let branch = just(Token::Branch)
+                    .ignore_then(identifier)
+                    .map(ast::Expr::Branch);
+
+                let break_ = just(Token::Ident("break".to_string()))
+                    .ignore_then(identifier.or_not())
+                    .map(|label| ast::Expr::Branch(label.unwrap_or_else(|| "#break".to_string())));
+
+                let continue_ = just(Token::Ident("continue".to_string()))
+                    .ignore_then(identifier.or_not())
+                    .map(|label| {
+                        ast::Expr::Branch(match label {
+                            Some(label) => format!("{}#continue", label),
+                            None => "#continue".to_string(),
+                        })
+                    });
+
+                let branch_if = just(Token::BranchIf)
+                    .ignore_then(expression.clone())
+                    .then_ignore(just(Token::Ctrl(':')))
+                    .then(identifier)
+                    .map(|(condition, label)| ast::Expr::BranchIf {
                         condition: Box::new(condition),
-                        if_true: Box::new(if_true),
-                        if_false: if_false.map(Box::new),
+                        label,
                     })
-            });
-
-            let block_expr = loop_expr.or(label_block_expr).or(if_expr).boxed();
-
-            block_expression = Some(block_expr.clone());
-
-            let branch = just(Token::Branch)
-                .ignore_then(identifier)
-                .map(ast::Expr::Branch);
+                    .boxed();
 
-            let branch_if = just(Token::BranchIf)
-                .ignore_then(expression.clone())
-                .then_ignore(just(Token::Ctrl(':')))
-                .then(identifier)
-                .map(|(condition, label)| ast::Expr::BranchIf {
-                    condition: Box::new(condition),
-                    label,
-                })
-                .boxed();
-
-            let let_ = just(Token::Let)
-                .ignore_then(
-                    (just(Token::Lazy)
-                        .to(ast::LetType::Lazy)
-                        .or(just(Token::Inline).to(ast::LetType::Inline)))
-                    .or_not(),
-                )
-                .then(identifier)
-                .then(just(Token::Ctrl(':')).ignore_then(type_parser()).or_not())
-                .then(
-                    just(Token::Op("=".to_string()))
-                        .ignore_then(expression.clone())
+                let let_ = just(Token::Let)
+                    .ignore_then(
+                        (just(Token::Lazy)
+                            .to(ast::LetType::Lazy)
+                            .or(just(Token::Inline).to(ast::LetType::Inline)))
                         .or_not(),
-                )
-                .map(|(((let_type, name), type_), value)| ast::Expr::Let {
-                    name,
-                    type_,
-                    value: value.map(Box::new),
-                    let_type: let_type.unwrap_or(ast::LetType::Normal),
-                    local_id: None,
-                })
-                .boxed();
+                    )
+                    .then(identifier)
+                    .then(just(Token::Ctrl(':')).ignore_then(type_parser()).or_not())
+                    .then(
+                        just(Token::Op("=".to_string()))
+                            .ignore_then(expression.clone())
+                            .or_not(),
+                    )
+                    .map(|(((let_type, name), type_), value)| ast::Expr::Let {
+                        name,
+                        type_,
+                        value: value.map(Box::new),
+                        let_type: let_type.unwrap_or(ast::LetType::Normal),
+                        local_id: None,
+                    })
+                    .boxed();
+
+                let select = just(Token::Select)
+                    .ignore_then(
+                        expression
+                            .clone()
+                            .then_ignore(just(Token::Ctrl(',')))
+                            .then(expression.clone())
+                            .then_ignore(just(Token::Ctrl(',')))
+                            .then(expression.clone())
+                            .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+                    )
+                    .map(|((condition, if_true), if_false)| ast::Expr::Select {
+                        condition: Box::new(condition),
+                        if_true: Box::new(if_true),
+                        if_false: Box::new(if_false),
+                    })
+                    .boxed();
 
-            let select = just(Token::Select)
-                .ignore_then(
-                    expression
-                        .clone()
-                        .then_ignore(just(Token::Ctrl(',')))
-                        .then(expression.clone())
-                        .then_ignore(just(Token::Ctrl(',')))
-                        .then(expression.clone())
-                        .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
-                )
-                .map(|((condition, if_true), if_false)| ast::Expr::Select {
-                    condition: Box::new(condition),
-                    if_true: Box::new(if_true),
-                    if_false: Box::new(if_false),
-                })
-                .boxed();
+                let assert = just(Token::Ident("assert".to_string()))
+                    .ignore_then(
+                        expression
+                            .clone()
+                            .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+                    )
+                    .map(|condition| ast::Expr::Assert(Box::new(condition)))
+                    .boxed();
 
-            let function_call = identifier
-                .then(
-                    expression
-                        .clone()
-                        .separated_by(just(Token::Ctrl(',')))
-                        .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
-                )
-                .map(|(name, params)| ast::Expr::FuncCall { name, params })
+                let function_call = identifier
+                    .then(
+                        expression
+                            .clone()
+                            .separated_by(just(Token::Ctrl(',')))
+                            .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+                    )
+                    .map(|(name, params)| ast::Expr::FuncCall { name, params })
+                    .boxed();
+
+                let return_ = just(Token::Return)
+                    .ignore_then(expression.clone().or_not())
+                    .map(|value| ast::Expr::Return {
+                        value: value.map(Box::new),
+                    });
+
+                let atom = choice((
+                    val,
+                    block_expr,
+                    break_,
+                    continue_,
+                    assert,
+                    function_call,
+                    local_tee,
+                    local_tee_op,
+                    variable,
+                    branch,
+                    branch_if,
+                    let_,
+                    select,
+                    return_,
+                ))
+                .map_with_span(|expr, span| expr.with_span(span))
+                .or(expression
+                    .clone()
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))))
+                .or(block)
+                .recover_with(nested_delimiters(
+                    Token::Ctrl('('),
+                    Token::Ctrl(')'),
+                    [(Token::Ctrl('{'), Token::Ctrl('}'))],
+                    |span| ast::Expr::Error.with_span(span),
+                ))
                 .boxed();
 
-            let return_ = just(Token::Return)
-                .ignore_then(expression.clone().or_not())
-                .map(|value| ast::Expr::Return {
-                    value: value.map(Box::new),
-                });
-
-            let atom = choice((
-                val,
-                function_call,
-                local_tee,
-                local_tee_op,
-                variable,
-                block_expr,
-                branch,
-                branch_if,
-                let_,
-                select,
-                return_,
-            ))
-            .map_with_span(|expr, span| expr.with_span(span))
-            .or(expression
-                .clone()
-                .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))))
-            .or(block)
-            .recover_with(nested_delimiters(
-                Token::Ctrl('('),
-                Token::Ctrl(')'),
-                [(Token::Ctrl('{'), Token::Ctrl('}'))],
-                |span| ast::Expr::Error.with_span(span),
-            ))
-            .boxed();
+                let unary_op = just(Token::Op("-".to_string()))
+                    .to(vec![ast::UnaryOp::Negate])
+                    .or(just(Token::Ctrl('!')).to(vec![ast::UnaryOp::Not]))
+                    .or(just(Token::Op("!!".to_string()))
+                        .to(vec![ast::UnaryOp::Not, ast::UnaryOp::Not]))
+                    .map_with_span(|ops, span: Span| {
+                        ops.into_iter()
+                            .map(|op| (op, span.clone()))
+                            .collect::<Vec<_>>()
+                    })
+                    .repeated()
+                    .flatten()
+                    .then(atom)
+                    .map(|(ops, value)| {
+                        ops.into_iter().rev().fold(value, |acc, (op, span)| {
+                            let span = (span.0, span.1.start..acc.span.1.end);
+                            ast::Expr::UnaryOp {
+                                op,
+                                value: Box::new(acc),
+                            }
+                            .with_span(span)
+                        })
+                    })
+                    .boxed();
 
-            let unary_op = just(Token::Op("-".to_string()))
-                .to(ast::UnaryOp::Negate)
-                .or(just(Token::Ctrl('!')).to(ast::UnaryOp::Not))
-                .map_with_span(|op, span| (op, span))
-                .repeated()
-                .then(atom)
-                .map(|(ops, value)| {
-                    ops.into_iter().rev().fold(value, |acc, (op, span)| {
-                        let span = (span.0, span.1.start..acc.span.1.end);
-                        ast::Expr::UnaryOp {
-                            op,
-                            value: Box::new(acc),
+                let op_cast = unary_op
+                    .clone()
+                    .then(
+                        just(Token::As)
+                            .ignore_then(type_parser())
+                            .map_with_span(|type_, span| (type_, span))
+                            .repeated(),
+                    )
+                    .foldl(|value, (type_, span)| {
+                        ast::Expr::Cast {
+                            value: Box::new(value),
+                            type_,
                         }
                         .with_span(span)
                     })
-                })
-                .boxed();
-
-            let op_cast = unary_op
-                .clone()
-                .then(
-                    just(Token::As)
-                        .ignore_then(type_parser())
-                        .map_with_span(|type_, span| (type_, span))
-                        .repeated(),
-                )
-                .foldl(|value, (type_, span)| {
-                    ast::Expr::Cast {
-                        value: Box::new(value),
-                        type_,
-                    }
-                    .with_span(span)
-                })
-                .boxed();
-
-            let mem_size = just(Token::Ctrl('?'))
-                .to(ast::MemSize::Byte)
-                .or(just(Token::Ctrl('!')).to(ast::MemSize::Word))
-                .or(just(Token::Ctrl('$')).to(ast::MemSize::Float));
-
-            let mem_op = mem_size.then(op_cast.clone());
-
-            fn make_memory_op(
-                left: ast::Expression,
-                peek_ops: Vec<(ast::MemSize, ast::Expression)>,
-                poke_op: Option<((ast::MemSize, ast::Expression), ast::Expression)>,
-            ) -> ast::Expression {
-                let left = peek_ops.into_iter().fold(left, |left, (size, right)| {
-                    let span = (left.span.0, left.span.1.start..right.span.1.end);
-                    ast::Expr::Peek(ast::MemoryLocation {
-                        span: span.clone(),
-                        left: Box::new(left),
-                        size,
-                        right: Box::new(right),
-                    })
-                    .with_span(span)
-                });
-                if let Some(((size, right), value)) = poke_op {
-                    let span = (left.span.0, left.span.1.start..value.span.1.end);
-                    ast::Expr::Poke {
-                        mem_location: ast::MemoryLocation {
+                    .boxed();
+
+                let mem_size = just(Token::Ctrl('?'))
+                    .to(ast::MemSize::Byte)
+                    .or(just(Token::Op("??".to_string())).to(ast::MemSize::SignedByte))
+                    .or(just(Token::Ctrl('~')).to(ast::MemSize::Half))
+                    .or(just(Token::Op("~~".to_string())).to(ast::MemSize::SignedHalf))
+                    .or(just(Token::Ctrl('!')).to(ast::MemSize::Word))
+                    .or(just(Token::Op("!!".to_string())).to(ast::MemSize::Long))
+                    .or(just(Token::Ctrl('$')).to(ast::MemSize::Float))
+                    .or(just(Token::Op("$$".to_string())).to(ast::MemSize::Double));
+
+                let mem_op = mem_size.then(op_cast.clone());
+
+                fn make_memory_op(
+                    left: ast::Expression,
+                    peek_ops: Vec<(ast::MemSize, ast::Expression)>,
+                    poke_op: Option<((ast::MemSize, ast::Expression), ast::Expression)>,
+                ) -> ast::Expression {
+                    let left = peek_ops.into_iter().fold(left, |left, (size, right)| {
+                        let span = (left.span.0, left.span.1.start..right.span.1.end);
+                        ast::Expr::Peek(ast::MemoryLocation {
                             span: span.clone(),
                             left: Box::new(left),
                             size,
                             right: Box::new(right),
-                        },
-                        value: Box::new(value),
+                        })
+                        .with_span(span)
+                    });
+                    if let Some(((size, right), value)) = poke_op {
+                        let span = (left.span.0, left.span.1.start..value.span.1.end);
+                        ast::Expr::Poke {
+                            mem_location: ast::MemoryLocation {
+                                span: span.clone(),
+                                left: Box::new(left),
+                                size,
+                                right: Box::new(right),
+                            },
+                            value: Box::new(value),
+                        }
+                        .with_span(span)
+                    } else {
+                        left
                     }
-                    .with_span(span)
-                } else {
-                    left
                 }
-            }
 
-            let memory_op = op_cast
-                .clone()
-                .then(
-                    mem_op
-                        .clone()
-                        .repeated()
-                        .at_least(1)
-                        .then(
-                            just(Token::Op("=".to_string()))
-                                .ignore_then(expression.clone())
-                                .or_not(),
-                        )
-                        .or_not(),
-                )
-                .map(|(left, ops)| {
-                    if let Some((mut peek_ops, poke_op)) = ops {
-                        if let Some(value) = poke_op {
-                            let poke_op = Some((peek_ops.pop().unwrap(), value));
-                            make_memory_op(left, peek_ops, poke_op)
+                let memory_op = op_cast
+                    .clone()
+                    .then(
+                        mem_op
+                            .clone()
+                            .repeated()
+                            .at_least(1)
+                            .then(
+                                just(Token::Op("=".to_string()))
+                                    .ignore_then(expression.clone())
+                                    .or_not(),
+                            )
+                            .or_not(),
+                    )
+                    .map(|(left, ops)| {
+                        if let Some((mut peek_ops, poke_op)) = ops {
+                            if let Some(value) = poke_op {
+                                let poke_op = Some((peek_ops.pop().unwrap(), value));
+                                make_memory_op(left, peek_ops, poke_op)
+                            } else {
+                                make_memory_op(left, peek_ops, None)
+                            }
                         } else {
-                            make_memory_op(left, peek_ops, None)
+                            left
                         }
-                    } else {
-                        left
-                    }
-                })
-                .boxed();
+                    })
+                    .boxed();
 
-            let op_product = memory_op
-                .clone()
-                .then(product_op.clone().then(memory_op.clone()).repeated())
-                .foldl(|left, (op, right)| {
-                    let span = (left.span.0, left.span.1.start..right.span.1.end);
-                    ast::Expr::BinOp {
-                        op,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    }
-                    .with_span(span)
-                })
-                .boxed();
+                let op_product = memory_op
+                    .clone()
+                    .then(product_op.clone().then(memory_op.clone()).repeated())
+                    .foldl(|left, (op, right)| {
+                        let span = (left.span.0, left.span.1.start..right.span.1.end);
+                        ast::Expr::BinOp {
+                            op,
+                            left: Box::new(left),
+                            right: Box::new(right),
+                        }
+                        .with_span(span)
+                    })
+                    .boxed();
 
-            let op_sum = op_product
-                .clone()
-                .then(sum_op.clone().then(op_product.clone()).repeated())
-                .foldl(|left, (op, right)| {
-                    let span = (left.span.0, left.span.1.start..right.span.1.end);
-                    ast::Expr::BinOp {
-                        op,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    }
-                    .with_span(span)
-                })
-                .boxed();
+                let op_sum = op_product
+                    .clone()
+                    .then(sum_op.clone().then(op_product.clone()).repeated())
+                    .foldl(|left, (op, right)| {
+                        let span = (left.span.0, left.span.1.start..right.span.1.end);
+                        ast::Expr::BinOp {
+                            op,
+                            left: Box::new(left),
+                            right: Box::new(right),
+                        }
+                        .with_span(span)
+                    })
+                    .boxed();
 
-            let op_shift = op_sum
-                .clone()
-                .then(shift_op.clone().then(op_sum.clone()).repeated())
-                .foldl(|left, (op, right)| {
-                    let span = (left.span.0, left.span.1.start..right.span.1.end);
-                    ast::Expr::BinOp {
-                        op,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    }
-                    .with_span(span)
-                })
-                .boxed();
+                let op_shift = op_sum
+                    .clone()
+                    .then(shift_op.clone().then(op_sum.clone()).repeated())
+                    .foldl(|left, (op, right)| {
+                        let span = (left.span.0, left.span.1.start..right.span.1.end);
+                        ast::Expr::BinOp {
+                            op,
+                            left: Box::new(left),
+                            right: Box::new(right),
+                        }
+                        .with_span(span)
+                    })
+                    .boxed();
 
-            let op_cmp = op_shift
-                .clone()
-                .then(
-                    just(Token::Op("==".to_string()))
-                        .to(ast::BinOp::Eq)
-                        .or(just(Token::Op("!=".to_string())).to(ast::BinOp::Ne))
-                        .or(just(Token::Op("<".to_string())).to(ast::BinOp::Lt))
-                        .or(just(Token::Op("#<".to_string())).to(ast::BinOp::LtU))
-                        .or(just(Token::Op("<=".to_string())).to(ast::BinOp::Le))
-                        .or(just(Token::Op("#<=".to_string())).to(ast::BinOp::LeU))
-                        .or(just(Token::Op(">".to_string())).to(ast::BinOp::Gt))
-                        .or(just(Token::Op("#>".to_string())).to(ast::BinOp::GtU))
-                        .or(just(Token::Op(">=".to_string())).to(ast::BinOp::Ge))
-                        .or(just(Token::Op("#>=".to_string())).to(ast::BinOp::GeU))
-                        .then(op_shift.clone())
-                        .repeated(),
-                )
-                .foldl(|left, (op, right)| {
-                    let span = (left.span.0, left.span.1.start..right.span.1.end);
-                    ast::Expr::BinOp {
-                        op,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    }
-                    .with_span(span)
-                })
-                .boxed();
+                let op_cmp = op_shift
+                    .clone()
+                    .then(
+                        just(Token::Op("==".to_string()))
+                            .to(ast::BinOp::Eq)
+                            .or(just(Token::Op("!=".to_string())).to(ast::BinOp::Ne))
+                            .or(just(Token::Op("<".to_string())).to(ast::BinOp::Lt))
+                            .or(just(Token::Op("#<".to_string())).to(ast::BinOp::LtU))
+                            .or(just(Token::Op("<=".to_string())).to(ast::BinOp::Le))
+                            .or(just(Token::Op("#<=".to_string())).to(ast::BinOp::LeU))
+                            .or(just(Token::Op(">".to_string())).to(ast::BinOp::Gt))
+                            .or(just(Token::Op("#>".to_string())).to(ast::BinOp::GtU))
+                            .or(just(Token::Op(">=".to_string())).to(ast::BinOp::Ge))
+                            .or(just(Token::Op("#>=".to_string())).to(ast::BinOp::GeU))
+                            .then(op_shift.clone())
+                            .repeated(),
+                    )
+                    .foldl(|left, (op, right)| {
+                        let span = (left.span.0, left.span.1.start..right.span.1.end);
+                        ast::Expr::BinOp {
+                            op,
+                            left: Box::new(left),
+                            right: Box::new(right),
+                        }
+                        .with_span(span)
+                    })
+                    .boxed();
 
-            let op_bit = op_cmp
-                .clone()
-                .then(bit_op.clone().then(op_cmp.clone()).repeated())
-                .foldl(|left, (op, right)| {
-                    let span = (left.span.0, left.span.1.start..right.span.1.end);
-                    ast::Expr::BinOp {
-                        op,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    }
-                    .with_span(span)
-                })
-                .boxed();
+                let op_bit = op_cmp
+                    .clone()
+                    .then(bit_op.clone().then(op_cmp.clone()).repeated())
+                    .foldl(|left, (op, right)| {
+                        let span = (left.span.0, left.span.1.start..right.span.1.end);
+                        ast::Expr::BinOp {
+                            op,
+                            left: Box::new(left),
+                            right: Box::new(right),
+                        }
+                        .with_span(span)
+                    })
+                    .boxed();
 
-            op_bit
-                .clone()
-                .then(
-                    just(Token::Op("<|".to_string()))
-                        .ignore_then(op_bit)
-                        .repeated(),
-                )
-                .foldl(|left, right| {
-                    let span = (left.span.0, left.span.1.start..right.span.1.end);
-                    ast::Expr::First {
-                        value: Box::new(left),
-                        drop: Box::new(right),
-                    }
-                    .with_span(span)
-                })
-                .boxed()
-        });
+                op_bit
+                    .clone()
+                    .then(
+                        just(Token::Op("<|".to_string()))
+                            .ignore_then(op_bit)
+                            .repeated(),
+                    )
+                    .foldl(|left, right| {
+                        let span = (left.span.0, left.span.1.start..right.span.1.end);
+                        ast::Expr::First {
+                            value: Box::new(left),
+                            drop: Box::new(right),
+                        }
+                        .with_span(span)
+                    })
+                    .boxed()
+            });
 
         expression_out = Some(expression.clone());
 
         let block_expression = block_expression.unwrap();
 
-        let assign = identifier
+        let assign = field_path
+            .clone()
             .then_ignore(just(Token::Op("=".to_string())))
             .then(expression.clone())
             .map(|(name, value)| ast::Expr::Assign {
@@ -901,7 +1366,8 @@ fn script_parser() -> impl Parser<Token, ast::Script, Error = ScriptError> + Clo
             .map_with_span(|expr, span| expr.with_span(span))
             .boxed();
 
-        let assign_op = identifier
+        let assign_op = field_path
+            .clone()
             .then(
                 product_op
                     .clone()
@@ -965,7 +1431,7 @@ fn script_parser() -> impl Parser<Token, ast::Script, Error = ScriptError> + Clo
 
     let expression = expression_out.unwrap();
 
-    let top_level_item = {
+    let top_level_item = recursive(|top_level_item| {
         let import_memory = just(Token::Ident("memory".to_string()))
             .ignore_then(
                 integer
@@ -1013,6 +1479,7 @@ fn script_parser() -> impl Parser<Token, ast::Script, Error = ScriptError> + Clo
             .map_with_span(|(import, type_), span| {
                 ast::TopLevelItem::Import(ast::Import {
                     span,
+                    public: false,
                     import,
                     type_,
                 })
@@ -1022,11 +1489,19 @@ fn script_parser() -> impl Parser<Token, ast::Script, Error = ScriptError> + Clo
         let parameter = identifier
             .then_ignore(just(Token::Ctrl(':')))
             .then(type_parser())
+            .map_with_span(|(name, type_), span| ast::Param { span, name, type_ })
             .boxed();
 
         let function = just(Token::Ident("export".to_string()))
             .or_not()
             .then(just(Token::Ident("start".to_string())).or_not())
+            .then(
+                just(Token::Inline)
+                    .or(just(Token::Ident("const".to_string())))
+                    .or(just(Token::Ident("test".to_string())))
+                    .or(just(Token::Ident("stub".to_string())))
+                    .or_not(),
+            )
             .then_ignore(just(Token::Fn))
             .then(identifier)
             .then(
@@ -1040,18 +1515,25 @@ fn script_parser() -> impl Parser<Token, ast::Script, Error = ScriptError> + Clo
                     .or_not(),
             )
             .then(block.clone())
-            .map_with_span(|(((((export, start), name), params), type_), body), span| {
-                ast::TopLevelItem::Function(ast::Function {
-                    span,
-                    params,
-                    export: export.is_some(),
-                    start: start.is_some(),
-                    name,
-                    type_,
-                    body,
-                    locals: ast::Locals::default(),
-                })
-            })
+            .map_with_span(
+                |((((((export, start), kind), name), params), type_), body), span| {
+                    ast::TopLevelItem::Function(ast::Function {
+                        span,
+                        public: false,
+                        params,
+                        export: export.is_some(),
+                        start: start.is_some(),
+                        inline: kind == Some(Token::Inline),
+                        const_: kind == Some(Token::Ident("const".to_string())),
+                        test: kind == Some(Token::Ident("test".to_string())),
+                        stub: kind == Some(Token::Ident("stub".to_string())),
+                        name,
+                        type_,
+                        body,
+                        locals: ast::Locals::default(),
+                    })
+                },
+            )
             .boxed();
 
         let global = just(Token::Global)
@@ -1067,6 +1549,7 @@ fn script_parser() -> impl Parser<Token, ast::Script, Error = ScriptError> + Clo
                     value,
                     mutable: mutable.is_some(),
                     span,
+                    public: false,
                 })
             })
             .boxed();
@@ -1082,28 +1565,185 @@ fn script_parser() -> impl Parser<Token, ast::Script, Error = ScriptError> + Clo
                     type_,
                     value,
                     span,
+                    public: false,
                 })
             })
             .boxed();
 
-        let data_i8 = just(Token::Ident("i8".to_string()))
+        let data_type = just(Token::Ident("i8".to_string()))
             .to(ast::DataType::I8)
             .or(just(Token::Ident("i16".to_string())).to(ast::DataType::I16))
             .or(just(Token::Ident("i32".to_string())).to(ast::DataType::I32))
             .or(just(Token::Ident("i64".to_string())).to(ast::DataType::I64))
             .or(just(Token::Ident("f32".to_string())).to(ast::DataType::F32))
             .or(just(Token::Ident("f64".to_string())).to(ast::DataType::F64))
+            .boxed();
+
+        let struct_field = identifier
+            .then_ignore(just(Token::Ctrl(':')))
+            .then(data_type.clone())
+            .map_with_span(|(name, type_), span| ast::StructField { span, name, type_ });
+
+        let struct_ = just(Token::Ident("struct".to_string()))
+            .ignore_then(identifier)
             .then(
-                expression
-                    .clone()
+                struct_field
+                    .separated_by(just(Token::Ctrl(',')))
+                    .allow_trailing()
+                    .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}'))),
+            )
+            .map_with_span(|(name, fields), span| {
+                ast::TopLevelItem::Struct(ast::Struct {
+                    span,
+                    public: false,
+                    name,
+                    fields,
+                })
+            })
+            .boxed();
+
+        let macro_ = just(Token::Ident("macro".to_string()))
+            .ignore_then(identifier)
+            .then(
+                identifier
+                    .separated_by(just(Token::Ctrl(',')))
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .then(block.clone())
+            .map_with_span(|((name, params), body), span| {
+                ast::TopLevelItem::Macro(ast::Macro {
+                    span,
+                    public: false,
+                    name,
+                    params,
+                    body,
+                })
+            })
+            .boxed();
+
+        let range = expression
+            .clone()
+            .then_ignore(just(Token::Op("..".to_string())))
+            .then(expression.clone());
+
+        // `for i in start..end: value`
+        let data_sequence = just(Token::Ident("for".to_string()))
+            .ignore_then(identifier)
+            .then_ignore(just(Token::Ident("in".to_string())))
+            .then(range.clone())
+            .then_ignore(just(Token::Ctrl(':')))
+            .then(expression.clone())
+            .map_with_span(|((index, (start, end)), value), span| {
+                ast::Expr::Sequence {
+                    index,
+                    start: Box::new(start),
+                    end: Box::new(end),
+                    value: Box::new(value),
+                }
+                .with_span(span)
+            });
+
+        // `f(start..end)` is short for `for i in start..end: f(i)`
+        let data_call_sequence = identifier
+            .then(range.delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))))
+            .map_with_span(|(name, (start, end)), span: Span| {
+                let index = "#i".to_string();
+                let value = ast::Expr::FuncCall {
+                    name,
+                    params: vec![ast::Expr::Variable {
+                        name: index.clone(),
+                        local_id: None,
+                    }
+                    .with_span(span.clone())],
+                };
+                ast::Expr::Sequence {
+                    index,
+                    start: Box::new(start),
+                    end: Box::new(end),
+                    value: Box::new(value.with_span(span.clone())),
+                }
+                .with_span(span)
+            });
+
+        let data_i8 = data_type
+            .clone()
+            .then(
+                data_sequence
+                    .or(data_call_sequence)
+                    .or(expression.clone())
                     .separated_by(just(Token::Ctrl(',')))
                     .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
             )
             .map(|(type_, values)| ast::DataValues::Array { type_, values });
 
-        let data_string = string.clone().map(ast::DataValues::String);
+        let data_string = bytes.map(ast::DataValues::String);
 
         let data_file = just(Token::Ident("file".to_string()))
+            .ignore_then(
+                string
+                    .clone()
+                    .then(
+                        just(Token::Ctrl(','))
+                            .ignore_then(expression.clone())
+                            .then(
+                                just(Token::Ctrl(','))
+                                    .ignore_then(expression.clone())
+                                    .or_not(),
+                            )
+                            .or_not(),
+                    )
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .map(|(s, slice)| {
+                let (offset, length) = match slice {
+                    Some((offset, length)) => (Some(offset), length),
+                    None => (None, None),
+                };
+                ast::DataValues::File {
+                    path: s.into(),
+                    format: ast::FileFormat::Raw,
+                    offset,
+                    length,
+                    data: vec![],
+                }
+            });
+
+        // `indexed8`, `4bpp`, `2bpp` or `1bpp`
+        let image_format = just(Token::Ident("indexed8".to_string())).to(8).or(integer
+            .clone()
+            .then_ignore(just(Token::Ident("bpp".to_string())))
+            .try_map(|bits, span| match bits {
+                1 | 2 | 4 | 8 => Ok(bits as u32),
+                _ => Err(ScriptError::custom(
+                    span,
+                    "Image format has to be indexed8, 4bpp, 2bpp or 1bpp",
+                )),
+            }));
+
+        let data_image = just(Token::Ident("image".to_string()))
+            .ignore_then(
+                string
+                    .clone()
+                    .then(
+                        just(Token::Ctrl(','))
+                            .ignore_then(just(Token::Ident("format".to_string())))
+                            .ignore_then(just(Token::Ctrl(':')))
+                            .ignore_then(image_format)
+                            .or_not(),
+                    )
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .map(|(s, bits)| ast::DataValues::File {
+                path: s.into(),
+                format: ast::FileFormat::Image {
+                    bits: bits.unwrap_or(8),
+                },
+                offset: None,
+                length: None,
+                data: vec![],
+            });
+
+        let data_palette = just(Token::Ident("palette".to_string()))
             .ignore_then(
                 string
                     .clone()
@@ -1111,54 +1751,530 @@ fn script_parser() -> impl Parser<Token, ast::Script, Error = ScriptError> + Clo
             )
             .map(|s| ast::DataValues::File {
                 path: s.into(),
+                format: ast::FileFormat::Palette,
+                offset: None,
+                length: None,
                 data: vec![],
             });
 
+        let data_fill = just(Token::Ident("fill".to_string()))
+            .ignore_then(
+                expression
+                    .clone()
+                    .then_ignore(just(Token::Ctrl(',')))
+                    .then(expression.clone())
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .map(|(value, count)| ast::DataValues::Fill { value, count });
+
+        let data_zeros = just(Token::Ident("zeros".to_string()))
+            .ignore_then(
+                expression
+                    .clone()
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .map(ast::DataValues::Zeros);
+
+        let data_align = just(Token::Ident("align".to_string()))
+            .ignore_then(
+                expression
+                    .clone()
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .map(ast::DataValues::Align);
+
+        let data_label = just(Token::Ident("pub".to_string()))
+            .or_not()
+            .then(identifier)
+            .then_ignore(just(Token::Ctrl(':')))
+            .map_with_span(|(public, name), span| ast::DataValues::Label {
+                span,
+                public: public.is_some(),
+                name,
+            });
+
+        let data_values = recursive(|data_values| {
+            let data_repeat = just(Token::Ident("repeat".to_string()))
+                .ignore_then(
+                    expression
+                        .clone()
+                        .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+                )
+                .then(data_values.delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}'))))
+                .map(|(count, values)| ast::DataValues::Repeat { count, values });
+
+            data_label
+                .or(data_i8)
+                .or(data_string)
+                .or(data_file)
+                .or(data_image)
+                .or(data_palette)
+                .or(data_fill)
+                .or(data_zeros)
+                .or(data_align)
+                .or(data_repeat)
+                .repeated()
+        });
+
         let data = just(Token::Ident("data".to_string()))
             .ignore_then(expression.clone())
+            .then(just(Token::Ident("compressed".to_string())).or_not())
             .then(
-                data_i8
-                    .or(data_string)
-                    .or(data_file)
-                    .repeated()
+                data_values
+                    .clone()
                     .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}'))),
             )
-            .map(|(offset, data)| {
+            .map(|((offset, compressed), data)| {
                 ast::TopLevelItem::Data(ast::Data {
                     offset: Box::new(offset),
                     data,
+                    compressed: compressed.is_some(),
+                })
+            })
+            .boxed();
+
+        let static_buffer = just(Token::Ctrl(':')).ignore_then(
+            data_type
+                .clone()
+                .then_ignore(just(Token::Ctrl(';')))
+                .then(expression.clone())
+                .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']')))
+                .then_ignore(just(Token::Ctrl(';')).or_not())
+                .map(|(type_, count)| {
+                    // a zero initialized buffer, aligned to its element type
+                    let span = count.span.clone();
+                    let size = ast::Expr::I32Const(type_.size() as i32).with_span(span.clone());
+                    vec![
+                        ast::DataValues::Align(size.clone()),
+                        ast::DataValues::Zeros(
+                            ast::Expr::BinOp {
+                                op: ast::BinOp::Mul,
+                                left: Box::new(count),
+                                right: Box::new(size),
+                            }
+                            .with_span(span),
+                        ),
+                    ]
+                }),
+        );
+
+        let static_ = just(Token::Ident("static".to_string()))
+            .ignore_then(identifier)
+            .then(
+                data_values
+                    .clone()
+                    .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
+                    .or(static_buffer),
+            )
+            .map_with_span(|(name, data), span| {
+                ast::TopLevelItem::Static(ast::Static {
+                    span,
+                    public: false,
+                    name,
+                    data,
                 })
             })
             .boxed();
 
-        let include =
-            just(Token::Ident("include".to_string())).ignore_then(string.clone().map_with_span(
-                |path, span| ast::TopLevelItem::Include(ast::Include { span, path }),
-            ));
-
-        import
-            .or(function)
-            .or(global)
-            .or(data)
-            .or(include)
-            .or(global_const)
+        let static_region = just(Token::Ident("static_region".to_string()))
+            .ignore_then(
+                expression
+                    .clone()
+                    .then(
+                        just(Token::Ctrl(','))
+                            .ignore_then(expression.clone())
+                            .or_not(),
+                    )
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .then_ignore(just(Token::Ctrl(';')))
+            .map_with_span(|(start, end), span| {
+                ast::TopLevelItem::StaticRegion(ast::StaticRegion { span, start, end })
+            })
+            .boxed();
+
+        let include = just(Token::Ident("include".to_string()))
+            .ignore_then(string.clone())
+            .then(just(Token::As).ignore_then(identifier).or_not())
+            .then_ignore(just(Token::Ctrl(';')).or_not())
+            .map_with_span(|(path, namespace), span| {
+                ast::TopLevelItem::Include(ast::Include {
+                    span,
+                    path,
+                    namespace,
+                })
+            });
+
+        let const_if = recursive(|const_if| {
+            let items = top_level_item
+                .repeated()
+                .flatten()
+                .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
+                .map(collect_items);
+            just(Token::If)
+                .ignore_then(just(Token::Ident("const".to_string())))
+                .ignore_then(expression.clone())
+                .then(items.clone())
+                .then(
+                    just(Token::Else)
+                        .ignore_then(items.or(const_if.map(|conditional| ast::Script {
+                            conditionals: vec![conditional],
+                            ..Default::default()
+                        })))
+                        .or_not(),
+                )
+                .map_with_span(|((condition, if_true), if_false), span| ast::Conditional {
+                    span,
+                    condition,
+                    if_true,
+                    if_false: if_false.unwrap_or_default(),
+                })
+        })
+        .map(|conditional| ast::TopLevelItem::ConstIf(Box::new(conditional)));
+
+        // `#[allow(lint, ..)]` or `#[warn(lint, ..)]`
+        let lint_attribute = just(Token::Ctrl('#')).ignore_then(
+            just(Token::Ident("allow".to_string()))
+                .to(false)
+                .or(just(Token::Ident("warn".to_string())).to(true))
+                .then(
+                    identifier
+                        .try_map(|name, span| {
+                            name.parse::<Lint>()
+                                .map_err(|err| ScriptError::custom(span, err.to_string()))
+                        })
+                        .separated_by(just(Token::Ctrl(',')))
+                        .at_least(1)
+                        .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+                )
+                .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']'))),
+        );
+
+        let item = just(Token::Ident("pub".to_string()))
+            .or_not()
+            .then(
+                import
+                    .or(function)
+                    .or(global)
+                    .or(data)
+                    .or(static_)
+                    .or(static_region)
+                    .or(include)
+                    .or(global_const)
+                    .or(struct_)
+                    .or(macro_)
+                    .or(const_if),
+            )
+            .try_map(|(public, mut item), span| {
+                if public.is_some() {
+                    match item {
+                        ast::TopLevelItem::Import(ref mut i) => i.public = true,
+                        ast::TopLevelItem::GlobalVar(ref mut v) => v.public = true,
+                        ast::TopLevelItem::Function(ref mut f) => f.public = true,
+                        ast::TopLevelItem::Const(ref mut c) => c.public = true,
+                        ast::TopLevelItem::Struct(ref mut s) => s.public = true,
+                        ast::TopLevelItem::Macro(ref mut m) => m.public = true,
+                        ast::TopLevelItem::Static(ref mut s) => s.public = true,
+                        ast::TopLevelItem::Data(_)
+                        | ast::TopLevelItem::StaticRegion(_)
+                        | ast::TopLevelItem::Include(_)
+                        | ast::TopLevelItem::ConstIf(_)
+                        | ast::TopLevelItem::LintAttribute(_) => {
+                            return Err(ScriptError::custom(
+                                span,
+                                "Only named items can be declared pub",
+                            ))
+                        }
+                    }
+                }
+                Ok(item)
+            });
+
+        lint_attribute
+            .repeated()
+            .then(item)
+            .map_with_span(|(attributes, item), span: Span| {
+                let mut items: Vec<_> = attributes
+                    .into_iter()
+                    .flat_map(|(enabled, lints)| lints.into_iter().map(move |lint| (enabled, lint)))
+                    .map(|(enabled, lint)| {
+                        ast::TopLevelItem::LintAttribute(ast::LintAttribute {
+                            span: span.clone(),
+                            lint,
+                            enabled,
+                        })
+                    })
+                    .collect();
+                items.push(item);
+                items
+            })
             .boxed()
-    };
+    });
+
+    top_level_item
+        .repeated()
+        .flatten()
+        .then_ignore(end())
+        .map(collect_items)
+}
+
+fn collect_items(items: Vec<ast::TopLevelItem>) -> ast::Script {
+    let mut script = ast::Script::default();
+    for item in items {
+        match item {
+            ast::TopLevelItem::Import(i) => script.imports.push(i),
+            ast::TopLevelItem::GlobalVar(v) => script.global_vars.push(v),
+            ast::TopLevelItem::Function(f) => script.functions.push(f),
+            ast::TopLevelItem::Data(d) => script.data.push(d),
+            ast::TopLevelItem::Static(s) => script.statics.push(s),
+            ast::TopLevelItem::StaticRegion(r) => script.static_regions.push(r),
+            ast::TopLevelItem::Include(i) => script.includes.push(i),
+            ast::TopLevelItem::Const(c) => script.consts.push(c),
+            ast::TopLevelItem::Struct(s) => script.structs.push(s),
+            ast::TopLevelItem::Macro(m) => script.macros.push(m),
+            ast::TopLevelItem::ConstIf(c) => script.conditionals.push(*c),
+            ast::TopLevelItem::LintAttribute(a) => script.lint_attributes.push(a),
+        }
+    }
+    script
+}
+
+// `while` and `for` loops, lowered to the plain block/loop/branch_if nodes
+enum LoopSugar {
+    While {
+        condition: ast::Expression,
+        body: ast::Expression,
+    },
+    For {
+        var: String,
+        start: Box<ast::Expression>,
+        end: Box<ast::Expression>,
+        step: Option<Box<ast::Expression>>,
+        unsigned: bool,
+        body: ast::Expression,
+    },
+}
+
+impl LoopSugar {
+    fn desugar(self, name: Option<String>, span: Span) -> ast::Expr {
+        use ast::Expr::*;
+        let with_span = |expr: ast::Expr| expr.with_span(span.clone());
+        let (break_label, continue_label, loop_label) = match name {
+            Some(name) => (
+                name.clone(),
+                format!("{}#continue", name),
+                format!("{}#loop", name),
+            ),
+            None => (
+                format!("#break@{}", span.1.start),
+                format!("#continue@{}", span.1.start),
+                format!("#loop@{}", span.1.start),
+            ),
+        };
 
-    top_level_item.repeated().then_ignore(end()).map(|items| {
-        let mut script = ast::Script::default();
-        for item in items {
-            match item {
-                ast::TopLevelItem::Import(i) => script.imports.push(i),
-                ast::TopLevelItem::GlobalVar(v) => script.global_vars.push(v),
-                ast::TopLevelItem::Function(f) => script.functions.push(f),
-                ast::TopLevelItem::Data(d) => script.data.push(d),
-                ast::TopLevelItem::Include(i) => script.includes.push(i),
-                ast::TopLevelItem::Const(c) => script.consts.push(c),
+        match self {
+            // block break { loop continue { branch_if !condition: break; body; branch continue } }
+            LoopSugar::While {
+                condition,
+                mut body,
+            } => {
+                resolve_loop_branches(&mut body, &break_label, &continue_label);
+                let exit = BranchIf {
+                    condition: Box::new(with_span(UnaryOp {
+                        op: ast::UnaryOp::Not,
+                        value: Box::new(condition),
+                    })),
+                    label: break_label.clone(),
+                };
+                let loop_body = Block {
+                    statements: vec![
+                        with_span(exit),
+                        body,
+                        with_span(Branch(continue_label.clone())),
+                    ],
+                    final_expression: None,
+                };
+                LabelBlock {
+                    label: break_label,
+                    block: Box::new(with_span(Loop {
+                        label: continue_label,
+                        block: Box::new(with_span(loop_body)),
+                    })),
+                }
+            }
+            // {
+            //     let var = start;
+            //     block break { loop loop {
+            //         branch_if var >= end: break; // var <= end for a negative step
+            //         block continue { body } // only if continue is used
+            //         var = var + step;
+            //         branch loop
+            //     } }
+            // }
+            // The direction depends on the value of the step, which is only known once the consts
+            // are folded, so the exit is an `if const` choosing between both comparisons
+            LoopSugar::For {
+                var,
+                start,
+                end,
+                step,
+                unsigned,
+                mut body,
+            } => {
+                let uses_continue = resolve_loop_branches(&mut body, &break_label, &continue_label);
+                let variable = || {
+                    Box::new(with_span(Variable {
+                        name: var.clone(),
+                        local_id: None,
+                    }))
+                };
+                let exit = |descending: bool| {
+                    let op = match (descending, unsigned) {
+                        (false, false) => ast::BinOp::Ge,
+                        (false, true) => ast::BinOp::GeU,
+                        (true, false) => ast::BinOp::Le,
+                        (true, true) => ast::BinOp::LeU,
+                    };
+                    with_span(BranchIf {
+                        condition: Box::new(with_span(BinOp {
+                            op,
+                            left: variable(),
+                            right: end.clone(),
+                        })),
+                        label: break_label.clone(),
+                    })
+                };
+                let (exit, step) = match step {
+                    Some(step) => {
+                        // `step - step` is a zero of the type of the step, except for infinite
+                        // steps, which `step < -step` covers
+                        let step_span = step.span.clone();
+                        let compare = |op, right: ast::Expr| {
+                            BinOp {
+                                op,
+                                left: step.clone(),
+                                right: Box::new(right.with_span(step_span.clone())),
+                            }
+                            .with_span(step_span.clone())
+                        };
+                        let negative = BinOp {
+                            op: ast::BinOp::Or,
+                            left: Box::new(compare(
+                                ast::BinOp::Lt,
+                                UnaryOp {
+                                    op: ast::UnaryOp::Negate,
+                                    value: step.clone(),
+                                },
+                            )),
+                            right: Box::new(compare(
+                                ast::BinOp::Lt,
+                                BinOp {
+                                    op: ast::BinOp::Sub,
+                                    left: step.clone(),
+                                    right: step.clone(),
+                                },
+                            )),
+                        };
+                        let exit = ConstIf {
+                            condition: Box::new(negative.with_span(step_span)),
+                            if_true: Box::new(exit(true)),
+                            if_false: Some(Box::new(exit(false))),
+                        };
+                        (with_span(exit), step)
+                    }
+                    // `1` of the type of `start` or `end` if one of them is a literal
+                    None => {
+                        let type_ = literal_type(&start)
+                            .or_else(|| literal_type(&end))
+                            .unwrap_or(ast::Type::I32);
+                        (exit(false), Box::new(with_span(ast::Expr::one(type_))))
+                    }
+                };
+                if uses_continue {
+                    body = with_span(LabelBlock {
+                        label: continue_label,
+                        block: Box::new(body),
+                    });
+                }
+                let increment = Assign {
+                    name: var.clone(),
+                    value: Box::new(with_span(BinOp {
+                        op: ast::BinOp::Add,
+                        left: variable(),
+                        right: step,
+                    })),
+                    local_id: None,
+                };
+                let loop_body = Block {
+                    statements: vec![
+                        exit,
+                        body,
+                        with_span(increment),
+                        with_span(Branch(loop_label.clone())),
+                    ],
+                    final_expression: None,
+                };
+                let init = Let {
+                    name: var,
+                    type_: None,
+                    value: Some(start),
+                    let_type: ast::LetType::Normal,
+                    local_id: None,
+                };
+                Block {
+                    statements: vec![
+                        with_span(init),
+                        with_span(LabelBlock {
+                            label: break_label,
+                            block: Box::new(with_span(Loop {
+                                label: loop_label,
+                                block: Box::new(with_span(loop_body)),
+                            })),
+                        }),
+                    ],
+                    final_expression: None,
+                }
             }
         }
-        script
-    })
+    }
+}
+
+// the type of a number literal, possibly negated or cast
+fn literal_type(expr: &ast::Expression) -> Option<ast::Type> {
+    match expr.expr {
+        ast::Expr::I32Const(_) => Some(ast::Type::I32),
+        ast::Expr::I64Const(_) => Some(ast::Type::I64),
+        ast::Expr::F32Const(_) => Some(ast::Type::F32),
+        ast::Expr::F64Const(_) => Some(ast::Type::F64),
+        ast::Expr::UnaryOp {
+            op: ast::UnaryOp::Negate,
+            ref value,
+        } => literal_type(value),
+        ast::Expr::Cast { type_, .. } => Some(type_),
+        _ => None,
+    }
+}
+
+// replaces the placeholder labels of unlabeled `break` and `continue` statements,
+// returns whether the body continues the loop
+fn resolve_loop_branches(
+    expr: &mut ast::Expression,
+    break_label: &str,
+    continue_label: &str,
+) -> bool {
+    let mut uses_continue = false;
+    if let ast::Expr::Branch(ref mut label) = expr.expr {
+        if label == "#break" {
+            *label = break_label.to_string();
+        } else if label == "#continue" {
+            *label = continue_label.to_string();
+        }
+        uses_continue = label == continue_label;
+    }
+    for child in expr.expr.children_mut() {
+        uses_continue |= resolve_loop_branches(child, break_label, continue_label);
+    }
+    uses_continue
 }
 
 fn type_parser() -> impl Parser<Token, ast::Type, Error = ScriptError> + Clone {
@@ -1179,3 +2295,51 @@ fn type_parser() -> impl Parser<Token, ast::Type, Error = ScriptError> + Clone {
         )),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex_f32(digits: &str, exponent: i64) -> Option<u32> {
+        hex_float_bits(digits, exponent, 23, 127).map(|bits| bits as u32)
+    }
+
+    #[test]
+    fn hex_float_rounding() {
+        assert_eq!(hex_f32("1", 0), Some(1f32.to_bits()));
+        // halfway cases round to even
+        assert_eq!(hex_f32("1000001", -24), Some(1f32.to_bits()));
+        assert_eq!(hex_f32("1000003", -24), Some(1f32.to_bits() + 2));
+        // anything above halfway rounds up, even past the 120 bits kept of the digits
+        assert_eq!(hex_f32("10000011", -28), Some(1f32.to_bits() + 1));
+        let digits = format!("1000001{}1", "0".repeat(40));
+        assert_eq!(hex_f32(&digits, -24 - 4 * 41), Some(1f32.to_bits() + 1));
+        // rounding up can carry into the exponent
+        assert_eq!(hex_f32("1ffffff", -24), Some(2f32.to_bits()));
+    }
+
+    #[test]
+    fn hex_float_subnormals() {
+        assert_eq!(hex_f32("1", -149), Some(1));
+        assert_eq!(hex_f32("1", -150), Some(0));
+        assert_eq!(hex_f32("3", -151), Some(1));
+        assert_eq!(hex_f32("7fffff", -149), Some(0x7fffff));
+        assert_eq!(hex_f32("fffffff", -154), Some(f32::MIN_POSITIVE.to_bits()));
+        assert_eq!(hex_f32("1", -126), Some(f32::MIN_POSITIVE.to_bits()));
+        assert_eq!(hex_f32("0", 1000), Some(0));
+    }
+
+    #[test]
+    fn hex_float_overflow() {
+        assert_eq!(hex_f32("1fffffe", 103), Some(f32::MAX.to_bits()));
+        assert_eq!(hex_f32("1", 128), None);
+        assert_eq!(hex_f32("1ffffff", 103), None);
+        assert_eq!(
+            hex_float_bits("1", 1023, 52, 1023),
+            Some(2f64.powi(1023).to_bits())
+        );
+        assert_eq!(hex_float_bits("1", 1024, 52, 1023), None);
+        assert_eq!(FloatLiteral::Decimal("1e39".into()).to_f32(), None);
+        assert_eq!(FloatLiteral::Decimal("1e39".into()).to_f64(), Some(1e39));
+    }
+}
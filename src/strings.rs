@@ -0,0 +1,137 @@
+use crate::{
+    ast,
+    parser::{Sources, Span},
+    typecheck::report_error,
+    StringFormat,
+};
+
+type Result<T> = std::result::Result<T, ()>;
+
+// name of the static holding all string literals used in expressions
+const POOL: &str = "#strings";
+
+// Replaces string literals in expressions by their address in a static string pool.
+// Identical strings are only stored once, and a string which is contained in a longer
+// one (like the suffix `"core:"` of `"Score:"`) points into the longer string.
+pub fn intern_strings(
+    script: &mut ast::Script,
+    sources: &Sources,
+    format: StringFormat,
+) -> Result<()> {
+    let mut literals = Vec::new();
+    for var in &mut script.global_vars {
+        collect_literals(&mut var.value, &mut literals);
+    }
+    for cnst in &mut script.consts {
+        collect_literals(&mut cnst.value, &mut literals);
+    }
+    for func in &mut script.functions {
+        collect_literals(&mut func.body, &mut literals);
+    }
+    for data in &mut script.data {
+        collect_literals(&mut data.offset, &mut literals);
+        for values in &mut data.data {
+            for value in values.expressions_mut() {
+                collect_literals(value, &mut literals);
+            }
+        }
+    }
+    for static_ in &mut script.statics {
+        for values in &mut static_.data {
+            for value in values.expressions_mut() {
+                collect_literals(value, &mut literals);
+            }
+        }
+    }
+    if literals.is_empty() {
+        return Ok(());
+    }
+
+    let mut strings = Vec::with_capacity(literals.len());
+    let mut result = Ok(());
+    for literal in &literals {
+        if let ast::Expr::Str(ref s) = literal.expr {
+            match encode(s, format) {
+                Some(bytes) => strings.push(bytes),
+                None => {
+                    result = report_error(
+                        "String is too long for a length prefix (max. 255 bytes)",
+                        &literal.span,
+                        sources,
+                    );
+                }
+            }
+        }
+    }
+    result?;
+
+    // placing the longest strings first allows the shorter ones to be found inside of them
+    let mut sorted: Vec<&Vec<u8>> = strings.iter().collect();
+    sorted.sort_by_key(|bytes| std::cmp::Reverse(bytes.len()));
+    let mut pool: Vec<u8> = Vec::new();
+    for bytes in sorted {
+        if find(&pool, bytes).is_none() {
+            pool.extend_from_slice(bytes);
+        }
+    }
+
+    let pool_span: Span = literals[0].span.clone();
+    for (literal, bytes) in literals.into_iter().zip(strings) {
+        let offset = find(&pool, &bytes).unwrap() as i32;
+        let span = literal.span.clone();
+        let address = ast::Expr::Variable {
+            name: POOL.to_string(),
+            local_id: None,
+        }
+        .with_span(span.clone());
+        literal.expr = if offset == 0 {
+            address.expr
+        } else {
+            ast::Expr::BinOp {
+                op: ast::BinOp::Add,
+                left: Box::new(address),
+                right: Box::new(ast::Expr::I32Const(offset).with_span(span)),
+            }
+        };
+    }
+
+    script.statics.push(ast::Static {
+        span: pool_span,
+        public: false,
+        name: POOL.to_string(),
+        data: vec![ast::DataValues::String(pool)],
+    });
+
+    Ok(())
+}
+
+fn collect_literals<'a>(
+    expr: &'a mut ast::Expression,
+    literals: &mut Vec<&'a mut ast::Expression>,
+) {
+    if matches!(expr.expr, ast::Expr::Str(_)) {
+        literals.push(expr);
+        return;
+    }
+    for child in expr.expr.children_mut() {
+        collect_literals(child, literals);
+    }
+}
+
+// the bytes stored for a string literal, `None` if it's too long for a length prefix
+fn encode(s: &[u8], format: StringFormat) -> Option<Vec<u8>> {
+    let mut bytes = s.to_vec();
+    match format {
+        StringFormat::NulTerminated => bytes.push(0),
+        StringFormat::LengthPrefixed => bytes.insert(0, u8::try_from(bytes.len()).ok()?),
+        StringFormat::Raw => (),
+    }
+    Some(bytes)
+}
+
+fn find(pool: &[u8], bytes: &[u8]) -> Option<usize> {
+    if bytes.is_empty() {
+        return Some(0);
+    }
+    pool.windows(bytes.len()).position(|window| window == bytes)
+}
@@ -0,0 +1,188 @@
+use std::cell::Cell;
+
+use crate::{
+    ast,
+    interp::{Imports, Instance, Module},
+    parser::{Sources, Span},
+    typecheck::report_error,
+};
+
+type Result<T> = std::result::Result<T, ()>;
+
+// imported function called by failing asserts with the index of the assert
+const ASSERT_FAILED: &str = "#assert_failed";
+
+// Removes tests, stubs and asserts from a normal build. When running tests, stubs replace
+// the imported functions of the same name and asserts call an imported function when they
+// fail. Returns the spans of the asserts.
+pub fn prepare_tests(script: &mut ast::Script, sources: &Sources, test: bool) -> Result<Vec<Span>> {
+    let mut asserts = Vec::new();
+    if !test {
+        script.functions.retain(|func| !func.test && !func.stub);
+        for func in &mut script.functions {
+            lower_asserts(&mut func.body, &mut asserts, false);
+        }
+        return Ok(asserts);
+    }
+
+    let mut result = Ok(());
+    for func in &mut script.functions {
+        if func.stub {
+            let import = script.imports.iter().position(|import| {
+                matches!(import.type_, ast::ImportType::Function { ref name, .. } if *name == func.name)
+            });
+            let Some(index) = import else {
+                result = report_error(
+                    &format!("Stub '{}' doesn't match any imported function", func.name),
+                    &func.span,
+                    sources,
+                );
+                continue;
+            };
+            if let ast::ImportType::Function {
+                ref params,
+                result: import_result,
+                ..
+            } = script.imports[index].type_
+            {
+                let stub_params: Vec<ast::Type> =
+                    func.params.iter().map(|param| param.type_).collect();
+                if stub_params != *params || func.type_ != import_result {
+                    result = report_error(
+                        &format!(
+                            "Stub '{}' doesn't have the same signature as the import",
+                            func.name
+                        ),
+                        &func.span,
+                        sources,
+                    );
+                    continue;
+                }
+            }
+            script.imports.remove(index);
+            func.stub = false;
+        }
+        if func.test
+            && (!func.params.is_empty() || func.type_.is_some() || func.export || func.start)
+        {
+            result = report_error(
+                "Test functions can't have parameters, return a value or be exported",
+                &func.span,
+                sources,
+            );
+        }
+        lower_asserts(&mut func.body, &mut asserts, true);
+    }
+    result?;
+
+    script.imports.push(ast::Import {
+        span: script
+            .functions
+            .first()
+            .map_or((0, 0..0), |func| func.span.clone()),
+        public: false,
+        import: format!("curlywas.{}", ASSERT_FAILED),
+        type_: ast::ImportType::Function {
+            name: ASSERT_FAILED.to_string(),
+            params: vec![ast::Type::I32],
+            result: None,
+        },
+    });
+
+    Ok(asserts)
+}
+
+// `assert(condition)` becomes `if condition {} else { #assert_failed(index) }`
+fn lower_asserts(expr: &mut ast::Expression, asserts: &mut Vec<Span>, test: bool) {
+    for child in expr.expr.children_mut() {
+        lower_asserts(child, asserts, test);
+    }
+    let ast::Expr::Assert(ref mut condition) = expr.expr else {
+        return;
+    };
+    let empty = |span: &Span| {
+        ast::Expr::Block {
+            statements: Vec::new(),
+            final_expression: None,
+        }
+        .with_span(span.clone())
+    };
+    if !test {
+        expr.expr = empty(&expr.span).expr;
+        return;
+    }
+    let failed = ast::Expr::FuncCall {
+        name: ASSERT_FAILED.to_string(),
+        params: vec![ast::Expr::I32Const(asserts.len() as i32).with_span(expr.span.clone())],
+    }
+    .with_span(expr.span.clone());
+    asserts.push(expr.span.clone());
+    expr.expr = ast::Expr::If {
+        condition: std::mem::replace(condition, Box::new(empty(&expr.span))),
+        if_true: Box::new(empty(&expr.span)),
+        if_false: Some(Box::new(failed)),
+    };
+}
+
+pub struct TestSummary {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+// Runs each test function in a fresh instance of the module.
+pub fn run_tests(
+    script: &ast::Script,
+    sources: &Sources,
+    asserts: &[Span],
+    wasm: &[u8],
+) -> anyhow::Result<TestSummary> {
+    let module = Module::new(wasm)?;
+    let imported = script
+        .imports
+        .iter()
+        .filter(|import| matches!(import.type_, ast::ImportType::Function { .. }))
+        .count();
+
+    let mut summary = TestSummary {
+        passed: 0,
+        failed: 0,
+    };
+    for (index, func) in script.functions.iter().enumerate() {
+        if !func.test {
+            continue;
+        }
+        let failed_assert = Cell::new(None);
+        let mut imports = Imports::new();
+        imports.function("curlywas", ASSERT_FAILED, |_, args| {
+            if let [crate::interp::Value::I32(index)] = *args {
+                failed_assert.set(Some(index as usize));
+            }
+            Err(crate::interp::Trap::Host("assertion failed".to_string()))
+        });
+        let result = Instance::new(&module, imports)
+            .and_then(|mut instance| instance.call_index((imported + index) as u32, &[]));
+        match result {
+            Ok(_) => {
+                println!("test {} ... ok", func.name);
+                summary.passed += 1;
+            }
+            Err(trap) => {
+                println!("test {} ... FAILED", func.name);
+                summary.failed += 1;
+                let _ = match failed_assert.get() {
+                    Some(index) => report_error("Assertion failed", &asserts[index], sources),
+                    None => report_error(
+                        &format!("Test '{}' trapped: {}", func.name, trap),
+                        &func.span,
+                        sources,
+                    ),
+                };
+            }
+        }
+    }
+    println!(
+        "test result: {} passed, {} failed",
+        summary.passed, summary.failed
+    );
+    Ok(summary)
+}
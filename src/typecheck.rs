@@ -1,9 +1,12 @@
 use ariadne::{Color, Label, Report, ReportKind};
 use std::collections::HashMap;
 
-use crate::ast::{self, MemSize};
+use crate::ast;
+use crate::emit::MEMORY_TRAP;
 use crate::intrinsics::Intrinsics;
+use crate::namespaces;
 use crate::parser::{Sources, Span};
+use crate::warnings::{self, Lint, Warnings};
 use ast::Type::*;
 
 type Result<T> = std::result::Result<T, ()>;
@@ -15,21 +18,50 @@ struct Var {
 }
 type Vars = HashMap<String, Var>;
 
-pub fn tc_script(script: &mut ast::Script, sources: &Sources) -> Result<()> {
+pub fn tc_script(
+    script: &mut ast::Script,
+    sources: &Sources,
+    warnings: &mut Warnings,
+) -> Result<()> {
     let mut context = Context {
         sources,
+        warnings,
         global_vars: HashMap::new(),
         functions: HashMap::new(),
+        inline_functions: HashMap::new(),
+        inline_stack: Vec::new(),
+        checked_inline_functions: HashMap::new(),
         locals: ast::Locals::default(),
         local_vars: LocalVars::new(),
         block_stack: Vec::new(),
         return_type: None,
         intrinsics: Intrinsics::new(),
+        memory: None,
     };
 
     let mut result = Ok(());
 
+    // a file included into several namespaces imports the memory once for each of them
+    let mut memories = Vec::new();
+    script.imports.retain(|import| match import.type_ {
+        ast::ImportType::Memory(min_size) => {
+            let memory = (import.import.clone(), min_size);
+            let duplicate = memories.contains(&memory);
+            memories.push(memory);
+            !duplicate
+        }
+        _ => true,
+    });
+
     for import in &script.imports {
+        // imports without a module name need the module linking proposal
+        if !import.import.contains('.') {
+            result = report_error(
+                "Import has to be of the form \"module.name\"",
+                &import.span,
+                sources,
+            );
+        }
         match import.type_ {
             ast::ImportType::Variable {
                 ref name,
@@ -77,7 +109,23 @@ pub fn tc_script(script: &mut ast::Script, sources: &Sources) -> Result<()> {
                     );
                 }
             }
-            ast::ImportType::Memory(..) => (),
+            ast::ImportType::Memory(min_size) => {
+                if let Some(ref span) = context.memory {
+                    result = report_duplicate_definition(
+                        "Memory already imported",
+                        &import.span,
+                        span,
+                        sources,
+                    );
+                } else if min_size > 65536 {
+                    result = report_error(
+                        "Memory size can be at most 65536 pages",
+                        &import.span,
+                        sources,
+                    );
+                }
+                context.memory = Some(import.span.clone());
+            }
         }
     }
 
@@ -115,8 +163,9 @@ pub fn tc_script(script: &mut ast::Script, sources: &Sources) -> Result<()> {
         }
     }
 
+    let mut exports: HashMap<&str, &Span> = HashMap::new();
     for f in &script.functions {
-        let params = f.params.iter().map(|(_, t)| *t).collect();
+        let params = f.params.iter().map(|param| param.type_).collect();
         if let Some(fnc) = context.functions.get(&f.name) {
             result = report_duplicate_definition(
                 "Function already defined",
@@ -134,26 +183,82 @@ pub fn tc_script(script: &mut ast::Script, sources: &Sources) -> Result<()> {
                 },
             );
         }
+        if f.export {
+            if let Some(span) = exports.get(namespaces::export_name(&f.name)) {
+                result = report_duplicate_definition(
+                    "Function with the same name already exported",
+                    &f.span,
+                    span,
+                    sources,
+                );
+            } else {
+                exports.insert(namespaces::export_name(&f.name), &f.span);
+            }
+        }
+        if f.inline && (f.export || f.start) {
+            result = report_error(
+                "Inline functions can't be exported or used as start function",
+                &f.span,
+                sources,
+            );
+        }
+    }
+
+    // called by the memory checks of debug builds
+    if let Some(trap) = context.functions.get(MEMORY_TRAP) {
+        let inline = script
+            .functions
+            .iter()
+            .any(|f| f.inline && f.name == MEMORY_TRAP);
+        if inline || trap.params != [I32, I32] || trap.type_.is_some() {
+            result = report_error(
+                &format!(
+                    "{} has to be a function taking (line: i32, address: i32) without a return value, and can't be inline",
+                    MEMORY_TRAP
+                ),
+                &trap.span,
+                sources,
+            );
+        }
+    }
+
+    // inline functions are expanded at each call site and not emitted themselves
+    let (inline_functions, functions): (Vec<_>, Vec<_>) = std::mem::take(&mut script.functions)
+        .into_iter()
+        .partition(|f| f.inline);
+    script.functions = functions;
+    for f in inline_functions {
+        context.inline_functions.insert(f.name.clone(), f);
+    }
+    // each body is checked once on its own, so errors are reported even if it's never called
+    let mut names: Vec<_> = context.inline_functions.keys().cloned().collect();
+    names.sort();
+    for name in names {
+        if !tc_inline_function(&mut context, &name) {
+            result = Err(());
+        }
     }
 
     for f in &mut script.functions {
         context.local_vars.clear();
         context.local_vars.push_scope();
-        for (name, type_) in &f.params {
-            if let Some(span) = context
+        for ast::Param { span, name, type_ } in &f.params {
+            if let Some(prev_span) = context
                 .local_vars
                 .get(name)
                 .map(|id| &context.locals[id].span)
                 .or_else(|| context.global_vars.get(name).map(|v| &v.span))
             {
-                result =
-                    report_duplicate_definition("Variable already defined", &f.span, span, sources);
+                result = report_duplicate_definition(
+                    "Variable already defined",
+                    span,
+                    prev_span,
+                    sources,
+                );
             } else {
                 context.local_vars.insert(
                     name.clone(),
-                    context
-                        .locals
-                        .add_param(f.span.clone(), name.clone(), *type_),
+                    context.locals.add_param(span.clone(), name.clone(), *type_),
                 );
             }
         }
@@ -213,6 +318,7 @@ pub fn tc_script(script: &mut ast::Script, sources: &Sources) -> Result<()> {
     }
 
     for data in &mut script.data {
+        tc_memory(&context, &data.offset.span)?;
         tc_const(&mut data.offset, sources)?;
         if data.offset.type_ != Some(I32) {
             result = type_mismatch(
@@ -247,7 +353,24 @@ pub fn tc_script(script: &mut ast::Script, sources: &Sources) -> Result<()> {
                         }
                     }
                 }
-                ast::DataValues::String(_) | ast::DataValues::File { .. } => (),
+                ast::DataValues::Fill { value, .. } => {
+                    tc_const(value, sources)?;
+                    if value.type_ != Some(I32) {
+                        result = type_mismatch(
+                            Some(I32),
+                            &value.span,
+                            value.type_,
+                            &value.span,
+                            sources,
+                        );
+                    }
+                }
+                ast::DataValues::String(_)
+                | ast::DataValues::File { .. }
+                | ast::DataValues::Zeros(_)
+                | ast::DataValues::Align(_)
+                | ast::DataValues::Label { .. } => (),
+                ast::DataValues::Repeat { .. } => unreachable!(),
             }
         }
     }
@@ -263,13 +386,20 @@ struct FunctionType {
 
 struct Context<'a> {
     sources: &'a Sources,
+    warnings: &'a mut Warnings,
     global_vars: Vars,
     functions: HashMap<String, FunctionType>,
+    inline_functions: HashMap<String, ast::Function>,
+    inline_stack: Vec<String>,
+    // whether the body of an inline function typechecked, calls to invalid ones are skipped
+    checked_inline_functions: HashMap<String, bool>,
     locals: ast::Locals,
     local_vars: LocalVars,
     block_stack: Vec<String>,
     return_type: Option<ast::Type>,
     intrinsics: Intrinsics,
+    // span of the memory import
+    memory: Option<Span>,
 }
 
 struct LocalVars(Vec<HashMap<String, u32>>);
@@ -398,11 +528,20 @@ fn immutable_assign(span: &Span, sources: &Sources) -> Result<()> {
     report_error("Trying to assign to immutable variable", span, sources)
 }
 
-fn missing_label(span: &Span, sources: &Sources) -> Result<()> {
-    report_error("Label not found", span, sources)
+fn missing_label(label: &str, span: &Span, sources: &Sources) -> Result<()> {
+    match label {
+        "#break" => report_error("break outside of a loop", span, sources),
+        "#continue" => report_error("continue outside of a loop", span, sources),
+        _ => report_error("Label not found", span, sources),
+    }
 }
 
 fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()> {
+    if let ast::Expr::FuncCall { ref name, .. } = expr.expr {
+        if context.inline_functions.contains_key(name) {
+            return tc_inline_call(context, expr);
+        }
+    }
     expr.type_ = match expr.expr {
         ast::Expr::Block {
             ref mut statements,
@@ -429,6 +568,9 @@ fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()
             ref mut local_id,
             ..
         } => {
+            if value.is_none() && let_type == ast::LetType::Inline {
+                return report_error("Inline variables need a value", &expr.span, context.sources);
+            }
             if let Some(ref mut value) = value {
                 tc_expression(context, value)?;
                 if let Some(type_) = type_ {
@@ -448,6 +590,23 @@ fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()
                 }
             }
             if let Some(type_) = type_ {
+                if let Some(prev) = context.local_vars.get(name) {
+                    let prev = &context.locals[prev];
+                    if prev.type_ != *type_ {
+                        if let Some(name) = warnings::source_name(name) {
+                            context.warnings.warn_with_note(
+                                Lint::ShadowedType,
+                                &format!("{} is shadowed with a different type", name),
+                                &expr.span,
+                                Some((
+                                    &prev.span,
+                                    &format!("Previously defined as {}", prev.type_),
+                                )),
+                                context.sources,
+                            );
+                        }
+                    }
+                }
                 let store = let_type != ast::LetType::Inline;
                 let id = context
                     .local_vars
@@ -470,11 +629,7 @@ fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()
         }
         ast::Expr::Peek(ref mut mem_location) => {
             tc_mem_location(context, mem_location)?;
-            let ty = match mem_location.size {
-                MemSize::Float => F32,
-                _ => I32,
-            };
-            Some(ty)
+            Some(mem_location.size.type_())
         }
         ast::Expr::Poke {
             ref mut mem_location,
@@ -482,14 +637,11 @@ fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()
         } => {
             tc_mem_location(context, mem_location)?;
             tc_expression(context, value)?;
-            let ty = match mem_location.size {
-                MemSize::Float => F32,
-                _ => I32,
-            };
+            let ty = mem_location.size.type_();
             if value.type_ != Some(ty) {
                 return type_mismatch(
                     Some(ty),
-                    &expr.span,
+                    &mem_location.span,
                     value.type_,
                     &value.span,
                     context.sources,
@@ -665,7 +817,7 @@ fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()
             context.block_stack.push(label.clone());
             tc_expression(context, block)?;
             context.block_stack.pop();
-            if block.type_ != None {
+            if block.type_.is_some() {
                 // TODO: implement, requires branches to optionally provide values
                 return type_mismatch(None, &expr.span, block.type_, &block.span, context.sources);
             }
@@ -673,7 +825,7 @@ fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()
         }
         ast::Expr::Branch(ref label) => {
             if !context.block_stack.contains(label) {
-                return missing_label(&expr.span, context.sources);
+                return missing_label(label, &expr.span, context.sources);
             }
             None
         }
@@ -692,7 +844,7 @@ fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()
                 );
             }
             if !context.block_stack.contains(label) {
-                return missing_label(&expr.span, context.sources);
+                return missing_label(label, &expr.span, context.sources);
             }
             None
         }
@@ -742,30 +894,15 @@ fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()
                 .map(|fnc| HashMap::from_iter([(fnc.params.clone(), fnc.type_)]))
                 .or_else(|| context.intrinsics.find_types(name))
             {
+                if name.starts_with("memory.") {
+                    tc_memory(context, &expr.span)?;
+                }
                 if let Some(rtype) =
                     type_map.get(&params.iter().map(|p| p.type_.unwrap()).collect::<Vec<_>>())
                 {
                     *rtype
                 } else {
-                    let mut report =
-                        Report::build(ReportKind::Error, expr.span.0, expr.span.1.start)
-                            .with_message("No matching function found");
-                    for (params, rtype) in type_map {
-                        let param_str: Vec<_> = params.into_iter().map(|t| t.to_string()).collect();
-                        let msg = format!(
-                            "Found {}({}){}",
-                            name,
-                            param_str.join(", "),
-                            if let Some(rtype) = rtype {
-                                format!(" -> {}", rtype)
-                            } else {
-                                String::new()
-                            }
-                        );
-                        report = report.with_label(Label::new(expr.span.clone()).with_message(msg));
-                    }
-                    report.finish().eprint(context.sources).unwrap();
-                    return Err(());
+                    return no_matching_function(name, type_map, &expr.span, context.sources);
                 }
             } else {
                 return report_error(
@@ -832,6 +969,13 @@ fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()
             }
         }
         ast::Expr::Return { ref mut value } => {
+            if !context.inline_stack.is_empty() {
+                return report_error(
+                    "Can't return from an inline function",
+                    &expr.span,
+                    context.sources,
+                );
+            }
             if let Some(ref mut value) = value {
                 tc_expression(context, value)?;
                 if value.type_ != context.return_type {
@@ -843,6 +987,14 @@ fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()
                         context.sources,
                     );
                 }
+            } else if context.return_type.is_some() {
+                return type_mismatch(
+                    context.return_type,
+                    &expr.span,
+                    None,
+                    &expr.span,
+                    context.sources,
+                );
             }
             None
         }
@@ -854,8 +1006,185 @@ fn tc_expression(context: &mut Context, expr: &mut ast::Expression) -> Result<()
             tc_expression(context, drop)?;
             value.type_
         }
-        ast::Expr::Error => unreachable!(),
+        ast::Expr::Repeat { .. }
+        | ast::Expr::ConstIf { .. }
+        | ast::Expr::Sequence { .. }
+        | ast::Expr::Str(_)
+        | ast::Expr::Assert(_)
+        | ast::Expr::Error => {
+            unreachable!()
+        }
+    };
+    Ok(())
+}
+
+fn no_matching_function(
+    name: &str,
+    type_map: HashMap<Vec<ast::Type>, Option<ast::Type>>,
+    span: &Span,
+    sources: &Sources,
+) -> Result<()> {
+    let mut report = Report::build(ReportKind::Error, span.0, span.1.start)
+        .with_message("No matching function found");
+    for (params, rtype) in type_map {
+        let param_str: Vec<_> = params.into_iter().map(|t| t.to_string()).collect();
+        let msg = format!(
+            "Found {}({}){}",
+            name,
+            param_str.join(", "),
+            if let Some(rtype) = rtype {
+                format!(" -> {}", rtype)
+            } else {
+                String::new()
+            }
+        );
+        report = report.with_label(Label::new(span.clone()).with_message(msg));
+    }
+    report.finish().eprint(sources).unwrap();
+    Err(())
+}
+
+// Checks the body of an inline function with its declared param types, returning whether it's valid.
+// This can happen in the middle of checking another function, whose state is kept aside meanwhile.
+fn tc_inline_function(context: &mut Context, name: &str) -> bool {
+    if let Some(&valid) = context.checked_inline_functions.get(name) {
+        return valid;
+    }
+    let outer_locals = std::mem::take(&mut context.locals);
+    let outer_vars = std::mem::replace(&mut context.local_vars, LocalVars::new());
+    let outer_blocks = std::mem::take(&mut context.block_stack);
+    let valid = tc_inline_body(context, name).is_ok();
+    context.locals = outer_locals;
+    context.local_vars = outer_vars;
+    context.block_stack = outer_blocks;
+    context
+        .checked_inline_functions
+        .insert(name.to_string(), valid);
+    valid
+}
+
+fn tc_inline_body(context: &mut Context, name: &str) -> Result<()> {
+    let f = context.inline_functions[name].clone();
+    context.local_vars.push_scope();
+    for ast::Param { span, name, type_ } in &f.params {
+        if let Some(prev_span) = context
+            .local_vars
+            .get(name)
+            .map(|id| &context.locals[id].span)
+            .or_else(|| context.global_vars.get(name).map(|v| &v.span))
+        {
+            return report_duplicate_definition(
+                "Variable already defined",
+                span,
+                prev_span,
+                context.sources,
+            );
+        }
+        let id = context.locals.add_param(span.clone(), name.clone(), *type_);
+        context.local_vars.insert(name.clone(), id);
+    }
+
+    let mut body = f.body;
+    context.inline_stack.push(f.name);
+    let result = tc_expression(context, &mut body);
+    context.inline_stack.pop();
+    result?;
+
+    if body.type_ != f.type_ {
+        return type_mismatch(f.type_, &f.span, body.type_, &body.span, context.sources);
+    }
+    Ok(())
+}
+
+// Replaces a call to an inline function with a block binding the arguments
+// to locals of the calling function, followed by a copy of the function body.
+fn tc_inline_call(context: &mut Context, expr: &mut ast::Expression) -> Result<()> {
+    let (name, mut params) = match std::mem::replace(&mut expr.expr, ast::Expr::Error) {
+        ast::Expr::FuncCall { name, params } => (name, params),
+        _ => unreachable!(),
+    };
+    for param in params.iter_mut() {
+        tc_expression(context, param)?;
+        if param.type_.is_none() {
+            return expected_type(&param.span, context.sources);
+        }
+    }
+
+    if context.inline_stack.contains(&name) {
+        return report_error(
+            &format!("Recursive call of inline function {}", name),
+            &expr.span,
+            context.sources,
+        );
+    }
+
+    let function = context.inline_functions[&name].clone();
+    let param_types: Vec<_> = function.params.iter().map(|param| param.type_).collect();
+    if params
+        .iter()
+        .map(|p| p.type_.unwrap())
+        .ne(param_types.iter().copied())
+    {
+        let type_map = HashMap::from_iter([(param_types, function.type_)]);
+        return no_matching_function(&name, type_map, &expr.span, context.sources);
+    }
+    if !tc_inline_function(context, &name) {
+        return Err(()); // already reported when checking the function
+    }
+    let span = function.span;
+    let type_ = function.type_;
+    let mut body = function.body;
+
+    // the body only sees its own params (and globals), not the locals of the caller
+    let mut local_vars = LocalVars::new();
+    local_vars.push_scope();
+    let mut statements = vec![];
+    for (param, value) in function.params.iter().zip(params) {
+        let (param_name, param_type) = (&param.name, &param.type_);
+        let let_type = if value.is_const() {
+            ast::LetType::Inline
+        } else {
+            ast::LetType::Normal
+        };
+        let id = context.locals.add_local(
+            param.span.clone(),
+            param_name.clone(),
+            *param_type,
+            let_type != ast::LetType::Inline,
+        );
+        context.locals[id].param = true;
+        local_vars.insert(param_name.clone(), id);
+        let value_span = value.span.clone();
+        statements.push(
+            ast::Expr::Let {
+                name: param_name.clone(),
+                type_: Some(*param_type),
+                value: Some(Box::new(value)),
+                let_type,
+                local_id: Some(id),
+            }
+            .with_span(value_span),
+        );
+    }
+
+    let outer_vars = std::mem::replace(&mut context.local_vars, local_vars);
+    let outer_blocks = std::mem::take(&mut context.block_stack);
+    context.inline_stack.push(name);
+    let result = tc_expression(context, &mut body);
+    context.inline_stack.pop();
+    context.local_vars = outer_vars;
+    context.block_stack = outer_blocks;
+    result?;
+
+    if body.type_ != type_ {
+        return type_mismatch(type_, &span, body.type_, &body.span, context.sources);
+    }
+
+    expr.expr = ast::Expr::Block {
+        statements,
+        final_expression: Some(Box::new(body)),
     };
+    expr.type_ = type_;
     Ok(())
 }
 
@@ -863,6 +1192,7 @@ fn tc_mem_location<'a>(
     context: &mut Context<'a>,
     mem_location: &mut ast::MemoryLocation,
 ) -> Result<()> {
+    tc_memory(context, &mem_location.span)?;
     tc_expression(context, &mut mem_location.left)?;
     tc_const(&mut mem_location.right, context.sources)?;
     if mem_location.left.type_ != Some(I32) {
@@ -898,7 +1228,20 @@ fn tc_const(expr: &mut ast::Expression, sources: &Sources) -> Result<()> {
     Ok(())
 }
 
+// memory instructions and data need an imported memory
+fn tc_memory(context: &Context, span: &Span) -> Result<()> {
+    if context.memory.is_none() {
+        return report_error(
+            "Memory access without an imported memory",
+            span,
+            context.sources,
+        );
+    }
+    Ok(())
+}
+
 fn tc_memarg(context: &mut Context, params: &mut [ast::Expression], span: &Span) -> Result<()> {
+    tc_memory(context, span)?;
     if params.is_empty() || params.len() > 3 {
         let msg = if params.is_empty() {
             "Missing base address parameter"
@@ -911,14 +1254,14 @@ fn tc_memarg(context: &mut Context, params: &mut [ast::Expression], span: &Span)
     for (index, param) in params.iter_mut().enumerate() {
         tc_expression(context, param)?;
         if param.type_ != Some(I32) {
-            return type_mismatch(Some(I32), &span, param.type_, &param.span, context.sources);
+            return type_mismatch(Some(I32), span, param.type_, &param.span, context.sources);
         }
         if index > 0 {
             tc_const(param, context.sources)?;
         }
         if index == 2 {
             let align = param.const_i32();
-            if align < 0 || align > 4 {
+            if !(0..=4).contains(&align) {
                 return report_error(
                     &format!("Alignment {} out of range (0-4)", align),
                     &param.span,
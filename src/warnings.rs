@@ -0,0 +1,595 @@
+use anyhow::bail;
+use ariadne::{Color, Label, Report, ReportKind};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::{
+    ast,
+    emit::MEMORY_TRAP,
+    intrinsics::Intrinsics,
+    lazy,
+    parser::{Sources, Span},
+};
+
+// Everything the compiler can warn about. Each lint can be enabled or disabled
+// with `Options::with_lint` and for a single item with an `#[allow(..)]` or
+// `#[warn(..)]` attribute in front of it. All lints are enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    // locals and params which are never used
+    UnusedVariable,
+    // locals and params which are assigned to but never read
+    UnreadVariable,
+    // values assigned to a local which are never read
+    UnusedAssignment,
+    UnusedConst,
+    // functions which are never called and not exported
+    UnusedFunction,
+    // a `let` shadowing a variable of a different type
+    ShadowedType,
+    // statements after a `return` or `branch`
+    UnreachableCode,
+    // a lazy variable first used in a branch, but also outside of it
+    LazyInBranch,
+    // a lazy variable evaluated after one of the locals it reads was assigned to
+    LazyInputReassigned,
+    // an inline variable calling a function or writing memory
+    InlineSideEffect,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 10] = [
+        Lint::UnusedVariable,
+        Lint::UnreadVariable,
+        Lint::UnusedAssignment,
+        Lint::UnusedConst,
+        Lint::UnusedFunction,
+        Lint::ShadowedType,
+        Lint::UnreachableCode,
+        Lint::LazyInBranch,
+        Lint::LazyInputReassigned,
+        Lint::InlineSideEffect,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused_variable",
+            Lint::UnreadVariable => "unread_variable",
+            Lint::UnusedAssignment => "unused_assignment",
+            Lint::UnusedConst => "unused_const",
+            Lint::UnusedFunction => "unused_function",
+            Lint::ShadowedType => "shadowed_type",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::LazyInBranch => "lazy_in_branch",
+            Lint::LazyInputReassigned => "lazy_input_reassigned",
+            Lint::InlineSideEffect => "inline_side_effect",
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for Lint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Lint> {
+        match Lint::ALL.into_iter().find(|lint| lint.name() == s) {
+            Some(lint) => Ok(lint),
+            None => bail!(
+                "Unknown lint {} (expected one of {})",
+                s,
+                Lint::ALL.map(Lint::name).join(", ")
+            ),
+        }
+    }
+}
+
+pub struct Warnings {
+    enabled: HashMap<Lint, bool>,
+    attributes: Vec<ast::LintAttribute>,
+    // code copied by inline functions and macros is only warned about once
+    reported: HashSet<(Lint, Span)>,
+}
+
+impl Warnings {
+    pub fn new(lints: &[(Lint, bool)]) -> Warnings {
+        let mut enabled: HashMap<Lint, bool> = Lint::ALL.map(|lint| (lint, true)).into();
+        enabled.extend(lints.iter().copied());
+        Warnings {
+            enabled,
+            attributes: Vec::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    // Takes the lint attributes of all items, including those in both branches of an `if const`.
+    pub fn add_attributes(&mut self, script: &mut ast::Script) {
+        self.attributes.append(&mut script.lint_attributes);
+        for conditional in &mut script.conditionals {
+            self.add_attributes(&mut conditional.if_true);
+            self.add_attributes(&mut conditional.if_false);
+        }
+    }
+
+    // the innermost attribute for the lint containing the span decides, otherwise the options
+    fn is_enabled(&self, lint: Lint, span: &Span) -> bool {
+        self.attributes
+            .iter()
+            .filter(|attribute| {
+                attribute.lint == lint
+                    && attribute.span.0 == span.0
+                    && attribute.span.1.start <= span.1.start
+                    && span.1.end <= attribute.span.1.end
+            })
+            .min_by_key(|attribute| attribute.span.1.len())
+            .map_or(self.enabled[&lint], |attribute| attribute.enabled)
+    }
+
+    pub fn warn(&mut self, lint: Lint, msg: &str, span: &Span, sources: &Sources) {
+        self.warn_with_note(lint, msg, span, None, sources);
+    }
+
+    // `note` is another span with a message, like the previous definition of a variable
+    pub fn warn_with_note(
+        &mut self,
+        lint: Lint,
+        msg: &str,
+        span: &Span,
+        note: Option<(&Span, &str)>,
+        sources: &Sources,
+    ) {
+        let original = (sources.original(span.0), span.1.clone());
+        if !self.is_enabled(lint, &original) || !self.reported.insert((lint, original)) {
+            return;
+        }
+        let mut report = Report::build(ReportKind::Warning, span.0, span.1.start)
+            .with_message(msg)
+            .with_label(
+                Label::new(span.clone())
+                    .with_message(msg)
+                    .with_color(Color::Yellow),
+            )
+            .with_note(format!("disable this warning with #[allow({})]", lint));
+        if let Some((note_span, note_msg)) = note {
+            report = report.with_label(
+                Label::new(note_span.clone())
+                    .with_message(note_msg)
+                    .with_color(Color::Blue),
+            );
+        }
+        report.finish().eprint(sources).unwrap();
+    }
+}
+
+// The name of a variable as written in the source, without the suffix added by
+// macro expansion. Variables generated by the compiler start with a `#` and
+// are never warned about, neither are the ones starting with `_`.
+pub fn source_name(name: &str) -> Option<&str> {
+    let name = name.split('#').next().unwrap();
+    (!name.is_empty() && !name.starts_with('_')).then_some(name)
+}
+
+// Warns about consts and functions of the root script which are never used,
+// included files often are libraries only partially used by each script.
+// This runs before conditional compilation, so references in either branch
+// of an `if const` count.
+pub fn check_unused_items(script: &ast::Script, sources: &Sources, warnings: &mut Warnings) {
+    let mut references = HashSet::new();
+    collect_references(script, &mut references);
+    report_unused_items(script, &references, sources, warnings);
+}
+
+fn collect_references<'a>(script: &'a ast::Script, references: &mut HashSet<&'a str>) {
+    for var in &script.global_vars {
+        expr_references(&var.value, None, references);
+    }
+    for cnst in &script.consts {
+        expr_references(&cnst.value, None, references);
+    }
+    for func in &script.functions {
+        expr_references(&func.body, Some(&func.name), references);
+    }
+    for mac in &script.macros {
+        expr_references(&mac.body, None, references);
+    }
+    for data in &script.data {
+        expr_references(&data.offset, None, references);
+        for values in &data.data {
+            for value in values.expressions() {
+                expr_references(value, None, references);
+            }
+        }
+    }
+    for static_ in &script.statics {
+        for values in &static_.data {
+            for value in values.expressions() {
+                expr_references(value, None, references);
+            }
+        }
+    }
+    for region in &script.static_regions {
+        expr_references(&region.start, None, references);
+        if let Some(ref end) = region.end {
+            expr_references(end, None, references);
+        }
+    }
+    for conditional in &script.conditionals {
+        expr_references(&conditional.condition, None, references);
+        collect_references(&conditional.if_true, references);
+        collect_references(&conditional.if_false, references);
+    }
+}
+
+// `function` is the function containing the expression, recursive calls don't count as a use
+fn expr_references<'a>(
+    expr: &'a ast::Expression,
+    function: Option<&str>,
+    references: &mut HashSet<&'a str>,
+) {
+    match expr.expr {
+        ast::Expr::Variable { ref name, .. }
+        | ast::Expr::Assign { ref name, .. }
+        | ast::Expr::LocalTee { ref name, .. } => {
+            // the base of a field access like `base.field`
+            references.insert(name.split('.').next().unwrap());
+        }
+        ast::Expr::FuncCall { ref name, .. } if Some(name.as_str()) != function => {
+            references.insert(name);
+        }
+        _ => (),
+    }
+    for child in expr.expr.children() {
+        expr_references(child, function, references);
+    }
+}
+
+fn report_unused_items(
+    script: &ast::Script,
+    references: &HashSet<&str>,
+    sources: &Sources,
+    warnings: &mut Warnings,
+) {
+    // the root script is the first source loaded, pub items are used by the scripts including them
+    let is_root = |span: &Span| span.0 == 0;
+    for cnst in &script.consts {
+        if is_root(&cnst.span) && !cnst.public && !references.contains(cnst.name.as_str()) {
+            warnings.warn(
+                Lint::UnusedConst,
+                &format!("Unused const {}", cnst.name),
+                &cnst.span,
+                sources,
+            );
+        }
+    }
+    for func in &script.functions {
+        if is_root(&func.span)
+            && !(func.public || func.export || func.start || func.test || func.stub)
+            && func.name != MEMORY_TRAP
+            && !references.contains(func.name.as_str())
+        {
+            warnings.warn(
+                Lint::UnusedFunction,
+                &format!("Function {} is never called", func.name),
+                &func.span,
+                sources,
+            );
+        }
+    }
+    for conditional in &script.conditionals {
+        report_unused_items(&conditional.if_true, references, sources, warnings);
+        report_unused_items(&conditional.if_false, references, sources, warnings);
+    }
+}
+
+// Warns about unused locals, assignments which are never read, unreachable code
+// and the pitfalls of lazy and inline variables in the (type checked) functions,
+// including the expanded inline functions.
+pub fn check_functions(script: &ast::Script, sources: &Sources, warnings: &mut Warnings) {
+    let functions: HashSet<&str> = script
+        .functions
+        .iter()
+        .map(|func| func.name.as_str())
+        .chain(
+            script
+                .imports
+                .iter()
+                .filter_map(|import| match import.type_ {
+                    ast::ImportType::Function { ref name, .. } => Some(name.as_str()),
+                    _ => None,
+                }),
+        )
+        .collect();
+    let intrinsics = Intrinsics::new();
+    for func in &script.functions {
+        check_function(func, sources, warnings);
+        lazy::check_function(func, &functions, &intrinsics, sources, warnings);
+    }
+}
+
+#[derive(Default)]
+struct Uses {
+    reads: HashSet<u32>,
+    // assignments after the declaration
+    writes: HashSet<u32>,
+    // the locals read by the value of each `let lazy` and `let inline`
+    lazy: HashMap<u32, Vec<u32>>,
+}
+
+fn check_function(func: &ast::Function, sources: &Sources, warnings: &mut Warnings) {
+    let mut uses = Uses::default();
+    collect_uses(&func.body, &mut uses, sources, warnings);
+
+    let locals = func.locals.params.iter().chain(&func.locals.locals);
+    for (id, local) in locals.enumerate() {
+        let id = id as u32;
+        let name = match source_name(&local.name) {
+            Some(name) => name,
+            None => continue,
+        };
+        let kind = if local.param { "Parameter" } else { "Variable" };
+        if !uses.writes.contains(&id) && !uses.reads.contains(&id) {
+            warnings.warn(
+                Lint::UnusedVariable,
+                &format!("Unused {} {}", kind.to_lowercase(), name),
+                &local.span,
+                sources,
+            );
+        } else if !uses.reads.contains(&id) {
+            warnings.warn(
+                Lint::UnreadVariable,
+                &format!("{} {} is assigned to, but never read", kind, name),
+                &local.span,
+                sources,
+            );
+        }
+    }
+
+    let mut liveness = Liveness {
+        func,
+        uses: &uses,
+        labels: Vec::new(),
+        report: true,
+        sources,
+        warnings,
+    };
+    liveness.live_in(&func.body, HashSet::new());
+}
+
+fn collect_uses(
+    expr: &ast::Expression,
+    uses: &mut Uses,
+    sources: &Sources,
+    warnings: &mut Warnings,
+) {
+    match expr.expr {
+        ast::Expr::Variable {
+            local_id: Some(id), ..
+        } => {
+            uses.reads.insert(id);
+        }
+        ast::Expr::Assign {
+            local_id: Some(id), ..
+        }
+        | ast::Expr::LocalTee {
+            local_id: Some(id), ..
+        } => {
+            uses.writes.insert(id);
+        }
+        ast::Expr::Let {
+            local_id: Some(id),
+            value: Some(ref value),
+            let_type,
+            ..
+        } if let_type != ast::LetType::Normal => {
+            let mut reads = Uses::default();
+            collect_uses(value, &mut reads, sources, warnings);
+            uses.lazy.insert(id, reads.reads.into_iter().collect());
+        }
+        ast::Expr::Block {
+            ref statements,
+            ref final_expression,
+        } => {
+            let mut rest = statements.iter().chain(final_expression.as_deref());
+            while let Some(statement) = rest.next() {
+                if let ast::Expr::Return { .. } | ast::Expr::Branch(_) = statement.expr {
+                    if let Some(next) = rest.next() {
+                        warnings.warn(
+                            Lint::UnreachableCode,
+                            "Unreachable code",
+                            &next.span,
+                            sources,
+                        );
+                    }
+                    break;
+                }
+            }
+        }
+        _ => (),
+    }
+    for child in expr.expr.children() {
+        collect_uses(child, uses, sources, warnings);
+    }
+}
+
+// Backwards liveness analysis of the locals, reporting assignments whose value is never read
+struct Liveness<'a> {
+    func: &'a ast::Function,
+    uses: &'a Uses,
+    // the live locals at the target of a branch to each label
+    labels: Vec<(&'a str, HashSet<u32>)>,
+    // disabled while iterating the body of a loop to a fixpoint
+    report: bool,
+    sources: &'a Sources,
+    warnings: &'a mut Warnings,
+}
+
+impl<'a> Liveness<'a> {
+    // returns the locals live before `expr`, given the ones live after it
+    fn live_in(&mut self, expr: &'a ast::Expression, mut live: HashSet<u32>) -> HashSet<u32> {
+        match expr.expr {
+            ast::Expr::Variable {
+                local_id: Some(id), ..
+            } => {
+                self.read(id, &mut live);
+                live
+            }
+            ast::Expr::Let {
+                local_id: Some(id),
+                value: Some(ref value),
+                let_type: ast::LetType::Normal,
+                ..
+            }
+            | ast::Expr::Assign {
+                local_id: Some(id),
+                ref value,
+                ..
+            } => {
+                self.write(id, &expr.span, &live);
+                live.remove(&id);
+                self.live_in(value, live)
+            }
+            // the value of lazy and inline lets is evaluated where they are read
+            ast::Expr::Let { .. } => live,
+            ast::Expr::Block {
+                ref statements,
+                ref final_expression,
+            } => {
+                // code after a `return` or `branch` is never run
+                let reachable = statements
+                    .iter()
+                    .position(|statement| {
+                        matches!(
+                            statement.expr,
+                            ast::Expr::Return { .. } | ast::Expr::Branch(_)
+                        )
+                    })
+                    .map_or(statements.len(), |index| index + 1);
+                let final_expression = final_expression
+                    .as_deref()
+                    .filter(|_| reachable == statements.len());
+                final_expression
+                    .into_iter()
+                    .chain(statements[..reachable].iter().rev())
+                    .fold(live, |live, statement| self.live_in(statement, live))
+            }
+            ast::Expr::LocalTee {
+                local_id: Some(id),
+                ref value,
+                ..
+            } => {
+                live.remove(&id);
+                self.live_in(value, live)
+            }
+            ast::Expr::If {
+                ref condition,
+                ref if_true,
+                ref if_false,
+            } => {
+                let mut branches = self.live_in(if_true, live.clone());
+                match if_false {
+                    Some(if_false) => branches.extend(self.live_in(if_false, live)),
+                    None => branches.extend(live),
+                }
+                self.live_in(condition, branches)
+            }
+            ast::Expr::Select {
+                ref condition,
+                ref if_true,
+                ref if_false,
+            } => {
+                // evaluated in the order `if_true`, `if_false`, `condition`
+                let live = self.live_in(condition, live);
+                let live = self.live_in(if_false, live);
+                self.live_in(if_true, live)
+            }
+            ast::Expr::LabelBlock {
+                ref label,
+                ref block,
+            } => {
+                self.labels.push((label, live.clone()));
+                let live = self.live_in(block, live);
+                self.labels.pop();
+                live
+            }
+            ast::Expr::Loop {
+                ref label,
+                ref block,
+            } => {
+                let report = std::mem::replace(&mut self.report, false);
+                let mut entry = HashSet::new();
+                loop {
+                    self.labels.push((label, entry));
+                    let new_entry = self.live_in(block, live.clone());
+                    entry = self.labels.pop().unwrap().1;
+                    if new_entry == entry {
+                        break;
+                    }
+                    entry = new_entry;
+                }
+                self.report = report;
+                if report {
+                    self.labels.push((label, entry));
+                    entry = self.live_in(block, live);
+                    self.labels.pop();
+                }
+                entry
+            }
+            ast::Expr::Branch(ref label) => self.branch_target(label),
+            ast::Expr::BranchIf {
+                ref condition,
+                ref label,
+            } => {
+                live.extend(self.branch_target(label));
+                self.live_in(condition, live)
+            }
+            ast::Expr::Return { ref value } => match value {
+                Some(value) => self.live_in(value, HashSet::new()),
+                None => HashSet::new(),
+            },
+            _ => expr
+                .expr
+                .children()
+                .into_iter()
+                .rev()
+                .fold(live, |live, child| self.live_in(child, live)),
+        }
+    }
+
+    // reading a lazy local reads the locals of its value
+    fn read(&self, id: u32, live: &mut HashSet<u32>) {
+        if live.insert(id) {
+            for &dependency in self.uses.lazy.get(&id).into_iter().flatten() {
+                self.read(dependency, live);
+            }
+        }
+    }
+
+    fn write(&mut self, id: u32, span: &Span, live: &HashSet<u32>) {
+        // never read at all is already reported for the whole variable
+        if !self.report || live.contains(&id) || !self.uses.reads.contains(&id) {
+            return;
+        }
+        let local = &self.func.locals[id];
+        if let Some(name) = source_name(&local.name) {
+            self.warnings.warn(
+                Lint::UnusedAssignment,
+                &format!("Value assigned to {} is never read", name),
+                span,
+                self.sources,
+            );
+        }
+    }
+
+    fn branch_target(&self, label: &str) -> HashSet<u32> {
+        self.labels
+            .iter()
+            .rev()
+            .find(|(l, _)| *l == label)
+            .map(|(_, live)| live.clone())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,142 @@
+// Checks the conversion of images and palettes and file slices in data blocks, using PNG files
+// written by the tests.
+
+mod common;
+
+use common::{run, Files};
+use curlywas::interp::{Imports, Instance, Module};
+use curlywas::{compile_file, Options};
+
+fn png(width: u32, color_type: png::ColorType, bit_depth: png::BitDepth, pixels: &[u8]) -> Vec<u8> {
+    png_with_palette(width, color_type, bit_depth, None, pixels)
+}
+
+// encodes an image, `pixels` holding the rows already packed
+fn png_with_palette(
+    width: u32,
+    color_type: png::ColorType,
+    bit_depth: png::BitDepth,
+    palette: Option<&[u8]>,
+    pixels: &[u8],
+) -> Vec<u8> {
+    let row_size = (width as usize * color_type.samples() * bit_depth as usize).div_ceil(8);
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, width, (pixels.len() / row_size) as u32);
+    encoder.set_color(color_type);
+    encoder.set_depth(bit_depth);
+    if let Some(palette) = palette {
+        encoder.set_palette(palette.to_vec());
+    }
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(pixels).unwrap();
+    writer.finish().unwrap();
+    data
+}
+
+// compiles main.cwa with a data block at address 0 and returns the start of memory
+fn data(files: &Files, data: &str, len: usize) -> anyhow::Result<Vec<u8>> {
+    files.write(
+        "main.cwa",
+        format!("import \"env.memory\" memory(1); data 0 {{ {} }}", data).as_bytes(),
+    );
+    let wasm = compile_file(files.path("main.cwa"), Options::default()).0?;
+    let module = Module::new(&wasm).unwrap();
+    let instance = Instance::new(&module, Imports::new()).unwrap();
+    Ok(instance.memory()[..len].to_vec())
+}
+
+#[test]
+fn indexed_images() {
+    let files = Files::new(&[]);
+    let palette = [0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255];
+    // 4x2 pixels with 2 bits per pixel, as stored in the PNG and by `format: 2bpp`
+    let indices = [0b00_01_10_11, 0b11_10_01_00];
+    files.write(
+        "sprite.png",
+        &png_with_palette(
+            4,
+            png::ColorType::Indexed,
+            png::BitDepth::Two,
+            Some(&palette),
+            &indices,
+        ),
+    );
+    assert_eq!(
+        data(&files, "image(\"sprite.png\", format: 2bpp)", 2).unwrap(),
+        indices
+    );
+    assert_eq!(
+        data(&files, "image(\"sprite.png\")", 8).unwrap(),
+        [0, 1, 2, 3, 3, 2, 1, 0]
+    );
+    assert_eq!(
+        data(&files, "image(\"sprite.png\", format: 4bpp)", 4).unwrap(),
+        [0x01, 0x23, 0x32, 0x10]
+    );
+    // color 2 doesn't fit into 1 bit
+    assert!(data(&files, "image(\"sprite.png\", format: 1bpp)", 1).is_err());
+    // the palette as i32 values 0xRRGGBB
+    assert_eq!(
+        data(&files, "palette(\"sprite.png\")", 12).unwrap(),
+        [0, 0, 0, 0, 0, 0, 255, 0, 0, 255, 0, 0]
+    );
+}
+
+#[test]
+fn rgb_images() {
+    let files = Files::new(&[]);
+    // white, black, gray, black: numbered by brightness
+    let pixels = [255, 255, 255, 0, 0, 0, 128, 128, 128, 0, 0, 0];
+    files.write(
+        "image.png",
+        &png(4, png::ColorType::Rgb, png::BitDepth::Eight, &pixels),
+    );
+    assert_eq!(
+        data(&files, "image(\"image.png\", format: 2bpp)", 1).unwrap(),
+        [0b10_00_01_00]
+    );
+    assert!(data(&files, "image(\"image.png\", format: 1bpp)", 1).is_err());
+    // without a palette, the color of each pixel
+    assert_eq!(
+        data(&files, "palette(\"image.png\")", 8).unwrap(),
+        [255, 255, 255, 0, 0, 0, 0, 0]
+    );
+}
+
+#[test]
+fn file_slices() {
+    let files = Files::new(&[]);
+    files.write("data.bin", &[1, 2, 3, 4, 5, 6]);
+    assert_eq!(
+        data(&files, "file(\"data.bin\", 2, 3) file(\"data.bin\", 4)", 6).unwrap(),
+        [3, 4, 5, 5, 6, 0]
+    );
+    assert!(data(&files, "file(\"data.bin\", 4, 3)", 1).is_err());
+    assert!(data(&files, "file(\"data.bin\", 7)", 1).is_err());
+}
+
+#[test]
+fn dependencies() {
+    let files = Files::new(&[]);
+    files.write("data.bin", &[1]);
+    files.write(
+        "image.png",
+        &png(1, png::ColorType::Grayscale, png::BitDepth::Eight, &[0]),
+    );
+    files.write(
+        "main.cwa",
+        b"import \"env.memory\" memory(1);
+        data 0 { file(\"data.bin\") image(\"image.png\") palette(\"image.png\") }
+        export fn f() -> i32 { 0?0 }",
+    );
+    let (wasm, mut dependencies) = compile_file(files.path("main.cwa"), Options::default());
+    assert!(run(&wasm.unwrap(), "f", &[]).is_ok());
+    dependencies.sort();
+    let mut expected = vec![
+        files.path("data.bin"),
+        files.path("image.png"),
+        files.path("main.cwa"),
+    ];
+    expected.sort();
+    assert_eq!(dependencies, expected);
+}
@@ -0,0 +1,83 @@
+// Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use curlywas::interp::{Imports, Instance, Module, Trap, Value};
+use curlywas::{compile_file, Options};
+
+// A temporary directory holding the files of a test, removed again when dropped.
+// Tests run in parallel, so each one gets its own directory.
+pub struct Files {
+    dir: PathBuf,
+}
+
+impl Files {
+    pub fn new(files: &[(&str, &str)]) -> Files {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "curlywas-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, source) in files {
+            std::fs::write(dir.join(name), source).unwrap();
+        }
+        Files { dir }
+    }
+
+    // adds a (binary) file
+    pub fn write(&self, name: &str, contents: &[u8]) {
+        std::fs::write(self.dir.join(name), contents).unwrap();
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for Files {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn compile(script: &str, options: Options) -> anyhow::Result<Vec<u8>> {
+    compile_files(&[("main.cwa", script)], options)
+}
+
+// compiles `main.cwa`, which can include the other files
+pub fn compile_files(files: &[(&str, &str)], options: Options) -> anyhow::Result<Vec<u8>> {
+    let files = Files::new(files);
+    compile_file(files.path("main.cwa"), options).0
+}
+
+// calls one of the exports of the module in the interpreter
+pub fn run(wasm: &[u8], function: &str, args: &[Value]) -> Result<Option<Value>, Trap> {
+    let module = Module::new(wasm).unwrap();
+    let mut instance = Instance::new(&module, Imports::new()).unwrap();
+    instance.call(function, args)
+}
+
+// compiles the script and calls one of its exports in the interpreter
+pub fn call(script: &str, function: &str, args: &[Value]) -> Result<Option<Value>, Trap> {
+    run(
+        &compile(script, Options::default()).unwrap(),
+        function,
+        args,
+    )
+}
+
+// compiles the script and returns the memory of a new instance, with the data (and the start function) applied
+pub fn memory(script: &str) -> Vec<u8> {
+    let wasm = compile(script, Options::default()).unwrap();
+    let module = Module::new(&wasm).unwrap();
+    let instance = Instance::new(&module, Imports::new()).unwrap();
+    instance.memory().to_vec()
+}
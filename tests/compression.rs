@@ -0,0 +1,99 @@
+// Checks that compressed data blocks are unpacked to the same memory contents as uncompressed ones,
+// for data exercising the different parts of the compression format.
+
+mod common;
+
+use common::Files;
+use curlywas::interp::{Imports, Instance, Module};
+use curlywas::{compile_file, Options};
+
+// compiles main.cwa and returns the size of the module and the memory after running the start function
+fn instantiate(files: &Files, script: &str) -> (usize, Vec<u8>) {
+    files.write("main.cwa", script.as_bytes());
+    let wasm = compile_file(files.path("main.cwa"), Options::default())
+        .0
+        .unwrap();
+    let module = Module::new(&wasm).unwrap();
+    let instance = Instance::new(&module, Imports::new()).unwrap();
+    (wasm.len(), instance.memory().to_vec())
+}
+
+// stores `data` at 0x100, with and without compression, and compares the memory
+fn round_trip(data: &[u8]) -> (usize, usize) {
+    let files = Files::new(&[]);
+    files.write("data.bin", data);
+    let block = |compressed: &str| {
+        format!(
+            "import \"env.memory\" memory(4);
+            data 0x10 {{ i8(1, 2, 3) }}
+            data 0x100 {} {{ file(\"data.bin\") }}
+            data 0x80 {{ i8(4) }}",
+            compressed
+        )
+    };
+    let (raw_size, raw) = instantiate(&files, &block(""));
+    let (compressed_size, memory) = instantiate(&files, &block("compressed"));
+    // the memory used by the compressed data is cleared again
+    assert!(memory == raw, "data differs after decompression");
+    (raw_size, compressed_size)
+}
+
+// xorshift, to get data without repetitions
+fn random_bytes(len: usize, mut seed: u32) -> Vec<u8> {
+    (0..len)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed as u8
+        })
+        .collect()
+}
+
+#[test]
+fn incompressible_data() {
+    // only literals, in chunks of up to 128 bytes
+    round_trip(&random_bytes(1000, 1));
+    round_trip(&[7]);
+}
+
+#[test]
+fn runs() {
+    // overlapping matches, longer than the longest match
+    let mut data = vec![0xaa; 1000];
+    data.extend(b"ab".repeat(300));
+    data.extend([0, 1, 2]);
+    data.extend(vec![0; 500]);
+    let (raw_size, compressed_size) = round_trip(&data);
+    assert!(compressed_size < raw_size - 1000);
+}
+
+#[test]
+fn distant_matches() {
+    // matches up to the maximal distance of 65536 bytes back
+    let chunk = random_bytes(30000, 2);
+    let mut data = chunk.clone();
+    data.extend(random_bytes(35536, 3));
+    data.extend(&chunk);
+    data.extend(random_bytes(100, 4));
+    data.extend(&chunk[..1000]);
+    let (raw_size, compressed_size) = round_trip(&data);
+    assert!(compressed_size < raw_size - 29000);
+}
+
+#[test]
+fn start_function() {
+    // the start function of the script runs after the data has been decompressed
+    let files = Files::new(&[]);
+    let (_, memory) = instantiate(
+        &files,
+        "import \"env.memory\" memory(1);
+        data 0x100 compressed { \"abcabcabcabc\" }
+        data 0x200 compressed { fill(5, 100) }
+        start fn init() { 0?0 = 0x10b?0 + 0x263?0; }",
+    );
+    assert_eq!(memory[0], b'c' + 5);
+    assert_eq!(&memory[0x100..0x10d], b"abcabcabcabc\0");
+    assert!(memory[0x200..0x264].iter().all(|&byte| byte == 5));
+    assert!(memory[0x264..].iter().all(|&byte| byte == 0));
+}
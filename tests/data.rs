@@ -0,0 +1,251 @@
+// Checks the contents of data blocks by compiling small scripts and looking at the memory of
+// the instantiated module.
+
+mod common;
+
+use common::{call, memory};
+use curlywas::interp::Value;
+use curlywas::{Options, StringFormat};
+
+#[test]
+fn labels() {
+    let script = "
+        import \"env.memory\" memory(1);
+        data 0x1000 {
+            greeting: \"Hello!\"
+            palette: i32(0x000000, 0xff0000, 0x00ff00)
+            msg: \"h\\u{e9}llo\" i8(1, 2, 3)
+            alias:
+            buffer: zeros(16) align(8)
+            table: repeat(2) { i16(1) i8(2) }
+            end:
+        }
+        export fn f() -> i32 {
+            greeting_len * 1000000 + palette_len * 10000 + msg_len * 100 + alias_len + end_len
+        }
+        export fn g() -> i32 { (palette - greeting) * 1000 + buffer_len * 10 + table_len }
+        export fn h() -> i32 { end - table }";
+    assert_eq!(call(script, "f", &[]), Ok(Some(Value::I32(6120616))));
+    assert_eq!(call(script, "g", &[]), Ok(Some(Value::I32(8167))));
+    // the second repetition is aligned for its i16
+    assert_eq!(call(script, "h", &[]), Ok(Some(Value::I32(7))));
+}
+
+#[test]
+fn generators() {
+    let memory = memory(
+        "
+        import \"env.memory\" memory(1);
+        data 16 {
+            i8(for i in 0..4: i * 3)
+            repeat(2) { i8(1) \"ab\" }
+            fill(0xff, 3)
+            zeros(2)
+            align(8)
+            i16(for i in 1..3: -i)
+            repeat(0) { i8(9) }
+            fill(7, 0)
+        }",
+    );
+    let expected: &[u8] = &[
+        0, 3, 6, 9, 1, b'a', b'b', 1, b'a', b'b', 0xff, 0xff, 0xff, 0, 0, 0, 0xff, 0xff, 0xfe,
+        0xff, 0,
+    ];
+    assert_eq!(&memory[16..37], expected);
+}
+
+#[test]
+fn zero_runs() {
+    // long runs of zeros split the data into several segments instead of being stored
+    let data = |zeros: usize| {
+        format!(
+            "import \"env.memory\" memory(1); data 0 {{ i8(1) zeros({}) i8(2) fill(0, {}) align(4096) i8(3) }}",
+            zeros, zeros
+        )
+    };
+    let small = common::compile(&data(16), Options::default()).unwrap();
+    let large = common::compile(&data(1000), Options::default()).unwrap();
+    assert!(large.len() < small.len() + 16);
+
+    let memory = memory(&data(1000));
+    assert_eq!(memory[0], 1);
+    assert!(memory[1..1001].iter().all(|&byte| byte == 0));
+    assert_eq!(memory[1001], 2);
+    assert!(memory[1002..4096].iter().all(|&byte| byte == 0));
+    assert_eq!(memory[4096], 3);
+}
+
+#[test]
+fn generator_errors() {
+    let scripts = [
+        "import \"env.memory\" memory(1); fn f(n: i32) { } data 0 { zeros(f) }",
+        "import \"env.memory\" memory(1); data 0 { fill(1, -1) }",
+        "import \"env.memory\" memory(1); data 0 { align(0) }",
+        "import \"env.memory\" memory(1); data 0 { repeat(2) { label: i8(1) } }",
+        "import \"env.memory\" memory(1); data 0 { i8(for i in 0..2: j) }",
+    ];
+    for script in scripts {
+        assert!(
+            common::compile(script, Options::default()).is_err(),
+            "compiled without an error: {}",
+            script
+        );
+    }
+}
+
+#[test]
+fn strings() {
+    let memory = memory(
+        "
+        import \"env.memory\" memory(1);
+        data 16 {
+            \"é\\u{e9}\\u{1f600}\\41\\n\"
+            b\"\\ff\\00\\80A\"
+        }",
+    );
+    let expected: &[u8] = &[
+        0xc3, 0xa9, 0xc3, 0xa9, 0xf0, 0x9f, 0x98, 0x80, b'A', b'\n', 0xff, 0, 0x80, b'A',
+    ];
+    assert_eq!(&memory[16..30], expected);
+
+    // string literals in expressions are NUL terminated by default
+    let script = "
+        import \"env.memory\" memory(1);
+        export fn f() -> i32 {
+            let s = \"\\u{e9}\";
+            (s~0 << 8) | s?2
+        }
+        export fn g() -> i32 { b\"\\fe\\ff\"~0 }
+        export fn h() -> i32 { ('\\u{e9}' << 8) + '\\ff' }";
+    assert_eq!(call(script, "f", &[]), Ok(Some(Value::I32(0xa9c300))));
+    assert_eq!(call(script, "g", &[]), Ok(Some(Value::I32(0xfffe))));
+    assert_eq!(call(script, "h", &[]), Ok(Some(Value::I32(0xa9c3ff))));
+}
+
+#[test]
+fn string_pool() {
+    let script = "
+        import \"env.memory\" memory(1);
+        export fn same() -> i32 { \"Score:\" == \"Score:\" }
+        export fn suffix() -> i32 { \"core:\" - \"Score:\" }
+        export fn prefix() -> i32 { \"Sco\" - \"Score:\" }
+        export fn bytes() -> i32 { let s = \"ab\"; (s?0 << 16) | (s?1 << 8) | s?2 }";
+    let call_with = |format, function| {
+        let wasm = common::compile(script, Options::default().with_string_format(format)).unwrap();
+        common::run(&wasm, function, &[])
+    };
+    let nul = |function| call_with(StringFormat::NulTerminated, function);
+    assert_eq!(nul("same"), Ok(Some(Value::I32(1))));
+    // "core:\0" is the end of "Score:\0", "Sco\0" isn't part of it
+    assert_eq!(nul("suffix"), Ok(Some(Value::I32(1))));
+    assert_ne!(nul("prefix"), Ok(Some(Value::I32(0))));
+    assert_eq!(nul("bytes"), Ok(Some(Value::I32(0x616200))));
+
+    let length = |function| call_with(StringFormat::LengthPrefixed, function);
+    assert_eq!(length("same"), Ok(Some(Value::I32(1))));
+    assert_eq!(length("bytes"), Ok(Some(Value::I32(0x026162))));
+
+    // without a terminator, prefixes are shared as well
+    let raw = |function| call_with(StringFormat::Raw, function);
+    assert_eq!(raw("suffix"), Ok(Some(Value::I32(1))));
+    assert_eq!(raw("prefix"), Ok(Some(Value::I32(0))));
+}
+
+#[test]
+fn string_errors() {
+    let scripts = [
+        // not valid UTF-8
+        "import \"env.memory\" memory(1); data 0 { \"\\ff\" }",
+        // byte strings are ASCII apart from the byte escapes
+        "import \"env.memory\" memory(1); data 0 { b\"é\" }",
+        "import \"env.memory\" memory(1); data 0 { b\"\\u{e9}\" }",
+        "import \"env.memory\" memory(1); data 0 { \"\\u{110000}\" }",
+        "import \"env.memory\" memory(1); data 0 { \"\\u{d800}\" }",
+        // at most 4 bytes in a character literal
+        "fn f() -> i32 { '\\u{e9}\\u{e9}\\u{e9}' }",
+    ];
+    for script in scripts {
+        assert!(
+            common::compile(script, Options::default()).is_err(),
+            "compiled without an error: {}",
+            script
+        );
+    }
+
+    // a length prefix limits strings to 255 bytes
+    let string = |len: usize| {
+        format!(
+            "import \"env.memory\" memory(1); export fn f() -> i32 {{ \"{}\" }}",
+            "a".repeat(len)
+        )
+    };
+    let length = || Options::default().with_string_format(StringFormat::LengthPrefixed);
+    assert!(common::compile(&string(255), length()).is_ok());
+    assert!(common::compile(&string(256), length()).is_err());
+    assert!(common::compile(&string(256), Options::default()).is_ok());
+}
+
+#[test]
+fn statics() {
+    // placed in the static region by decreasing alignment, keeping the order of equally aligned ones
+    let script = "
+        import \"env.memory\" memory(1);
+        static_region(0x100, 0x200);
+        static bytes { i8(1, 2, 3) }
+        static words: [i32; 2];
+        static more_bytes { i8(4) }
+        static longs { i64(7i64) }
+        export fn f() -> i32 { longs }
+        export fn g() -> i32 { words }
+        export fn h() -> i32 { (bytes << 16) | more_bytes }";
+    assert_eq!(call(script, "f", &[]), Ok(Some(Value::I32(0x100))));
+    assert_eq!(call(script, "g", &[]), Ok(Some(Value::I32(0x108))));
+    assert_eq!(call(script, "h", &[]), Ok(Some(Value::I32(0x1100113))));
+    let memory = memory(script);
+    assert_eq!(memory[0x100], 7);
+    assert_eq!(&memory[0x110..0x114], &[1, 2, 3, 4]);
+
+    // without a static region, right after the last data block
+    let script = "
+        import \"env.memory\" memory(1);
+        data 0x40 { i8(1, 2, 3) }
+        data 0x20 { i8(4) }
+        static half { i16(5) }
+        export fn f() -> i32 { half }";
+    assert_eq!(call(script, "f", &[]), Ok(Some(Value::I32(0x44))));
+}
+
+#[test]
+fn static_errors() {
+    let scripts = [
+        // overlaps the data block
+        "import \"env.memory\" memory(1); static_region(0x10); data 0x12 { i8(1) } static s { i8(1, 2, 3) }",
+        // doesn't fit into the static region
+        "import \"env.memory\" memory(1); static_region(0x10, 0x14); static s: [i32; 2];",
+        // or the memory
+        "import \"env.memory\" memory(1); static_region(0xfffc); static s: [i32; 2];",
+    ];
+    for script in scripts {
+        assert!(
+            common::compile(script, Options::default()).is_err(),
+            "compiled without an error: {}",
+            script
+        );
+    }
+    // a static ending right at the end of the region is fine
+    let script = "import \"env.memory\" memory(1); static_region(0x10, 0x18); static s: [i32; 2];";
+    assert!(common::compile(script, Options::default()).is_ok());
+}
+
+#[test]
+fn zero_statics() {
+    // zero initialized statics don't need a data segment
+    let script = "
+        import \"env.memory\" memory(1);
+        static buffer: [f32; 256];
+        static zeros { zeros(16) }
+        export fn f() -> i32 { zeros - buffer }";
+    let wasm = common::compile(script, Options::default()).unwrap();
+    assert!(!wasmprinter::print_bytes(&wasm).unwrap().contains("(data"));
+    assert_eq!(call(script, "f", &[]), Ok(Some(Value::I32(1024))));
+}
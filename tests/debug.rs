@@ -0,0 +1,344 @@
+// Checks the memory checks of debug builds by running small scripts in the interpreter,
+// and the line table they include.
+
+mod common;
+
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use common::compile;
+use curlywas::interp::{Imports, Instance, Module, Trap, Value};
+use curlywas::Options;
+use wasmparser::{BinaryReader, Operator, Parser, Payload};
+
+const SCRIPT: &str = "import \"env.memory\" memory(1);
+import \"env.memory_trap\" fn memory_trap(i32, i32);
+export fn peek(a: i32) -> i32 { a!4 }
+export fn poke(a: i32) { a$0 = 1.5; }
+export fn byte(a: i32) -> i32 { a?0 }
+export fn store(a: i32) { i64.store(1i64, a, 8); }
+export fn unaligned(a: i32) -> i32 { i32.load(a, 0, 0) }
+";
+
+// calls `function` with the address, returning the line and address passed to the trap function
+fn run(wasm: &[u8], function: &str, address: i32) -> Option<(i32, i32)> {
+    let module = Module::new(wasm).unwrap();
+    let trapped = Cell::new(None);
+    let mut imports = Imports::new();
+    imports.function("env", "memory_trap", |_, args| {
+        if let [Value::I32(line), Value::I32(address)] = *args {
+            trapped.set(Some((line, address)));
+        }
+        Ok(None)
+    });
+    let mut instance = Instance::new(&module, imports).unwrap();
+    let result = instance.call(function, &[Value::I32(address)]);
+    match trapped.get() {
+        Some(_) => assert_eq!(result, Err(Trap::Unreachable)),
+        None => assert!(result.is_ok(), "{}({}) trapped", function, address),
+    }
+    trapped.get()
+}
+
+#[test]
+fn alignment() {
+    let wasm = compile(SCRIPT, Options::default().with_debug()).unwrap();
+    assert_eq!(run(&wasm, "peek", 8), None);
+    assert_eq!(run(&wasm, "peek", 2), Some((3, 6)));
+    assert_eq!(run(&wasm, "poke", 5), Some((4, 5)));
+    assert_eq!(run(&wasm, "byte", 5), None);
+    assert_eq!(run(&wasm, "store", 16), None);
+    // the intrinsics only hint at the alignment
+    assert_eq!(run(&wasm, "store", 4), None);
+    assert_eq!(run(&wasm, "unaligned", 3), None);
+}
+
+#[test]
+fn range() {
+    let options = Options::default()
+        .with_debug()
+        .with_memory_range(0x100..0x1000);
+    let wasm = compile(SCRIPT, options).unwrap();
+    assert_eq!(run(&wasm, "byte", 0xff), Some((5, 0xff)));
+    assert_eq!(run(&wasm, "byte", 0x100), None);
+    assert_eq!(run(&wasm, "byte", 0xfff), None);
+    assert_eq!(run(&wasm, "byte", 0x1000), Some((5, 0x1000)));
+    assert_eq!(run(&wasm, "peek", 0xffc), Some((3, 0x1000)));
+    assert_eq!(run(&wasm, "peek", 0xff8), None);
+    assert_eq!(run(&wasm, "peek", 0xfc), None);
+    assert_eq!(run(&wasm, "peek", -4), Some((3, 0)));
+}
+
+#[test]
+fn compressed_data() {
+    // the decompressor reads halfwords at unaligned addresses
+    let script = "import \"env.memory\" memory(1);
+        data 0x100 compressed { \"abcdabcdabcdabcdabcdabcdabcdabcdabcdabc\" }
+        export fn peek(a: i32) -> i32 { a?0 }";
+    let wasm = compile(script, Options::default().with_debug()).unwrap();
+    let module = Module::new(&wasm).unwrap();
+    let mut instance = Instance::new(&module, Imports::new()).unwrap();
+    assert_eq!(&instance.memory()[0x100..0x103], b"abc");
+    assert_eq!(&instance.memory()[0x123..0x128], b"dabc\0");
+    assert_eq!(
+        instance.call("peek", &[Value::I32(0x101)]),
+        Ok(Some(Value::I32(b'b' as i32)))
+    );
+}
+
+#[test]
+fn without_trap_function() {
+    let script = "import \"env.memory\" memory(1);
+        export fn peek(a: i32) -> i32 { a!0 }";
+    let module_bytes = compile(script, Options::default().with_debug()).unwrap();
+    let module = Module::new(&module_bytes).unwrap();
+    let mut instance = Instance::new(&module, Imports::new()).unwrap();
+    assert_eq!(
+        instance.call("peek", &[Value::I32(4)]),
+        Ok(Some(Value::I32(0)))
+    );
+    assert_eq!(
+        instance.call("peek", &[Value::I32(1)]),
+        Err(Trap::Unreachable)
+    );
+
+    // without --debug, the access isn't checked
+    let module_bytes = compile(script, Options::default()).unwrap();
+    let module = Module::new(&module_bytes).unwrap();
+    let mut instance = Instance::new(&module, Imports::new()).unwrap();
+    assert_eq!(
+        instance.call("peek", &[Value::I32(1)]),
+        Ok(Some(Value::I32(0)))
+    );
+}
+
+#[test]
+fn trap_function_signature() {
+    let script = "import \"env.memory\" memory(1);
+        fn memory_trap(line: i32) {}";
+    assert!(compile(script, Options::default()).is_err());
+}
+
+#[test]
+fn line_table() {
+    let script = "export fn f(a: i32, b: i32) -> i32 {
+    let x = a + 1;
+    x / b
+}
+";
+    let wasm = compile(script, Options::default().with_debug()).unwrap();
+    let mut code_start = 0;
+    let mut instructions = Vec::new();
+    let mut debug_line = None;
+    for payload in Parser::new(0).parse_all(&wasm) {
+        match payload.unwrap() {
+            Payload::CodeSectionStart { range, .. } => code_start = range.start,
+            Payload::CodeSectionEntry(body) => {
+                let mut reader = body.get_operators_reader().unwrap();
+                while !reader.eof() {
+                    let (op, offset) = reader.read_with_offset().unwrap();
+                    instructions.push(((offset - code_start) as u32, op));
+                }
+            }
+            Payload::CustomSection {
+                name: ".debug_line",
+                data,
+                ..
+            } => debug_line = Some(data),
+            _ => (),
+        }
+    }
+    let rows = line_rows(debug_line.unwrap());
+    let line = |instruction: fn(&Operator) -> bool| {
+        let (offset, _) = instructions.iter().find(|(_, op)| instruction(op)).unwrap();
+        rows.iter()
+            .rev()
+            .find(|(address, _)| address <= offset)
+            .unwrap()
+            .1
+    };
+    assert_eq!(line(|op| matches!(op, Operator::I32Add)), 2);
+    assert_eq!(line(|op| matches!(op, Operator::I32DivS)), 3);
+}
+
+// decodes the (address, line) rows of a line program using only the standard opcodes
+fn line_rows(data: &[u8]) -> Vec<(u32, u32)> {
+    let mut reader = BinaryReader::new(data);
+    reader.read_u32().unwrap(); // unit length
+    reader.read_bytes(2).unwrap(); // version
+    let header_length = reader.read_u32().unwrap();
+    reader.read_bytes(header_length as usize).unwrap();
+    let (mut address, mut line) = (0, 1);
+    let mut rows = Vec::new();
+    while !reader.eof() {
+        match reader.read_u8().unwrap() {
+            0 => {
+                let length = reader.read_var_u32().unwrap();
+                match reader.read_u8().unwrap() {
+                    2 => address = reader.read_u32().unwrap(),
+                    _ => {
+                        reader.read_bytes(length as usize - 1).unwrap();
+                        line = 1;
+                    }
+                }
+            }
+            1 => rows.push((address, line)),
+            2 => address += reader.read_var_u32().unwrap(),
+            3 => line = (line as i64 + reader.read_var_i64().unwrap()) as u32,
+            4 | 5 => {
+                reader.read_var_u32().unwrap();
+            }
+            opcode => panic!("unexpected opcode {}", opcode),
+        }
+    }
+    rows
+}
+
+#[test]
+fn debug_info() {
+    let script = "export fn f(a: i32, b: i32) -> i32 {
+    let x = a + 1;
+    x / b
+}
+";
+    let files = common::Files::new(&[("main.cwa", script)]);
+    let options = Options::default().with_debug();
+    let wasm = curlywas::compile_file(files.path("main.cwa"), options)
+        .0
+        .unwrap();
+    let section = |name: &str| {
+        Parser::new(0)
+            .parse_all(&wasm)
+            .find_map(|payload| match payload.unwrap() {
+                Payload::CustomSection {
+                    name: section_name,
+                    data,
+                    ..
+                } if section_name == name => Some(data),
+                _ => None,
+            })
+            .unwrap()
+    };
+    let entries = debug_info_entries(section(".debug_abbrev"), section(".debug_info"));
+    let attribute = |tag: u32, name: &str, attribute: u32| {
+        entries
+            .iter()
+            .find(|(entry_tag, attributes)| {
+                *entry_tag == tag
+                    && attributes.contains(&(DW_AT_NAME, Attribute::String(name.to_string())))
+            })
+            .and_then(|(_, attributes)| {
+                attributes
+                    .iter()
+                    .find(|(entry_attribute, _)| *entry_attribute == attribute)
+            })
+            .map(|(_, value)| value.clone())
+    };
+
+    // the compilation directory is the one of the script, not the working directory
+    let dir = files.dir().canonicalize().unwrap();
+    assert_eq!(
+        attribute(DW_TAG_COMPILE_UNIT, "main", DW_AT_COMP_DIR),
+        Some(Attribute::String(dir.display().to_string()))
+    );
+    // `x` is stored in the local after the two params: DW_OP_WASM_location 0 (a local) 2, DW_OP_stack_value
+    assert_eq!(
+        attribute(DW_TAG_VARIABLE, "x", DW_AT_LOCATION),
+        Some(Attribute::Block(vec![0xed, 0, 2, 0x9f]))
+    );
+    assert_eq!(
+        attribute(DW_TAG_FORMAL_PARAMETER, "b", DW_AT_LOCATION),
+        Some(Attribute::Block(vec![0xed, 0, 1, 0x9f]))
+    );
+    assert_eq!(
+        attribute(DW_TAG_VARIABLE, "x", DW_AT_DECL_LINE),
+        Some(Attribute::Int(2))
+    );
+}
+
+const DW_TAG_FORMAL_PARAMETER: u32 = 0x05;
+const DW_TAG_COMPILE_UNIT: u32 = 0x11;
+const DW_TAG_VARIABLE: u32 = 0x34;
+const DW_AT_LOCATION: u32 = 0x02;
+const DW_AT_NAME: u32 = 0x03;
+const DW_AT_COMP_DIR: u32 = 0x1b;
+const DW_AT_DECL_LINE: u32 = 0x3b;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Attribute {
+    String(String),
+    Int(u64),
+    Block(Vec<u8>),
+}
+
+// decodes the (tag, attributes) of the entries of the first unit, supporting the forms used by the compiler
+fn debug_info_entries(abbrev: &[u8], info: &[u8]) -> Vec<(u32, Vec<(u32, Attribute)>)> {
+    let mut abbreviations = HashMap::new();
+    let mut reader = BinaryReader::new(abbrev);
+    loop {
+        let code = reader.read_var_u32().unwrap();
+        if code == 0 {
+            break;
+        }
+        let tag = reader.read_var_u32().unwrap();
+        reader.read_u8().unwrap(); // has children
+        let mut attributes = Vec::new();
+        loop {
+            let (attribute, form) = (
+                reader.read_var_u32().unwrap(),
+                reader.read_var_u32().unwrap(),
+            );
+            if attribute == 0 {
+                break;
+            }
+            attributes.push((attribute, form));
+        }
+        abbreviations.insert(code, (tag, attributes));
+    }
+
+    let mut reader = BinaryReader::new(info);
+    let length = reader.read_u32().unwrap() as usize;
+    let mut reader = BinaryReader::new(&info[4..4 + length]);
+    reader.read_bytes(2 + 4 + 1).unwrap(); // version, abbreviation offset and address size
+    let int = |reader: &mut BinaryReader, size: usize| {
+        let bytes = reader.read_bytes(size).unwrap();
+        Attribute::Int(
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |value, &byte| value << 8 | byte as u64),
+        )
+    };
+    let mut entries = Vec::new();
+    while !reader.eof() {
+        let code = reader.read_var_u32().unwrap();
+        if code == 0 {
+            continue; // the end of the children of an entry
+        }
+        let (tag, forms) = &abbreviations[&code];
+        let mut attributes = Vec::new();
+        for &(attribute, form) in forms {
+            let value = match form {
+                0x0b => int(&mut reader, 1),
+                0x05 => int(&mut reader, 2),
+                0x01 | 0x06 | 0x13 | 0x17 => int(&mut reader, 4),
+                0x0f => Attribute::Int(reader.read_var_u64().unwrap()),
+                0x08 => {
+                    let mut string = Vec::new();
+                    while let byte @ 1.. = reader.read_u8().unwrap() {
+                        string.push(byte as u8);
+                    }
+                    Attribute::String(String::from_utf8(string).unwrap())
+                }
+                0x18 => {
+                    let length = reader.read_var_u32().unwrap();
+                    Attribute::Block(reader.read_bytes(length as usize).unwrap().to_vec())
+                }
+                form => panic!("unexpected form {:#x}", form),
+            };
+            attributes.push((attribute, value));
+        }
+        entries.push((*tag, attributes));
+    }
+    entries
+}
@@ -0,0 +1,26 @@
+// Scripts which used to crash the compiler or compile to invalid modules, found by fuzzing
+// (see `fuzz/`). Each of them has to fail with an error instead.
+
+mod common;
+
+use curlywas::Options;
+
+const SCRIPTS: &[&str] = &[
+    "fn f() -> i32 { let inline x: i32; x }",
+    "fn f() -> i32 { return; 1 }",
+    "import \"env\" fn f(i32);",
+    "fn f() -> i32 { 1?0 }",
+    "fn f() { i32.store(1, 2); }",
+    "fn f() { memory.fill(1, 2, 3); }",
+    "data 0 { i8(1) }",
+    "import \"env.memory\" memory(65537);",
+    "import \"env.memory\" memory(1); import \"env.memory2\" memory(1);",
+];
+
+#[test]
+fn errors() {
+    for script in SCRIPTS {
+        let result = common::compile(script, Options::default());
+        assert!(result.is_err(), "compiled without an error: {}", script);
+    }
+}
@@ -0,0 +1,107 @@
+// Checks inline and const functions by running small scripts in the interpreter.
+
+mod common;
+
+use common::{call, compile, run};
+use curlywas::interp::Value;
+use curlywas::Options;
+
+#[test]
+fn inline_functions() {
+    let script = "
+        global mut g: i32 = 0;
+        inline fn bump(n: i32) -> i32 {
+            g = g + n;
+            g
+        }
+        inline fn twice(n: i32) -> i32 { bump(n) + bump(n) }
+        export fn f(a: i32) -> i32 {
+            let n = 10;
+            twice(a) * n + twice(2)
+        }";
+    // 1 + 2 = 3 times 10, plus 4 + 6
+    assert_eq!(
+        call(script, "f", &[Value::I32(1)]),
+        Ok(Some(Value::I32(40)))
+    );
+}
+
+#[test]
+fn inline_function_errors() {
+    // bodies are checked even if the function is never called
+    let scripts = [
+        "inline fn bad() -> i32 { 1.0 }",
+        "inline fn bad(a: f32) -> i32 { a }",
+        "inline fn bad() -> i32 { return 1; }",
+        "inline fn a() -> i32 { b() } inline fn b() -> i32 { a() }",
+        "inline fn bad(a: i32) -> i32 { x }",
+    ];
+    for script in scripts {
+        assert!(
+            compile(script, Options::default()).is_err(),
+            "compiled without an error: {}",
+            script
+        );
+    }
+}
+
+#[test]
+fn const_functions() {
+    let script = "
+        import \"env.memory\" memory(1);
+        const fn fib(n: i32) -> i32 {
+            if n < 2 { return n; }
+            fib(n - 1) + fib(n - 2)
+        }
+        const fn sum_to(n: i32) -> i32 {
+            let sum = 0;
+            for i in 0..n {
+                sum = sum + i;
+            }
+            sum
+        }
+        const fn gamma(i: i32) -> i32 {
+            nearest(pow(i as f32 / 15 as f32, 2.2) * 255 as f32) as i32
+        }
+        const fn depth(n: i32) -> i32 {
+            if n == 0 { return 0; }
+            depth(n - 1) + 1
+        }
+        const N = 4;
+        const FIB = fib(10);
+        // close to the recursion limit
+        const DEEP = depth(250);
+        data 0 { i8(sum_to(0..N)) i8(gamma(0), gamma(15)) }
+        export fn f() -> i32 { FIB * 10000 + sum_to(N + 1) * 100 + sum_to(0) + DEEP }
+        export fn g() -> i32 { 0!0 }
+        export fn h() -> i32 { 4~0 }";
+    assert_eq!(call(script, "f", &[]), Ok(Some(Value::I32(551250))));
+    // sum_to(0..4) is 0, 0, 1, 3
+    assert_eq!(call(script, "g", &[]), Ok(Some(Value::I32(0x03010000))));
+    assert_eq!(call(script, "h", &[]), Ok(Some(Value::I32(0xff00))));
+    // const fns are not part of the module
+    let wasm = compile(script, Options::default()).unwrap();
+    assert!(run(&wasm, "fib", &[Value::I32(1)]).is_err());
+}
+
+#[test]
+fn const_function_errors() {
+    let scripts = [
+        // non-constant argument
+        "const fn a(x: i32) -> i32 { x } export fn f(y: i32) -> i32 { a(y) }",
+        // global access
+        "global mut g: i32 = 0; const fn a() -> i32 { g } const A = a();",
+        // memory access
+        "import \"env.memory\" memory(1); const fn a() -> i32 { 0!0 } const A = a();",
+        // endless recursion
+        "const fn a(x: i32) -> i32 { a(x) } const A = a(1);",
+        "const fn a(x: i32) -> i32 { 1 / x } const A = a(0);",
+    ];
+    for script in scripts {
+        assert!(
+            compile(script, Options::default()).is_err(),
+            "compiled without an error: {}",
+            script
+        );
+    }
+}
@@ -0,0 +1,134 @@
+// Compiles the example and test scripts and compares the output to the snapshots in
+// `tests/golden`. After an intended change to the generated code, update the snapshots with
+// `UPDATE_GOLDEN=1 cargo test --test golden` and review the diff of the `.wat` files.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use curlywas::{compile_file, Options};
+
+const GOLDEN_DIR: &str = "tests/golden";
+// the directories holding the scripts to compile, scratch files and the fuzz corpus elsewhere are skipped
+const SCRIPT_DIRS: &[&str] = &["examples", "test"];
+
+fn find_files(dir: &Path, extension: &str, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            find_files(&path, extension, files);
+        } else if path.extension().is_some_and(|ext| ext == extension) {
+            files.push(path);
+        }
+    }
+}
+
+// the first line which differs, to show what changed without a full diff
+fn first_difference(expected: &[u8], actual: &[u8]) -> String {
+    let expected = String::from_utf8_lossy(expected);
+    let actual = String::from_utf8_lossy(actual);
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(a), Some(b)) if a == b => (),
+            (None, None) => break,
+            (a, b) => {
+                return format!(
+                    "line {}:\n    expected: {}\n    actual:   {}",
+                    line,
+                    a.unwrap_or("<end of file>"),
+                    b.unwrap_or("<end of file>")
+                )
+            }
+        }
+    }
+    "binary difference".to_string()
+}
+
+#[test]
+fn golden() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let golden_dir = root.join(GOLDEN_DIR);
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut scripts = Vec::new();
+    for dir in SCRIPT_DIRS {
+        find_files(&root.join(dir), "cwa", &mut scripts);
+    }
+    scripts.sort();
+    assert!(!scripts.is_empty());
+
+    let mut failures = Vec::new();
+    let mut snapshots = HashSet::new();
+    for script in &scripts {
+        let relative = script.strip_prefix(root).unwrap();
+        let wasm = match compile_file(script, Options::default()).0 {
+            Ok(wasm) => wasm,
+            Err(err) => {
+                failures.push(format!("{}: {}", relative.display(), err));
+                continue;
+            }
+        };
+        if let Err(err) = wasmparser::validate(&wasm) {
+            failures.push(format!("{}: invalid module: {}", relative.display(), err));
+            continue;
+        }
+        let wat = wasmprinter::print_bytes(&wasm).unwrap();
+
+        let golden = golden_dir.join(relative);
+        for (path, output) in [
+            (golden.with_extension("wat"), wat.into_bytes()),
+            (golden.with_extension("wasm"), wasm),
+        ] {
+            if update {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, &output).unwrap();
+            } else {
+                match fs::read(&path) {
+                    Ok(expected) if expected == output => (),
+                    Ok(expected) => failures.push(format!(
+                        "{} differs from {}, {}",
+                        relative.display(),
+                        path.strip_prefix(root).unwrap().display(),
+                        first_difference(&expected, &output)
+                    )),
+                    Err(_) => failures.push(format!(
+                        "{}: missing snapshot {}",
+                        relative.display(),
+                        path.strip_prefix(root).unwrap().display()
+                    )),
+                }
+            }
+            snapshots.insert(path);
+        }
+    }
+
+    // snapshots of scripts which no longer exist
+    let mut existing = Vec::new();
+    for extension in ["wat", "wasm"] {
+        if golden_dir.exists() {
+            find_files(&golden_dir, extension, &mut existing);
+        }
+    }
+    for path in existing {
+        if !snapshots.contains(&path) {
+            if update {
+                fs::remove_file(&path).unwrap();
+            } else {
+                failures.push(format!(
+                    "stale snapshot {}",
+                    path.strip_prefix(root).unwrap().display()
+                ));
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\n\nRun `UPDATE_GOLDEN=1 cargo test --test golden` to update the snapshots.",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,88 @@
+(module
+  (type (;0;) (func (param f32 f32 f32 i32)))
+  (type (;1;) (func (param f32) (result f32)))
+  (type (;2;) (func (param i32)))
+  (import "env" "memory" (memory (;0;) 4))
+  (import "env" "circle" (func (;0;) (type 0)))
+  (import "env" "sin" (func (;1;) (type 1)))
+  (import "env" "cls" (func (;2;) (type 2)))
+  (func (;3;) (type 2) (param i32)
+    (local i32 f32 f32)
+    i32.const 32
+    call 2
+    loop ;; label = @1
+      local.get 1
+      f32.convert_i32_s
+      i32.const 4
+      f32.convert_i32_s
+      f32.div
+      local.tee 2
+      i32.const 2
+      f32.convert_i32_s
+      f32.div
+      local.get 0
+      f32.convert_i32_s
+      i32.const 5000
+      f32.convert_i32_s
+      f32.div
+      local.tee 3
+      i32.const 5
+      f32.convert_i32_s
+      f32.mul
+      f32.add
+      call 1
+      i32.const 1
+      f32.convert_i32_s
+      f32.add
+      i32.const 160
+      f32.convert_i32_s
+      f32.mul
+      local.get 2
+      i32.const 3
+      f32.convert_i32_s
+      f32.div
+      local.get 3
+      i32.const 4
+      f32.convert_i32_s
+      f32.mul
+      f32.add
+      call 1
+      i32.const 1
+      f32.convert_i32_s
+      f32.add
+      i32.const 120
+      f32.convert_i32_s
+      f32.mul
+      local.get 3
+      i32.const 17
+      f32.convert_i32_s
+      f32.mul
+      local.get 2
+      i32.const 2
+      f32.convert_i32_s
+      f32.mul
+      f32.add
+      call 1
+      i32.const 2
+      f32.convert_i32_s
+      f32.add
+      i32.const 16
+      f32.convert_i32_s
+      f32.mul
+      local.get 1
+      i32.const 2
+      i32.mul
+      i32.const 63
+      i32.add
+      call 0
+      local.get 1
+      i32.const 1
+      i32.add
+      local.tee 1
+      i32.const 63
+      i32.lt_s
+      br_if 0 (;@1;)
+    end
+  )
+  (export "tic" (func 3))
+)
\ No newline at end of file
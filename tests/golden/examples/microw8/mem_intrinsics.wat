@@ -0,0 +1,57 @@
+(module
+  (type (;0;) (func (result i32)))
+  (type (;1;) (func))
+  (import "env" "memory" (memory (;0;) 4))
+  (import "env" "random" (func (;0;) (type 0)))
+  (func (;1;) (type 1)
+    (local i32 i32)
+    loop ;; label = @1
+      local.get 0
+      local.get 0
+      i32.const 76481
+      i32.add
+      i32.const 76800
+      i32.rem_s
+      i32.load8_u offset=120
+      i32.const 24
+      i32.shl
+      local.get 0
+      i32.const 76480
+      i32.add
+      i32.const 76800
+      i32.rem_s
+      i32.load8_u offset=120
+      i32.const 16
+      i32.shl
+      i32.or
+      local.get 0
+      i32.load16_u offset=119
+      i32.or
+      call 0
+      local.tee 1
+      i32.const 3
+      i32.and
+      i32.const 8
+      i32.mul
+      i32.shr_u
+      local.get 1
+      i32.const 31
+      i32.and
+      local.get 1
+      i32.const 22
+      i32.shr_u
+      i32.eqz
+      i32.mul
+      i32.xor
+      i32.store8 offset=120
+      local.get 0
+      i32.const 1
+      i32.add
+      local.tee 0
+      i32.const 76800
+      i32.lt_s
+      br_if 0 (;@1;)
+    end
+  )
+  (export "upd" (func 1))
+)
\ No newline at end of file
@@ -0,0 +1,192 @@
+(module
+  (type (;0;) (func (param f32 f32) (result f32)))
+  (type (;1;) (func (param f32) (result f32)))
+  (type (;2;) (func (param f32 f32 f32 f32 i32)))
+  (type (;3;) (func))
+  (import "env" "memory" (memory (;0;) 4))
+  (import "env" "pow" (func (;0;) (type 0)))
+  (import "env" "sin" (func (;1;) (type 1)))
+  (import "env" "cos" (func (;2;) (type 1)))
+  (import "env" "atan2" (func (;3;) (type 0)))
+  (import "env" "tan" (func (;4;) (type 1)))
+  (import "env" "atan" (func (;5;) (type 1)))
+  (import "env" "rectangle" (func (;6;) (type 2)))
+  (func (;7;) (type 3)
+    (local i32)
+    loop ;; label = @1
+      local.get 0
+      i32.const 16
+      i32.rem_s
+      i32.const 15
+      i32.mul
+      f32.convert_i32_s
+      local.get 0
+      i32.const 16
+      i32.div_s
+      i32.const 15
+      i32.mul
+      f32.convert_i32_s
+      i32.const 15
+      f32.convert_i32_s
+      i32.const 15
+      f32.convert_i32_s
+      local.get 0
+      call 6
+      local.get 0
+      i32.const 1
+      i32.add
+      local.tee 0
+      i32.const 256
+      i32.lt_s
+      br_if 0 (;@1;)
+    end
+  )
+  (func (;8;) (type 3)
+    (local i32 i32 i32 i32 f32 f32 f32 f32 f32)
+    i32.const 575
+    local.set 0
+    loop ;; label = @1
+      local.get 0
+      i32.const 3
+      i32.rem_s
+      local.get 0
+      i32.const 3
+      i32.div_s
+      i32.const 4
+      i32.mul
+      i32.add
+      i32.const 0
+      f32.convert_i32_s
+      local.get 4
+      f32.const 0x1.99999ap-1 (;=0.8;)
+      f32.sub
+      local.get 4
+      f32.const 0x1.99999ap-1 (;=0.8;)
+      f32.add
+      f32.const 0x1.99999ap-2 (;=0.4;)
+      local.get 0
+      f32.convert_i32_s
+      f32.const 0x1.0a8f5cp+1 (;=2.0825;)
+      f32.mul
+      call 2
+      f32.sub
+      local.tee 6
+      f32.min
+      f32.max
+      local.get 0
+      i32.const 48
+      i32.rem_s
+      f32.convert_i32_s
+      i32.const 48
+      f32.convert_i32_s
+      f32.div
+      local.tee 5
+      f32.const 0x1.99999ap-5 (;=0.05;)
+      f32.add
+      f32.mul
+      local.tee 7
+      local.get 5
+      local.get 5
+      f32.mul
+      f32.const 0x1.99999ap-1 (;=0.8;)
+      f32.mul
+      local.tee 8
+      f32.add
+      local.get 7
+      local.get 8
+      f32.mul
+      f32.sub
+      i32.const 1
+      f32.convert_i32_s
+      f32.min
+      f32.max
+      local.get 5
+      local.get 0
+      i32.const 528
+      i32.lt_s
+      select
+      i32.const 255
+      f32.convert_i32_s
+      f32.mul
+      i32.trunc_f32_s
+      i32.store8 offset=77824
+      local.get 4
+      local.get 6
+      f32.add
+      f32.const 0x1p-1 (;=0.5;)
+      f32.mul
+      local.set 4
+      local.get 0
+      i32.const 1
+      i32.sub
+      local.tee 0
+      br_if 0 (;@1;)
+    end
+    i32.const 255
+    local.set 0
+    loop ;; label = @1
+      local.get 0
+      local.get 0
+      i32.const 2
+      i32.shr_s
+      local.tee 2
+      i32.const 32
+      i32.rem_s
+      i32.const 2
+      i32.div_s
+      local.get 2
+      i32.const 2
+      i32.mul
+      local.get 2
+      i32.const 32
+      i32.ge_s
+      local.tee 3
+      select
+      i32.const 4
+      i32.mul
+      local.get 0
+      i32.const 3
+      i32.and
+      local.tee 1
+      i32.add
+      i32.load8_u offset=78592
+      local.get 2
+      i32.const 1
+      i32.add
+      i32.const 32
+      i32.rem_s
+      i32.const 2
+      i32.div_s
+      local.get 2
+      i32.const 2
+      i32.mul
+      i32.const 1
+      i32.add
+      local.get 3
+      select
+      i32.const 4
+      i32.mul
+      local.get 1
+      i32.add
+      i32.load8_u offset=78592
+      i32.add
+      i32.const 3
+      local.get 3
+      i32.add
+      i32.mul
+      i32.const 8
+      i32.div_s
+      i32.store8 offset=78592
+      local.get 0
+      i32.const 1
+      i32.sub
+      local.tee 0
+      i32.const 0
+      i32.ge_s
+      br_if 0 (;@1;)
+    end
+  )
+  (export "upd" (func 7))
+  (start 8)
+  (data (;0;) (i32.const 78592) "\1a\1c,\00]']\00\b1>S\00\ef}W\00\ff\cdu\00\a7\f0p\008\b7d\00%qy\00)6o\00;]\c9\00A\a6\f6\00s\ef\f7\00\f4\f4\f4\00\94\b0\c2\00Vl\86\003<W\00")
+)
\ No newline at end of file
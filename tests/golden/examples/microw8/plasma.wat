@@ -0,0 +1,81 @@
+(module
+  (type (;0;) (func (param f32) (result f32)))
+  (type (;1;) (func (param i32)))
+  (import "env" "memory" (memory (;0;) 4))
+  (import "env" "sin" (func (;0;) (type 0)))
+  (import "env" "cos" (func (;1;) (type 0)))
+  (func (;2;) (type 1) (param i32)
+    (local i32 f32 f32 f32)
+    loop ;; label = @1
+      local.get 1
+      local.get 1
+      i32.const 320
+      i32.rem_s
+      f32.convert_i32_s
+      i32.const 48
+      f32.convert_i32_s
+      f32.div
+      local.tee 2
+      local.get 1
+      i32.const 320
+      i32.div_s
+      f32.convert_i32_s
+      i32.const 48
+      f32.convert_i32_s
+      f32.div
+      local.tee 3
+      local.get 0
+      f32.convert_i32_s
+      i32.const 200
+      f32.convert_i32_s
+      f32.div
+      local.tee 4
+      i32.const 4
+      f32.convert_i32_s
+      f32.div
+      f32.sub
+      call 0
+      i32.const 3
+      f32.convert_i32_s
+      f32.mul
+      f32.add
+      local.get 4
+      i32.const 5
+      f32.convert_i32_s
+      f32.div
+      f32.add
+      call 0
+      local.get 3
+      local.get 2
+      local.get 4
+      i32.const 3
+      f32.convert_i32_s
+      f32.div
+      f32.sub
+      call 0
+      f32.add
+      local.get 4
+      i32.const 6
+      f32.convert_i32_s
+      f32.div
+      f32.add
+      call 0
+      f32.add
+      i32.const 63
+      f32.convert_i32_s
+      f32.mul
+      i32.trunc_f32_s
+      i32.const 128
+      i32.add
+      i32.store8 offset=120
+      local.get 1
+      i32.const 1
+      i32.add
+      local.tee 1
+      i32.const 81920
+      i32.lt_s
+      br_if 0 (;@1;)
+    end
+  )
+  (export "tic" (func 2))
+)
\ No newline at end of file
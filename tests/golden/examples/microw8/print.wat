@@ -0,0 +1,47 @@
+(module
+  (type (;0;) (func (param i32)))
+  (type (;1;) (func))
+  (import "env" "memory" (memory (;0;) 4))
+  (import "env" "printString" (func (;0;) (type 0)))
+  (import "env" "printChar" (func (;1;) (type 0)))
+  (func (;2;) (type 1)
+    (local i32)
+    i32.const 12
+    call 1
+    i32.const 1953719636
+    call 1
+    i32.const 1052703
+    call 1
+    i32.const 174285409
+    call 1
+    i32.const 0
+    call 0
+    i32.const 32
+    i32.load offset=32
+    i32.const 1000
+    i32.div_s
+    i32.const 3
+    i32.rem_u
+    local.set 0
+    local.get 0
+    i32.const 0
+    i32.eq
+    if ;; label = @1
+      i32.const 6647407
+      call 1
+    else
+      local.get 0
+      i32.const 1
+      i32.eq
+      if ;; label = @2
+        i32.const 7305076
+        call 1
+      else
+        i32.const 2037277037
+        call 1
+      end
+    end
+  )
+  (export "upd" (func 2))
+  (data (;0;) (i32.const 0) "\0ed\22Colors!!!\22\0e\01\0d\0a\00")
+)
\ No newline at end of file
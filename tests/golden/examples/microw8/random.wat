@@ -0,0 +1,99 @@
+(module
+  (type (;0;) (func (result i32)))
+  (type (;1;) (func (result i64)))
+  (type (;2;) (func (result f32)))
+  (type (;3;) (func (param i32)))
+  (import "env" "memory" (memory (;0;) 4))
+  (func (;0;) (type 0) (result i32)
+    call 1
+    i64.const 32
+    i64.shr_s
+    i32.wrap_i64
+  )
+  (func (;1;) (type 1) (result i64)
+    (local i64)
+    global.get 0
+    global.get 0
+    i64.const 12
+    i64.shr_u
+    i64.xor
+    local.tee 0
+    local.get 0
+    i64.const 25
+    i64.shl
+    i64.xor
+    local.tee 0
+    local.get 0
+    i64.const 27
+    i64.shr_u
+    i64.xor
+    global.set 0
+    global.get 0
+    i64.const 2685821657736338717
+    i64.mul
+  )
+  (func (;2;) (type 2) (result f32)
+    i32.const 1065353216
+    call 0
+    i32.const 9
+    i32.shr_u
+    i32.or
+    f32.reinterpret_i32
+    i32.const 1
+    f32.convert_i32_s
+    f32.sub
+  )
+  (func (;3;) (type 3) (param i32)
+    local.get 0
+    i64.extend_i32_s
+    i64.const 32
+    i64.shl
+    i32.const 63
+    local.get 0
+    i32.sub
+    i64.extend_i32_s
+    i64.xor
+    global.set 0
+    call 1
+    global.set 0
+    call 1
+    global.set 0
+  )
+  (func (;4;) (type 3) (param i32)
+    (local i32)
+    loop ;; label = @1
+      local.get 1
+      local.get 0
+      i32.const 10
+      i32.div_s
+      i32.const 320
+      i32.mul
+      i32.add
+      call 3
+      local.get 1
+      call 2
+      i32.const 256
+      f32.convert_i32_s
+      f32.mul
+      local.get 0
+      f32.convert_i32_s
+      i32.const 10
+      f32.convert_i32_s
+      f32.div
+      f32.add
+      i32.trunc_f32_s
+      i32.const 128
+      i32.and
+      i32.store8 offset=120
+      local.get 1
+      i32.const 1
+      i32.add
+      local.tee 1
+      i32.const 76800
+      i32.lt_s
+      br_if 0 (;@1;)
+    end
+  )
+  (global (;0;) (mut i64) i64.const 37)
+  (export "tic" (func 4))
+)
\ No newline at end of file
@@ -0,0 +1,149 @@
+(module
+  (type (;0;) (func (param f32) (result f32)))
+  (type (;1;) (func (result f32)))
+  (type (;2;) (func (param i32 i32 i32)))
+  (type (;3;) (func))
+  (import "env" "memory" (memory (;0;) 4))
+  (import "env" "sin" (func (;0;) (type 0)))
+  (import "env" "time" (func (;1;) (type 1)))
+  (import "env" "setPixel" (func (;2;) (type 2)))
+  (func (;3;) (type 3)
+    (local i32 i32 f32 f32 f32 f32)
+    loop ;; label = @1
+      local.get 0
+      local.get 1
+      i32.const 0
+      f32.convert_i32_s
+      local.get 0
+      f32.convert_i32_s
+      f32.const 0x1.41p+7 (;=160.5;)
+      f32.sub
+      local.get 0
+      f32.convert_i32_s
+      f32.const 0x1.41p+7 (;=160.5;)
+      f32.sub
+      local.get 0
+      f32.convert_i32_s
+      f32.const 0x1.41p+7 (;=160.5;)
+      f32.sub
+      f32.mul
+      local.get 1
+      i32.const 120
+      i32.sub
+      f32.convert_i32_s
+      local.get 1
+      i32.const 120
+      i32.sub
+      f32.convert_i32_s
+      f32.mul
+      f32.add
+      f32.sqrt
+      local.tee 3
+      f32.div
+      local.tee 4
+      local.get 4
+      call 1
+      i32.const 2
+      f32.convert_i32_s
+      f32.div
+      call 0
+      f32.const 0x1.8p-1 (;=0.75;)
+      f32.mul
+      local.tee 2
+      f32.mul
+      local.get 4
+      local.get 4
+      f32.mul
+      local.get 2
+      f32.mul
+      local.get 2
+      f32.mul
+      i32.const 1
+      f32.convert_i32_s
+      f32.add
+      local.get 2
+      local.get 2
+      f32.mul
+      f32.sub
+      f32.sqrt
+      f32.add
+      local.tee 5
+      f32.mul
+      local.get 2
+      f32.sub
+      i32.const 10
+      f32.convert_i32_s
+      f32.mul
+      call 1
+      i32.const 2
+      f32.convert_i32_s
+      f32.div
+      f32.add
+      i32.trunc_f32_s
+      local.get 1
+      i32.const 120
+      i32.sub
+      f32.convert_i32_s
+      local.get 3
+      f32.div
+      local.get 5
+      f32.mul
+      i32.const 10
+      f32.convert_i32_s
+      f32.mul
+      call 1
+      i32.const 2
+      f32.convert_i32_s
+      f32.div
+      f32.add
+      i32.trunc_f32_s
+      i32.xor
+      local.get 5
+      i32.const 100
+      f32.convert_i32_s
+      f32.mul
+      local.get 3
+      f32.div
+      call 1
+      f32.add
+      i32.const 10
+      f32.convert_i32_s
+      f32.mul
+      i32.trunc_f32_s
+      i32.and
+      i32.const 5
+      i32.and
+      f32.convert_i32_s
+      i32.const 4
+      f32.convert_i32_s
+      local.get 5
+      i32.const 100
+      f32.convert_i32_s
+      f32.mul
+      local.get 3
+      f32.div
+      f32.sub
+      f32.mul
+      f32.max
+      i32.trunc_f32_s
+      i32.const 32
+      i32.sub
+      call 2
+      local.get 0
+      i32.const 1
+      i32.add
+      i32.const 320
+      i32.rem_s
+      local.tee 0
+      br_if 0 (;@1;)
+      local.get 1
+      i32.const 1
+      i32.add
+      i32.const 320
+      i32.rem_s
+      local.tee 1
+      br_if 0 (;@1;)
+    end
+  )
+  (export "upd" (func 3))
+)
\ No newline at end of file
@@ -0,0 +1,161 @@
+(module
+  (type (;0;) (func (param f32 f32) (result f32)))
+  (type (;1;) (func (param i32)))
+  (import "env" "memory" (memory (;0;) 2))
+  (import "env" "fmod" (func (;0;) (type 0)))
+  (func (;1;) (type 1) (param i32)
+    (local i32 f32 f32 f32 f32 f32 f32)
+    loop ;; label = @1
+      local.get 1
+      i32.const 15
+      i32.const 7
+      local.get 1
+      i32.const 320
+      i32.rem_s
+      i32.const 160
+      i32.sub
+      f32.convert_i32_s
+      local.tee 3
+      i32.const 9
+      f32.convert_i32_s
+      local.get 1
+      i32.const 320
+      i32.div_s
+      f32.convert_i32_s
+      f32.const 0x1.e2p+6 (;=120.5;)
+      f32.sub
+      local.tee 4
+      f32.div
+      local.get 0
+      f32.convert_i32_s
+      i32.const 1000
+      f32.convert_i32_s
+      f32.div
+      local.tee 2
+      f32.add
+      local.tee 7
+      local.get 2
+      f32.sub
+      f32.mul
+      f32.abs
+      i32.const 9
+      f32.convert_i32_s
+      f32.lt
+      select
+      local.get 7
+      i32.trunc_f32_s
+      i32.const 2
+      i32.rem_s
+      i32.sub
+      local.get 2
+      i32.const 20
+      f32.convert_i32_s
+      local.get 3
+      local.get 3
+      f32.mul
+      local.get 4
+      local.get 4
+      f32.mul
+      f32.add
+      f32.sqrt
+      f32.div
+      f32.add
+      local.tee 5
+      i32.trunc_f32_s
+      i32.const 2
+      i32.rem_s
+      i32.const 3
+      local.get 5
+      i32.trunc_f32_s
+      i32.const 9
+      i32.rem_s
+      i32.const 6
+      i32.ge_s
+      select
+      i32.const 12
+      f32.convert_i32_s
+      local.get 4
+      i32.const 23
+      f32.convert_i32_s
+      f32.div
+      f32.add
+      local.get 4
+      i32.const 4
+      f32.convert_i32_s
+      f32.div
+      i32.const 1
+      f32.convert_i32_s
+      call 0
+      f32.add
+      i32.trunc_f32_s
+      local.get 4
+      local.get 5
+      local.get 5
+      i32.trunc_f32_s
+      local.get 5
+      i32.trunc_f32_s
+      i32.const 9
+      i32.rem_s
+      i32.const 6
+      i32.sub
+      i32.sub
+      f32.convert_i32_s
+      local.get 5
+      i32.trunc_f32_s
+      i32.const 9
+      i32.rem_s
+      i32.const 6
+      i32.ge_s
+      select
+      local.tee 6
+      local.get 2
+      f32.sub
+      f32.mul
+      i32.const -99
+      f32.convert_i32_s
+      local.get 3
+      local.get 6
+      local.get 2
+      f32.sub
+      f32.mul
+      i32.const 50
+      f32.convert_i32_s
+      f32.div
+      local.get 3
+      local.get 6
+      local.get 2
+      f32.sub
+      f32.mul
+      i32.const 50
+      f32.convert_i32_s
+      f32.div
+      f32.mul
+      i32.const 1
+      f32.convert_i32_s
+      f32.add
+      f32.div
+      f32.gt
+      select
+      local.get 4
+      i32.const 0
+      f32.convert_i32_s
+      f32.gt
+      local.get 7
+      local.get 6
+      f32.lt
+      i32.and
+      select
+      i32.const 16
+      i32.mul
+      i32.store8 offset=120
+      local.get 1
+      i32.const 1
+      i32.add
+      local.tee 1
+      i32.const 76800
+      i32.lt_s
+      br_if 0 (;@1;)
+    end
+  )
+  (export "tic" (func 1))
+)
\ No newline at end of file
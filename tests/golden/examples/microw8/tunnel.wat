@@ -0,0 +1,64 @@
+(module
+  (type (;0;) (func (param f32 f32) (result f32)))
+  (type (;1;) (func (param i32)))
+  (import "env" "memory" (memory (;0;) 4))
+  (import "env" "atan2" (func (;0;) (type 0)))
+  (func (;1;) (type 1) (param i32)
+    (local i32 f32 f32 f32)
+    loop ;; label = @1
+      local.get 1
+      i32.const 20000
+      f32.convert_i32_s
+      local.get 1
+      i32.const 320
+      i32.rem_s
+      f32.convert_i32_s
+      f32.const 0x1.403334p+7 (;=160.1;)
+      f32.sub
+      local.tee 3
+      local.get 3
+      f32.mul
+      local.get 1
+      i32.const 320
+      i32.div_s
+      i32.const 120
+      i32.sub
+      f32.convert_i32_s
+      local.tee 4
+      local.get 4
+      f32.mul
+      f32.add
+      f32.sqrt
+      f32.div
+      local.get 0
+      f32.convert_i32_s
+      i32.const 10
+      f32.convert_i32_s
+      f32.div
+      local.tee 2
+      f32.add
+      i32.trunc_f32_s
+      local.get 3
+      local.get 4
+      call 0
+      i32.const 512
+      f32.convert_i32_s
+      f32.mul
+      f32.const 0x1.920c4ap+1 (;=3.141;)
+      f32.div
+      local.get 2
+      f32.add
+      i32.trunc_f32_s
+      i32.xor
+      i32.store8 offset=120
+      local.get 1
+      i32.const 1
+      i32.add
+      local.tee 1
+      i32.const 76800
+      i32.lt_s
+      br_if 0 (;@1;)
+    end
+  )
+  (export "tic" (func 1))
+)
\ No newline at end of file
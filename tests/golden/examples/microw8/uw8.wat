@@ -0,0 +1,13 @@
+(module
+  (type (;0;) (func (param f32 f32) (result f32)))
+  (type (;1;) (func (param f32) (result f32)))
+  (type (;2;) (func (param f32 f32 f32 f32 i32)))
+  (import "env" "memory" (memory (;0;) 4))
+  (import "env" "pow" (func (;0;) (type 0)))
+  (import "env" "sin" (func (;1;) (type 1)))
+  (import "env" "cos" (func (;2;) (type 1)))
+  (import "env" "atan2" (func (;3;) (type 0)))
+  (import "env" "tan" (func (;4;) (type 1)))
+  (import "env" "atan" (func (;5;) (type 1)))
+  (import "env" "rectangle" (func (;6;) (type 2)))
+)
\ No newline at end of file
@@ -0,0 +1,64 @@
+(module
+  (type (;0;) (func (result f32)))
+  (type (;1;) (func))
+  (import "env" "memory" (memory (;0;) 2))
+  (import "env" "time" (func (;0;) (type 0)))
+  (func (;1;) (type 1)
+    (local i32 f32 f32 f32)
+    loop ;; label = @1
+      local.get 0
+      local.get 0
+      i32.const 320
+      i32.rem_s
+      f32.convert_i32_s
+      f32.const 0x1.403334p+7 (;=160.1;)
+      f32.sub
+      local.tee 1
+      i32.const 1024
+      f32.convert_i32_s
+      local.get 1
+      local.get 1
+      f32.mul
+      local.get 0
+      i32.const 320
+      i32.div_s
+      i32.const 120
+      i32.sub
+      f32.convert_i32_s
+      local.tee 2
+      local.get 2
+      f32.mul
+      f32.add
+      f32.div
+      local.tee 3
+      f32.mul
+      call 0
+      i32.const 4
+      f32.convert_i32_s
+      f32.mul
+      f32.add
+      i32.trunc_f32_s
+      local.get 2
+      local.get 3
+      f32.mul
+      call 0
+      i32.const 4
+      f32.convert_i32_s
+      f32.mul
+      f32.add
+      i32.trunc_f32_s
+      i32.xor
+      i32.const -32
+      i32.or
+      i32.store8 offset=120
+      local.get 0
+      i32.const 1
+      i32.add
+      local.tee 0
+      i32.const 76800
+      i32.lt_s
+      br_if 0 (;@1;)
+    end
+  )
+  (export "upd" (func 1))
+)
\ No newline at end of file
@@ -0,0 +1,30 @@
+(module
+  (type (;0;) (func (param i32)))
+  (import "env" "memory" (memory (;0;) 2))
+  (func (;0;) (type 0) (param i32)
+    (local i32)
+    loop ;; label = @1
+      local.get 1
+      local.get 1
+      i32.const 320
+      i32.rem_s
+      local.get 0
+      i32.const 10
+      i32.div_s
+      i32.add
+      local.get 1
+      i32.const 320
+      i32.div_s
+      i32.xor
+      i32.store8 offset=120
+      local.get 1
+      i32.const 1
+      i32.add
+      local.tee 1
+      i32.const 76800
+      i32.lt_s
+      br_if 0 (;@1;)
+    end
+  )
+  (export "tic" (func 0))
+)
\ No newline at end of file
@@ -0,0 +1,14 @@
+(module
+  (type (;0;) (func (param i32 i32 i32)))
+  (type (;1;) (func))
+  (import "env" "memory" (memory (;0;) 1))
+  (import "env" "text" (func (;0;) (type 0)))
+  (func (;1;) (type 1)
+    i32.const 63
+    i32.const 8
+    i32.const 8
+    call 0
+  )
+  (export "update" (func 1))
+  (data (;0;) (i32.const 63) "Hello, World!")
+)
\ No newline at end of file
@@ -0,0 +1,217 @@
+(module
+  (type (;0;) (func (param i32 i32 i32 i32)))
+  (type (;1;) (func (param i32 i32 i32)))
+  (type (;2;) (func (param i32) (result i32)))
+  (type (;3;) (func))
+  (import "env" "memory" (memory (;0;) 1))
+  (import "env" "rect" (func (;0;) (type 0)))
+  (import "env" "oval" (func (;1;) (type 0)))
+  (import "env" "text" (func (;2;) (type 1)))
+  (func (;3;) (type 2) (param i32) (result i32)
+    i32.const 94614859
+    local.get 0
+    local.get 0
+    i32.const 17
+    i32.shr_u
+    i32.xor
+    i32.mul
+  )
+  (func (;4;) (type 2) (param i32) (result i32)
+    i32.const 0
+    local.get 0
+    i32.store8 offset=20
+    i32.const 6
+  )
+  (func (;5;) (type 3)
+    (local i32 i32 i32 i32 i32 i32 i32 i32 f32 f32 f32 f32)
+    global.get 0
+    local.set 1
+    global.get 3
+    f32.const 0x1.99999ap-4 (;=0.1;)
+    f32.add
+    global.get 4
+    f32.const 0x1.eb851ep-6 (;=0.03;)
+    local.tee 9
+    f32.add
+    i32.const 0
+    i32.load8_u offset=22
+    local.tee 2
+    i32.const 1
+    i32.and
+    f32.convert_i32_s
+    f32.mul
+    f32.sub
+    global.set 3
+    global.get 4
+    f32.const 0x1.666666p-1 (;=0.7;)
+    f32.mul
+    global.set 4
+    loop ;; label = @1
+      i32.const 8003
+      local.get 0
+      i32.sub
+      local.get 1
+      i32.const 10
+      i32.div_s
+      local.tee 1
+      i32.const 10
+      i32.rem_s
+      i32.const 48
+      i32.add
+      i32.store8
+      i32.const 80
+      local.get 0
+      f32.convert_i32_s
+      i32.const 4000
+      local.get 0
+      i32.const 1
+      i32.add
+      local.tee 0
+      i32.div_s
+      global.get 0
+      i32.add
+      i32.const 20
+      i32.div_s
+      local.tee 3
+      call 3
+      call 3
+      call 3
+      call 3
+      i32.const 30
+      i32.shr_s
+      f32.convert_i32_s
+      global.get 1
+      f32.sub
+      local.tee 10
+      f32.mul
+      i32.trunc_f32_s
+      i32.add
+      local.tee 4
+      local.get 0
+      local.get 0
+      f32.convert_i32_s
+      i32.const 9
+      f32.convert_i32_s
+      local.get 3
+      f32.convert_i32_s
+      f32.sqrt
+      f32.div
+      local.tee 11
+      f32.mul
+      i32.trunc_f32_s
+      local.tee 5
+      local.get 0
+      local.get 3
+      i32.const 1
+      i32.and
+      i32.const 2
+      i32.add
+      i32.const 17
+      i32.mul
+      local.tee 6
+      i32.const 17
+      i32.add
+      call 4
+      i32.div_s
+      call 0
+      i32.const 8000
+      local.get 6
+      call 4
+      local.get 4
+      local.get 0
+      local.get 5
+      i32.const 1
+      call 0
+      i32.const 4
+      call 4
+      call 2
+      local.get 0
+      i32.const 120
+      i32.eq
+      global.get 2
+      f32.const 0x0p+0 (;=0;)
+      local.tee 8
+      f32.gt
+      i32.and
+      if ;; label = @2
+        local.get 10
+        local.get 11
+        f32.neg
+        f32.lt
+        local.get 10
+        local.get 8
+        f32.gt
+        i32.or
+        if ;; label = @3
+          local.get 2
+          i32.const 2
+          i32.and
+          if ;; label = @4
+            i32.const 30
+            global.set 0
+            local.get 8
+            global.set 1
+          end
+          return
+        end
+        local.get 8
+        global.set 2
+        local.get 8
+        global.set 3
+        f32.const 0x1.99999ap-1 (;=0.8;)
+        global.set 4
+      end
+      local.get 0
+      i32.const 160
+      i32.lt_s
+      br_if 0 (;@1;)
+    end
+    i32.const 74
+    i32.const 103
+    global.get 2
+    i32.trunc_f32_s
+    i32.add
+    local.tee 7
+    i32.const 5
+    i32.add
+    i32.const 50
+    call 4
+    i32.const 69
+    local.get 7
+    i32.const 22
+    i32.const 22
+    call 1
+    i32.const 17
+    call 4
+    call 1
+    global.get 1
+    local.get 2
+    i32.const 16
+    i32.and
+    i32.eqz
+    local.get 2
+    i32.const 32
+    i32.and
+    i32.eqz
+    i32.sub
+    f32.convert_i32_s
+    local.get 9
+    f32.mul
+    f32.add
+    global.set 1
+    global.get 2
+    global.get 3
+    f32.add
+    global.set 2
+    global.get 0
+    i32.const 1
+    i32.add
+    global.set 0
+  )
+  (global (;0;) (mut i32) i32.const 30)
+  (global (;1;) (mut f32) f32.const 0x0p+0 (;=0;))
+  (global (;2;) (mut f32) f32.const 0x0p+0 (;=0;))
+  (global (;3;) (mut f32) f32.const 0x0p+0 (;=0;))
+  (global (;4;) (mut f32) f32.const 0x0p+0 (;=0;))
+  (export "update" (func 5))
+)
\ No newline at end of file
@@ -0,0 +1,42 @@
+(module
+  (type (;0;) (func (param i32) (result i32)))
+  (func (;0;) (type 0) (param i32) (result i32)
+    (local i32)
+    local.get 0
+    local.get 0
+    i32.const 13
+    i32.shl
+    i32.xor
+    local.tee 1
+    local.get 1
+    i32.const 17
+    i32.shr_u
+    i32.xor
+    local.tee 1
+    local.get 1
+    i32.const 5
+    i32.shl
+    i32.xor
+  )
+  (func (;1;) (type 0) (param i32) (result i32)
+    local.get 0
+    local.get 0
+    i32.const 13
+    i32.shl
+    i32.xor
+    local.set 0
+    local.get 0
+    local.get 0
+    i32.const 17
+    i32.shr_u
+    i32.xor
+    local.set 0
+    local.get 0
+    local.get 0
+    i32.const 5
+    i32.shl
+    i32.xor
+    local.set 0
+    local.get 0
+  )
+)
\ No newline at end of file
@@ -0,0 +1,104 @@
+// Checks how included files are resolved into namespaces by compiling small sets of files.
+
+mod common;
+
+use common::{compile_files, run};
+use curlywas::interp::Value;
+use curlywas::Options;
+
+const COUNTER: &str = "
+import \"env.memory\" memory(1);
+global mut count: i32 = 0;
+pub fn next() -> i32 {
+    count = count + 1;
+    count
+}";
+
+#[test]
+fn namespaces() {
+    let main = "
+        include \"counter.cwa\" as a;
+        include \"counter.cwa\" as b;
+        export fn f() -> i32 {
+            a::next();
+            a::next();
+            b::next() * 10 + a::next()
+        }";
+    let wasm = compile_files(
+        &[("main.cwa", main), ("counter.cwa", COUNTER)],
+        Options::default(),
+    )
+    .unwrap();
+    // each namespace has its own copy of the global
+    assert_eq!(run(&wasm, "f", &[]), Ok(Some(Value::I32(13))));
+}
+
+#[test]
+fn name_lookup() {
+    let lib = "
+        pub const X = 1;
+        const Y = 3;
+        pub fn get() -> i32 { X * 10 + Y }";
+    let main = "
+        const X = 2;
+        include \"lib.cwa\" as lib;
+        export fn f() -> i32 { lib::get() * 10 + X + lib::X }";
+    let wasm = compile_files(&[("main.cwa", main), ("lib.cwa", lib)], Options::default()).unwrap();
+    // the items of its own namespace take precedence
+    assert_eq!(run(&wasm, "f", &[]), Ok(Some(Value::I32(133))));
+
+    let main = "
+        include \"lib.cwa\" as lib;
+        export fn f() -> i32 { lib::Y }";
+    assert!(compile_files(&[("main.cwa", main), ("lib.cwa", lib)], Options::default()).is_err());
+}
+
+#[test]
+fn export_in_two_namespaces() {
+    let lib = "export fn f() -> i32 { 1 }";
+    let main = "
+        include \"lib.cwa\" as a;
+        include \"lib.cwa\" as b;";
+    assert!(compile_files(&[("main.cwa", main), ("lib.cwa", lib)], Options::default()).is_err());
+
+    let main = "
+        include \"lib.cwa\" as a;
+        export fn f() -> i32 { 2 }";
+    assert!(compile_files(&[("main.cwa", main), ("lib.cwa", lib)], Options::default()).is_err());
+}
+
+#[test]
+fn conditional_includes() {
+    let files = |main| {
+        [
+            ("main.cwa", main),
+            ("a.cwa", "fn value() -> i32 { 1 }"),
+            ("b.cwa", "fn value() -> i32 { 2 }"),
+        ]
+    };
+    let main = "
+        if const MODE == 1 {
+            include \"a.cwa\";
+        } else {
+            include \"b.cwa\";
+        }
+        export fn f() -> i32 { value() }";
+    for (mode, value) in [("1", 1), ("0", 2)] {
+        let options = Options::default().with_define("MODE", mode).unwrap();
+        let wasm = compile_files(&files(main), options).unwrap();
+        assert_eq!(run(&wasm, "f", &[]), Ok(Some(Value::I32(value))));
+    }
+
+    // a file included unconditionally is only merged once, whichever include comes first
+    for main in [
+        "include \"a.cwa\";
+        if const 1 { include \"a.cwa\"; }
+        export fn f() -> i32 { value() }",
+        "if const 1 { include \"a.cwa\"; }
+        include \"a.cwa\";
+        export fn f() -> i32 { value() }",
+    ] {
+        let wasm = compile_files(&files(main), Options::default()).unwrap();
+        assert_eq!(run(&wasm, "f", &[]), Ok(Some(Value::I32(1))));
+    }
+}
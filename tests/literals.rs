@@ -0,0 +1,93 @@
+// Checks the values of literals by running small scripts in the interpreter.
+
+mod common;
+
+use common::call;
+use curlywas::interp::Value;
+
+#[test]
+fn float_literals() {
+    let script = "
+        const BIG = -inf;
+        export fn big() -> f32 { BIG }
+        export fn nan_f64() -> i32 { nanf64 != nanf64 }
+        export fn hex() -> f64 { 0x1.8p1f64 }
+        export fn tiny() -> f32 { 0x1p-149 }
+        export fn sci() -> f32 { 2.5E+3 }";
+    assert_eq!(
+        call(script, "big", &[]),
+        Ok(Some(Value::F32(-f32::INFINITY)))
+    );
+    assert_eq!(call(script, "nan_f64", &[]), Ok(Some(Value::I32(1))));
+    assert_eq!(call(script, "hex", &[]), Ok(Some(Value::F64(3.0))));
+    assert_eq!(
+        call(script, "tiny", &[]),
+        Ok(Some(Value::F32(f32::from_bits(1))))
+    );
+    assert_eq!(call(script, "sci", &[]), Ok(Some(Value::F32(2500.0))));
+}
+
+#[test]
+fn inf_and_nan_as_names() {
+    let script = "
+        global mut nan: i32 = 2;
+        export fn local(inf: i32) -> i32 { inf + nan }
+        export fn literal() -> f32 { inf }
+        export fn scoped() -> f32 {
+            let x = { let inf = 1.5; inf };
+            x - inf
+        }
+        export fn loop_var() -> f32 {
+            let sum = 0.0;
+            for inf in 0.0..2.0 step 0.5 { sum = sum + inf; }
+            sum
+        }";
+    assert_eq!(
+        call(script, "local", &[Value::I32(1)]),
+        Ok(Some(Value::I32(3)))
+    );
+    assert_eq!(
+        call(script, "literal", &[]),
+        Ok(Some(Value::F32(f32::INFINITY)))
+    );
+    // the local `inf` is only visible inside of its block
+    assert_eq!(
+        call(script, "scoped", &[]),
+        Ok(Some(Value::F32(-f32::INFINITY)))
+    );
+    assert_eq!(call(script, "loop_var", &[]), Ok(Some(Value::F32(3.0))));
+}
+
+#[test]
+fn inf_is_folded() {
+    // `-inf` is a single constant, not a negated one
+    let wasm = common::compile(
+        "export fn f() -> f32 { -inf } export fn g(x: f64) -> f64 { x * -inff64 }",
+        curlywas::Options::default(),
+    )
+    .unwrap();
+    let wat = wasmprinter::print_bytes(wasm).unwrap();
+    assert!(!wat.contains(".neg"), "{}", wat);
+    assert!(wat.contains("f32.const -inf"), "{}", wat);
+    assert!(wat.contains("f64.const -inf"), "{}", wat);
+}
+
+#[test]
+fn float_literal_errors() {
+    let scripts = [
+        // exponents overflowing in the conversion
+        "const X = 0x10p9223372036854775807;",
+        "const X = 0x1.0p-9223372036854775808;",
+        "const X = 0x1p99999999999999999999;",
+        "const X = 0x1p128;",
+        "const X = 0x1p1024f64;",
+        "const X = 1e39;",
+    ];
+    for script in scripts {
+        assert!(
+            common::compile(script, curlywas::Options::default()).is_err(),
+            "compiled without an error: {}",
+            script
+        );
+    }
+}
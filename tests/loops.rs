@@ -0,0 +1,129 @@
+// Checks the `while` and `for` loops by running small scripts in the interpreter.
+
+mod common;
+
+use common::{call, compile};
+use curlywas::interp::Value;
+use curlywas::Options;
+
+#[test]
+fn for_loops() {
+    let script = "
+        const NEG = -1;
+        export fn up(n: i32) -> i32 {
+            let sum = 0;
+            for i in 0..n { sum = sum * 10 + i; }
+            sum
+        }
+        export fn down() -> i32 {
+            let sum = 0;
+            for i in 5..0 step NEG { sum = sum * 10 + i; }
+            sum
+        }
+        export fn stepped() -> i32 {
+            let sum = 0;
+            for i in 10..0 step -3 { sum = sum * 100 + i; }
+            sum
+        }
+        export fn long() -> i64 {
+            let sum = 0i64;
+            for i in 0i64..10i64 { sum = sum + i; }
+            sum
+        }
+        export fn float() -> f32 {
+            let sum = 0.0;
+            for x in 0.0..2.0 step 0.5 { sum = sum + x; }
+            sum
+        }
+        export fn unsigned() -> i32 {
+            let count = 0;
+            for i in 0..-1 { count = count + 1; }
+            for i in 0x7ffffffe #.. 0x80000001 { count = count + 10; }
+            count
+        }";
+    assert_eq!(
+        call(script, "up", &[Value::I32(4)]),
+        Ok(Some(Value::I32(123)))
+    );
+    assert_eq!(
+        call(script, "up", &[Value::I32(-1)]),
+        Ok(Some(Value::I32(0)))
+    );
+    assert_eq!(call(script, "down", &[]), Ok(Some(Value::I32(54321))));
+    assert_eq!(call(script, "stepped", &[]), Ok(Some(Value::I32(10070401))));
+    assert_eq!(call(script, "long", &[]), Ok(Some(Value::I64(45))));
+    assert_eq!(call(script, "float", &[]), Ok(Some(Value::F32(3.0))));
+    assert_eq!(call(script, "unsigned", &[]), Ok(Some(Value::I32(30))));
+}
+
+#[test]
+fn break_and_continue() {
+    let script = "
+        export fn f() -> i32 {
+            let sum = 0;
+            outer: for i in 0..10 {
+                if i == 2 { continue; }
+                let j = 0;
+                while 1 {
+                    if j == i { break; }
+                    if i == 5 { branch outer; }
+                    j = j + 1;
+                    sum = sum + 1;
+                }
+            }
+            sum
+        }";
+    // 0 + 1 + 3 + 4 + 0 (i = 5 leaves the outer loop)
+    assert_eq!(call(script, "f", &[]), Ok(Some(Value::I32(8))));
+}
+
+#[test]
+fn const_fn_loops() {
+    let script = "
+        const fn sum_down(from: i32, step: i32) -> i32 {
+            let sum = 0;
+            for i in from..0 step step { sum = sum + i; }
+            sum
+        }
+        const SUM = sum_down(10, -2);
+        export fn f() -> i32 { SUM }";
+    assert_eq!(call(script, "f", &[]), Ok(Some(Value::I32(30))));
+}
+
+#[test]
+fn non_constant_step() {
+    let script = "export fn f(s: i32) { for i in 0..10 step s {} }";
+    assert!(compile(script, Options::default()).is_err());
+    let script = "export fn f() { for i in 0..10 step 1i64 {} }";
+    assert!(compile(script, Options::default()).is_err());
+}
+
+#[test]
+fn float_steps() {
+    let script = "
+        const QUARTER = 0.25;
+        export fn up() -> i32 {
+            let count = 0;
+            for x in 0.0..10.0 step inf { count = count + 1; }
+            count
+        }
+        export fn down() -> i32 {
+            let count = 0;
+            for x in 1.0..0.0 step -inf { count = count + 1; }
+            count
+        }
+        export fn quarters() -> f32 {
+            let sum = 0.0;
+            for x in 0.0..1.0 step QUARTER { sum = sum + x; }
+            sum
+        }
+        export fn default_step() -> f64 {
+            let sum = 0.0f64;
+            for x in 0.0f64..3.0f64 { sum = sum + x; }
+            sum
+        }";
+    assert_eq!(call(script, "up", &[]), Ok(Some(Value::I32(1))));
+    assert_eq!(call(script, "down", &[]), Ok(Some(Value::I32(1))));
+    assert_eq!(call(script, "quarters", &[]), Ok(Some(Value::F32(1.5))));
+    assert_eq!(call(script, "default_step", &[]), Ok(Some(Value::F64(3.0))));
+}
@@ -0,0 +1,111 @@
+// Checks macro expansion and repeat blocks by running small scripts in the interpreter.
+
+mod common;
+
+use common::{call, compile};
+use curlywas::interp::Value;
+use curlywas::Options;
+
+#[test]
+fn macros() {
+    let script = "
+        macro swap(a, b) {
+            let t = a;
+            a = b;
+            b = t;
+        }
+        macro sq(x) { x * x }
+        macro sum_sq(a, b) { sq(a) + sq(b) }
+        export fn f(a: i32, b: i32) -> i32 {
+            let t = 1000;
+            swap(a, b);
+            // the `t` of the macro doesn't clash with the one here
+            t + a * 10 + b
+        }
+        export fn g(a: i32) -> i32 {
+            let inline c = 3;
+            sum_sq(a + 1, c)
+        }";
+    assert_eq!(
+        call(script, "f", &[Value::I32(1), Value::I32(2)]),
+        Ok(Some(Value::I32(1021)))
+    );
+    // arguments are substituted as written: (a + 1) * (a + 1) + 9
+    assert_eq!(
+        call(script, "g", &[Value::I32(2)]),
+        Ok(Some(Value::I32(18)))
+    );
+}
+
+#[test]
+fn labels_in_macros() {
+    // each expansion gets its own `done` label
+    let script = "
+        import \"env.memory\" memory(1);
+        macro first_above(ptr, count, limit) {
+            let i = 0;
+            block done {
+                loop search {
+                    branch_if (ptr + i)?0 > limit: done;
+                    branch_if (i := i + 1) < count: search;
+                }
+            }
+            i
+        }
+        export fn f() -> i32 {
+            let p = 16;
+            p?0 = 1;
+            p?1 = 5;
+            p?2 = 9;
+            first_above(p, 3, 4) * 10 + first_above(p, 3, 8)
+        }";
+    assert_eq!(call(script, "f", &[]), Ok(Some(Value::I32(12))));
+}
+
+#[test]
+fn repeat() {
+    let script = "
+        import \"env.memory\" memory(1);
+        macro clear(ptr, count) {
+            repeat count as i {
+                (ptr + i * 4)!0 = 0;
+            }
+        }
+        export fn f() -> i32 {
+            let sum = 0;
+            repeat 4 as i {
+                (i * 4)!32 = i + 1;
+            }
+            repeat 2 as i {
+                repeat 3 as j {
+                    sum = sum + i * 10 + j;
+                }
+            }
+            clear(36, 2);
+            sum * 100 + 32!0 * 10 + 44!0
+        }";
+    // the sum is 0 + 1 + 2 + 10 + 11 + 12, the words at 36 and 40 are cleared
+    assert_eq!(call(script, "f", &[]), Ok(Some(Value::I32(3614))));
+}
+
+#[test]
+fn macro_errors() {
+    let scripts = [
+        // recursion
+        "macro a(x) { a(x) } fn f() -> i32 { a(1) }",
+        "macro a(x) { b(x) } macro b(x) { a(x) } fn f() -> i32 { a(1) }",
+        // wrong number of arguments
+        "macro a(x) { x } fn f() -> i32 { a(1, 2) }",
+        // the variables of the body aren't visible at the invocation site
+        "macro a() { let t = 1; } fn f() -> i32 { a(); t }",
+        // the count of a repeat has to be constant
+        "fn f(n: i32) { repeat n as i { } }",
+    ];
+    for script in scripts {
+        assert!(
+            compile(script, Options::default()).is_err(),
+            "compiled without an error: {}",
+            script
+        );
+    }
+}
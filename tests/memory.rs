@@ -0,0 +1,79 @@
+// Checks the memory access operators by storing and loading values in the interpreter.
+
+mod common;
+
+use common::call;
+use curlywas::interp::Value;
+
+#[test]
+fn load_and_store() {
+    let script = "
+        import \"env.memory\" memory(1);
+        export fn bytes() -> i32 {
+            0?0 = 0x1ff;
+            (0?0 << 16) | (0??0 & 0xffff)
+        }
+        export fn signed_byte() -> i32 { 0??0 = -2; 0??0 }
+        export fn half() -> i32 { 0~2 = 0x18765; 0~2 }
+        export fn halves() -> i32 { 0~2 = 0x18765; 0~2 - 0~~2 }
+        export fn signed_half() -> i32 { 0~~2 = -300; 0~~2 }
+        export fn long() -> i64 {
+            16!!0 = 0x1122334455667788i64;
+            ((16!4) as i64 << 32i64) | (16!!0 & 0xffffffffi64)
+        }
+        export fn double() -> f64 { 8$$0 = 1.5f64; 8$$0 * 2.0f64 }
+        export fn double_bits() -> i64 { 8$$0 = 1.0f64; 8!!0 }
+        export fn float() -> f32 { 4$0 = 0.25; 4$0 }";
+    // `?` and `~` zero-extend, `??` and `~~` sign-extend
+    assert_eq!(call(script, "bytes", &[]), Ok(Some(Value::I32(0xffffff))));
+    assert_eq!(call(script, "signed_byte", &[]), Ok(Some(Value::I32(-2))));
+    assert_eq!(call(script, "half", &[]), Ok(Some(Value::I32(0x8765))));
+    assert_eq!(call(script, "halves", &[]), Ok(Some(Value::I32(0x10000))));
+    assert_eq!(call(script, "signed_half", &[]), Ok(Some(Value::I32(-300))));
+    assert_eq!(
+        call(script, "long", &[]),
+        Ok(Some(Value::I64(0x1122334455667788)))
+    );
+    assert_eq!(call(script, "double", &[]), Ok(Some(Value::F64(3.0))));
+    assert_eq!(
+        call(script, "double_bits", &[]),
+        Ok(Some(Value::I64(0x3ff0000000000000)))
+    );
+    assert_eq!(call(script, "float", &[]), Ok(Some(Value::F32(0.25))));
+}
+
+#[test]
+fn double_bang() {
+    // `!!` after a value is an i64 load, in front of one it's two `!`
+    let script = "
+        import \"env.memory\" memory(1);
+        export fn load(a: i32) -> i64 { 0!!0 = 0x100000000i64; a!!0 }
+        export fn not_not(a: i32) -> i32 { !!a }
+        export fn not(a: i32) -> i32 { !a }
+        export fn not_not_not(a: i32) -> i32 { !!!a }
+        export fn both(a: i32) -> i32 { 0!!0 = 5i64; !!((a!!0) as i32) }";
+    assert_eq!(
+        call(script, "load", &[Value::I32(0)]),
+        Ok(Some(Value::I64(0x100000000)))
+    );
+    assert_eq!(
+        call(script, "not_not", &[Value::I32(7)]),
+        Ok(Some(Value::I32(1)))
+    );
+    assert_eq!(
+        call(script, "not_not", &[Value::I32(0)]),
+        Ok(Some(Value::I32(0)))
+    );
+    assert_eq!(
+        call(script, "not", &[Value::I32(7)]),
+        Ok(Some(Value::I32(0)))
+    );
+    assert_eq!(
+        call(script, "not_not_not", &[Value::I32(7)]),
+        Ok(Some(Value::I32(0)))
+    );
+    assert_eq!(
+        call(script, "both", &[Value::I32(0)]),
+        Ok(Some(Value::I32(1)))
+    );
+}
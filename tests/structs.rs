@@ -0,0 +1,83 @@
+// Checks struct layouts and field accesses by running small scripts in the interpreter.
+
+mod common;
+
+use common::{call, compile};
+use curlywas::interp::Value;
+use curlywas::Options;
+
+const STRUCTS: &str = "
+    import \"env.memory\" memory(1);
+    struct Player {
+        x: f32,
+        flags: i8,
+        kind: i16,
+        score: i64,
+        y: f64,
+    }
+    struct Pixel { flags: i8, color: i8 }";
+
+#[test]
+fn layout() {
+    let script = format!(
+        "{}
+        const SIZE = sizeof(Player);
+        export fn sizes() -> i32 {{ SIZE * 100 + sizeof(Pixel) }}
+        export fn offsets() -> i32 {{
+            offsetof(Player, flags) * 10000 + offsetof(Player, kind) * 1000
+                + offsetof(Player, score) * 100 + offsetof(Player, y) + offsetof(Pixel, color)
+        }}",
+        STRUCTS
+    );
+    // fields are aligned to their size, the struct to its largest field
+    assert_eq!(call(&script, "sizes", &[]), Ok(Some(Value::I32(2402))));
+    assert_eq!(call(&script, "offsets", &[]), Ok(Some(Value::I32(46817))));
+}
+
+#[test]
+fn field_access() {
+    let script = format!(
+        "{}
+        export fn f(p: i32) -> i32 {{
+            let q = p + sizeof(Player);
+            p.x = 1.5;
+            p.Player.flags = 255;
+            p->kind = 70000;
+            p.score = 1i64 << 40i64;
+            p.y = 2.0f64;
+            q.Pixel.flags = 7;
+            q.color = 9;
+            p.score += 3i64;
+            // narrow fields store the low bits and are read back unsigned
+            (p!0 == 0x3fc00000) as i32 * 1000
+                + (p.Player.flags == 255 & p.kind == 70000 - 65536 & q.Pixel.flags == 7 & q->color == 9) as i32 * 100
+                + (p.score == (1i64 << 40i64) + 3i64 & p.y == 2.0f64 & p.x == 1.5) as i32
+        }}",
+        STRUCTS
+    );
+    assert_eq!(
+        call(&script, "f", &[Value::I32(64)]),
+        Ok(Some(Value::I32(1101)))
+    );
+}
+
+#[test]
+fn struct_errors() {
+    let scripts = [
+        // unknown field
+        "struct A { x: i32 } fn f(p: i32) -> i32 { p.z }",
+        // ambiguous field
+        "struct A { x: i32 } struct B { x: i32 } fn f(p: i32) -> i32 { p.x }",
+        // duplicate field
+        "struct A { x: i32, x: i8 }",
+        "fn f() -> i32 { sizeof(A) }",
+        "struct A { x: i32 } fn f() -> i32 { offsetof(A, y) }",
+    ];
+    for script in scripts {
+        assert!(
+            compile(script, Options::default()).is_err(),
+            "compiled without an error: {}",
+            script
+        );
+    }
+}
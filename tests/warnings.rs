@@ -0,0 +1,175 @@
+// Runs the compiler on small scripts and checks the warnings printed for them.
+
+mod common;
+
+use std::process::Command;
+
+fn warnings(script: &str, args: &[&str]) -> Vec<String> {
+    let files = common::Files::new(&[("main.cwa", script)]);
+    let output = Command::new(env!("CARGO_BIN_EXE_curlywas"))
+        .args(args)
+        .arg(files.path("main.cwa"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "compiling failed: {}", script);
+    String::from_utf8(output.stderr)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.split_once("Warning:"))
+        .map(|(_, msg)| msg.replace("\x1b[0m", "").trim().to_string())
+        .collect()
+}
+
+const SCRIPT: &str = "
+const UNUSED = 1;
+const USED = 2;
+
+fn never_called() {}
+
+fn helper(a: i32, b: i32) -> i32 {
+    a + USED
+}
+
+export fn f(p: i32) -> i32 {
+    let x = 1;
+    let z = 2;
+    z = 3;
+    let w = 5;
+    w = 6;
+    let t = p;
+    let t = t as f32;
+    if p {
+        return w;
+        p = 3;
+    }
+    helper(w, 2) + t as i32
+}
+";
+
+#[test]
+fn lints() {
+    let mut found = warnings(SCRIPT, &[]);
+    found.sort();
+    assert_eq!(
+        found,
+        [
+            "Function never_called is never called",
+            "Unreachable code",
+            "Unused const UNUSED",
+            "Unused parameter b",
+            "Unused variable x",
+            "Value assigned to w is never read",
+            "Variable z is assigned to, but never read",
+            "t is shadowed with a different type",
+        ]
+    );
+}
+
+#[test]
+fn disabled_lints() {
+    let found = warnings(
+        SCRIPT,
+        &[
+            "-A",
+            "unused_const",
+            "-A",
+            "unused_function",
+            "-A",
+            "unused_variable",
+            "-A",
+            "unread_variable",
+            "-A",
+            "unused_assignment",
+            "-A",
+            "shadowed_type",
+            "-A",
+            "unreachable_code",
+        ],
+    );
+    assert!(found.is_empty(), "{:?}", found);
+}
+
+#[test]
+fn attributes() {
+    let script = "
+        #[allow(unused_const)]
+        const A = 1;
+        #[allow(unused_function, unused_variable)]
+        fn f(x: i32) { let y = 1; }
+        #[warn(unused_function)]
+        fn g() {}
+    ";
+    assert_eq!(
+        warnings(script, &["-A", "unused_function"]),
+        ["Function g is never called"]
+    );
+}
+
+#[test]
+fn lazy_and_inline_hazards() {
+    let script = "
+        import \"env.memory\" memory(1);
+        import \"env.rand\" fn rand() -> i32;
+        import \"env.print\" fn print(i32);
+
+        export fn f() {
+            let inline r = rand();
+            print(r);
+
+            let x = 1;
+            let lazy y = x * 2;
+            x = 5;
+            print(y + x);
+
+            let lazy z = rand();
+            if x {
+                print(z);
+            } else {
+                print(z + 1);
+            }
+        }
+    ";
+    let mut found = warnings(script, &[]);
+    found.sort();
+    assert_eq!(
+        found,
+        [
+            "Inline variable r has side effects, which happen at every use",
+            "Lazy variable y is evaluated after x was assigned to",
+            "Lazy variable z is only evaluated in a branch, but used outside of it",
+            "Value assigned to x is never read",
+        ]
+    );
+}
+
+#[test]
+fn no_false_positives() {
+    let script = "
+        inline fn add(a: i32, b: i32) -> i32 { a + b }
+
+        // `sum = 1` is read by the lazy value, the warning about that is disabled
+        #[allow(lazy_input_reassigned)]
+        export fn f(n: i32) -> i32 {
+            let sum = 0;
+            let i = 0;
+            loop l {
+                sum = add(sum, i);
+                branch_if (i := i + 1) < n: l;
+            }
+            let lazy doubled = sum * 2;
+            sum = 1;
+            let _unused = 0;
+            // `i` is assigned by the evaluation of `j`, declared before `k`
+            let lazy j = (i := i + 1);
+            let lazy k = i * 2;
+            let lazy m = n;
+            if n {
+                sum = m;
+                sum = m + sum;
+            }
+            doubled + sum + j + k
+        }
+    ";
+    let found = warnings(script, &[]);
+    assert!(found.is_empty(), "{:?}", found);
+}